@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+// how a single key of a task's query_map has been used, kept by the task thread while
+// ServerConfig::access_metadata is on and sent along with every QueryOk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyAccess {
+    // how often the key has been queried, the query this came with included
+    pub queries: u64,
+    // the task's version when the key was last written, 0 for values the task was created with
+    pub updated_at_version: u64,
+    // clock time of the query before this one, None for the first
+    pub last_access: Option<Duration>,
+}
+
+impl KeyAccess {
+    // notes a query at now and returns what the querier is told
+    pub(crate) fn queried(&mut self, now: Duration) -> KeyAccess {
+        self.queries += 1;
+        let seen = *self;
+        self.last_access = Some(now);
+        seen
+    }
+
+    // notes a write of the key at the task's version, what has been counted so far is kept
+    pub(crate) fn written(&mut self, version: u64) {
+        self.updated_at_version = version;
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::mpsc::{Receiver, SendError, Sender};
+
+use crate::select::Doorbell;
+use crate::{TaskId, TaskSnapshot, TaskStats};
+
+// operator commands for the worker. they travel on their own channel next to the request channel and the worker
+// takes them before every request it handles, so they get through however deep its queues are.
+// see ServerThread::admin
+#[derive(Debug)]
+pub enum AdminCommand {
+    DumpStats { reply_tx: Sender<WorkerStats> },
+    // ids of every running task, in id order
+    ListTasks { reply_tx: Sender<Vec<TaskId>> },
+    // the task no longer takes requests and is killed the way chaos kills it. an instruction it is running is
+    // finished, whatever it had queued behind it is dropped unanswered. replies whether there was such a task
+    KillTask { id: TaskId, reply_tx: Sender<bool> },
+    // the task's counters, asked behind whatever it has queued. reply_tx is dropped if there is no such task
+    TaskStatus { id: TaskId, reply_tx: Sender<TaskStats> },
+    // the worker keeps taking requests off its channel but stops handling them until ResumeIntake.
+    // pings and shutdowns are still handled, a shutdown ends the pause
+    PauseIntake,
+    ResumeIntake,
+    // asks every task for its state behind whatever it already has queued, and tasks kept after expiring for theirs.
+    // replies right away with each task's id and a receiver that gets a single TaskSnapshot. handler tasks never answer theirs
+    Snapshot { reply_tx: Sender<Vec<(TaskId, Receiver<TaskSnapshot>)>> },
+}
+
+// what the worker reports for AdminCommand::DumpStats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerStats {
+    pub active_tasks: usize,
+    pub queue_depth: usize,  // requests waiting in the worker's QoS queues
+    pub paused: bool,
+    pub tasks: Vec<TaskId>,  // running tasks, in id order
+    pub dead_letters: usize,
+    pub finished_tasks: TaskStats, // the counters of every task run that has ended, added up
+}
+
+// the sending half of the worker's admin channel. every command wakes the worker right away, even one that is idle or
+// paused, see WorkerThread::admin_sender. cloning is cheap
+#[derive(Clone)]
+pub struct AdminSender {
+    tx: Sender<AdminCommand>,
+    bell: Doorbell,
+}
+
+impl AdminSender {
+    pub(crate) fn new(tx: Sender<AdminCommand>, bell: Doorbell) -> Self {
+        Self { tx, bell }
+    }
+
+    pub fn send(&self, command: AdminCommand) -> Result<(), SendError<AdminCommand>> {
+        self.tx.send(command)?;
+        self.bell.ring();
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::backpressure::CountingSender;
+use crate::{Clock, Envelope, EventBus, ServerEvent};
+
+// bounds and thresholds for the autoscaler, see ServerConfig::autoscale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoscaleConfig {
+    pub min_tasks: usize,
+    pub max_tasks: usize,
+    pub step: usize,          // how far the cap moves per decision
+    pub window: Duration,     // the throttle rate covers creates answered within the last window
+    pub interval: Duration,   // how often the controller decides
+    pub scale_up_rate: f64,   // raise the cap once at least this share of creates in the window were throttled
+    pub queue_high: usize,    // or once this many requests are waiting on the worker channel
+}
+
+impl AutoscaleConfig {
+    // ServerConfig::validate turns down a min_tasks above max_tasks, one that wasn't checked ends up at max_tasks
+    fn bound(&self, cap: usize) -> usize {
+        cap.max(self.min_tasks).min(self.max_tasks)
+    }
+}
+
+impl Default for AutoscaleConfig {
+    fn default() -> Self {
+        Self {
+            min_tasks: 1,
+            max_tasks: 16,
+            step: 1,
+            window: Duration::from_secs(1),
+            interval: Duration::from_millis(100),
+            scale_up_rate: 0.1,
+            queue_high: 32,
+        }
+    }
+}
+
+// watches how many creates get throttled and moves the worker's concurrency cap to match
+// the cap goes up while creates are being throttled or the worker channel backs up, and down a step at a time
+// while nothing was throttled in the window and the cap has room to spare. every move is published as
+// ServerEvent::Scaled
+// cloning is cheap, every clone shares the same window
+#[derive(Clone)]
+pub struct Autoscaler {
+    config: AutoscaleConfig,
+    samples: Arc<Mutex<VecDeque<(Duration, bool)>>>, // clock time of each create's result, and whether it was throttled
+    clock: Arc<dyn Clock>,
+}
+
+impl Autoscaler {
+    pub fn new(config: AutoscaleConfig, clock: Arc<dyn Clock>) -> Self {
+        Self { config, samples: Arc::new(Mutex::new(VecDeque::new())), clock }
+    }
+
+    pub fn config(&self) -> AutoscaleConfig {
+        self.config
+    }
+
+    // called by the listener for every create result
+    pub fn observe(&self, throttled: bool) {
+        let now = self.clock.now();
+        self.samples.lock().unwrap().push_back((now, throttled));
+    }
+
+    // share of the creates answered within the window that were throttled, 0.0 when there were none
+    pub fn throttle_rate(&self) -> f64 {
+        let now = self.clock.now();
+        let mut samples = self.samples.lock().unwrap();
+        while samples.front().is_some_and(|(at, _)| now.saturating_sub(*at) > self.config.window) {
+            samples.pop_front();
+        }
+        if samples.is_empty() {
+            return 0.0;
+        }
+        samples.iter().filter(|(_, throttled)| *throttled).count() as f64 / samples.len() as f64
+    }
+
+    // the cap the controller wants next, given the current one
+    fn decide(&self, cap: usize, active: usize, queue_depth: usize, throttle_rate: f64) -> usize {
+        let config = &self.config;
+        let target = if (throttle_rate > 0.0 && throttle_rate >= config.scale_up_rate) || queue_depth >= config.queue_high {
+            cap.saturating_add(config.step)
+        } else if throttle_rate == 0.0 && active + config.step <= cap {
+            cap.saturating_sub(config.step)
+        } else {
+            cap
+        };
+        config.bound(target)
+    }
+
+    // pulls the cap into bounds and starts the controller thread, which decides every interval
+    // the thread exits once the shutdown flag is set
+    pub(crate) fn spawn(
+        &self,
+        cap: Arc<AtomicUsize>,
+        active_tasks: Arc<AtomicUsize>,
+        worker_tx: CountingSender<Envelope>,
+        events: EventBus,
+        shutdown_flag: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        let config = self.config;
+        let start = cap.load(Ordering::Relaxed);
+        cap.store(config.bound(start), Ordering::Relaxed);
+
+        let autoscaler = self.clone();
+        thread::spawn(move || {
+            while !shutdown_flag.load(Ordering::Relaxed) {
+                autoscaler.clock.sleep(config.interval);
+                let from = cap.load(Ordering::Relaxed);
+                let throttle_rate = autoscaler.throttle_rate();
+                let queue_depth = worker_tx.depth();
+                let to = autoscaler.decide(from, active_tasks.load(Ordering::Acquire), queue_depth, throttle_rate);
+                if to != from {
+                    cap.store(to, Ordering::Relaxed);
+                    println!("[Autoscaler] Concurrency cap {from} -> {to} (throttle rate {throttle_rate:.2}, queue depth {queue_depth})");
+                    events.publish(ServerEvent::Scaled { from, to, throttle_rate, queue_depth });
+                }
+            }
+            println!("[Autoscaler] Shutdown flag detected. Autoscaler exiting.");
+        })
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, SendError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "crossbeam")]
+use crossbeam_channel::{Receiver, Sender};
+#[cfg(not(feature = "crossbeam"))]
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::clock::{self, Clock};
+use crate::fault::MessageSender;
+use crate::select::Doorbell;
+
+// which channels carry requests to the worker and instructions to the tasks. std's mpsc unless the crate is built with
+// the crossbeam feature. result channels are std's either way, their senders are part of the public types
+#[cfg(not(feature = "crossbeam"))]
+pub const CHANNEL_BACKEND: &str = "mpsc";
+#[cfg(feature = "crossbeam")]
+pub const CHANNEL_BACKEND: &str = "crossbeam";
+
+// how a counting channel's sends went, for comparing the backends, see ServerStats::channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelStats {
+    pub backend: &'static str,
+    pub sends: u64,
+    // wall clock time one send took, on average. None before the first
+    pub average_send: Option<Duration>,
+}
+
+// called with the depth on the sending thread whenever the depth climbs to the high-water mark
+pub type HighWaterCallback = Box<dyn Fn(usize) + Send>;
+
+struct Gauge {
+    depth: AtomicUsize,
+    high_water: Mutex<Option<(usize, HighWaterCallback)>>,
+    // rung after every send, for a receiver that selects over this channel and others
+    bell: Option<Doorbell>,
+    sends: AtomicU64,
+    send_nanos: AtomicU64,
+}
+
+// a channel Sender, see CHANNEL_BACKEND, that counts the messages it has sent and the receiving side has not taken yet
+// cloning is cheap, every clone adds to the same count
+pub struct CountingSender<T> {
+    tx: Sender<T>,
+    gauge: Arc<Gauge>,
+}
+
+// the receiving half of a counting channel, taking a message off it lowers the count
+pub struct CountingReceiver<T> {
+    rx: Receiver<T>,
+    gauge: Arc<Gauge>,
+}
+
+pub fn channel<T>() -> (CountingSender<T>, CountingReceiver<T>) {
+    counting_channel(None)
+}
+
+// a channel that rings bell on every send, see select::select
+pub(crate) fn channel_with_doorbell<T>(bell: Doorbell) -> (CountingSender<T>, CountingReceiver<T>) {
+    counting_channel(Some(bell))
+}
+
+fn counting_channel<T>(bell: Option<Doorbell>) -> (CountingSender<T>, CountingReceiver<T>) {
+    #[cfg(not(feature = "crossbeam"))]
+    let (tx, rx) = mpsc::channel();
+    #[cfg(feature = "crossbeam")]
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let gauge = Arc::new(Gauge {
+        depth: AtomicUsize::new(0),
+        high_water: Mutex::new(None),
+        bell,
+        sends: AtomicU64::new(0),
+        send_nanos: AtomicU64::new(0),
+    });
+    (CountingSender { tx, gauge: Arc::clone(&gauge) }, CountingReceiver { rx, gauge })
+}
+
+impl<T> Clone for CountingSender<T> {
+    fn clone(&self) -> Self {
+        Self { tx: self.tx.clone(), gauge: Arc::clone(&self.gauge) }
+    }
+}
+
+impl<T> CountingSender<T> {
+    // counted before the send so the receiver can never take a message the count doesn't include yet
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        let depth = self.gauge.depth.fetch_add(1, Ordering::AcqRel) + 1;
+        let started = Instant::now();
+        if let Err(e) = self.tx.send(msg) {
+            self.gauge.depth.fetch_sub(1, Ordering::AcqRel);
+            return Err(SendError(e.0));
+        }
+        self.gauge.send_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.gauge.sends.fetch_add(1, Ordering::Relaxed);
+        if let Some(bell) = &self.gauge.bell {
+            bell.ring();
+        }
+        if let Some((mark, callback)) = &*self.gauge.high_water.lock().unwrap() {
+            if depth == *mark {
+                callback(depth);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn depth(&self) -> usize {
+        self.gauge.depth.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        let sends = self.gauge.sends.load(Ordering::Relaxed);
+        let nanos = self.gauge.send_nanos.load(Ordering::Relaxed);
+        let average_send = (sends > 0).then(|| Duration::from_nanos(nanos / sends));
+        ChannelStats { backend: CHANNEL_BACKEND, sends, average_send }
+    }
+
+    // replaces any earlier mark. the callback runs every time the depth climbs to mark, not while it stays above it,
+    // and must not set a new mark itself
+    pub fn set_high_water(&self, mark: usize, callback: HighWaterCallback) {
+        *self.gauge.high_water.lock().unwrap() = Some((mark, callback));
+    }
+
+    pub fn clear_high_water(&self) {
+        *self.gauge.high_water.lock().unwrap() = None;
+    }
+}
+
+impl<T: Send + 'static> MessageSender<T> for CountingSender<T> {
+    fn send_message(&self, msg: T) -> Result<(), SendError<T>> {
+        self.send(msg)
+    }
+}
+
+impl<T> CountingReceiver<T> {
+    // clock::recv_timeout on the underlying receiver
+    pub fn recv_timeout(&self, clock: &dyn Clock, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        #[cfg(not(feature = "crossbeam"))]
+        let msg = clock::recv_timeout(clock, &self.rx, timeout)?;
+        #[cfg(feature = "crossbeam")]
+        let msg = clock::recv_timeout_with(clock, timeout, |wait| {
+            self.rx.recv_timeout(wait).map_err(|err| match err {
+                crossbeam_channel::RecvTimeoutError::Timeout => RecvTimeoutError::Timeout,
+                crossbeam_channel::RecvTimeoutError::Disconnected => RecvTimeoutError::Disconnected,
+            })
+        })?;
+        self.gauge.depth.fetch_sub(1, Ordering::AcqRel);
+        Ok(msg)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        #[cfg(not(feature = "crossbeam"))]
+        let msg = self.rx.try_recv()?;
+        #[cfg(feature = "crossbeam")]
+        let msg = self.rx.try_recv().map_err(|err| match err {
+            crossbeam_channel::TryRecvError::Empty => TryRecvError::Empty,
+            crossbeam_channel::TryRecvError::Disconnected => TryRecvError::Disconnected,
+        })?;
+        self.gauge.depth.fetch_sub(1, Ordering::AcqRel);
+        Ok(msg)
+    }
+}
+
+// whatever is still on the channel goes with the receiver, so it no longer counts
+impl<T> Drop for CountingReceiver<T> {
+    fn drop(&mut self) {
+        while self.try_recv().is_ok() {}
+    }
+}
@@ -0,0 +1,201 @@
+// interactive front end to the simulator: starts a ServerThread and reads commands from stdin
+//   create [key=value]... [update_id:value]...   a task with those query values and update functions
+//   query <task id> <key>
+//   update <task id> <update_id>
+//   stats
+//   quit
+// results are printed as the listener records them, in between the simulator's own logs
+// settings come from sws.conf in the working directory when there is one, and from SWS_ variables, see ServerConfig::from_file
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::thread;
+
+use server_worker_sim::{ResultFilter, RunMode, ServerConfig, ServerThread, ShutdownMode, TaskBuilder};
+
+enum Command {
+    Create(Box<TaskBuilder>),
+    Query { id: usize, key: String },
+    Update { id: usize, update_id: String },
+    Stats,
+    Help,
+    Quit,
+}
+
+fn parse(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let task_id = |words: &mut std::str::SplitWhitespace| {
+        let word = words.next().ok_or("missing task id")?;
+        word.parse::<usize>().map_err(|_| format!("'{word}' is not a task id"))
+    };
+    let parsed = match command {
+        "create" => {
+            let mut builder = TaskBuilder::new();
+            for word in words.by_ref() {
+                if let Some((key, value)) = word.split_once('=') {
+                    builder = builder.query(key, value);
+                } else if let Some((update_id, value)) = word.split_once(':') {
+                    let value = value.to_string();
+                    builder = builder.update(update_id, move |_| Ok(value.clone()));
+                } else {
+                    return Err(format!("'{word}' is neither key=value nor update_id:value"));
+                }
+            }
+            Command::Create(Box::new(builder))
+        }
+        "query" => {
+            let id = task_id(&mut words)?;
+            Command::Query { id, key: words.next().ok_or("missing key")?.to_string() }
+        }
+        "update" => {
+            let id = task_id(&mut words)?;
+            Command::Update { id, update_id: words.next().ok_or("missing update id")?.to_string() }
+        }
+        "stats" => Command::Stats,
+        "help" => Command::Help,
+        "quit" | "exit" => Command::Quit,
+        other => return Err(format!("unknown command '{other}', try help")),
+    };
+    match words.next() {
+        Some(extra) => Err(format!("unexpected '{extra}'")),
+        None => Ok(parsed),
+    }
+}
+
+const CONFIG_FILE: &str = "sws.conf";
+
+// always persistent, idling between two commands shouldn't shut the server down under a person typing
+fn config() -> ServerConfig {
+    let config = if Path::new(CONFIG_FILE).exists() {
+        ServerConfig::from_file(CONFIG_FILE)
+    } else {
+        ServerConfig::from_env()
+    };
+    let mut config = config.unwrap_or_else(|err| {
+        println!("[sws] {err}, using the defaults");
+        ServerConfig::default()
+    });
+    config.run_mode = RunMode::Persistent;
+    config
+}
+
+// a server with a thread printing every result it records
+fn start() -> ServerThread {
+    let s = ServerThread::with_config(config());
+    #[cfg(feature = "signals")]
+    if let Err(err) = s.shutdown_on_signal() {
+        println!("[sws] could not install the signal handlers: {err}");
+    }
+    let results = s.subscribe_results(ResultFilter::default());
+    thread::spawn(move || {
+        for result in results {
+            println!("[sws] => {result:?}");
+        }
+    });
+    s
+}
+
+fn main() {
+    let mut s = start();
+    println!("[sws] ready, type help for the commands");
+    let stdin = io::stdin();
+    loop {
+        print!("sws> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match parse(&line) {
+            Ok(command) => command,
+            Err(err) => {
+                println!("[sws] {err}");
+                continue;
+            }
+        };
+        match command {
+            Command::Create(builder) => match builder.build() {
+                Ok(spec) => {
+                    let id = s.create_task_from(spec);
+                    println!("[sws] creating task {id}");
+                }
+                Err(err) => println!("[sws] {err}"),
+            },
+            Command::Query { id, key } => s.query_task(id, &key),
+            Command::Update { id, update_id } => s.update_task(id, &update_id),
+            Command::Stats => {
+                let health = s.health();
+                let recorded = s.results.snapshot().iter().flatten().count();
+                println!(
+                    "[sws] worker alive: {}, listener alive: {}, readiness: {:?}, active tasks: {}, queued: {}, requests: {}, results: {}, dead letters: {}",
+                    health.worker_alive,
+                    health.listener_alive,
+                    health.readiness,
+                    health.active_tasks,
+                    health.queue_depth,
+                    s.request_counter,
+                    recorded,
+                    s.dead_letters().len(),
+                );
+            }
+            Command::Help => {
+                println!("[sws] create [key=value]... [update_id:value]...");
+                println!("[sws] query <task id> <key>");
+                println!("[sws] update <task id> <update_id>");
+                println!("[sws] stats");
+                println!("[sws] quit");
+            }
+            Command::Quit => break,
+        }
+    }
+    s.shutdown_with(ShutdownMode::Drain);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server_worker_sim::TaskState;
+
+    #[test]
+    fn parse_create() {
+        let Ok(Command::Create(builder)) = parse("create status=running greet:hello") else { panic!("not a create") };
+        let mut spec = builder.build().unwrap();
+        assert_eq!(spec.query_map.get("status").map(String::as_str), Some("running"));
+        let greet = spec.update_map.get_mut("greet").unwrap();
+        assert_eq!(greet(&mut TaskState::default()), Ok("hello".to_string()));
+
+        // a word with both separators is a key=value, whichever comes first
+        let Ok(Command::Create(builder)) = parse("create url=a:b note:x=y") else { panic!("not a create") };
+        let spec = builder.build().unwrap();
+        assert_eq!(spec.query_map.get("url").map(String::as_str), Some("a:b"));
+        assert_eq!(spec.query_map.get("note:x").map(String::as_str), Some("y"));
+        assert!(spec.update_map.is_empty());
+        assert_eq!(parse("create status").err(), Some("'status' is neither key=value nor update_id:value".to_string()));
+    }
+
+    #[test]
+    fn parse_task_commands() {
+        assert!(matches!(parse("query 3 status"), Ok(Command::Query { id: 3, key }) if key == "status"));
+        assert!(matches!(parse("  update 0   bump "), Ok(Command::Update { id: 0, update_id }) if update_id == "bump"));
+        assert_eq!(parse("query three status").err(), Some("'three' is not a task id".to_string()));
+        assert_eq!(parse("update -1 bump").err(), Some("'-1' is not a task id".to_string()));
+        assert_eq!(parse("query").err(), Some("missing task id".to_string()));
+        assert_eq!(parse("query 3").err(), Some("missing key".to_string()));
+        assert_eq!(parse("update 3").err(), Some("missing update id".to_string()));
+        assert_eq!(parse("query 3 status now").err(), Some("unexpected 'now'".to_string()));
+    }
+
+    #[test]
+    fn parse_other_commands() {
+        assert!(matches!(parse("stats"), Ok(Command::Stats)));
+        assert!(matches!(parse("help"), Ok(Command::Help)));
+        assert!(matches!(parse("quit"), Ok(Command::Quit)));
+        assert!(matches!(parse("exit"), Ok(Command::Quit)));
+        assert_eq!(parse("delete 3").err(), Some("unknown command 'delete', try help".to_string()));
+        assert_eq!(parse("").err(), Some("unknown command '', try help".to_string()));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::limits::{Oversize, TaskLimits};
+use crate::{Priority, Schema, SchemaViolation, TaskState, UpdateFn, UpdateOutcome};
+
+// a task put together one entry at a time and checked before it is sent, see ServerThread::create_task_from
+//   TaskBuilder::new().query("k", "v").update("u", |_| Ok("done".into())).idle_timeout(d).build()
+// problems are collected as the entries come in and build reports the first one
+#[derive(Default)]
+pub struct TaskBuilder {
+    query_map: HashMap<String, String>,
+    blobs: HashMap<String, Vec<u8>>,
+    update_map: HashMap<String, UpdateFn>,
+    idle_timeout: Option<Duration>,
+    group: Option<String>,
+    priority: Priority,
+    limits: TaskLimits,
+    schema: Schema,
+    disjoint_keys: bool,
+    error: Option<TaskBuildError>,
+}
+
+// what build hands to the server: the maps of a CreateTask, the task's own idle timeout, its group and its priority
+pub struct TaskSpec {
+    pub query_map: HashMap<String, String>,
+    // binary values, queried like text ones, see ServerThread::query_bytes
+    pub blobs: HashMap<String, Vec<u8>>,
+    pub update_map: HashMap<String, UpdateFn>,
+    // None keeps the server's task timeout
+    pub idle_timeout: Option<Duration>,
+    // None puts it in no group
+    pub group: Option<String>,
+    pub priority: Priority,
+    // checked against query_map already, see ServerThread::set_value for what it checks later
+    pub schema: Schema,
+}
+
+// why build refused a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskBuildError {
+    EmptyQueryKey,
+    EmptyUpdateId,
+    DuplicateQueryKey(String),
+    DuplicateUpdateId(String),
+    // with disjoint_keys, a name used both as a query key and as an update id
+    SharedKey(String),
+    ZeroIdleTimeout,
+    TooLarge(Oversize),
+    SchemaViolation(SchemaViolation),
+}
+
+impl fmt::Display for TaskBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskBuildError::EmptyQueryKey => write!(f, "query keys can't be empty"),
+            TaskBuildError::EmptyUpdateId => write!(f, "update ids can't be empty"),
+            TaskBuildError::DuplicateQueryKey(key) => write!(f, "query key '{key}' is given twice"),
+            TaskBuildError::DuplicateUpdateId(update_id) => write!(f, "update '{update_id}' is given twice"),
+            TaskBuildError::SharedKey(key) => write!(f, "'{key}' is both a query key and an update id"),
+            TaskBuildError::ZeroIdleTimeout => write!(f, "the idle timeout has to be longer than zero"),
+            TaskBuildError::TooLarge(oversize) => write!(f, "task is too large: {oversize}"),
+            TaskBuildError::SchemaViolation(violation) => write!(f, "schema violation: {violation}"),
+        }
+    }
+}
+
+impl TaskBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        if key.is_empty() {
+            self.fail(TaskBuildError::EmptyQueryKey);
+        } else if self.blobs.contains_key(key) || self.query_map.insert(key.to_string(), value.to_string()).is_some() {
+            self.fail(TaskBuildError::DuplicateQueryKey(key.to_string()));
+        }
+        self
+    }
+
+    // a binary value. a key can't hold text and bytes at once
+    pub fn bytes(mut self, key: &str, value: impl Into<Vec<u8>>) -> Self {
+        if key.is_empty() {
+            self.fail(TaskBuildError::EmptyQueryKey);
+        } else if self.query_map.contains_key(key) || self.blobs.insert(key.to_string(), value.into()).is_some() {
+            self.fail(TaskBuildError::DuplicateQueryKey(key.to_string()));
+        }
+        self
+    }
+
+    pub fn update(self, update_id: &str, f: impl FnMut(&mut TaskState) -> UpdateOutcome + Send + 'static) -> Self {
+        self.update_boxed(update_id, Box::new(f))
+    }
+
+    // for update functions that are boxed already, e.g. ones built by an UpdateRegistry
+    pub fn update_boxed(mut self, update_id: &str, f: UpdateFn) -> Self {
+        if update_id.is_empty() {
+            self.fail(TaskBuildError::EmptyUpdateId);
+        } else if self.update_map.insert(update_id.to_string(), f).is_some() {
+            self.fail(TaskBuildError::DuplicateUpdateId(update_id.to_string()));
+        }
+        self
+    }
+
+    // how long this task waits for an instruction before it exits, in place of the server's task timeout.
+    // anything longer than the listener timeout outlives the listener, whose results are then lost
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        if timeout.is_zero() {
+            self.fail(TaskBuildError::ZeroIdleTimeout);
+        }
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    // the task group the task counts against, see ServerConfig::group_caps
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+
+    // which tasks it may preempt and be preempted by, 0 unless set. see ServerConfig::preemption
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn limits(mut self, limits: TaskLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // what the task's values have to look like, from creation on
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    // rejects a name that is used as a query key and as an update id at the same time
+    pub fn disjoint_keys(mut self) -> Self {
+        self.disjoint_keys = true;
+        self
+    }
+
+    fn fail(&mut self, error: TaskBuildError) {
+        self.error.get_or_insert(error);
+    }
+
+    pub fn build(self) -> Result<TaskSpec, TaskBuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.disjoint_keys {
+            let mut shared: Vec<_> = self.update_map.keys().filter(|id| self.query_map.contains_key(*id)).collect();
+            shared.sort();
+            if let Some(key) = shared.first() {
+                return Err(TaskBuildError::SharedKey(key.to_string()));
+            }
+        }
+        self.limits.check(&self.query_map, self.update_map.len()).map_err(TaskBuildError::TooLarge)?;
+        self.schema
+            .check_all(self.query_map.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+            .map_err(TaskBuildError::SchemaViolation)?;
+        Ok(TaskSpec {
+            query_map: self.query_map,
+            blobs: self.blobs,
+            update_map: self.update_map,
+            idle_timeout: self.idle_timeout,
+            group: self.group,
+            priority: self.priority,
+            schema: self.schema,
+        })
+    }
+}
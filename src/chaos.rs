@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::backpressure::CountingSender;
+use crate::fault::Rng;
+use crate::{
+    Clock, Envelope, EventBus, RequestOptions, ServerEvent, TaskId, TaskInstruction, TaskRequest, TaskSenders,
+};
+
+// what the chaos thread killed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosTarget {
+    Task(TaskId),
+    Worker,
+}
+
+// every interval (clock time) the chaos thread rolls once: below worker_kill_rate it kills the worker,
+// below worker_kill_rate + task_kill_rate it kills one live task, otherwise it does nothing
+// the same seed and the same set of live tasks give the same kills
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChaosConfig {
+    pub seed: u64,
+    pub interval: Duration,
+    pub task_kill_rate: f64,
+    pub worker_kill_rate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            interval: Duration::from_millis(100),
+            task_kill_rate: 0.1,
+            worker_kill_rate: 0.0,
+        }
+    }
+}
+
+// kills tasks and the worker on a seeded schedule and reports every kill as ServerEvent::ChaosKill
+// a killed task exits without removing itself from the worker's task map, a killed worker exits without stopping its tasks
+pub(crate) struct Chaos {
+    config: ChaosConfig,
+    rng: Rng,
+    tasks: TaskSenders,
+    worker_tx: CountingSender<Envelope>,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+}
+
+impl Chaos {
+    pub(crate) fn new(
+        config: ChaosConfig,
+        tasks: TaskSenders,
+        worker_tx: CountingSender<Envelope>,
+        events: EventBus,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self { rng: Rng(config.seed), config, tasks, worker_tx, events, clock }
+    }
+
+    // the thread exits once the shutdown flag is set or after it killed the worker, there is nothing left to kill then
+    pub(crate) fn spawn(mut self, shutdown_flag: Arc<AtomicBool>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while !shutdown_flag.load(Ordering::Relaxed) {
+                self.clock.sleep(self.config.interval);
+                if let Some(target) = self.tick() {
+                    println!("[Chaos] Killed {:?}", target);
+                    self.events.publish(ServerEvent::ChaosKill { target });
+                    if target == ChaosTarget::Worker {
+                        break;
+                    }
+                }
+            }
+            println!("[Chaos] Chaos thread exiting.");
+        })
+    }
+
+    fn tick(&mut self) -> Option<ChaosTarget> {
+        let roll = self.rng.next_f64();
+        if roll < self.config.worker_kill_rate {
+            let kill = Envelope { opts: RequestOptions::default(), request: TaskRequest::Kill };
+            return self.worker_tx.send(kill).ok().map(|_| ChaosTarget::Worker);
+        }
+        if roll >= self.config.worker_kill_rate + self.config.task_kill_rate {
+            return None;
+        }
+
+        // sorted so the pick only depends on the seed and which tasks exist
+        let tasks = self.tasks.lock().unwrap();
+        let mut ids: Vec<TaskId> = tasks.keys().copied().collect();
+        if ids.is_empty() {
+            return None;
+        }
+        ids.sort_unstable();
+        let id = ids[(self.rng.next_u64() % ids.len() as u64) as usize];
+        // a task killed earlier is still in the map but can't receive anymore, that tick is a miss
+        tasks[&id].send(TaskInstruction::Kill).ok().map(|_| ChaosTarget::Task(id))
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::replay::{escape, unescape};
+use crate::{Task, TaskId, TenantId, UpdateFn};
+
+// what ServerThread::checkpoint and checkpoint_incremental wrote
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckpointSummary {
+    pub written: usize,       // tasks written, in full or, incrementally, as a change
+    pub skipped: Vec<TaskId>, // tasks left out: handler tasks and ones that didn't answer in time. in id order
+}
+
+// the state of one task at the moment it took a TaskInstruction::Snapshot off its queue.
+// update functions can't be written to a file, only their ids are kept and an UpdateRegistry supplies them again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSnapshot {
+    pub id: TaskId,
+    pub tenant: TenantId,
+    pub version: u64,
+    pub values: Vec<(String, String)>, // sorted by key
+    pub update_ids: Vec<String>,       // sorted, without the ones that timed out
+}
+
+impl TaskSnapshot {
+    pub(crate) fn of(task: &Task, tenant: TenantId) -> Self {
+        let mut values: Vec<_> = task.query_map.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut update_ids: Vec<_> = task.update_map.keys().cloned().collect();
+        values.sort();
+        update_ids.sort();
+        Self { id: task.id, tenant, version: task.version, values, update_ids }
+    }
+
+    // brings a snapshot up to the one delta was taken against it. Gone leaves it as it is
+    pub fn apply(&mut self, delta: &TaskDelta) {
+        let TaskDelta::Changed { version, changed, removed, update_ids, .. } = delta else { return };
+        let mut values: BTreeMap<String, String> = self.values.drain(..).collect();
+        for key in removed {
+            values.remove(key);
+        }
+        values.extend(changed.iter().cloned());
+        self.values = values.into_iter().collect();
+        self.version = *version;
+        self.update_ids = update_ids.clone();
+    }
+}
+
+// how a task differs from its previous snapshot in an incremental checkpoint, see ServerThread::checkpoint_incremental
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskDelta {
+    Changed {
+        id: TaskId,
+        version: u64,
+        changed: Vec<(String, String)>, // sorted by key, values that are new or differ from the previous snapshot
+        removed: Vec<String>,           // sorted, keys the previous snapshot had
+        update_ids: Vec<String>,        // every one of them, they are few and hardly ever change
+    },
+    // the task was in the previous snapshot but isn't running anymore
+    Gone { id: TaskId },
+}
+
+impl TaskDelta {
+    pub fn id(&self) -> TaskId {
+        match self {
+            TaskDelta::Changed { id, .. } | TaskDelta::Gone { id } => *id,
+        }
+    }
+
+    // None when after is the same as before
+    pub fn between(before: &TaskSnapshot, after: &TaskSnapshot) -> Option<TaskDelta> {
+        if before == after {
+            return None;
+        }
+        let previous: HashMap<_, _> = before.values.iter().map(|(k, v)| (k, v)).collect();
+        let current: HashMap<_, _> = after.values.iter().map(|(k, v)| (k, v)).collect();
+        let changed = after.values.iter().filter(|(k, v)| previous.get(k) != Some(&v)).cloned().collect();
+        let removed = before.values.iter().filter(|(k, _)| !current.contains_key(k)).map(|(k, _)| k.clone()).collect();
+        Some(TaskDelta::Changed {
+            id: after.id,
+            version: after.version,
+            changed,
+            removed,
+            update_ids: after.update_ids.clone(),
+        })
+    }
+}
+
+// every task a server had, see ServerThread::checkpoint and ServerThread::resume
+// the file format is plain text, one task per line, tab separated:
+//   <id> <tenant> <version> <number of values> <key> <value>... <update_id>...
+// an incremental checkpoint goes on with a line per task that changed since the previous one:
+//   ~ <id> <version> <number of values> <key> <value>... <number of removed keys> <key>... <update_id>...
+//   - <id>
+// escaped the same way as recordings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub tasks: Vec<TaskSnapshot>,
+    pub deltas: Vec<TaskDelta>, // in the order they were taken, applied on top of tasks by restore
+}
+
+// why a checkpoint could not be loaded or resumed
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    Parse { line: usize, msg: String },
+    // the checkpoint names an update function the registry has nothing for. nothing is resumed then
+    UnknownUpdate { id: TaskId, update_id: String },
+    // a delta for a task the checkpoint has no full snapshot of, the start of the chain is missing
+    MissingBase { id: TaskId },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "{err}"),
+            CheckpointError::Parse { line, msg } => write!(f, "line {line}: {msg}"),
+            CheckpointError::UnknownUpdate { id, update_id } => {
+                write!(f, "Task {id} has update '{update_id}', which is not registered")
+            }
+            CheckpointError::MissingBase { id } => write!(f, "Task {id} has a delta but no snapshot to apply it to"),
+        }
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    // adds the lines of self to the end of path, for the next link of an incremental checkpoint
+    pub fn append(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::OpenOptions::new().append(true).create(true).open(path)?.write_all(self.to_string().as_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Checkpoint, CheckpointError> {
+        Checkpoint::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Checkpoint, CheckpointError> {
+        let mut checkpoint = Checkpoint::default();
+        for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+            let parse_error = |msg| CheckpointError::Parse { line: i + 1, msg };
+            match line.split('\t').next() {
+                Some("~" | "-") => checkpoint.deltas.push(parse_delta(line).map_err(parse_error)?),
+                _ => checkpoint.tasks.push(parse_task(line).map_err(parse_error)?),
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    // every task as of the last delta that names it, by id. tasks that are Gone are left out
+    pub fn restore(&self) -> Result<Vec<TaskSnapshot>, CheckpointError> {
+        let mut tasks: BTreeMap<TaskId, TaskSnapshot> = self.tasks.iter().map(|task| (task.id, task.clone())).collect();
+        for delta in &self.deltas {
+            match delta {
+                TaskDelta::Gone { id } => tasks.remove(id),
+                TaskDelta::Changed { id, .. } => {
+                    tasks.get_mut(id).ok_or(CheckpointError::MissingBase { id: *id })?.apply(delta);
+                    None
+                }
+            };
+        }
+        Ok(tasks.into_values().collect())
+    }
+}
+
+// what an incremental checkpoint has written so far, see ServerThread::checkpoint_incremental
+#[derive(Debug, Clone, Default)]
+pub struct CheckpointChain {
+    pub path: PathBuf,
+    pub links: usize,                          // checkpoints written to path, the full one first
+    pub bases: HashMap<TaskId, TaskSnapshot>,  // every task as the chain has it so far
+}
+
+impl CheckpointChain {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self { path: path.as_ref().to_path_buf(), ..Default::default() }
+    }
+
+    // the next link for tasks, which are every task running now but the skipped ones: deltas for the ones the chain
+    // has, full snapshots for the ones it hasn't. the chain takes tasks as its new bases, a skipped task isn't gone
+    // and keeps its base
+    pub fn next(&mut self, tasks: Vec<TaskSnapshot>, skipped: &[TaskId]) -> Checkpoint {
+        let mut link = Checkpoint::default();
+        let mut gone: Vec<TaskId> = self.bases.keys().copied().filter(|id| !skipped.contains(id)).collect();
+        for task in tasks {
+            gone.retain(|id| *id != task.id);
+            match self.bases.get(&task.id) {
+                Some(base) => link.deltas.extend(TaskDelta::between(base, &task)),
+                None => link.tasks.push(task.clone()),
+            }
+            self.bases.insert(task.id, task);
+        }
+        gone.sort();
+        for id in gone {
+            self.bases.remove(&id);
+            link.deltas.push(TaskDelta::Gone { id });
+        }
+        self.links += 1;
+        link
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for task in &self.tasks {
+            let mut fields =
+                vec![task.id.to_string(), task.tenant.to_string(), task.version.to_string(), task.values.len().to_string()];
+            for (key, value) in &task.values {
+                fields.extend([escape(key), escape(value)]);
+            }
+            fields.extend(task.update_ids.iter().map(|u| escape(u)));
+            writeln!(f, "{}", fields.join("\t"))?;
+        }
+        for delta in &self.deltas {
+            let TaskDelta::Changed { id, version, changed, removed, update_ids } = delta else {
+                writeln!(f, "-\t{}", delta.id())?;
+                continue;
+            };
+            let mut fields = vec!["~".to_string(), id.to_string(), version.to_string(), changed.len().to_string()];
+            for (key, value) in changed {
+                fields.extend([escape(key), escape(value)]);
+            }
+            fields.push(removed.len().to_string());
+            fields.extend(removed.iter().map(|k| escape(k)));
+            fields.extend(update_ids.iter().map(|u| escape(u)));
+            writeln!(f, "{}", fields.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_task(line: &str) -> Result<TaskSnapshot, String> {
+    let fields: Vec<String> = line.split('\t').map(unescape).collect::<Result<_, _>>()?;
+    let number = |i: usize, what: &str| -> Result<u64, String> {
+        let field = fields.get(i).ok_or(format!("missing {what}"))?;
+        field.parse().map_err(|_| format!("{what} '{field}' is not a number"))
+    };
+    let pairs = number(3, "value count")? as usize;
+    let start = 4 + 2 * pairs;
+    if fields.len() < start {
+        return Err(format!("expected {pairs} values"));
+    }
+    Ok(TaskSnapshot {
+        id: number(0, "task id")? as TaskId,
+        tenant: number(1, "tenant")? as TenantId,
+        version: number(2, "version")?,
+        values: fields[4..start].chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect(),
+        update_ids: fields[start..].to_vec(),
+    })
+}
+
+fn parse_delta(line: &str) -> Result<TaskDelta, String> {
+    let fields: Vec<String> = line.split('\t').map(unescape).collect::<Result<_, _>>()?;
+    let number = |i: usize, what: &str| -> Result<u64, String> {
+        let field = fields.get(i).ok_or(format!("missing {what}"))?;
+        field.parse().map_err(|_| format!("{what} '{field}' is not a number"))
+    };
+    let id = number(1, "task id")? as TaskId;
+    if fields[0] == "-" {
+        return Ok(TaskDelta::Gone { id });
+    }
+    let pairs = number(3, "value count")? as usize;
+    let removed_at = 4 + 2 * pairs;
+    let removed = number(removed_at, "removed count")? as usize;
+    let start = removed_at + 1 + removed;
+    if fields.len() < start {
+        return Err(format!("expected {removed} removed keys"));
+    }
+    Ok(TaskDelta::Changed {
+        id,
+        version: number(2, "version")?,
+        changed: fields[4..removed_at].chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect(),
+        removed: fields[removed_at + 1..start].to_vec(),
+        update_ids: fields[start..].to_vec(),
+    })
+}
+
+// update functions by name, to bind a resumed task's update ids to code again
+// a factory per name, so every task that names it gets its own closure
+#[derive(Default)]
+pub struct UpdateRegistry {
+    factories: HashMap<String, Box<dyn Fn() -> UpdateFn + Send>>,
+}
+
+impl UpdateRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(mut self, update_id: &str, factory: impl Fn() -> UpdateFn + Send + 'static) -> Self {
+        self.insert(update_id, factory);
+        self
+    }
+
+    // replaces whatever was registered under update_id before
+    pub fn insert(&mut self, update_id: &str, factory: impl Fn() -> UpdateFn + Send + 'static) {
+        self.factories.insert(update_id.to_string(), Box::new(factory));
+    }
+
+    pub fn contains(&self, update_id: &str) -> bool {
+        self.factories.contains_key(update_id)
+    }
+
+    pub fn build(&self, update_id: &str) -> Option<UpdateFn> {
+        self.factories.get(update_id).map(|factory| factory())
+    }
+}
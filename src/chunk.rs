@@ -0,0 +1,51 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{RequestId, TaskId, TaskResult};
+
+// a QueryOk whose value is longer than size bytes, as QueryChunks of at most size bytes each, see
+// ServerConfig::query_chunk_size. anything else, and everything when size is 0, comes back as it is
+pub(crate) fn split(result: TaskResult, size: usize) -> Vec<TaskResult> {
+    match result {
+        TaskResult::QueryOk { req_id, id, value, .. } if size > 0 && value.len() > size => {
+            let count = value.len().div_ceil(size);
+            value
+                .into_bytes()
+                .chunks(size)
+                .enumerate()
+                .map(|(seq, bytes)| TaskResult::QueryChunk { req_id, id, seq, last: seq + 1 == count, bytes: bytes.to_vec() })
+                .collect()
+        }
+        result => vec![result],
+    }
+}
+
+#[derive(Debug, Default)]
+struct Partial {
+    chunks: BTreeMap<usize, Vec<u8>>,
+    last: Option<usize>, // seq of the chunk marked last, once it is in
+}
+
+// puts the chunks of a streamed query value back together, one per listener shard.
+// chunks may come in any order and more than once, the value is complete once every seq up to the last one is in
+#[derive(Debug, Default)]
+pub(crate) struct ChunkAssembler {
+    partial: HashMap<RequestId, Partial>,
+}
+
+impl ChunkAssembler {
+    // the QueryOk the chunk completes, if it does. access metadata doesn't travel with chunks, it is None
+    pub(crate) fn add(&mut self, req_id: RequestId, id: TaskId, seq: usize, last: bool, bytes: Vec<u8>) -> Option<TaskResult> {
+        let partial = self.partial.entry(req_id).or_default();
+        partial.chunks.insert(seq, bytes);
+        if last {
+            partial.last = Some(seq);
+        }
+        let last = partial.last?;
+        if partial.chunks.len() != last + 1 || partial.chunks.keys().next_back() != Some(&last) {
+            return None;
+        }
+        let bytes: Vec<u8> = self.partial.remove(&req_id)?.chunks.into_values().flatten().collect();
+        let value = String::from_utf8_lossy(&bytes).into_owned();
+        Some(TaskResult::QueryOk { req_id, id, value, access: None })
+    }
+}
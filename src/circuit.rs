@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Clock, RequestId, ResultSink, TaskResult};
+
+// when the circuit breaker trips and how long it stays open, see ServerConfig::circuit_breaker
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreakerConfig {
+    pub window: usize,         // how many of the latest outcomes are looked at. the circuit can't trip before it is full
+    pub failure_ratio: f64,    // trips once at least this share of the window was Throttled, Busy, Undeliverable or Shed
+    pub cool_down: Duration,   // how long it stays open before letting a single probe through
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self { window: 20, failure_ratio: 0.5, cool_down: Duration::from_secs(1) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,   // requests go through and their outcomes are counted
+    Open,     // requests are answered with CircuitOpen right away
+    HalfOpen, // one probe is out, its outcome closes the circuit or opens it again
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    outcomes: VecDeque<bool>, // whether each of the latest outcomes was a failure, oldest first
+    opened_at: Duration,      // clock time the circuit last opened
+    probe: Option<RequestId>,
+}
+
+// fails requests fast while too many of the recent ones came back overloaded. the server asks it before sending
+// every request and it watches the results as one of the server's sinks.
+// requests that never reach the worker (rate limited, rejected, shutting down, turned away by the circuit) don't count
+// cloning is cheap, every clone shares the same state
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    breaker: Arc<Mutex<Breaker>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig, clock: Arc<dyn Clock>) -> Self {
+        let breaker = Breaker { state: CircuitState::Closed, outcomes: VecDeque::new(), opened_at: Duration::ZERO, probe: None };
+        Self { config, breaker: Arc::new(Mutex::new(breaker)), clock }
+    }
+
+    pub fn config(&self) -> CircuitBreakerConfig {
+        self.config
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.breaker.lock().unwrap().state
+    }
+
+    // whether req_id may be sent. once the cool-down has passed the first request asked about becomes the probe
+    pub(crate) fn allow(&self, req_id: RequestId) -> bool {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.state {
+            CircuitState::Closed => true,
+            CircuitState::Open if self.clock.now().saturating_sub(breaker.opened_at) >= self.config.cool_down => {
+                println!("[CircuitBreaker] Half-open, req {req_id} is the probe");
+                breaker.state = CircuitState::HalfOpen;
+                breaker.probe = Some(req_id);
+                true
+            }
+            CircuitState::Open | CircuitState::HalfOpen => false,
+        }
+    }
+
+    fn open(&self, breaker: &mut Breaker) {
+        breaker.state = CircuitState::Open;
+        breaker.opened_at = self.clock.now();
+        breaker.probe = None;
+        breaker.outcomes.clear();
+    }
+}
+
+impl ResultSink for CircuitBreaker {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        let failed = match result {
+            TaskResult::Throttled { .. }
+            | TaskResult::Busy { .. }
+            | TaskResult::Undeliverable { .. }
+            | TaskResult::Shed { .. } => true,
+            TaskResult::RateLimited { .. }
+            | TaskResult::Rejected { .. }
+            | TaskResult::ShuttingDown { .. }
+            | TaskResult::CircuitOpen { .. } => return,
+            _ => false,
+        };
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.state {
+            CircuitState::Closed => {
+                breaker.outcomes.push_back(failed);
+                if breaker.outcomes.len() > self.config.window {
+                    breaker.outcomes.pop_front();
+                }
+                let failures = breaker.outcomes.iter().filter(|failed| **failed).count();
+                if breaker.outcomes.len() == self.config.window
+                    && failures as f64 >= self.config.failure_ratio * self.config.window as f64
+                {
+                    println!("[CircuitBreaker] Open, {failures} of the last {} requests failed", self.config.window);
+                    self.open(&mut breaker);
+                }
+            }
+            // results of requests sent before the circuit opened are not the probe's
+            CircuitState::HalfOpen if breaker.probe == Some(req_id) => {
+                if failed {
+                    println!("[CircuitBreaker] Probe failed, open again");
+                    self.open(&mut breaker);
+                } else {
+                    println!("[CircuitBreaker] Probe succeeded, closed");
+                    breaker.state = CircuitState::Closed;
+                    breaker.probe = None;
+                }
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {}
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Condvar, Mutex, mpsc::{Receiver, RecvTimeoutError}};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// source of time for every timeout in the crate (task idle expiry, listener and worker timeouts, the watchdog, ...)
+// times are measured as a Duration since the clock's own epoch
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+    // how long a waiter blocks in real time before it checks the clock again
+    // `remaining` is what is left of the timeout in clock time
+    fn poll_interval(&self, remaining: Duration) -> Duration;
+    // a thread is about to block until deadline (clock time) at the latest, and has stopped blocking again.
+    // only a SimClock keeps track, see SimClock::next_deadline
+    fn wait_started(&self, _deadline: Duration) {}
+    fn wait_ended(&self, _deadline: Duration) {}
+}
+
+// registered with the clock for as long as a thread is blocked on it
+pub(crate) struct Blocked<'a> {
+    clock: &'a dyn Clock,
+    deadline: Duration,
+}
+
+impl<'a> Blocked<'a> {
+    pub(crate) fn new(clock: &'a dyn Clock, deadline: Duration) -> Self {
+        clock.wait_started(deadline);
+        Self { clock, deadline }
+    }
+}
+
+impl Drop for Blocked<'_> {
+    fn drop(&mut self) {
+        self.clock.wait_ended(self.deadline);
+    }
+}
+
+// real wall clock time. the default
+#[derive(Debug)]
+pub struct SystemClock {
+    epoch: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { epoch: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    // clock time is real time, so a single wait covers the whole timeout
+    fn poll_interval(&self, remaining: Duration) -> Duration {
+        remaining
+    }
+}
+
+// virtual time that only moves when advance() is called
+// a thread waiting on a SimClock timeout keeps waiting no matter how much real time passes,
+// so timeout behaviour can be tested deterministically and without multi-second sleeps
+#[derive(Debug, Default)]
+pub struct SimClock {
+    now: Mutex<Duration>,
+    advanced: Condvar,
+    waits: Mutex<Waits>,
+}
+
+#[derive(Debug, Default)]
+struct Waits {
+    // deadline of every thread blocked on the clock, with how many are blocked until it
+    deadlines: BTreeMap<Duration, usize>,
+    // bumped whenever a thread starts or stops waiting, so a quiet period can be told apart from a busy one
+    changes: u64,
+}
+
+// how often threads blocked on a SimClock timeout check whether virtual time has moved past their deadline
+const SIM_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+impl SimClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        self.advanced.notify_all();
+    }
+
+    // moves the clock to time, never backwards
+    pub fn advance_to(&self, time: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = (*now).max(time);
+        drop(now);
+        self.advanced.notify_all();
+    }
+
+    // the earliest deadline after now that a thread is blocked until, the next point where a timeout fires
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let now = self.now();
+        self.waits.lock().unwrap().deadlines.range(now + Duration::from_nanos(1)..).next().map(|(deadline, _)| *deadline)
+    }
+
+    // changes every time a thread starts or stops waiting on the clock
+    pub fn activity(&self) -> u64 {
+        self.waits.lock().unwrap().changes
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        let deadline = *now + duration;
+        let _blocked = Blocked::new(self, deadline);
+        while *now < deadline {
+            now = self.advanced.wait(now).unwrap();
+        }
+    }
+
+    fn poll_interval(&self, _remaining: Duration) -> Duration {
+        SIM_POLL_INTERVAL
+    }
+
+    fn wait_started(&self, deadline: Duration) {
+        let mut waits = self.waits.lock().unwrap();
+        *waits.deadlines.entry(deadline).or_insert(0) += 1;
+        waits.changes += 1;
+    }
+
+    fn wait_ended(&self, deadline: Duration) {
+        let mut waits = self.waits.lock().unwrap();
+        if let Some(count) = waits.deadlines.get_mut(&deadline) {
+            *count -= 1;
+            if *count == 0 {
+                waits.deadlines.remove(&deadline);
+            }
+        }
+        waits.changes += 1;
+    }
+}
+
+// Receiver::recv_timeout measured against the given clock instead of the wall clock
+pub fn recv_timeout<T>(clock: &dyn Clock, rx: &Receiver<T>, timeout: Duration) -> Result<T, RecvTimeoutError> {
+    recv_timeout_with(clock, timeout, |wait| rx.recv_timeout(wait))
+}
+
+// the same for any receiver, recv waits on it for up to the wall clock time it is given
+pub(crate) fn recv_timeout_with<T>(
+    clock: &dyn Clock,
+    timeout: Duration,
+    mut recv: impl FnMut(Duration) -> Result<T, RecvTimeoutError>,
+) -> Result<T, RecvTimeoutError> {
+    let deadline = clock.now() + timeout;
+    let _blocked = Blocked::new(clock, deadline);
+    loop {
+        let now = clock.now();
+        if now >= deadline {
+            return Err(RecvTimeoutError::Timeout);
+        }
+        match recv(clock.poll_interval(deadline - now)) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            other => return other,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Clock, RequestId, ResultSink, TaskId, TaskResult, TenantId};
+
+type QueryKey = (TenantId, TaskId, String);
+
+#[derive(Debug)]
+struct Leader {
+    key: QueryKey,
+    sent_at: Duration, // clock time
+    // the queries riding along, each gets a copy of the leader's result under its own req_id
+    followers: Vec<(RequestId, Sender<TaskResult>)>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    // the leader a new query for the key joins. closed by a change to the task, the leader stays in `leaders`
+    open: HashMap<QueryKey, RequestId>,
+    // every leader whose result hasn't come back yet
+    leaders: HashMap<RequestId, Leader>,
+    coalesced: usize,
+}
+
+impl Inner {
+    // takes the leader out along with its key, unless it was closed and a newer leader took the key
+    fn remove(&mut self, req_id: RequestId) -> Option<Leader> {
+        let leader = self.leaders.remove(&req_id)?;
+        if self.open.get(&leader.key) == Some(&req_id) {
+            self.open.remove(&leader.key);
+        }
+        Some(leader)
+    }
+
+    // the leaders sent at least open_for ago, taken out
+    fn take_lost(&mut self, now: Duration, open_for: Duration) -> Vec<Leader> {
+        let lost: Vec<RequestId> = self
+            .leaders
+            .iter()
+            .filter(|(_, leader)| now.saturating_sub(leader.sent_at) >= open_for)
+            .map(|(req_id, _)| *req_id)
+            .collect();
+        lost.into_iter().filter_map(|req_id| self.remove(req_id)).collect()
+    }
+}
+
+// answers the followers of a leader whose result isn't coming
+fn undeliverable(leader: Leader) {
+    let id = leader.key.1;
+    for (req_id, result_tx) in leader.followers {
+        let _ = result_tx.send(TaskResult::Undeliverable { req_id, id });
+    }
+}
+
+// collapses identical queries: a query for the same key of the same task, by the same tenant, while one is on its
+// way to the worker isn't sent, it waits for the one that was and gets a copy of its result. the followers go with
+// the leader's request options, a deadline or QoS class of their own doesn't count. an update, set or publish sent to
+// the task closes its queries to joining, a later query goes out on its own. a leader that can't be sent, or whose
+// result hasn't come back open_for after it was, is taken for lost: its followers are answered Undeliverable and
+// the next query goes out on its own. see ServerConfig::coalesce_queries.
+// cloning is cheap, every clone shares the same table
+#[derive(Clone)]
+pub struct QueryCoalescer {
+    inner: Arc<Mutex<Inner>>,
+    clock: Arc<dyn Clock>,
+    open_for: Duration,
+}
+
+impl QueryCoalescer {
+    pub fn new(clock: Arc<dyn Clock>, open_for: Duration) -> Self {
+        Self { inner: Arc::default(), clock, open_for }
+    }
+
+    // true if req_id joined an identical query in flight and is answered along with it, false if it has to be sent
+    // and is now the one others join
+    pub(crate) fn join(&self, tenant: TenantId, id: TaskId, query_id: &str, req_id: RequestId, result_tx: Sender<TaskResult>) -> bool {
+        let now = self.clock.now();
+        let mut inner = self.inner.lock().unwrap();
+        let lost = inner.take_lost(now, self.open_for);
+        let key = (tenant, id, query_id.to_string());
+        let joined = match inner.open.get(&key).copied().and_then(|leader| inner.leaders.get_mut(&leader)) {
+            Some(leader) => {
+                leader.followers.push((req_id, result_tx));
+                inner.coalesced += 1;
+                true
+            }
+            None => {
+                inner.open.insert(key.clone(), req_id);
+                inner.leaders.insert(req_id, Leader { key, sent_at: now, followers: vec![] });
+                false
+            }
+        };
+        drop(inner);
+        lost.into_iter().for_each(undeliverable);
+        joined
+    }
+
+    // the leader req_id couldn't be sent, nobody is answered with its result
+    pub(crate) fn failed(&self, req_id: RequestId) {
+        let leader = self.inner.lock().unwrap().remove(req_id);
+        if let Some(leader) = leader {
+            undeliverable(leader);
+        }
+    }
+
+    // a request that may change task id is on its way, no query sent before it can answer one sent after.
+    // the followers already waiting still get their leader's result
+    pub(crate) fn close(&self, id: TaskId) {
+        self.inner.lock().unwrap().open.retain(|(_, task, _), _| *task != id);
+    }
+
+    // queries answered with another one's result instead of being sent
+    pub fn coalesced(&self) -> usize {
+        self.inner.lock().unwrap().coalesced
+    }
+
+    // queries sent that others may still join
+    pub fn in_flight(&self) -> usize {
+        self.inner.lock().unwrap().open.len()
+    }
+}
+
+impl fmt::Debug for QueryCoalescer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("QueryCoalescer")
+            .field("in_flight", &inner.open.len())
+            .field("coalesced", &inner.coalesced)
+            .finish()
+    }
+}
+
+// the leader's result goes out to every follower, whatever it is
+impl ResultSink for QueryCoalescer {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        let Some(leader) = self.inner.lock().unwrap().remove(req_id) else { return };
+        for (follower, result_tx) in leader.followers {
+            let _ = result_tx.send(result.clone().readdressed(follower));
+        }
+    }
+}
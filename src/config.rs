@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::autoscale::AutoscaleConfig;
+use crate::chaos::ChaosConfig;
+use crate::circuit::CircuitBreakerConfig;
+use crate::clock::{Clock, SystemClock};
+use crate::context::ServerContext;
+use crate::config_file::ConfigError;
+use crate::failure::FailureSchedule;
+use crate::fault::FaultConfig;
+use crate::heartbeat::HeartbeatConfig;
+use crate::limits::TaskLimits;
+use crate::negative_cache::NegativeCacheConfig;
+use crate::preemption::Preemption;
+#[cfg(feature = "profiler")]
+use crate::profiler::ProfilerConfig;
+use crate::qos::DEFAULT_BATCH_SHARE;
+use crate::rate_limit::RateLimit;
+use crate::shedding::LoadShedding;
+use crate::slow_request::SlowRequestConfig;
+use crate::sink::SinkChain;
+use crate::tenant::TenantId;
+use crate::timeouts::Timeouts;
+use crate::{RunMode, UPDATE_TIMEOUT};
+
+// per-server knobs. everything defaults to the behaviour of ServerThread::new()
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    // per-client token bucket in front of the worker. None disables rate limiting
+    pub rate_limit: Option<RateLimit>,
+    // while batch requests are waiting, at least one out of every batch_share requests the worker handles is a batch one
+    pub batch_share: usize,
+    // instructions unanswered for longer than this are reported as ServerEvent::SlowTask. None disables the watchdog
+    // has to be shorter than the task timeout
+    pub slow_task_threshold: Option<Duration>,
+    // how long a single update function may run before the task gives up on it with UpdateTimedOut
+    pub update_timeout: Duration,
+    // time source for every timeout. swap in a SimClock for deterministic simulations
+    pub clock: Arc<dyn Clock>,
+    // seeded message faults between server, worker, tasks and listener. None disables fault injection
+    pub faults: Option<FaultConfig>,
+    // seeded kills of tasks and the worker while the server runs. None disables the chaos thread
+    pub chaos: Option<ChaosConfig>,
+    // kills, lock poisoning and disconnects at fixed clock times since the server started. None disables the failure thread
+    pub failures: Option<FailureSchedule>,
+    // marks every request at each hop and publishes its spans once it completes, see trace.rs
+    pub tracing: bool,
+    // how many instructions may wait in a single task's queue before the worker answers further ones with Busy.
+    // None leaves the queues unbounded
+    pub task_queue_capacity: Option<usize>,
+    // how many requests the worker handles in one turn before sending anything on. the instructions of a turn that
+    // are for the same task go out to it as one TaskInstruction::Batch. None or 1 sends every instruction on its own
+    pub instruction_batch: Option<usize>,
+    // how many instructions a single task may have been handed without answering them, the one it is running included,
+    // before the worker answers further ones with Busy. models a service with limited internal parallelism.
+    // None disables the limit
+    pub max_in_flight_per_task: Option<usize>,
+    // query values longer than this many bytes are sent back in pieces of at most this size, as TaskResult::QueryChunk,
+    // and put together again by the listener. None sends every value whole
+    pub query_chunk_size: Option<usize>,
+    // keeps tasks that exit from inactivity, state and all, and starts one again when a request targets it.
+    // its answer comes with TaskResult::Respawned. a respawn counts against the caps like a create, at one it is Throttled
+    pub respawn_expired: bool,
+    // how many listener threads collect results, each from its own channel into its own shard of the results store.
+    // requests are spread over them by req_id. 0 is treated as 1
+    pub listener_shards: usize,
+    // extra places every recorded result goes, ahead of the results store. empty by default
+    pub result_sinks: SinkChain,
+    // how many of its latest results to keep per task, see ServerThread::task_history. None keeps none
+    pub task_history: Option<usize>,
+    // moves the concurrency cap within bounds based on how many creates get throttled. None keeps it where it is set
+    pub autoscale: Option<AutoscaleConfig>,
+    // how many tasks each listed tenant may have running, on top of the server-wide cap. creates past it are throttled.
+    // tenants that are not listed are only held to the server-wide cap
+    pub tenant_caps: HashMap<TenantId, usize>,
+    // how many tasks of each listed task group may be running, on top of the server-wide and tenant caps.
+    // creates past it are throttled with the group in TaskResult::Throttled. groups that are not listed have no cap
+    pub group_caps: HashMap<String, usize>,
+    // a create that finds the server at its concurrency cap stops the least important idle task of lower priority
+    // instead of being throttled, see TaskBuilder::priority. None never preempts
+    pub preemption: Option<Preemption>,
+    // share of the worker's turns each tenant gets while several have requests queued in the same class, see FairQueue.
+    // tenants that are not listed weigh 1
+    pub tenant_weights: HashMap<TenantId, u32>,
+    // how long tasks, the listener and the worker wait for their next message
+    pub timeouts: Timeouts,
+    // how many keys, update functions and value bytes a created task may have. bigger ones are answered with
+    // TaskResult::RejectedTooLarge. unbounded by default
+    pub task_limits: TaskLimits,
+    // tasks count the queries of each key and note when it was last read and written, and QueryOk carries it, see KeyAccess
+    pub access_metadata: bool,
+    // fails requests fast with TaskResult::CircuitOpen while too many recent ones came back Throttled, Busy, Undeliverable or Shed.
+    // None never fails fast
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    // drops requests of low priority instead of queueing them while the worker is overloaded. None queues everything
+    pub load_shedding: Option<LoadShedding>,
+    // whether the server winds down by itself once it goes quiet, or keeps running until shutdown_with
+    pub run_mode: RunMode,
+    // starts a replacement when the worker panics, on the same request channel and with the same tasks, see supervisor.rs
+    pub supervise_worker: bool,
+    // the worker beats at a fixed interval and the server fails requests fast once it misses too many, see
+    // ServerThread::worker_liveness. None sends no heartbeats
+    pub heartbeat: Option<HeartbeatConfig>,
+    // how many requests may be queued in the worker before health reports Readiness::QueueFull and try_query_task and
+    // try_update_task fail with WouldBlock. the plain requests queue past it. None ignores the queue
+    pub ready_queue_depth: Option<usize>,
+    // requests slower than a threshold are published as ServerEvent::SlowRequest and the slowest kept, see
+    // ServerThread::slowest_requests. None doesn't look
+    pub slow_requests: Option<SlowRequestConfig>,
+    // answers a query the server has seen a QueryOk for from that, without the worker, until an UpdateOk for the task
+    // comes back, see QueryCache
+    pub query_cache: bool,
+    // answers queries for a task that was just answered with NotFound the same way, without the worker, for a while.
+    // None asks the worker every time, see NegativeCache
+    pub negative_cache: Option<NegativeCacheConfig>,
+    // every task publishes a copy of its values to a ReplicaStore at most this often (clock time) while they change, for
+    // queries sent with RequestOptions::stale_ok. None keeps no replicas
+    pub replica_interval: Option<Duration>,
+    // a query for the same key of the same task as one still on its way isn't sent, it gets a copy of that one's
+    // result, see QueryCoalescer
+    pub coalesce_queries: bool,
+    // a query still unanswered this long (clock time) after the worker handed it to its task is sent to the task a second
+    // time, the first answer is recorded and the other dropped, see Hedges. None sends every query once
+    pub hedge_after: Option<Duration>,
+    // read-only data shared by every task instead of copied into its maps, see TaskState::context. None shares nothing
+    pub context: Option<ServerContext>,
+    // samples what the worker, the tasks and the listener are doing every interval, see ServerThread::profile.
+    // None doesn't sample
+    #[cfg(feature = "profiler")]
+    pub profiler: Option<ProfilerConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit: None,
+            batch_share: DEFAULT_BATCH_SHARE,
+            slow_task_threshold: None,
+            update_timeout: UPDATE_TIMEOUT,
+            clock: Arc::new(SystemClock::new()),
+            faults: None,
+            chaos: None,
+            failures: None,
+            tracing: false,
+            task_queue_capacity: None,
+            instruction_batch: None,
+            max_in_flight_per_task: None,
+            query_chunk_size: None,
+            respawn_expired: false,
+            task_history: None,
+            listener_shards: 1,
+            result_sinks: SinkChain::new(),
+            autoscale: None,
+            tenant_caps: HashMap::new(),
+            group_caps: HashMap::new(),
+            preemption: None,
+            tenant_weights: HashMap::new(),
+            timeouts: Timeouts::default(),
+            task_limits: TaskLimits::default(),
+            access_metadata: false,
+            circuit_breaker: None,
+            load_shedding: None,
+            run_mode: RunMode::Simulation,
+            supervise_worker: false,
+            heartbeat: None,
+            ready_queue_depth: None,
+            slow_requests: None,
+            query_cache: false,
+            negative_cache: None,
+            replica_interval: None,
+            coalesce_queries: false,
+            hedge_after: None,
+            context: None,
+            #[cfg(feature = "profiler")]
+            profiler: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    // checks the assumptions written down next to TASK_TIMEOUT and LISTENER_TIMEOUT against the configured timeouts
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.timeouts.validate()?;
+        let task = self.timeouts.task;
+        // assumption 1 has to hold even for an update that runs into its timeout
+        if self.update_timeout >= task {
+            return Err(ConfigError::Invalid(format!(
+                "update timeout ({:?}) has to be shorter than the task timeout ({task:?})",
+                self.update_timeout
+            )));
+        }
+        if let Some(threshold) = self.slow_task_threshold.filter(|threshold| *threshold >= task) {
+            return Err(ConfigError::Invalid(format!(
+                "slow task threshold ({threshold:?}) has to be shorter than the task timeout ({task:?})"
+            )));
+        }
+        if let Some(breaker) = self.circuit_breaker {
+            if breaker.window == 0 || !(breaker.failure_ratio > 0.0 && breaker.failure_ratio <= 1.0) {
+                return Err(ConfigError::Invalid(
+                    "circuit breaker needs a window of at least 1 and a failure ratio in (0, 1]".to_string(),
+                ));
+            }
+        }
+        if let Some(heartbeat) = self.heartbeat {
+            if heartbeat.interval.is_zero() || heartbeat.missed == 0 {
+                return Err(ConfigError::Invalid("heartbeats need an interval and at least 1 missed beat".to_string()));
+            }
+            if heartbeat.restart && !self.supervise_worker {
+                return Err(ConfigError::Invalid("restarting a dead worker needs supervise_worker".to_string()));
+            }
+        }
+        if let Some(autoscale) = self.autoscale {
+            if autoscale.min_tasks > autoscale.max_tasks || autoscale.step == 0 {
+                return Err(ConfigError::Invalid(
+                    "autoscaling needs min_tasks no higher than max_tasks and a step of at least 1".to_string(),
+                ));
+            }
+        }
+        if self.batch_share == 0 {
+            return Err(ConfigError::Invalid("batch share has to be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
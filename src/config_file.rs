@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{ServerConfig, Timeouts};
+
+// prefix of the environment variables that override a config, e.g. SWS_TASK_TIMEOUT
+pub const ENV_PREFIX: &str = "SWS_";
+
+// the keys a config file or SWS_ variable may set. durations are in seconds, fractions allowed
+pub const CONFIG_KEYS: &[&str] = &[
+    "base_timeout",
+    "task_timeout",
+    "listener_timeout",
+    "worker_timeout",
+    "update_timeout",
+    "slow_task_threshold",
+    "batch_share",
+    "task_queue_capacity",
+    "max_in_flight_per_task",
+    "instruction_batch",
+    "query_chunk_size",
+    "respawn_expired",
+    "listener_shards",
+    "task_history",
+    "tracing",
+    "run_mode",
+    "supervise_worker",
+    "preemption",
+    "ready_queue_depth",
+    "query_cache",
+    "negative_cache_ttl",
+    "negative_cache_size",
+    "replica_interval",
+    "coalesce_queries",
+    "hedge_after",
+    "max_query_keys",
+    "max_update_fns",
+    "max_value_bytes",
+];
+
+// why a config could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    // a line of the file that is not `key = value`. line counts from 1
+    Syntax { line: usize, message: String },
+    UnknownKey { key: String, source: String },
+    BadValue { key: String, value: String, source: String },
+    // every value parsed but together they break one of the documented assumptions
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config: {err}"),
+            ConfigError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            ConfigError::UnknownKey { key, source } => write!(f, "{source}: unknown key {key}"),
+            ConfigError::BadValue { key, value, source } => write!(f, "{source}: {value} is not a valid {key}"),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl ServerConfig {
+    // the defaults, then the `key = value` lines of a config file, then any SWS_ variables, validated.
+    // the file is flat key = value lines, not TOML: no sections, arrays or quoted keys, see apply_lines
+    // only the keys in CONFIG_KEYS are understood, everything that takes code (clock, sinks, faults...) stays default
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_file_with_vars(path, env::vars())
+    }
+
+    // from_file with the given variables in place of the process environment
+    pub fn from_file_with_vars(
+        path: impl AsRef<Path>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        config.apply_lines(&fs::read_to_string(path)?)?;
+        config.apply_vars(vars)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // the defaults with any SWS_ variables applied, validated
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(env::vars())
+    }
+
+    // from_env with the given variables in place of the process environment
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        config.apply_vars(vars)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // sets the key of every `key = value` line. # starts a comment, blank lines are skipped, a value may be quoted.
+    // anything else, e.g. a [section], is a syntax error
+    pub fn apply_lines(&mut self, text: &str) -> Result<(), ConfigError> {
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let syntax = |message: &str| ConfigError::Syntax { line: index + 1, message: message.to_string() };
+            if line.starts_with('[') {
+                return Err(syntax("sections are not supported, the file is flat key = value lines"));
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| syntax("expected key = value"))?;
+            let value = value.trim();
+            // a quoted value is taken as written, so `batch_share = "4"` works too
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            self.set(key.trim(), value, &format!("line {}", index + 1))?;
+        }
+        Ok(())
+    }
+
+    // sets every key that has a SWS_<KEY> variable, e.g. SWS_TASK_TIMEOUT=3
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_vars(env::vars())
+    }
+
+    // apply_env over the given (name, value) pairs, names without the SWS_ prefix or key are ignored
+    pub fn apply_vars(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), ConfigError> {
+        let vars: HashMap<String, String> = vars.into_iter().collect();
+        for key in CONFIG_KEYS {
+            let var = format!("{ENV_PREFIX}{}", key.to_uppercase());
+            if let Some(value) = vars.get(&var) {
+                self.set(key, value.trim(), &var)?;
+            }
+        }
+        Ok(())
+    }
+
+    // source names where the value came from, for the error
+    fn set(&mut self, key: &str, value: &str, source: &str) -> Result<(), ConfigError> {
+        let bad = || ConfigError::BadValue { key: key.to_string(), value: value.to_string(), source: source.to_string() };
+        match key {
+            // sets all three, see Timeouts::derived, and the update timeout to half of base as in the defaults, so any
+            // base passes validate. in a file it goes before any of them that should differ,
+            // SWS_ variables are applied in CONFIG_KEYS order so it always comes first there
+            "base_timeout" => {
+                let base = seconds(value).ok_or_else(bad)?;
+                self.timeouts = Timeouts::derived(base);
+                self.update_timeout = base / 2;
+            }
+            "task_timeout" => self.timeouts.task = seconds(value).ok_or_else(bad)?,
+            "listener_timeout" => self.timeouts.listener = seconds(value).ok_or_else(bad)?,
+            "worker_timeout" => self.timeouts.worker = seconds(value).ok_or_else(bad)?,
+            "update_timeout" => self.update_timeout = seconds(value).ok_or_else(bad)?,
+            "slow_task_threshold" => self.slow_task_threshold = Some(seconds(value).ok_or_else(bad)?),
+            "batch_share" => self.batch_share = parse(value).ok_or_else(bad)?,
+            "task_queue_capacity" => self.task_queue_capacity = Some(parse(value).ok_or_else(bad)?),
+            "max_in_flight_per_task" => self.max_in_flight_per_task = Some(parse(value).ok_or_else(bad)?),
+            "instruction_batch" => self.instruction_batch = Some(parse(value).ok_or_else(bad)?),
+            "query_chunk_size" => self.query_chunk_size = Some(parse(value).ok_or_else(bad)?),
+            "respawn_expired" => self.respawn_expired = parse(value).ok_or_else(bad)?,
+            "listener_shards" => self.listener_shards = parse(value).ok_or_else(bad)?,
+            "task_history" => self.task_history = Some(parse(value).ok_or_else(bad)?),
+            "tracing" => self.tracing = parse(value).ok_or_else(bad)?,
+            "run_mode" => self.run_mode = parse(value).ok_or_else(bad)?,
+            "supervise_worker" => self.supervise_worker = parse(value).ok_or_else(bad)?,
+            "preemption" => self.preemption = Some(parse(value).ok_or_else(bad)?),
+            "ready_queue_depth" => self.ready_queue_depth = Some(parse(value).ok_or_else(bad)?),
+            "query_cache" => self.query_cache = parse(value).ok_or_else(bad)?,
+            "replica_interval" => self.replica_interval = Some(seconds(value).ok_or_else(bad)?),
+            "coalesce_queries" => self.coalesce_queries = parse(value).ok_or_else(bad)?,
+            "hedge_after" => self.hedge_after = Some(seconds(value).ok_or_else(bad)?),
+            "negative_cache_ttl" => {
+                self.negative_cache.get_or_insert_with(Default::default).ttl = seconds(value).ok_or_else(bad)?
+            }
+            "negative_cache_size" => {
+                self.negative_cache.get_or_insert_with(Default::default).capacity = parse(value).ok_or_else(bad)?
+            }
+            "max_query_keys" => self.task_limits.max_query_keys = Some(parse(value).ok_or_else(bad)?),
+            "max_update_fns" => self.task_limits.max_update_fns = Some(parse(value).ok_or_else(bad)?),
+            "max_value_bytes" => self.task_limits.max_value_bytes = Some(parse(value).ok_or_else(bad)?),
+            _ => return Err(ConfigError::UnknownKey { key: key.to_string(), source: source.to_string() }),
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Option<T> {
+    value.parse().ok()
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(value.parse().ok()?).ok()
+}
+
+// everything from a # that is not inside a quoted value
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
@@ -0,0 +1,28 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+// read-only data every task of a server can get at, e.g. configuration or a reference dataset, see
+// ServerConfig::context. update functions reach it through TaskState::context. cloning is cheap, every clone and every
+// task shares the one value instead of carrying its own copy in its maps
+#[derive(Clone)]
+pub struct ServerContext {
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl ServerContext {
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self { value: Arc::new(value) }
+    }
+
+    // None if the context holds something other than a T
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for ServerContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerContext").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::RequestId;
+
+// where a request with a deadline was given up on, see RequestOptions::deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineStage {
+    Worker, // taken off the worker's queues after its deadline
+    Task,   // taken off its task's queue after its deadline
+    Update, // its deadline passed before the task got to run the update function
+}
+
+// the deadline of every request that has one, as a clock time. filled by the server as it sends requests and
+// read by the worker and the tasks, which drop a request instead of working on it once its deadline has passed
+// cloning is cheap, every clone shares the same table
+#[derive(Debug, Clone, Default)]
+pub struct DeadlineTable {
+    entries: Arc<Mutex<HashMap<RequestId, Duration>>>,
+}
+
+impl DeadlineTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, req_id: RequestId, deadline: Duration) {
+        self.entries.lock().unwrap().insert(req_id, deadline);
+    }
+
+    pub fn get(&self, req_id: RequestId) -> Option<Duration> {
+        self.entries.lock().unwrap().get(&req_id).copied()
+    }
+
+    // how much of its budget req_id has left at now. None for requests without a deadline
+    pub fn remaining(&self, req_id: RequestId, now: Duration) -> Option<Duration> {
+        Some(self.get(req_id)?.saturating_sub(now))
+    }
+
+    // true once req_id's deadline has passed, never for requests without one
+    pub fn expired(&self, req_id: RequestId, now: Duration) -> bool {
+        self.get(req_id).is_some_and(|deadline| now >= deadline)
+    }
+}
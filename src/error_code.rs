@@ -0,0 +1,34 @@
+use std::fmt;
+
+// what went wrong with a request, so callers can branch on it without reading the detail text
+// QueryError and UpdateError carry one, TaskResult::error_code gives it for every other failed result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    KeyNotFound,    // the task's query_map has no such key
+    UpdateNotFound, // the task's update_map has no such update
+    UpdateFailed,   // the update function ran and returned Err
+    TaskGone,       // no task with that id, or it exited before the instruction reached it
+    Throttled,      // turned away by the concurrency cap or the rate limiter
+    Busy,           // the task's queue or in-flight limit was full
+    Panicked,       // the update function panicked, it is lost with its thread
+    TimedOut,       // the update function ran past the update timeout, now or earlier
+    PathNotFound,   // the value isn't a JSON document, or the path selects nothing in it
+    SchemaViolation, // the value doesn't fit the task's schema, it was not stored
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorCode::KeyNotFound => "key not found",
+            ErrorCode::UpdateNotFound => "update not found",
+            ErrorCode::UpdateFailed => "update failed",
+            ErrorCode::TaskGone => "task gone",
+            ErrorCode::Throttled => "throttled",
+            ErrorCode::Busy => "busy",
+            ErrorCode::Panicked => "panicked",
+            ErrorCode::TimedOut => "timed out",
+            ErrorCode::PathNotFound => "path not found",
+            ErrorCode::SchemaViolation => "schema violation",
+        })
+    }
+}
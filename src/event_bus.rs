@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, mpsc::{self, Sender, Receiver}};
+use std::time::Duration;
+
+use crate::backpressure::CountingSender;
+use crate::{ChaosTarget, FailureAction, RequestId, Span, TaskExit, TaskId, TaskInstruction, TaskStats, TenantId};
+
+// subscribing to this topic delivers every event published on the bus
+pub const ALL_TOPICS: &str = "*";
+
+// events that flow through the EventBus
+// every event has a topic (see ServerEvent::topic) which is what subscribers register against
+#[derive(Debug, PartialEq, Clone)]
+pub enum ServerEvent {
+    // a task published a named event, either explicitly through TaskInstruction::Publish
+    // or implicitly after one of its update functions ran (topic "update/<update_id>")
+    Published { topic: String, id: TaskId, payload: String },
+    // the watchdog saw an instruction go unanswered for longer than the configured threshold (topic "slow_task")
+    SlowTask { id: TaskId, req_id: RequestId, elapsed: Duration },
+    // an update function ran past the update timeout and the task is now degraded (topic "task_degraded")
+    TaskDegraded { id: TaskId, update_id: String },
+    // the chaos thread killed a task or the worker (topic "chaos")
+    ChaosKill { target: ChaosTarget },
+    // one leg of a completed request's trip, published by the listener when tracing is on (topic "trace")
+    Span(Span),
+    // the autoscaler moved the concurrency cap (topic "autoscale")
+    Scaled { from: usize, to: usize, throttle_rate: f64, queue_depth: usize },
+    // a failure from ServerConfig::failures was injected, at is its scheduled time (topic "failure")
+    FailureInjected { at: Duration, action: FailureAction },
+    // the worker started a newly created task (topic "task_created")
+    TaskCreated { id: TaskId, tenant: TenantId },
+    // a task thread exited, with what it did during this run (topic "task_finished")
+    TaskFinished { id: TaskId, exit: TaskExit, stats: TaskStats },
+    // the worker turned a create away at the concurrency cap or the tenant's cap (topic "throttled")
+    Throttled { req_id: RequestId, id: TaskId },
+    // the supervisor replaced a worker that panicked, restarts counts the replacements so far (topic "worker_restarted")
+    WorkerRestarted { restarts: usize },
+    // a request took longer than ServerConfig::slow_requests allows, from being sent to its result (topic "slow_request")
+    SlowRequest { req_id: RequestId, id: TaskId, latency: Duration },
+}
+
+impl ServerEvent {
+    pub fn topic(&self) -> &str {
+        match self {
+            ServerEvent::Published { topic, .. } => topic,
+            ServerEvent::SlowTask { .. } => "slow_task",
+            ServerEvent::TaskDegraded { .. } => "task_degraded",
+            ServerEvent::ChaosKill { .. } => "chaos",
+            ServerEvent::Span(_) => "trace",
+            ServerEvent::Scaled { .. } => "autoscale",
+            ServerEvent::FailureInjected { .. } => "failure",
+            ServerEvent::TaskCreated { .. } => "task_created",
+            ServerEvent::TaskFinished { .. } => "task_finished",
+            ServerEvent::Throttled { .. } => "throttled",
+            ServerEvent::WorkerRestarted { .. } => "worker_restarted",
+            ServerEvent::SlowRequest { .. } => "slow_request",
+        }
+    }
+}
+
+// a subscriber is either an external component holding a Receiver<ServerEvent>
+// or a task, in which case the event is pushed onto the task's instruction channel as TaskInstruction::Deliver
+enum Subscriber {
+    Channel(Sender<ServerEvent>),
+    Task { id: TaskId, tx: CountingSender<TaskInstruction> },
+}
+
+impl Subscriber {
+    // returns false if the receiving half is gone, so the subscriber can be pruned
+    fn deliver(&self, event: &ServerEvent) -> bool {
+        match self {
+            Subscriber::Channel(tx) => tx.send(event.clone()).is_ok(),
+            Subscriber::Task { tx, .. } => tx.send(TaskInstruction::Deliver { event: event.clone() }).is_ok(),
+        }
+    }
+}
+
+// broker between publishers (tasks, server components) and subscribers
+// cloning an EventBus is cheap and every clone shares the same subscriber table
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<HashMap<String, Vec<Subscriber>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // subscribe an external component to a topic (or ALL_TOPICS)
+    // dropping the returned receiver unsubscribes it on the next publish to that topic
+    pub fn subscribe(&self, topic: &str) -> Receiver<ServerEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .entry(topic.to_string())
+            .or_default()
+            .push(Subscriber::Channel(tx));
+        rx
+    }
+
+    // subscribe a task to a topic. events are delivered on the task's own instruction channel,
+    // so once the task exits its receiver is dropped and the subscription is pruned on the next publish.
+    // subscribing the same task twice to the same topic keeps a single subscription on the task's latest sender,
+    // so a respawned task doesn't lose events to the channel of the thread it replaced
+    pub(crate) fn subscribe_task(&self, topic: &str, id: TaskId, tx: CountingSender<TaskInstruction>) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let entry = subscribers.entry(topic.to_string()).or_default();
+        match entry.iter_mut().find(|s| matches!(s, Subscriber::Task { id: sub_id, .. } if *sub_id == id)) {
+            Some(Subscriber::Task { tx: old, .. }) => *old = tx,
+            _ => entry.push(Subscriber::Task { id, tx }),
+        }
+    }
+
+    // publish an event to everyone subscribed to its topic and to ALL_TOPICS
+    // returns the number of subscribers the event was delivered to
+    pub fn publish(&self, event: ServerEvent) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let mut delivered = 0;
+        let mut topics = vec![event.topic()];
+        if event.topic() != ALL_TOPICS {
+            topics.push(ALL_TOPICS);
+        }
+        for topic in topics {
+            if let Some(subs) = subscribers.get_mut(topic) {
+                subs.retain(|s| {
+                    let ok = s.deliver(&event);
+                    if ok {
+                        delivered += 1;
+                    }
+                    ok
+                });
+            }
+        }
+        delivered
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::{RequestId, TaskResult};
+
+// why ServerThread::expect_eventually failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpectError {
+    // a result was recorded but it is not the expected one
+    // boxed so a Result<(), ExpectError> stays small however large TaskResult gets
+    Mismatch { req_id: RequestId, expected: Box<TaskResult>, actual: Box<TaskResult> },
+    // nothing was recorded before the timeout, or before the listener exited
+    Missing { req_id: RequestId, expected: Box<TaskResult>, waited: Duration },
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectError::Mismatch { req_id, expected, actual } => {
+                writeln!(f, "req:{req_id} mismatch (- expected, + actual)")?;
+                write!(f, "{}", diff(&format!("{expected:#?}"), &format!("{actual:#?}")))
+            }
+            ExpectError::Missing { req_id, expected, waited } => {
+                write!(f, "req:{req_id} had no result after {waited:?}, expected {expected:?}")
+            }
+        }
+    }
+}
+
+// line by line diff of two pretty-printed results
+// two results of the same variant print the same lines in the same order, so comparing by position
+// points straight at the fields that differ. different variants just show up as every line differing
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push_str(&format!("  {e}\n")),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push_str(&format!("- {e}\n"));
+                }
+                if let Some(a) = a {
+                    out.push_str(&format!("+ {a}\n"));
+                }
+            }
+        }
+    }
+    out
+}
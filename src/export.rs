@@ -0,0 +1,82 @@
+use std::io::{self, Write};
+
+use crate::result_log::json_string;
+use crate::{RequestId, ResultMeta, TaskResult};
+
+// file format of ServerThread::export_results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    // a header line, then req_id,id,kind,latency_ms,outcome per result. latency_ms is empty when unknown
+    Csv,
+    // an array of {"req_id","id","kind","latency_ms","outcome"} objects. latency_ms is null when unknown
+    Json,
+}
+
+// one line of the report
+struct Row {
+    req_id: RequestId,
+    id: usize,
+    kind: &'static str,
+    latency_ms: Option<f64>, // from being sent to being recorded, clock time
+    outcome: String,
+}
+
+impl Row {
+    fn new(req_id: RequestId, result: &TaskResult, meta: Option<&ResultMeta>) -> Self {
+        Self {
+            req_id,
+            id: result.id(),
+            kind: result.kind(),
+            latency_ms: meta.map(|meta| meta.total().as_secs_f64() * 1000.0),
+            outcome: outcome(result),
+        }
+    }
+}
+
+// "ok" for results that did what was asked, the error code for failures that have one, the kind otherwise
+fn outcome(result: &TaskResult) -> String {
+    match result {
+        TaskResult::Created { .. }
+        | TaskResult::QueryOk { .. }
+        | TaskResult::QueryManyOk { .. }
+        | TaskResult::UpdateOk { .. }
+        | TaskResult::Published { .. }
+        | TaskResult::Subscribed { .. } => "ok".to_string(),
+        result => result.error_code().map_or_else(|| result.kind().to_string(), |code| format!("{code:?}")),
+    }
+}
+
+// results in req_id order, each with its timing if it has any
+pub(crate) fn write(
+    format: ExportFormat,
+    results: impl IntoIterator<Item = (RequestId, TaskResult, Option<ResultMeta>)>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let rows = results.into_iter().map(|(req_id, result, meta)| Row::new(req_id, &result, meta.as_ref()));
+    match format {
+        ExportFormat::Csv => {
+            writeln!(out, "req_id,id,kind,latency_ms,outcome")?;
+            for row in rows {
+                let latency = row.latency_ms.map_or(String::new(), |ms| ms.to_string());
+                writeln!(out, "{},{},{},{latency},{}", row.req_id, row.id, row.kind, row.outcome)?;
+            }
+        }
+        ExportFormat::Json => {
+            write!(out, "[")?;
+            for (i, row) in rows.enumerate() {
+                let latency = row.latency_ms.map_or("null".to_string(), |ms| ms.to_string());
+                write!(
+                    out,
+                    "{}\n  {{\"req_id\":{},\"id\":{},\"kind\":{},\"latency_ms\":{latency},\"outcome\":{}}}",
+                    if i == 0 { "" } else { "," },
+                    row.req_id,
+                    row.id,
+                    json_string(row.kind),
+                    json_string(&row.outcome),
+                )?;
+            }
+            writeln!(out, "\n]")?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::backpressure::CountingSender;
+use crate::{
+    Clock, Envelope, EventBus, FaultChannel, FaultInjector, RequestOptions, ServerEvent, TaskId, TaskInstruction,
+    TaskRequest, TaskSenders,
+};
+
+// something that goes wrong on purpose at a scheduled point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    // the task exits without removing itself from the worker's task map, like a chaos kill
+    KillTask(TaskId),
+    // the worker exits without stopping its tasks
+    KillWorker,
+    // a thread panics while holding the lock on the worker's task map, so the next thread to take it panics too
+    PoisonTaskMap,
+    // every later message on the channel is lost, as if its receiving end had gone away
+    Disconnect(FaultChannel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduledFailure {
+    // clock time since the server started
+    pub at: Duration,
+    pub action: FailureAction,
+}
+
+// failures to inject at fixed clock times, see ServerConfig::failures
+// with a SimClock each one happens exactly when the clock is advanced past it, so recovery paths can be tested deterministically
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FailureSchedule {
+    pub failures: Vec<ScheduledFailure>,
+}
+
+impl FailureSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn at(mut self, at: Duration, action: FailureAction) -> Self {
+        self.failures.push(ScheduledFailure { at, action });
+        self
+    }
+}
+
+// works through a schedule in time order and reports every failure as ServerEvent::FailureInjected
+pub(crate) struct FailureRunner {
+    schedule: FailureSchedule,
+    tasks: TaskSenders,
+    worker_tx: CountingSender<Envelope>,
+    faults: FaultInjector,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+}
+
+impl FailureRunner {
+    pub(crate) fn new(
+        schedule: FailureSchedule,
+        tasks: TaskSenders,
+        worker_tx: CountingSender<Envelope>,
+        faults: FaultInjector,
+        events: EventBus,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self { schedule, tasks, worker_tx, faults, events, clock }
+    }
+
+    // the thread exits after the last failure, or once the shutdown flag is set
+    // failures scheduled at the same time happen in the order they were added
+    pub(crate) fn spawn(mut self, shutdown_flag: Arc<AtomicBool>) -> JoinHandle<()> {
+        let started = self.clock.now();
+        self.schedule.failures.sort_by_key(|failure| failure.at);
+        thread::spawn(move || {
+            for failure in &self.schedule.failures {
+                let now = self.clock.now();
+                if started + failure.at > now {
+                    self.clock.sleep(started + failure.at - now);
+                }
+                if shutdown_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                println!("[Failures] {:?} at {:?}", failure.action, failure.at);
+                self.inject(failure.action);
+                self.events.publish(ServerEvent::FailureInjected { at: failure.at, action: failure.action });
+            }
+            println!("[Failures] Failure thread exiting.");
+        })
+    }
+
+    fn inject(&self, action: FailureAction) {
+        match action {
+            FailureAction::KillTask(id) => {
+                // a task that is gone already has nothing left to kill
+                if let Some(tx) = self.tasks.lock().unwrap().get(&id) {
+                    let _ = tx.send(TaskInstruction::Kill);
+                }
+            }
+            FailureAction::KillWorker => {
+                let _ = self.worker_tx.send(Envelope { opts: RequestOptions::default(), request: TaskRequest::Kill });
+            }
+            FailureAction::PoisonTaskMap => {
+                let tasks = Arc::clone(&self.tasks);
+                let _ = thread::spawn(move || {
+                    let _held = tasks.lock();
+                    panic!("scheduled failure: poisoning the task map");
+                })
+                .join();
+            }
+            FailureAction::Disconnect(channel) => self.faults.disconnect(channel),
+        }
+    }
+}
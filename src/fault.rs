@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, mpsc::{SendError, Sender}};
+use std::thread;
+use std::time::Duration;
+
+use crate::Clock;
+
+// anything FaultInjector::send can put messages on: plain channels and counting ones
+pub trait MessageSender<T>: Clone + Send + 'static {
+    fn send_message(&self, msg: T) -> Result<(), SendError<T>>;
+}
+
+impl<T: Send + 'static> MessageSender<T> for Sender<T> {
+    fn send_message(&self, msg: T) -> Result<(), SendError<T>> {
+        self.send(msg)
+    }
+}
+
+// probabilities (0.0..=1.0) of each fault for messages on one channel. at most one fault is applied per message
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultRates {
+    pub drop: f64,
+    pub delay: f64,
+    pub duplicate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultConfig {
+    pub seed: u64,
+    pub requests: FaultRates,     // server -> worker
+    pub instructions: FaultRates, // worker -> task
+    pub results: FaultRates,      // task/worker -> listener
+    pub max_delay: Duration,      // delays are uniform in 0..max_delay
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            requests: FaultRates::default(),
+            instructions: FaultRates::default(),
+            results: FaultRates::default(),
+            max_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaultChannel {
+    Requests,
+    Instructions,
+    Results,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    None,
+    Drop,
+    Delay(Duration),
+    Duplicate,
+}
+
+// how many faults were injected on a channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub dropped: usize,
+    pub delayed: usize,
+    pub duplicated: usize,
+}
+
+// splitmix64, good enough for picking faults and needs no dependency
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in 0.0..1.0
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+struct Inner {
+    config: FaultConfig,
+    // one generator per channel. each channel is only ever fed by one thread (server, worker, listener),
+    // so the same seed and the same message sequence give the same faults
+    rngs: HashMap<FaultChannel, Mutex<Rng>>,
+    stats: Mutex<HashMap<FaultChannel, FaultStats>>,
+}
+
+// decides which messages get dropped, delayed or duplicated on their way between components
+// cloning is cheap, every clone shares the same generators and stats. without a config it never injects anything
+#[derive(Clone)]
+pub struct FaultInjector {
+    inner: Option<Arc<Inner>>,
+    // channels that lose every message, see disconnect. works with or without a config
+    disconnected: Arc<Mutex<HashSet<FaultChannel>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl FaultInjector {
+    pub fn new(config: Option<FaultConfig>, clock: Arc<dyn Clock>) -> Self {
+        let inner = config.map(|config| {
+            let rngs = [FaultChannel::Requests, FaultChannel::Instructions, FaultChannel::Results]
+                .into_iter()
+                .enumerate()
+                .map(|(i, channel)| (channel, Mutex::new(Rng(config.seed ^ (i as u64 + 1).wrapping_mul(0xA24B_AED4_963E_E407)))))
+                .collect();
+            Arc::new(Inner { config, rngs, stats: Mutex::new(HashMap::new()) })
+        });
+        Self { inner, disconnected: Arc::new(Mutex::new(HashSet::new())), clock }
+    }
+
+    // every later message on the channel is dropped, for good. not counted in the stats
+    pub fn disconnect(&self, channel: FaultChannel) {
+        self.disconnected.lock().unwrap().insert(channel);
+    }
+
+    pub fn is_disconnected(&self, channel: FaultChannel) -> bool {
+        self.disconnected.lock().unwrap().contains(&channel)
+    }
+
+    // picks the fault (if any) for the next message on the channel and counts it
+    pub fn decide(&self, channel: FaultChannel) -> Fault {
+        if self.is_disconnected(channel) {
+            return Fault::Drop;
+        }
+        let Some(inner) = &self.inner else { return Fault::None };
+        let rates = match channel {
+            FaultChannel::Requests => inner.config.requests,
+            FaultChannel::Instructions => inner.config.instructions,
+            FaultChannel::Results => inner.config.results,
+        };
+        let mut rng = inner.rngs[&channel].lock().unwrap();
+        let roll = rng.next_f64();
+
+        let fault = if roll < rates.drop {
+            Fault::Drop
+        } else if roll < rates.drop + rates.delay {
+            Fault::Delay(inner.config.max_delay.mul_f64(rng.next_f64()))
+        } else if roll < rates.drop + rates.delay + rates.duplicate {
+            Fault::Duplicate
+        } else {
+            Fault::None
+        };
+
+        let mut stats = inner.stats.lock().unwrap();
+        let entry = stats.entry(channel).or_default();
+        match fault {
+            Fault::Drop => entry.dropped += 1,
+            Fault::Delay(_) => entry.delayed += 1,
+            Fault::Duplicate => entry.duplicated += 1,
+            Fault::None => {}
+        }
+        fault
+    }
+
+    pub fn stats(&self, channel: FaultChannel) -> FaultStats {
+        self.inner
+            .as_ref()
+            .and_then(|inner| inner.stats.lock().unwrap().get(&channel).copied())
+            .unwrap_or_default()
+    }
+
+    // sends msg on tx, subject to the faults configured for channel
+    // a dropped message counts as sent. a delayed one is sent from a helper thread once the delay has passed on the clock,
+    // so it can overtake or be overtaken by later messages. duplicating needs `copy`, messages it can't copy are sent once
+    pub fn send<T: Send + 'static>(
+        &self,
+        channel: FaultChannel,
+        tx: &impl MessageSender<T>,
+        msg: T,
+        copy: impl FnOnce(&T) -> Option<T>,
+    ) -> Result<(), SendError<T>> {
+        match self.decide(channel) {
+            Fault::None => tx.send_message(msg),
+            Fault::Drop => {
+                println!("[FaultInjector] Dropped a message on {channel:?}");
+                Ok(())
+            }
+            Fault::Delay(delay) => {
+                println!("[FaultInjector] Delaying a message on {channel:?} by {delay:?}");
+                let tx = tx.clone();
+                let clock = Arc::clone(&self.clock);
+                thread::spawn(move || {
+                    clock.sleep(delay);
+                    let _ = tx.send_message(msg);
+                });
+                Ok(())
+            }
+            Fault::Duplicate => {
+                println!("[FaultInjector] Duplicating a message on {channel:?}");
+                if let Some(duplicate) = copy(&msg) {
+                    let _ = tx.send_message(duplicate);
+                }
+                tx.send_message(msg)
+            }
+        }
+    }
+}
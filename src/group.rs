@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// running task threads per task group, for the per-group caps in ServerConfig::group_caps.
+// a task joins a group when it is created, see TaskBuilder::group, and tasks without one are in none.
+// cloning is cheap, every clone shares the same counters
+#[derive(Debug, Clone, Default)]
+pub struct GroupTable {
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl GroupTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // running task threads of the group
+    pub fn active(&self, group: &str) -> usize {
+        self.active.lock().unwrap().get(group).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn started(&self, group: &str) {
+        *self.active.lock().unwrap().entry(group.to_string()).or_default() += 1;
+    }
+
+    pub(crate) fn exited(&self, group: &str) {
+        if let Some(active) = self.active.lock().unwrap().get_mut(group) {
+            *active = active.saturating_sub(1);
+        }
+    }
+}
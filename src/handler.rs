@@ -0,0 +1,48 @@
+use std::sync::mpsc::Sender;
+
+use crate::{KeyPattern, RequestId, ServerEvent, TaskId, TaskInstruction, TaskResult};
+
+// the part of a TaskInstruction a TaskHandler answers, with the ids its TaskResult has to carry
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Query { req_id: RequestId, id: TaskId, query_id: String },
+    QueryMany { req_id: RequestId, id: TaskId, keys: Vec<String> },
+    QueryMatching { req_id: RequestId, id: TaskId, pattern: KeyPattern },
+    Update { req_id: RequestId, id: TaskId, update_id: String },
+}
+
+impl Instruction {
+    pub fn req_id(&self) -> RequestId {
+        match self {
+            Instruction::Query { req_id, .. }
+            | Instruction::QueryMany { req_id, .. }
+            | Instruction::QueryMatching { req_id, .. }
+            | Instruction::Update { req_id, .. } => *req_id,
+        }
+    }
+
+    // the instructions a handler answers, with where the answer goes. everything else is given back
+    pub(crate) fn split(msg: TaskInstruction, id: TaskId) -> Result<(Instruction, Sender<TaskResult>), TaskInstruction> {
+        Ok(match msg {
+            TaskInstruction::Query { req_id, query_id, result_tx } => (Instruction::Query { req_id, id, query_id }, result_tx),
+            TaskInstruction::QueryMany { req_id, keys, result_tx } => (Instruction::QueryMany { req_id, id, keys }, result_tx),
+            TaskInstruction::QueryMatching { req_id, pattern, result_tx } => {
+                (Instruction::QueryMatching { req_id, id, pattern }, result_tx)
+            }
+            TaskInstruction::Update { req_id, update_id, result_tx } => (Instruction::Update { req_id, id, update_id }, result_tx),
+            other => return Err(other),
+        })
+    }
+}
+
+// task behaviour as code instead of a query map and an update map, for tasks that keep their own state
+// or step through a protocol. see ServerThread::create_handler_task
+// handle runs on the task thread and has to answer with the instruction's req_id and id. unlike update_map
+// closures it is not bounded by the update timeout, a handler that never returns hangs its task.
+// publishing and subscribing work the same as for any other task, successful updates are published as "update/<update_id>"
+pub trait TaskHandler: Send {
+    fn handle(&mut self, instr: Instruction) -> TaskResult;
+
+    // an event the task subscribed to. ignored unless the handler cares
+    fn deliver(&mut self, _event: &ServerEvent) {}
+}
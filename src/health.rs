@@ -0,0 +1,37 @@
+// what the worker reports back when pinged
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WorkerStatus {
+    pub active_tasks: usize,
+    pub queue_depth: usize, // requests pulled off the channel and waiting in the worker's QoS queues
+}
+
+// returned by ServerThread::health()
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct HealthReport {
+    pub worker_alive: bool,   // worker answered a ping in time
+    pub listener_alive: bool, // listener thread has not exited
+    pub active_tasks: usize,
+    pub queue_depth: usize,   // 0 if the worker did not answer
+    pub readiness: Readiness, // whether a create sent now would get a task, alive or not
+}
+
+// alive is whether the threads run at all, ready is whether a CreateTask sent now is going to start a task.
+// when more than one reason applies the first one listed here is reported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Readiness {
+    Ready,
+    // shutdown_with has been called, everything is answered with ShuttingDown
+    Draining,
+    // the worker did not answer the ping
+    WorkerDown,
+    // active tasks are at the concurrency cap, a create would be throttled
+    AtCapacity,
+    // the worker has at least ServerConfig::ready_queue_depth requests queued
+    QueueFull,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        *self == Readiness::Ready
+    }
+}
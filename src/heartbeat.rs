@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::clock::{self, Clock};
+
+// the worker beats every interval (clock time) and is declared dead after missed intervals in a row without a beat.
+// see ServerConfig::heartbeat
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub missed: u32,
+    // a dead worker that has exited is replaced by the supervisor, needs ServerConfig::supervise_worker.
+    // a worker that is stuck can't be replaced, it still holds the request channel
+    pub restart: bool,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { interval: Duration::from_millis(100), missed: 3, restart: false }
+    }
+}
+
+// what the server makes of the worker's heartbeats, see ServerThread::worker_liveness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    Alive,
+    // missed intervals in a row without a beat, fewer than it takes to be dead
+    Late { missed: u32 },
+    // requests are failed fast with TaskResult::Undeliverable until it beats again
+    Dead,
+}
+
+// the server's end of the heartbeat channel. cloning is cheap, every clone shares the same state
+#[derive(Debug, Clone)]
+pub struct HeartbeatMonitor {
+    config: HeartbeatConfig,
+    liveness: Arc<Mutex<Liveness>>,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self { config, liveness: Arc::new(Mutex::new(Liveness::Alive)) }
+    }
+
+    pub fn config(&self) -> HeartbeatConfig {
+        self.config
+    }
+
+    pub fn liveness(&self) -> Liveness {
+        *self.liveness.lock().unwrap()
+    }
+
+    // starts the monitor thread on the worker's beats. revive_tx is told once every time the worker goes dead.
+    // the thread exits once the shutdown flag is set, or when every worker has let go of its end (the worker is dead then)
+    pub(crate) fn spawn(
+        &self,
+        beats: Receiver<Duration>,
+        clock: Arc<dyn Clock>,
+        revive_tx: Option<Sender<()>>,
+        shutdown_flag: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        let monitor = self.clone();
+        thread::spawn(move || {
+            let mut missed = 0;
+            while !shutdown_flag.load(Ordering::Relaxed) {
+                let liveness = match clock::recv_timeout(&*clock, &beats, monitor.config.interval) {
+                    Ok(_) => {
+                        missed = 0;
+                        Liveness::Alive
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        missed += 1;
+                        if missed < monitor.config.missed {
+                            Liveness::Late { missed }
+                        } else {
+                            Liveness::Dead
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        *monitor.liveness.lock().unwrap() = Liveness::Dead;
+                        break;
+                    }
+                };
+                let previous = std::mem::replace(&mut *monitor.liveness.lock().unwrap(), liveness);
+                if liveness == Liveness::Dead && previous != Liveness::Dead {
+                    println!("[Heartbeat] Worker missed {missed} heartbeats, marking it dead");
+                    if let Some(revive_tx) = &revive_tx {
+                        let _ = revive_tx.send(());
+                    }
+                }
+            }
+            println!("[Heartbeat] Monitor exiting.");
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{RequestId, TaskId, TaskInstruction};
+
+// how hedging went, see ServerStats::hedges
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HedgeStats {
+    pub sent: usize,       // duplicates sent for queries still unanswered after ServerConfig::hedge_after
+    pub suppressed: usize, // answers that lost the race and were dropped
+}
+
+#[derive(Debug)]
+enum Hedge {
+    // waiting for an answer until due (clock time), then the copy goes to the task
+    Watching { due: Duration, id: TaskId, instruction: TaskInstruction },
+    // the copy went out at this clock time, the first answer either way is recorded
+    Sent { at: Duration },
+    // the first answer came in at this clock time, the second is dropped
+    Answered { at: Duration },
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    hedges: HashMap<RequestId, Hedge>,
+    stats: HedgeStats,
+}
+
+// queries the worker may send a second time, see ServerConfig::hedge_after. the worker watches every query it
+// dispatches and sends a copy to the same task once one has gone unanswered for too long, the listener records
+// whichever answer comes first and drops the other. a query is forgotten keep_for after its copy went out or its
+// first answer came in, whichever was last, so one whose answers were lost for good doesn't stay in the table.
+// an answer slower than that is recorded as well.
+// cloning is cheap, every clone shares the same table
+#[derive(Debug, Clone)]
+pub struct Hedges {
+    inner: Arc<Mutex<Inner>>,
+    keep_for: Duration,
+}
+
+impl Hedges {
+    pub fn new(keep_for: Duration) -> Self {
+        Self { inner: Arc::default(), keep_for }
+    }
+
+    pub fn stats(&self) -> HedgeStats {
+        self.inner.lock().unwrap().stats
+    }
+
+    // queries in the table, watched or hedged
+    pub fn tracked(&self) -> usize {
+        self.inner.lock().unwrap().hedges.len()
+    }
+
+    // instruction is the one dispatched to task id for req_id, a copy of it goes out at due unless it was answered
+    pub(crate) fn watch(&self, req_id: RequestId, id: TaskId, due: Duration, instruction: TaskInstruction) {
+        self.inner.lock().unwrap().hedges.insert(req_id, Hedge::Watching { due, id, instruction });
+    }
+
+    // the earliest clock time a copy is due, for the worker to wake up at
+    pub(crate) fn next_due(&self) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        inner.hedges.values().filter_map(|hedge| if let Hedge::Watching { due, .. } = hedge { Some(*due) } else { None }).min()
+    }
+
+    // the copies due by now, by request id, counted as sent. forgets whatever has been kept for keep_for
+    pub(crate) fn take_due(&self, now: Duration) -> Vec<(RequestId, TaskId, TaskInstruction)> {
+        let mut inner = self.inner.lock().unwrap();
+        let keep_for = self.keep_for;
+        inner.hedges.retain(|_, hedge| match hedge {
+            Hedge::Watching { .. } => true,
+            Hedge::Sent { at } | Hedge::Answered { at } => now.saturating_sub(*at) < keep_for,
+        });
+        let mut due: Vec<RequestId> = inner
+            .hedges
+            .iter()
+            .filter(|(_, hedge)| matches!(hedge, Hedge::Watching { due, .. } if *due <= now))
+            .map(|(req_id, _)| *req_id)
+            .collect();
+        due.sort();
+        inner.stats.sent += due.len();
+        due.into_iter()
+            .filter_map(|req_id| match inner.hedges.insert(req_id, Hedge::Sent { at: now }) {
+                Some(Hedge::Watching { id, instruction, .. }) => Some((req_id, id, instruction)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // a copy take_due handed out that couldn't be sent, its task is gone. forgotten and not counted
+    pub(crate) fn unsent(&self, req_id: RequestId) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(Hedge::Sent { .. }) = inner.hedges.remove(&req_id) {
+            inner.stats.sent -= 1;
+        }
+    }
+
+    // whether the result for req_id that came in at now is the one to record, false for the loser of a hedge
+    pub(crate) fn answered(&self, req_id: RequestId, now: Duration) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.hedges.remove(&req_id) {
+            Some(Hedge::Sent { .. }) => {
+                inner.hedges.insert(req_id, Hedge::Answered { at: now });
+                true
+            }
+            Some(Hedge::Answered { .. }) => {
+                inner.stats.suppressed += 1;
+                false
+            }
+            Some(Hedge::Watching { .. }) | None => true,
+        }
+    }
+}
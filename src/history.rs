@@ -0,0 +1,47 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::{RequestId, ResultSink, TaskId, TaskResult};
+
+// the last few results recorded for each task, oldest first, filled by the listener
+// next to the per-req_id results store. see ServerConfig::task_history
+// cloning is cheap, every clone shares the same table
+#[derive(Debug, Clone)]
+pub struct TaskHistory {
+    capacity: usize,
+    entries: Arc<Mutex<HashMap<TaskId, VecDeque<TaskResult>>>>,
+}
+
+impl TaskHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // once a task has capacity results its oldest one makes room
+    pub fn record(&self, result: &TaskResult) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let history = entries.entry(result.id()).or_default();
+        if history.len() == self.capacity {
+            history.pop_front();
+        }
+        history.push_back(result.clone());
+    }
+
+    // oldest first, empty for a task nothing has been recorded for
+    pub fn get(&self, id: TaskId) -> Vec<TaskResult> {
+        self.entries.lock().unwrap().get(&id).map_or_else(Vec::new, |history| history.iter().cloned().collect())
+    }
+}
+
+impl ResultSink for TaskHistory {
+    fn accept(&self, _req_id: RequestId, result: &TaskResult) {
+        self.record(result);
+    }
+}
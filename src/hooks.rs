@@ -0,0 +1,15 @@
+use std::thread;
+
+use crate::{EventBus, ServerEvent};
+
+// runs f on a thread of its own for every event published on topic, so a slow hook holds up neither the worker
+// nor the task that published the event. events are handed over in the order they were published.
+// the thread ends once every handle to the bus is gone, i.e. the server, its worker and all tasks
+pub(crate) fn spawn_hook(events: &EventBus, topic: &str, mut f: impl FnMut(ServerEvent) + Send + 'static) {
+    let rx = events.subscribe(topic);
+    thread::spawn(move || {
+        for event in rx {
+            f(event);
+        }
+    });
+}
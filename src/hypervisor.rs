@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::quota::{Meter, Quota, QuotaResource, Usage};
+use crate::script::{Script, ScriptError, ScriptErrorKind, UpdateMap};
+use crate::{RequestId, RequestOptions, ResultStore, ServerConfig, ServerThread, TaskId, TaskResult};
+
+// file extension load_dir picks up, the file stem is the script's name
+pub const SCRIPT_EXTENSION: &str = "script";
+
+// why load_dir could not register a directory's scripts
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Script { name: String, error: ScriptError },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{err}"),
+            LoadError::Script { name, error } => write!(f, "script '{name}': {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+// what became of one hypervisor request, in terms of the task's script rather than the raw TaskResult
+// req_ids and task ids are the hypervisor's own, see Hypervisor::attach
+#[derive(Debug, Clone, PartialEq)]
+pub enum HypervisorOutcome {
+    Created { req_id: RequestId, id: TaskId },
+    // a query was answered or an update ran to completion
+    Value { req_id: RequestId, id: TaskId, value: String },
+    // the update failed: its script or module stopped early, e.g. on a division by zero, and left the total alone,
+    // or the task has no such update
+    Failed { req_id: RequestId, id: TaskId, reason: String },
+    InvalidScript { req_id: RequestId, id: TaskId, error: ScriptError },
+    // turned away by the worker's task limit or the client's rate limit
+    Throttled { req_id: RequestId, id: TaskId },
+    // the update function did not return within the update timeout
+    TimedOut { req_id: RequestId, id: TaskId },
+    QuotaExceeded { req_id: RequestId, id: TaskId, resource: QuotaResource, terminated: bool },
+    // the task doesn't exist (anymore), or belongs to another hypervisor
+    NotFound { req_id: RequestId, id: TaskId },
+    // any other answer, e.g. an unknown query id. result is as the server recorded it, with the server's ids
+    Rejected { req_id: RequestId, result: TaskResult },
+    // no result arrived in time
+    Missing { req_id: RequestId },
+}
+
+impl HypervisorOutcome {
+    // req_id and id are the hypervisor's, the ids inside result are the server's
+    fn from_result(req_id: RequestId, id: TaskId, result: Option<TaskResult>) -> Self {
+        let Some(result) = result else { return HypervisorOutcome::Missing { req_id } };
+        match result {
+            TaskResult::Created { .. } => HypervisorOutcome::Created { req_id, id },
+            TaskResult::QueryOk { value, .. } => HypervisorOutcome::Value { req_id, id, value },
+            TaskResult::UpdateOk { value, .. } => HypervisorOutcome::Value { req_id, id, value },
+            TaskResult::UpdateError { code, detail, .. } => {
+                HypervisorOutcome::Failed { req_id, id, reason: detail.unwrap_or_else(|| code.to_string()) }
+            }
+            TaskResult::InvalidScript { error, .. } => HypervisorOutcome::InvalidScript { req_id, id, error },
+            TaskResult::Throttled { .. } | TaskResult::RateLimited { .. } => HypervisorOutcome::Throttled { req_id, id },
+            TaskResult::UpdateTimedOut { .. } => HypervisorOutcome::TimedOut { req_id, id },
+            TaskResult::QuotaExceeded { resource, terminated, .. } => {
+                HypervisorOutcome::QuotaExceeded { req_id, id, resource, terminated }
+            }
+            TaskResult::NotFound { .. } => HypervisorOutcome::NotFound { req_id, id },
+            result => HypervisorOutcome::Rejected { req_id, result },
+        }
+    }
+}
+
+// drives a ServerThread with tasks described by scripts instead of hand-built query and update maps
+// see script.rs for the grammar
+//
+// several hypervisors can drive the same server, see attach. each one numbers its tasks and requests from 0
+// and only sees its own: task ids and req_ids taken or returned by its methods are local to it and are mapped
+// to the server's on the way through. a hypervisor that never had another one attached uses the server's ids as they are
+pub struct Hypervisor {
+    // shared by every hypervisor attached to the same server
+    pub server: Arc<Mutex<ServerThread>>,
+    // the server's results, kept here so waiting on them doesn't hold the server's lock
+    results: ResultStore,
+    shutdown_flag: Arc<AtomicBool>,
+    // the server's task id for each of this hypervisor's tasks, indexed by local task id
+    tasks: Vec<TaskId>,
+    // the server's req_id and the local task id for each of this hypervisor's requests, indexed by local req_id
+    requests: Vec<(RequestId, TaskId)>,
+    // every registered version of every named script, version n lives at index n - 1
+    scripts: HashMap<String, Vec<Script>>,
+    // which registered script and version each task was created from
+    task_scripts: HashMap<TaskId, (String, u32)>,
+    // quota given to tasks created from now on
+    quota: Quota,
+    // resource accounting of every task this hypervisor created
+    meters: HashMap<TaskId, Meter>,
+}
+
+impl Hypervisor {
+    pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+
+    pub fn with_config(config: ServerConfig) -> Self {
+        Self::over(Arc::new(Mutex::new(ServerThread::with_config(config))))
+    }
+
+    // a new hypervisor driving the same server, worker and listener as this one, e.g. for another tenant.
+    // it starts with no tasks, no registered scripts and the default quota
+    pub fn attach(&self) -> Self {
+        Self::over(Arc::clone(&self.server))
+    }
+
+    fn over(server: Arc<Mutex<ServerThread>>) -> Self {
+        let (results, shutdown_flag) = {
+            let server = server.lock().unwrap();
+            (server.results.clone(), Arc::clone(&server.shutdown_flag))
+        };
+        Self {
+            server,
+            results,
+            shutdown_flag,
+            tasks: vec![],
+            requests: vec![],
+            scripts: HashMap::new(),
+            task_scripts: HashMap::new(),
+            quota: Quota::default(),
+            meters: HashMap::new(),
+        }
+    }
+
+    // every query and update of a task created from now on is checked against quota. an instruction that goes over
+    // answers TaskResult::QuotaExceeded instead of its usual result, and with quota.terminate the task exits afterwards
+    // tasks that already exist keep the quota they were created with
+    pub fn set_quota(&mut self, quota: Quota) {
+        self.quota = quota;
+    }
+
+    // the shared server, locked. hold on to it only briefly, every attached hypervisor needs it for each request
+    pub fn server(&self) -> MutexGuard<'_, ServerThread> {
+        self.server.lock().unwrap()
+    }
+
+    // what a task has used so far, None for tasks this hypervisor didn't create
+    pub fn task_usage(&self, id: TaskId) -> Option<Usage> {
+        self.meters.get(&id).map(Meter::usage)
+    }
+
+    // parses the script, builds the task's maps from args and creates it
+    // an invalid script still takes up a req_id and a task id, and is recorded as TaskResult::InvalidScript
+    // without anything reaching the worker
+    pub fn create_task(&mut self, script: &str, args: Vec<i64>) -> Result<TaskId, ScriptError> {
+        let script_result = Script::parse(script);
+        self.create_from(script, |meter| script_result?.build_metered(&args, meter))
+    }
+
+    // creates a task from a compiled WASM module instead of a script, see wasm.rs for what the module has to export
+    // every call into the module runs with fuel as its budget. a module that can't be compiled or instantiated
+    // is recorded as TaskResult::InvalidScript, same as a bad script
+    #[cfg(feature = "wasm")]
+    pub fn create_wasm_task(&mut self, wasm: &[u8], args: Vec<i64>, fuel: u64) -> Result<TaskId, ScriptError> {
+        let module = crate::wasm::WasmModule::compile(wasm);
+        self.create_from("<wasm module>", |meter| module?.build(&args, fuel, meter))
+    }
+
+    // registers source under name as a new version and returns that version, starting at 1
+    // tasks keep the version they were created from, registering a new one only affects tasks created afterwards
+    pub fn register_script(&mut self, name: &str, source: &str) -> Result<u32, ScriptError> {
+        let script = Script::parse(source)?;
+        let versions = self.scripts.entry(name.to_string()).or_default();
+        versions.push(script);
+        println!("[Hypervisor] Registered script '{name}' version {}", versions.len());
+        Ok(versions.len() as u32)
+    }
+
+    // registers every *.script file in dir under its file stem, in file name order
+    // stops at the first file that can't be read or parsed, the ones before it stay registered
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Result<Vec<(String, u32)>, LoadError> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION));
+        paths.sort();
+
+        let mut loaded = vec![];
+        for path in paths {
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+            let source = fs::read_to_string(&path)?;
+            let version = self
+                .register_script(name, source.trim())
+                .map_err(|error| LoadError::Script { name: name.to_string(), error })?;
+            loaded.push((name.to_string(), version));
+        }
+        Ok(loaded)
+    }
+
+    // latest registered version of a script
+    pub fn script_version(&self, name: &str) -> Option<u32> {
+        self.scripts.get(name).map(|versions| versions.len() as u32)
+    }
+
+    // the registered script and version a task was created from, None for tasks created from source
+    pub fn task_script(&self, id: TaskId) -> Option<(String, u32)> {
+        self.task_scripts.get(&id).cloned()
+    }
+
+    // creates a task from the latest version of a registered script
+    pub fn create_registered_task(&mut self, name: &str, args: Vec<i64>) -> Result<TaskId, ScriptError> {
+        match self.script_version(name) {
+            Some(version) => self.create_registered_task_version(name, version, args),
+            None => {
+                let kind = ScriptErrorKind::UnknownScript { name: name.to_string(), version: None };
+                self.create_from(name, |_| Err(ScriptError { pos: 0, kind }))
+            }
+        }
+    }
+
+    // creates a task from a specific version of a registered script
+    pub fn create_registered_task_version(&mut self, name: &str, version: u32, args: Vec<i64>) -> Result<TaskId, ScriptError> {
+        let script = version
+            .checked_sub(1)
+            .and_then(|i| self.scripts.get(name)?.get(i as usize))
+            .cloned()
+            .ok_or(ScriptError {
+                pos: 0,
+                kind: ScriptErrorKind::UnknownScript { name: name.to_string(), version: Some(version) },
+            });
+        let id = self.create_from(name, |meter| script?.build_metered(&args, meter))?;
+        self.task_scripts.insert(id, (name.to_string(), version));
+        Ok(id)
+    }
+
+    fn create_from(
+        &mut self,
+        label: &str,
+        build: impl FnOnce(&Meter) -> Result<(HashMap<String, String>, UpdateMap), ScriptError>,
+    ) -> Result<TaskId, ScriptError> {
+        let meter = Meter::new(self.quota);
+        let maps = build(&meter);
+        let local_id = self.tasks.len();
+        let mut server = self.server.lock().unwrap();
+        self.requests.push((server.request_counter, local_id));
+        match maps {
+            Ok((query_map, update_map)) => {
+                let opts = RequestOptions::default();
+                let id = server.create_task_metered(opts, query_map, update_map, Some(meter.clone()));
+                self.tasks.push(id);
+                self.meters.insert(local_id, meter);
+                Ok(local_id)
+            }
+            Err(error) => {
+                let req_id = server.next_req_id();
+                let id = server.next_task_id();
+                println!("[req:{req_id}] [Hypervisor] Invalid script {label:?} for Task {id}: {error}");
+                server.record(TaskResult::InvalidScript { req_id, id, error: error.clone() });
+                self.tasks.push(id);
+                Err(error)
+            }
+        }
+    }
+
+    // code is a query code from the task's script, e.g. "0"
+    pub fn query_task(&mut self, id: TaskId, code: &str) {
+        self.send(id, |server, id| server.query_task(id, code));
+    }
+
+    // code is an update code from the task's script, e.g. "1a"
+    pub fn update_task(&mut self, id: TaskId, code: &str) {
+        self.send(id, |server, id| server.update_task(id, code));
+    }
+
+    // sends a request for the local task id through the server under the task's server id.
+    // ids this hypervisor never handed out are answered with NotFound right away, so they can't reach another one's tasks
+    fn send(&mut self, id: TaskId, request: impl FnOnce(&mut ServerThread, TaskId)) {
+        let mut server = self.server.lock().unwrap();
+        self.requests.push((server.request_counter, id));
+        match self.tasks.get(id) {
+            Some(&server_id) => request(&mut server, server_id),
+            None => {
+                let req_id = server.next_req_id();
+                println!("[req:{req_id}] [Hypervisor] Task {id} does not belong to this hypervisor");
+                server.record(TaskResult::NotFound { req_id, id, ctx: "Task not found in this hypervisor" });
+            }
+        }
+    }
+
+    // waits up to timeout for every request this hypervisor made so far to be answered, then returns one outcome
+    // per request in local req_id order. requests still unanswered by then come back as Missing
+    // like ServerThread::wait_idle, stops waiting early once the listener has exited
+    pub fn collect_results(&self, timeout: Duration) -> Vec<HypervisorOutcome> {
+        let deadline = Instant::now() + timeout;
+        self.results.wait_until(deadline, || {
+            self.requests.iter().all(|(req_id, _)| self.results.is_recorded(*req_id)) || self.shutdown_flag.load(Ordering::Relaxed)
+        });
+        self.requests
+            .iter()
+            .enumerate()
+            .map(|(local, &(req_id, id))| HypervisorOutcome::from_result(local, id, self.results.get(req_id)))
+            .collect()
+    }
+
+    // waits for the listener to finish, then prints every result of this hypervisor's requests in req_id order
+    pub fn listen_for_results(&mut self) {
+        self.server().join_listener();
+        for (req_id, _) in &self.requests {
+            match self.results.get(*req_id) {
+                Some(result) => println!("[Hypervisor] {:?}", result),
+                None => println!("[Hypervisor] (no result)"),
+            }
+        }
+    }
+}
+
+impl Default for Hypervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// how many requests the worker has queued and whether its intake is paused, published by the worker as it goes
+// so the server can look without asking it, see ServerThread::try_query_task
+#[derive(Debug, Clone, Default)]
+pub struct IntakeGauge {
+    queued: Arc<AtomicUsize>,
+    paused: Arc<AtomicBool>,
+}
+
+impl IntakeGauge {
+    // requests taken off the worker's channel and waiting in its queues, as of its last turn
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_queued(&self, queued: usize) {
+        self.queued.store(queued, Ordering::Release);
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+}
+
+// why a try_ request was turned down. nothing was sent and no request id was used up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WouldBlock {
+    // depth requests were waiting for the worker, on its channel and in its queues, see ServerConfig::ready_queue_depth
+    QueueFull { depth: usize, capacity: usize },
+    // the worker's intake is paused, see AdminCommand::PauseIntake
+    Paused,
+}
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WouldBlock::QueueFull { depth, capacity } => write!(f, "worker queue is full ({depth} of {capacity})"),
+            WouldBlock::Paused => f.write_str("worker intake is paused"),
+        }
+    }
+}
+
+impl std::error::Error for WouldBlock {}
@@ -0,0 +1,79 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::{RequestOptions, TaskRequest};
+
+// what an interceptor wants done with a request it has seen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    // on to the next interceptor, or the worker after the last one
+    Continue,
+    // answered with TaskResult::Rejected carrying the reason, nothing reaches the worker. later interceptors don't see it
+    Reject(String),
+    // sent this much later on the server's clock, requests sent in the meantime may overtake it.
+    // the delays of several interceptors add up
+    Delay(Duration),
+}
+
+// sees every request the server is about to send to the worker, see ServerThread::add_interceptor.
+// it may change the request or its options in place, but has to leave req_id and id alone, the server has noted them already.
+// pings and shutdowns are not intercepted
+pub trait RequestInterceptor: Send {
+    fn intercept(&mut self, opts: &mut RequestOptions, request: &mut TaskRequest) -> Verdict;
+}
+
+impl<F> RequestInterceptor for F
+where
+    F: FnMut(&mut RequestOptions, &mut TaskRequest) -> Verdict + Send,
+{
+    fn intercept(&mut self, opts: &mut RequestOptions, request: &mut TaskRequest) -> Verdict {
+        self(opts, request)
+    }
+}
+
+// interceptors run one after the other, in the order they were added
+#[derive(Default)]
+pub struct InterceptorChain {
+    interceptors: Vec<Box<dyn RequestInterceptor>>,
+}
+
+impl InterceptorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, interceptor: impl RequestInterceptor + 'static) {
+        self.interceptors.push(Box::new(interceptor));
+    }
+
+    pub fn len(&self) -> usize {
+        self.interceptors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+
+    // the first rejection wins, otherwise the total delay, Continue if there is none
+    pub fn intercept(&mut self, opts: &mut RequestOptions, request: &mut TaskRequest) -> Verdict {
+        let mut delay = Duration::ZERO;
+        for interceptor in &mut self.interceptors {
+            match interceptor.intercept(opts, request) {
+                Verdict::Continue => {}
+                Verdict::Reject(reason) => return Verdict::Reject(reason),
+                Verdict::Delay(by) => delay += by,
+            }
+        }
+        if delay.is_zero() {
+            Verdict::Continue
+        } else {
+            Verdict::Delay(delay)
+        }
+    }
+}
+
+impl fmt::Debug for InterceptorChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterceptorChain").field("len", &self.len()).finish()
+    }
+}
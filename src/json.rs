@@ -0,0 +1,352 @@
+use std::fmt;
+
+use crate::result_log::json_string;
+
+// deeper documents are refused rather than risking the task thread's stack
+const MAX_DEPTH: usize = 128;
+
+// a JSON document as a task value holds it, see ServerThread::query_path. objects keep their keys in document order,
+// numbers their original text so a fragment comes back exactly as it was stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub offset: usize, // byte offset in the text where parsing gave up
+    pub reason: &'static str,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.reason, self.offset)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser { text: text.as_bytes(), at: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.at != text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    // the member key of an object, None for anything else or a missing key. the first one wins on duplicates
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn index(&self, index: usize) -> Option<&Json> {
+        match self {
+            Json::Array(items) => items.get(index),
+            _ => None,
+        }
+    }
+}
+
+// compact, no whitespace between tokens
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => f.write_str(n),
+            Json::String(s) => f.write_str(&json_string(s)),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    f.write_str(&json_string(key))?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &'static str) -> JsonError {
+        JsonError { offset: self.at, reason }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.text.get(self.at), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.text.get(self.at) == Some(&byte) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        if self.text[self.at..].starts_with(word.as_bytes()) {
+            self.at += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unknown literal"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deep"));
+        }
+        self.skip_whitespace();
+        match self.text.get(self.at) {
+            None => Err(self.error("unexpected end")),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = vec![];
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error("expected ',' or ']'"));
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.at += 1;
+                let mut members = vec![];
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        if self.text.get(self.at) != Some(&b'"') {
+                            return Err(self.error("expected a key"));
+                        }
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return Err(self.error("expected ':'"));
+                        }
+                        members.push((key, self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error("expected ',' or '}'"));
+                        }
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.at;
+        while matches!(self.text.get(self.at), Some(b'0'..=b'9')) {
+            self.at += 1;
+        }
+        self.at - start
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.at;
+        if self.text[self.at] == b'-' {
+            self.at += 1;
+        }
+        let leading_zero = self.text.get(self.at) == Some(&b'0');
+        match self.digits() {
+            0 => return Err(self.error("expected a digit")),
+            n if n > 1 && leading_zero => return Err(self.error("leading zero")),
+            _ => {}
+        }
+        if self.text.get(self.at) == Some(&b'.') {
+            self.at += 1;
+            if self.digits() == 0 {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        if matches!(self.text.get(self.at), Some(b'e' | b'E')) {
+            self.at += 1;
+            if matches!(self.text.get(self.at), Some(b'+' | b'-')) {
+                self.at += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        // only ascii went in, the slice is valid utf-8
+        Ok(Json::Number(String::from_utf8_lossy(&self.text[start..self.at]).into_owned()))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.text.get(self.at..self.at + 4).ok_or_else(|| self.error("short \\u escape"))?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.error("bad \\u escape"))?;
+        let n = u32::from_str_radix(digits, 16).map_err(|_| self.error("bad \\u escape"))?;
+        self.at += 4;
+        Ok(n)
+    }
+
+    // at the opening quote
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.at += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(&byte) = self.text.get(self.at) else { return Err(self.error("unterminated string")) };
+            self.at += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.text.get(self.at) else { return Err(self.error("unterminated string")) };
+                    self.at += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut n = self.hex4()?;
+                            // a surrogate pair spells one character in two escapes
+                            if (0xd800..0xdc00).contains(&n) && self.text[self.at..].starts_with(b"\\u") {
+                                self.at += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("bad surrogate pair"));
+                                }
+                                n = 0x10000 + ((n - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(n).ok_or_else(|| self.error("bad \\u escape"))?
+                        }
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0..=0x1f => return Err(self.error("control character in string")),
+                byte => out.push(byte),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid utf-8"))
+    }
+}
+
+// one step of a JsonPath
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathStep {
+    Field(String), // .name or ["name"]
+    Index(usize),  // [0]
+}
+
+// where a fragment sits in a document, written like $.a.b[0] or $["a key"][2]. $ alone is the whole document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    pub steps: Vec<PathStep>,
+}
+
+impl JsonPath {
+    // the fragment the path points at, None when a step finds nothing
+    pub fn select<'a>(&self, document: &'a Json) -> Option<&'a Json> {
+        self.steps.iter().try_fold(document, |json, step| match step {
+            PathStep::Field(name) => json.get(name),
+            PathStep::Index(index) => json.index(*index),
+        })
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("$")?;
+        for step in &self.steps {
+            match step {
+                PathStep::Field(name) if !name.is_empty() && !name.contains(['.', '[', '"', '\\']) => write!(f, ".{name}")?,
+                PathStep::Field(name) => {
+                    f.write_str("[")?;
+                    f.write_str(&json_string(name))?;
+                    f.write_str("]")?;
+                }
+                PathStep::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(mut rest) = s.strip_prefix('$') else { return Err(format!("path '{s}' doesn't start with '$'")) };
+        let mut steps = vec![];
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(format!("empty field name in path '{s}'"));
+                }
+                steps.push(PathStep::Field(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = if after.starts_with('"') {
+                    // a quoted name ends at the first '"]' that isn't escaped
+                    let mut parser = Parser { text: after.as_bytes(), at: 0 };
+                    let name = parser.string().map_err(|err| format!("bad field name in path '{s}': {err}"))?;
+                    steps.push(PathStep::Field(name));
+                    parser.at
+                } else {
+                    let end = after.find(']').ok_or_else(|| format!("unclosed '[' in path '{s}'"))?;
+                    let index = after[..end].parse().map_err(|_| format!("bad index '{}' in path '{s}'", &after[..end]))?;
+                    steps.push(PathStep::Index(index));
+                    end
+                };
+                rest = after[end..].strip_prefix(']').ok_or_else(|| format!("unclosed '[' in path '{s}'"))?;
+            } else {
+                return Err(format!("expected '.' or '[' in path '{s}'"));
+            }
+        }
+        Ok(JsonPath { steps })
+    }
+}
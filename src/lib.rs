@@ -1,15 +1,175 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, mpsc::{self, Sender, Receiver}};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, mpsc::{self, Sender, Receiver}};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::atomic::AtomicBool;
 
-pub const MAX_CONCURRENT_TASKS: usize = 4;
+use chaos::Chaos;
+use chunk::ChunkAssembler;
+use failure::FailureRunner;
+use replica::ReplicaPublisher;
+use select::{Doorbell, Selected};
+use shutdown::ShutdownSignal;
+
+pub mod access;
+pub mod admin;
+pub mod autoscale;
+pub mod backpressure;
+pub mod builder;
+pub mod chaos;
+pub mod checkpoint;
+pub mod chunk;
+pub mod circuit;
+pub mod clock;
+pub mod coalesce;
+pub mod config;
+pub mod config_file;
+pub mod context;
+pub mod deadline;
+pub mod error_code;
+pub mod event_bus;
+pub mod expect;
+pub mod export;
+pub mod failure;
+pub mod group;
+pub mod fault;
+pub mod handler;
+pub mod heartbeat;
+pub mod health;
+pub mod hedge;
+pub mod history;
+pub mod hooks;
+pub mod hypervisor;
+pub mod interceptor;
+pub mod intake;
+pub mod json;
+pub mod lifecycle;
+pub mod limits;
+pub mod loadgen;
+pub mod negative_cache;
+pub mod pattern;
+pub mod preemption;
+pub mod presence;
+pub mod profiler;
+pub mod qos;
+pub mod query_cache;
+pub mod queue_wait;
+pub mod quota;
+pub mod rate_limit;
+pub mod replay;
+pub mod replica;
+pub mod result_filter;
+pub mod result_log;
+pub mod results;
+pub mod schema;
+pub mod script;
+pub mod select;
+pub mod shedding;
+pub mod shutdown;
+#[cfg(feature = "signals")]
+pub mod signals;
+pub mod sim_run;
+pub mod sink;
+pub mod slow_request;
+pub mod stats;
+pub mod store;
+pub mod supervisor;
+pub mod task_state;
+pub mod task_stats;
+pub mod template;
+pub mod tenant;
+pub mod timeouts;
+pub mod trace;
+pub mod value;
+pub mod watchdog;
+pub mod wfq;
+pub mod worker_summary;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use access::KeyAccess;
+pub use admin::{AdminCommand, AdminSender, WorkerStats};
+pub use autoscale::{AutoscaleConfig, Autoscaler};
+pub use backpressure::{ChannelStats, CountingReceiver, CountingSender, HighWaterCallback, CHANNEL_BACKEND};
+pub use builder::{TaskBuildError, TaskBuilder, TaskSpec};
+pub use chaos::{ChaosConfig, ChaosTarget};
+pub use checkpoint::{Checkpoint, CheckpointChain, CheckpointError, CheckpointSummary, TaskDelta, TaskSnapshot, UpdateRegistry};
+pub use circuit::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+pub use clock::{Clock, SimClock, SystemClock};
+pub use coalesce::QueryCoalescer;
+pub use config::ServerConfig;
+pub use config_file::{ConfigError, CONFIG_KEYS, ENV_PREFIX};
+pub use context::ServerContext;
+pub use deadline::{DeadlineStage, DeadlineTable};
+pub use error_code::ErrorCode;
+pub use event_bus::{EventBus, ServerEvent, ALL_TOPICS};
+pub use expect::ExpectError;
+pub use export::ExportFormat;
+pub use failure::{FailureAction, FailureSchedule, ScheduledFailure};
+pub use fault::{Fault, FaultChannel, FaultConfig, FaultInjector, FaultRates, FaultStats};
+pub use group::GroupTable;
+pub use handler::{Instruction, TaskHandler};
+pub use heartbeat::{HeartbeatConfig, HeartbeatMonitor, Liveness};
+pub use health::{HealthReport, Readiness, WorkerStatus};
+pub use hedge::{HedgeStats, Hedges};
+pub use history::TaskHistory;
+pub use hypervisor::{Hypervisor, HypervisorOutcome, LoadError, SCRIPT_EXTENSION};
+pub use intake::{IntakeGauge, WouldBlock};
+pub use interceptor::{InterceptorChain, RequestInterceptor, Verdict};
+pub use json::{Json, JsonError, JsonPath, PathStep};
+pub use lifecycle::{LifecycleTable, RequestLifecycle, RequestState, ResultEnvelope, ResultMeta, StuckRequest};
+pub use limits::{Oversize, TaskLimits};
+pub use loadgen::{
+    Arrival, LoadGenerator, LoadProfile, LoadReport, TaskLifetime, LOADGEN_QUERY, LOADGEN_TEMPLATE, LOADGEN_UPDATE,
+};
+pub use negative_cache::{NegativeCache, NegativeCacheConfig};
+pub use pattern::KeyPattern;
+pub use preemption::{Preemption, Priority};
+pub use presence::TaskPresence;
+pub use profiler::{Activity, Component, Probe};
+#[cfg(feature = "profiler")]
+pub use profiler::{ProfileReport, Profiler, ProfilerConfig};
+pub use qos::{QosClass, QosQueues};
+pub use query_cache::QueryCache;
+pub use queue_wait::{QueueHop, QueueWaits, WaitDistribution};
+pub use quota::{Meter, Quota, QuotaResource, Usage};
+pub use rate_limit::{RateLimit, RateLimiter};
+pub use result_filter::{ResultFilter, ResultSubscribers};
+pub use result_log::JsonlSink;
+pub use results::ResultStore;
+pub use replay::{RecordedEntry, RecordedRequest, Recorder, Recording, ReplayError, Replayer, UpdateFactory};
+pub use replica::{Replica, ReplicaStore};
+pub use schema::{Schema, SchemaViolation, ValueType};
+pub use script::{ArgError, ArgType, RuntimeError, Script, ScriptError, ScriptErrorKind, MAX_LOOP_ITERATIONS};
+pub use shedding::{LoadShedding, ShedPolicy};
+pub use shutdown::ShutdownHooks;
+pub use sim_run::{SimRun, SimRunReport, DEFAULT_SETTLE};
+pub use sink::{ResultSink, SinkChain};
+pub use slow_request::{SlowRequest, SlowRequestConfig, SlowRequests};
+pub use stats::{ResultCounts, ServerStats};
+pub use store::KvStore;
+pub use task_state::{update_fn, TaskMemory, TaskState};
+pub use task_stats::TaskStats;
+pub use template::{TaskTemplate, TemplateError};
+pub use tenant::{TenantId, TenantStats, TenantTable};
+pub use timeouts::Timeouts;
+pub use trace::{Hop, Span, Tracer};
+pub use value::{base64_decode, base64_encode, Encoding, Value};
+pub use watchdog::Watchdog;
+pub use wfq::FairQueue;
+pub use worker_summary::WorkerSummary;
+#[cfg(feature = "wasm")]
+pub use wasm::{WasmModule, DEFAULT_WASM_FUEL};
+
+pub const MAX_CONCURRENT_TASKS: usize = 4; // starting concurrency cap, see ServerThread::set_max_concurrent_tasks
 pub const MAX_REQ_ID: usize = 100; // maximum number of request ids that can be generated
 
 // assumption 1: TASK_TIMEOUT is larger than how long any task would take to execute a request
 // assumption 2: LISTENER_TIMEOUT > TASK_TIMEOUT
+// these are the defaults, ServerConfig::timeouts changes them and ServerConfig::validate checks both assumptions.
+// Timeouts::derived scales all three from one base duration
 // because after getting a ReceivedRequest, the theoretical upper bound for execution time is TASK_TIMEOUT (from assumption 1)
 // so listener will listen for a minimum of TASK_TIMEOUT so that we don't lose the output of that task by closing
 // the listener thread too hastily
@@ -18,29 +178,316 @@ pub const MAX_REQ_ID: usize = 100; // maximum number of request ids that can be
 pub const TASK_TIMEOUT: u64 = 2;
 pub const LISTENER_TIMEOUT: u64 = 5;
 pub const WORKER_TIMEOUT: u64 = 5;
+// how long health() waits for the worker to answer a ping
+pub const HEALTH_TIMEOUT: Duration = Duration::from_secs(1);
+// how long dropping a ServerThread waits for its listeners and worker to finish, real time
+pub const DROP_TIMEOUT: Duration = Duration::from_secs(2);
+// default upper bound on a single update function run, see ServerConfig::update_timeout
+// has to stay below TASK_TIMEOUT so assumption 1 holds even for a misbehaving update
+pub const UPDATE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// what an update function returns. Ok is answered with UpdateOk, Err with UpdateError
+pub type UpdateOutcome = Result<String, String>;
+// an update function, run against the task's TaskState. see update_fn for building one from a closure
+pub type UpdateFn = Box<dyn FnMut(&mut TaskState) -> UpdateOutcome + Send + 'static>;
 
 type TaskId = usize;
 type RequestId = usize;
-type SharedResults = Arc<Mutex<Vec<Option<TaskResult>>>>;
+type ClientId = usize;
+type SharedDeadLetters = Arc<Mutex<Vec<DeadLetter>>>;
+type TaskSenders = Arc<Mutex<HashMap<TaskId, CountingSender<TaskInstruction>>>>;
+type Outbox = Vec<(TaskId, CountingSender<TaskInstruction>, TaskInstruction)>;
 
 pub struct Task {
     pub id: usize,
-    pub query_map: HashMap<String, String>,
-    pub update_map: HashMap<String, Box<dyn FnMut() -> String + Send + 'static>>
+    pub query_map: Box<dyn KvStore>,
+    // binary values next to query_map's text ones, see ServerThread::query_bytes. not part of checkpoints or recordings
+    pub blobs: HashMap<String, Vec<u8>>,
+    pub update_map: HashMap<String, UpdateFn>,
+    // checked by set_value and by updates named after a schema key. not part of checkpoints
+    pub schema: Schema,
+    // counters and extensions the update functions keep between runs, see TaskState
+    pub memory: TaskMemory,
+    // the server's, see ServerConfig::context. handed to every update through its TaskState
+    pub context: Option<ServerContext>,
+    // updates that exceeded the update timeout. their closures are stuck on a helper thread and can't be run again
+    pub timed_out_updates: HashSet<String>,
+    // resource accounting for tasks created with one, every query and update is checked against its quota
+    pub meter: Option<Meter>,
+    // answers queries and updates in place of query_map and update_map, see TaskHandler
+    pub handler: Option<Box<dyn TaskHandler>>,
+    // starts at 0 and goes up by one with every successful update, see TaskInstruction::UpdateIfVersion
+    pub version: u64,
+    // exits after this long without an instruction, in place of the server's task timeout, see TaskBuilder::idle_timeout
+    pub idle_timeout: Option<Duration>,
+    // per-key usage of query_map, only kept while ServerConfig::access_metadata is on
+    pub access: HashMap<String, KeyAccess>,
+    // the task group it counts against, see ServerConfig::group_caps
+    pub group: Option<String>,
+    // which tasks it may preempt and be preempted by, see ServerConfig::preemption
+    pub priority: Priority,
+}
+
+impl Task {
+    // a task is degraded once any of its update functions has timed out
+    pub fn is_degraded(&self) -> bool {
+        !self.timed_out_updates.is_empty()
+    }
+}
+
+// what a created task gets besides its maps, see TaskBuilder
+#[derive(Debug, Clone, Default)]
+struct TaskSettings {
+    blobs: HashMap<String, Vec<u8>>,
+    schema: Schema,
+    idle_timeout: Option<Duration>,
+    group: Option<String>,
+    priority: Priority,
 }
 
 // to be returned when a TaskRequest is sent
-// ReceivedRequest is sent whenever a Task receives a new TaskRequest
+// ReceivedRequest{req_id, id} is sent whenever a Task receives a new TaskRequest, the listener notes it in the lifecycle table
 // this will later be followed by another TaskResult that shows the appropriate response for that TaskRequest
 #[derive(Debug, PartialEq, Clone)]
 pub enum TaskResult {
-    QueryOk { req_id: RequestId, id: TaskId, value: String },
-    QueryError { req_id: RequestId, id: TaskId, msg: String },
+    Created { req_id: RequestId, id: TaskId },
+    // access is the key's KeyAccess when ServerConfig::access_metadata is on, None otherwise
+    QueryOk { req_id: RequestId, id: TaskId, value: String, access: Option<KeyAccess> },
+    QueryError { req_id: RequestId, id: TaskId, code: ErrorCode, detail: Option<String> },
+    // answer to query_bytes, value is Bytes or base64 Text as the query asked for
+    BytesOk { req_id: RequestId, id: TaskId, value: Value },
+    // answer to a multi-key query. missing lists the requested keys the task has no value for, in request order
+    // a pattern query answers with every matching entry and nothing missing
+    QueryManyOk { req_id: RequestId, id: TaskId, values: HashMap<String, String>, missing: Vec<String> },
     UpdateOk { req_id: RequestId, id: TaskId, value: String },
-    UpdateError { req_id: RequestId, id: TaskId, msg: String },
+    UpdateError { req_id: RequestId, id: TaskId, code: ErrorCode, detail: Option<String> },
+    UpdateTimedOut { req_id: RequestId, id: TaskId },
     NotFound { req_id: RequestId, id: TaskId, ctx: &'static str },
-    Throttled { req_id: RequestId, id: TaskId },
-    ReceivedRequest
+    // group is set when the create was held to its task group's cap rather than a server-wide or tenant cap
+    Throttled { req_id: RequestId, id: TaskId, group: Option<String> },
+    Published { req_id: RequestId, id: TaskId, topic: String, delivered: usize },
+    Subscribed { req_id: RequestId, id: TaskId, topic: String },
+    Undeliverable { req_id: RequestId, id: TaskId },
+    RateLimited { req_id: RequestId, id: TaskId, client: ClientId },
+    ShuttingDown { req_id: RequestId, id: TaskId },
+    // the hypervisor could not turn the task's script into a task, nothing was sent to the worker
+    InvalidScript { req_id: RequestId, id: TaskId, error: ScriptError },
+    // a metered query or update went over its quota, an update's value is discarded.
+    // terminated says whether the task exited because of it
+    QuotaExceeded { req_id: RequestId, id: TaskId, resource: QuotaResource, terminated: bool },
+    // the task's instruction queue was full or it had as many instructions in flight as it takes,
+    // see ServerConfig::task_queue_capacity and max_in_flight_per_task. nothing was sent to it
+    Busy { req_id: RequestId, id: TaskId },
+    // a compare-and-swap update found the task at another version than expected and did not run
+    VersionConflict { req_id: RequestId, id: TaskId, expected: u64, actual: u64 },
+    ReceivedRequest { req_id: RequestId, id: TaskId },
+    // the task had expired from inactivity and was brought back to take the request, see ServerConfig::respawn_expired.
+    // sent ahead of the task's answer and noted in the request's ResultMeta
+    Respawned { req_id: RequestId, id: TaskId },
+    // the task went over ServerConfig::task_limits and was never started
+    RejectedTooLarge { req_id: RequestId, id: TaskId, reason: Oversize },
+    // a RequestInterceptor turned the request away before it was sent, see Verdict::Reject
+    Rejected { req_id: RequestId, id: TaskId, reason: String },
+    // the circuit breaker was open, the request was failed without being sent, see ServerConfig::circuit_breaker
+    CircuitOpen { req_id: RequestId, id: TaskId },
+    // the worker was overloaded and dropped the request instead of queueing it, see ServerConfig::load_shedding
+    Shed { req_id: RequestId, id: TaskId },
+    // the request's deadline passed before it was done and stage gave up on it, see RequestOptions::deadline
+    TimedOut { req_id: RequestId, id: TaskId, stage: DeadlineStage },
+    // task id was stopped to make room for the more important task by, created by req_id, see ServerConfig::preemption.
+    // it goes to the sinks ahead of the create's own answer, but not to the results store, see task_history
+    Preempted { req_id: RequestId, id: TaskId, by: TaskId, paused: bool },
+    // the answers to several requests in one message, e.g. to the instructions of a TaskInstruction::Batch.
+    // req_ids are those of results, in the same order. the listener records every result under its own req_id,
+    // nothing else ever sees the batch itself
+    Batch { req_ids: Vec<RequestId>, results: Vec<TaskResult> },
+    // one piece of a query value longer than ServerConfig::query_chunk_size, seq counting from 0. the listener puts
+    // the pieces back together and records a QueryOk once the last one is in, nothing else ever sees a chunk
+    QueryChunk { req_id: RequestId, id: TaskId, seq: usize, last: bool, bytes: Vec<u8> },
+}
+
+impl TaskResult {
+    // the request this result answers. ReceivedRequest and Respawned only say something about the request and answer nothing
+    pub fn req_id(&self) -> Option<RequestId> {
+        match self {
+            TaskResult::Created { req_id, .. }
+            | TaskResult::QueryOk { req_id, .. }
+            | TaskResult::QueryError { req_id, .. }
+            | TaskResult::BytesOk { req_id, .. }
+            | TaskResult::QueryManyOk { req_id, .. }
+            | TaskResult::UpdateOk { req_id, .. }
+            | TaskResult::UpdateError { req_id, .. }
+            | TaskResult::UpdateTimedOut { req_id, .. }
+            | TaskResult::NotFound { req_id, .. }
+            | TaskResult::Throttled { req_id, .. }
+            | TaskResult::Published { req_id, .. }
+            | TaskResult::Subscribed { req_id, .. }
+            | TaskResult::Undeliverable { req_id, .. }
+            | TaskResult::RateLimited { req_id, .. }
+            | TaskResult::ShuttingDown { req_id, .. }
+            | TaskResult::InvalidScript { req_id, .. }
+            | TaskResult::QuotaExceeded { req_id, .. }
+            | TaskResult::Busy { req_id, .. }
+            | TaskResult::VersionConflict { req_id, .. }
+            | TaskResult::RejectedTooLarge { req_id, .. }
+            | TaskResult::Rejected { req_id, .. }
+            | TaskResult::CircuitOpen { req_id, .. }
+            | TaskResult::Shed { req_id, .. }
+            | TaskResult::TimedOut { req_id, .. }
+            | TaskResult::Preempted { req_id, .. } => Some(*req_id),
+            TaskResult::ReceivedRequest { .. }
+            | TaskResult::Respawned { .. }
+            | TaskResult::Batch { .. }
+            | TaskResult::QueryChunk { .. } => None,
+        }
+    }
+
+    // the same result answering to, e.g. for a query that was coalesced with another. results that answer nothing
+    // themselves, see req_id, come back as they were
+    pub fn readdressed(mut self, to: RequestId) -> TaskResult {
+        match &mut self {
+            TaskResult::Created { req_id, .. }
+            | TaskResult::QueryOk { req_id, .. }
+            | TaskResult::QueryError { req_id, .. }
+            | TaskResult::BytesOk { req_id, .. }
+            | TaskResult::QueryManyOk { req_id, .. }
+            | TaskResult::UpdateOk { req_id, .. }
+            | TaskResult::UpdateError { req_id, .. }
+            | TaskResult::UpdateTimedOut { req_id, .. }
+            | TaskResult::NotFound { req_id, .. }
+            | TaskResult::Throttled { req_id, .. }
+            | TaskResult::Published { req_id, .. }
+            | TaskResult::Subscribed { req_id, .. }
+            | TaskResult::Undeliverable { req_id, .. }
+            | TaskResult::RateLimited { req_id, .. }
+            | TaskResult::ShuttingDown { req_id, .. }
+            | TaskResult::InvalidScript { req_id, .. }
+            | TaskResult::QuotaExceeded { req_id, .. }
+            | TaskResult::Busy { req_id, .. }
+            | TaskResult::VersionConflict { req_id, .. }
+            | TaskResult::RejectedTooLarge { req_id, .. }
+            | TaskResult::Rejected { req_id, .. }
+            | TaskResult::CircuitOpen { req_id, .. }
+            | TaskResult::Shed { req_id, .. }
+            | TaskResult::TimedOut { req_id, .. }
+            | TaskResult::Preempted { req_id, .. } => *req_id = to,
+            TaskResult::ReceivedRequest { .. }
+            | TaskResult::Respawned { .. }
+            | TaskResult::Batch { .. }
+            | TaskResult::QueryChunk { .. } => {}
+        }
+        self
+    }
+
+    // what kind of failure the result reports, None for results that aren't failures or that ErrorCode doesn't cover
+    // (rate limiting, shutdown, quotas, invalid scripts, version conflicts)
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            TaskResult::QueryError { code, .. } | TaskResult::UpdateError { code, .. } => Some(*code),
+            TaskResult::UpdateTimedOut { .. } | TaskResult::TimedOut { .. } => Some(ErrorCode::TimedOut),
+            TaskResult::NotFound { .. } | TaskResult::Undeliverable { .. } => Some(ErrorCode::TaskGone),
+            TaskResult::Throttled { .. } => Some(ErrorCode::Throttled),
+            TaskResult::Busy { .. } => Some(ErrorCode::Busy),
+            _ => None,
+        }
+    }
+
+    // the task the result is about. a batch is about the task of its first result, 0 when it is empty
+    pub fn id(&self) -> TaskId {
+        match self {
+            TaskResult::Created { id, .. }
+            | TaskResult::QueryOk { id, .. }
+            | TaskResult::QueryError { id, .. }
+            | TaskResult::BytesOk { id, .. }
+            | TaskResult::QueryManyOk { id, .. }
+            | TaskResult::UpdateOk { id, .. }
+            | TaskResult::UpdateError { id, .. }
+            | TaskResult::UpdateTimedOut { id, .. }
+            | TaskResult::NotFound { id, .. }
+            | TaskResult::Throttled { id, .. }
+            | TaskResult::Published { id, .. }
+            | TaskResult::Subscribed { id, .. }
+            | TaskResult::Undeliverable { id, .. }
+            | TaskResult::RateLimited { id, .. }
+            | TaskResult::ShuttingDown { id, .. }
+            | TaskResult::InvalidScript { id, .. }
+            | TaskResult::QuotaExceeded { id, .. }
+            | TaskResult::Busy { id, .. }
+            | TaskResult::VersionConflict { id, .. }
+            | TaskResult::ReceivedRequest { id, .. }
+            | TaskResult::Respawned { id, .. }
+            | TaskResult::RejectedTooLarge { id, .. }
+            | TaskResult::Rejected { id, .. }
+            | TaskResult::CircuitOpen { id, .. }
+            | TaskResult::Shed { id, .. }
+            | TaskResult::TimedOut { id, .. }
+            | TaskResult::Preempted { id, .. }
+            | TaskResult::QueryChunk { id, .. } => *id,
+            TaskResult::Batch { results, .. } => results.first().map_or(0, TaskResult::id),
+        }
+    }
+
+    // the variant's name in snake_case, e.g. "update_ok", for filtering and reporting
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TaskResult::Created { .. } => "created",
+            TaskResult::QueryOk { .. } => "query_ok",
+            TaskResult::QueryError { .. } => "query_error",
+            TaskResult::BytesOk { .. } => "bytes_ok",
+            TaskResult::QueryManyOk { .. } => "query_many_ok",
+            TaskResult::UpdateOk { .. } => "update_ok",
+            TaskResult::UpdateError { .. } => "update_error",
+            TaskResult::UpdateTimedOut { .. } => "update_timed_out",
+            TaskResult::NotFound { .. } => "not_found",
+            TaskResult::Throttled { .. } => "throttled",
+            TaskResult::Published { .. } => "published",
+            TaskResult::Subscribed { .. } => "subscribed",
+            TaskResult::Undeliverable { .. } => "undeliverable",
+            TaskResult::RateLimited { .. } => "rate_limited",
+            TaskResult::ShuttingDown { .. } => "shutting_down",
+            TaskResult::InvalidScript { .. } => "invalid_script",
+            TaskResult::QuotaExceeded { .. } => "quota_exceeded",
+            TaskResult::Busy { .. } => "busy",
+            TaskResult::VersionConflict { .. } => "version_conflict",
+            TaskResult::ReceivedRequest { .. } => "received_request",
+            TaskResult::Respawned { .. } => "respawned",
+            TaskResult::Preempted { .. } => "preempted",
+            TaskResult::RejectedTooLarge { .. } => "rejected_too_large",
+            TaskResult::Rejected { .. } => "rejected",
+            TaskResult::CircuitOpen { .. } => "circuit_open",
+            TaskResult::Shed { .. } => "shed",
+            TaskResult::TimedOut { .. } => "timed_out",
+            TaskResult::Batch { .. } => "batch",
+            TaskResult::QueryChunk { .. } => "query_chunk",
+        }
+    }
+}
+
+// per-request metadata supplied by the caller of ServerThread
+// travels to the worker alongside the request inside an Envelope
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestOptions {
+    pub client: ClientId, // who is sending the request, used for rate limiting. defaults to client 0
+    pub qos: QosClass,    // which worker queue the request goes into. defaults to Interactive
+    pub tenant: TenantId, // whose tasks the request may reach, tasks are created under it. defaults to tenant 0
+    // clock time by which the request has to be done, e.g. s.clock.now() + budget. the worker, the task and an update
+    // each check it before going on and answer with TaskResult::TimedOut once it has passed. None waits as long as it takes
+    pub deadline: Option<Duration>,
+    // a query may be answered by the worker from its task's replica, up to ServerConfig::replica_interval behind the
+    // task, instead of by the task. ignored by everything but plain queries and without replicas
+    pub stale_ok: bool,
+}
+
+// what actually travels over the server-worker channel
+pub struct Envelope {
+    pub opts: RequestOptions,
+    pub request: TaskRequest,
+}
+
+impl Envelope {
+    fn try_clone(&self) -> Option<Envelope> {
+        Some(Envelope { opts: self.opts.clone(), request: self.request.try_clone()? })
+    }
 }
 
 // task requests
@@ -48,8 +495,23 @@ pub enum TaskRequest {
     CreateTask {
         req_id: RequestId,
         id: TaskId,
-        query_map: HashMap<String, String>,
-        update_map: HashMap<String, Box<dyn FnMut() -> String + Send + 'static>>,
+        query_map: Box<dyn KvStore>,
+        blobs: HashMap<String, Vec<u8>>,
+        update_map: HashMap<String, UpdateFn>,
+        schema: Schema,
+        meter: Option<Meter>,
+        version: u64, // what the task's version starts at, 0 unless it is resumed from a checkpoint
+        idle_timeout: Option<Duration>,
+        group: Option<String>,
+        priority: Priority,
+        result_tx: Sender<TaskResult>,
+    },
+    // a task whose queries and updates are answered by handler
+    CreateHandlerTask {
+        req_id: RequestId,
+        id: TaskId,
+        handler: Box<dyn TaskHandler>,
+        meter: Option<Meter>,
         result_tx: Sender<TaskResult>,
     },
     QueryTask {
@@ -58,90 +520,940 @@ pub enum TaskRequest {
         query_id: String,
         result_tx: Sender<TaskResult>,
     },
+    // fetches a value as bytes or base64, whether the task holds it as text or as bytes
+    QueryBytesTask {
+        req_id: RequestId,
+        id: TaskId,
+        key: String,
+        encoding: Encoding,
+        result_tx: Sender<TaskResult>,
+    },
+    // fetches several keys of a task's query_map in one round-trip
+    QueryManyTask {
+        req_id: RequestId,
+        id: TaskId,
+        keys: Vec<String>,
+        result_tx: Sender<TaskResult>,
+    },
+    // fetches every entry of a task's query_map whose key matches the pattern
+    QueryMatchingTask {
+        req_id: RequestId,
+        id: TaskId,
+        pattern: KeyPattern,
+        result_tx: Sender<TaskResult>,
+    },
+    // the fragment at path of the JSON document stored under key
+    QueryPathTask {
+        req_id: RequestId,
+        id: TaskId,
+        key: String,
+        path: JsonPath,
+        result_tx: Sender<TaskResult>,
+    },
     UpdateTask {
         req_id: RequestId,
         id: TaskId,
         update_id: String,
         result_tx: Sender<TaskResult>,
     },
+    // stores value under key, answered like an update
+    SetTask {
+        req_id: RequestId,
+        id: TaskId,
+        key: String,
+        value: String,
+        result_tx: Sender<TaskResult>,
+    },
+    // runs the update only if the task is still at expected_version
+    UpdateIfVersionTask {
+        req_id: RequestId,
+        id: TaskId,
+        update_id: String,
+        expected_version: u64,
+        result_tx: Sender<TaskResult>,
+    },
+    // asks a task to publish a named event on the EventBus
+    PublishTask {
+        req_id: RequestId,
+        id: TaskId,
+        topic: String,
+        payload: String,
+        result_tx: Sender<TaskResult>,
+    },
+    // subscribes a task to a topic on the EventBus
+    // events on that topic are delivered to the task and stored in its query_map under "event/<topic>"
+    SubscribeTask {
+        req_id: RequestId,
+        id: TaskId,
+        topic: String,
+        result_tx: Sender<TaskResult>,
+    },
+    // liveness probe. answered by the worker as soon as it is pulled off the channel, it never waits in a QoS queue
+    // not a request in the req_id sense, so it does not consume one
+    Ping {
+        reply_tx: Sender<WorkerStatus>,
+    },
+    // tells the worker to stop. like Ping it skips the QoS queues, see ShutdownMode
+    Shutdown {
+        mode: ShutdownMode,
+    },
+    // ends the worker loop on the spot, without stopping its tasks or answering anything queued
+    // only sent by the chaos thread, see ChaosConfig
+    Kill,
+}
+
+// what ends a server, see ServerConfig::run_mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunMode {
+    // the listener shuts everything down once no result has come in for the listener timeout, or on shutdown_with.
+    // a simulation ends by itself once its work is done
+    #[default]
+    Simulation,
+    // the listener and the worker keep running however long it is quiet, until shutdown_with is called.
+    // join_listener doesn't return before that. tasks still expire from inactivity
+    Persistent,
+}
+
+impl std::str::FromStr for RunMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simulation" => Ok(RunMode::Simulation),
+            "persistent" => Ok(RunMode::Persistent),
+            other => Err(format!("unknown run mode '{other}'")),
+        }
+    }
+}
+
+// how ServerThread::shutdown_with winds things down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownMode {
+    // stop accepting new requests, but everything already queued in the worker or a task is still processed
+    // and answered before the threads exit
+    Drain,
+    // stop accepting and stop processing. requests queued in the worker are answered with ShuttingDown,
+    // instructions queued in a task are dropped. an instruction a task is already executing still finishes
+    Immediate,
+}
+
+impl TaskRequest {
+    // the variant's name in snake_case, e.g. "query_task", see WorkerSummary::handled
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TaskRequest::CreateTask { .. } => "create_task",
+            TaskRequest::CreateHandlerTask { .. } => "create_handler_task",
+            TaskRequest::QueryTask { .. } => "query_task",
+            TaskRequest::QueryBytesTask { .. } => "query_bytes_task",
+            TaskRequest::QueryManyTask { .. } => "query_many_task",
+            TaskRequest::QueryMatchingTask { .. } => "query_matching_task",
+            TaskRequest::QueryPathTask { .. } => "query_path_task",
+            TaskRequest::UpdateTask { .. } => "update_task",
+            TaskRequest::SetTask { .. } => "set_task",
+            TaskRequest::UpdateIfVersionTask { .. } => "update_if_version_task",
+            TaskRequest::PublishTask { .. } => "publish_task",
+            TaskRequest::SubscribeTask { .. } => "subscribe_task",
+            TaskRequest::Ping { .. } => "ping",
+            TaskRequest::Shutdown { .. } => "shutdown",
+            TaskRequest::Kill => "kill",
+        }
+    }
+
+    pub fn req_id(&self) -> Option<RequestId> {
+        match self {
+            TaskRequest::CreateTask { req_id, .. }
+            | TaskRequest::CreateHandlerTask { req_id, .. }
+            | TaskRequest::QueryTask { req_id, .. }
+            | TaskRequest::QueryBytesTask { req_id, .. }
+            | TaskRequest::QueryManyTask { req_id, .. }
+            | TaskRequest::QueryMatchingTask { req_id, .. }
+            | TaskRequest::QueryPathTask { req_id, .. }
+            | TaskRequest::UpdateTask { req_id, .. }
+            | TaskRequest::SetTask { req_id, .. }
+            | TaskRequest::UpdateIfVersionTask { req_id, .. }
+            | TaskRequest::PublishTask { req_id, .. }
+            | TaskRequest::SubscribeTask { req_id, .. } => Some(*req_id),
+            TaskRequest::Ping { .. } | TaskRequest::Shutdown { .. } | TaskRequest::Kill => None,
+        }
+    }
+
+    // every request except the creates can be copied, update functions and handlers can't be cloned
+    pub fn try_clone(&self) -> Option<TaskRequest> {
+        Some(match self {
+            TaskRequest::CreateTask { .. } | TaskRequest::CreateHandlerTask { .. } => return None,
+            TaskRequest::QueryTask { req_id, id, query_id, result_tx } => TaskRequest::QueryTask {
+                req_id: *req_id,
+                id: *id,
+                query_id: query_id.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::QueryBytesTask { req_id, id, key, encoding, result_tx } => TaskRequest::QueryBytesTask {
+                req_id: *req_id,
+                id: *id,
+                key: key.clone(),
+                encoding: *encoding,
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::QueryManyTask { req_id, id, keys, result_tx } => TaskRequest::QueryManyTask {
+                req_id: *req_id,
+                id: *id,
+                keys: keys.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::QueryMatchingTask { req_id, id, pattern, result_tx } => TaskRequest::QueryMatchingTask {
+                req_id: *req_id,
+                id: *id,
+                pattern: pattern.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::QueryPathTask { req_id, id, key, path, result_tx } => TaskRequest::QueryPathTask {
+                req_id: *req_id,
+                id: *id,
+                key: key.clone(),
+                path: path.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::SetTask { req_id, id, key, value, result_tx } => TaskRequest::SetTask {
+                req_id: *req_id,
+                id: *id,
+                key: key.clone(),
+                value: value.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::UpdateTask { req_id, id, update_id, result_tx } => TaskRequest::UpdateTask {
+                req_id: *req_id,
+                id: *id,
+                update_id: update_id.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::UpdateIfVersionTask { req_id, id, update_id, expected_version, result_tx } => {
+                TaskRequest::UpdateIfVersionTask {
+                    req_id: *req_id,
+                    id: *id,
+                    update_id: update_id.clone(),
+                    expected_version: *expected_version,
+                    result_tx: result_tx.clone(),
+                }
+            }
+            TaskRequest::PublishTask { req_id, id, topic, payload, result_tx } => TaskRequest::PublishTask {
+                req_id: *req_id,
+                id: *id,
+                topic: topic.clone(),
+                payload: payload.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::SubscribeTask { req_id, id, topic, result_tx } => TaskRequest::SubscribeTask {
+                req_id: *req_id,
+                id: *id,
+                topic: topic.clone(),
+                result_tx: result_tx.clone(),
+            },
+            TaskRequest::Ping { reply_tx } => TaskRequest::Ping { reply_tx: reply_tx.clone() },
+            TaskRequest::Shutdown { mode } => TaskRequest::Shutdown { mode: *mode },
+            TaskRequest::Kill => TaskRequest::Kill,
+        })
+    }
+
+    // (req_id, task id, result channel) for requests that expect an answer
+    fn reply_to(&self) -> Option<(RequestId, TaskId, &Sender<TaskResult>)> {
+        match self {
+            TaskRequest::CreateTask { req_id, id, result_tx, .. }
+            | TaskRequest::CreateHandlerTask { req_id, id, result_tx, .. }
+            | TaskRequest::QueryTask { req_id, id, result_tx, .. }
+            | TaskRequest::QueryBytesTask { req_id, id, result_tx, .. }
+            | TaskRequest::QueryManyTask { req_id, id, result_tx, .. }
+            | TaskRequest::QueryMatchingTask { req_id, id, result_tx, .. }
+            | TaskRequest::QueryPathTask { req_id, id, result_tx, .. }
+            | TaskRequest::UpdateTask { req_id, id, result_tx, .. }
+            | TaskRequest::SetTask { req_id, id, result_tx, .. }
+            | TaskRequest::UpdateIfVersionTask { req_id, id, result_tx, .. }
+            | TaskRequest::PublishTask { req_id, id, result_tx, .. }
+            | TaskRequest::SubscribeTask { req_id, id, result_tx, .. } => Some((*req_id, *id, result_tx)),
+            TaskRequest::Ping { .. } | TaskRequest::Shutdown { .. } | TaskRequest::Kill => None,
+        }
+    }
 }
 
 // enum with a similar structure to TaskRequest, but made especially for a specific Task.
 // this is why the id: TaskId attribute is removed
 // think of it as a subset of TaskRequest
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TaskInstruction {
     Query {
         req_id: usize,
         query_id: String,
         result_tx: Sender<TaskResult>,
     },
+    QueryBytes {
+        req_id: usize,
+        key: String,
+        encoding: Encoding,
+        result_tx: Sender<TaskResult>,
+    },
+    QueryMany {
+        req_id: usize,
+        keys: Vec<String>,
+        result_tx: Sender<TaskResult>,
+    },
+    QueryMatching {
+        req_id: usize,
+        pattern: KeyPattern,
+        result_tx: Sender<TaskResult>,
+    },
+    QueryPath {
+        req_id: usize,
+        key: String,
+        path: JsonPath,
+        result_tx: Sender<TaskResult>,
+    },
     Update {
         req_id: usize,
         update_id: String,
         result_tx: Sender<TaskResult>,
     },
+    Set {
+        req_id: usize,
+        key: String,
+        value: String,
+        result_tx: Sender<TaskResult>,
+    },
+    // answered with VersionConflict unless the task's version is expected_version, otherwise the same as Update
+    UpdateIfVersion {
+        req_id: usize,
+        update_id: String,
+        expected_version: u64,
+        result_tx: Sender<TaskResult>,
+    },
+    Publish {
+        req_id: usize,
+        topic: String,
+        payload: String,
+        result_tx: Sender<TaskResult>,
+    },
+    // the worker registers the subscription on the EventBus before forwarding this,
+    // so the task only has to acknowledge it
+    Subscribe {
+        req_id: usize,
+        topic: String,
+        result_tx: Sender<TaskResult>,
+    },
+    // an event the task subscribed to. pushed by the EventBus, not by the worker, so there is no req_id
+    Deliver {
+        event: ServerEvent,
+    },
+    // sent by the worker when it shuts down. the task exits once it gets here, so with ShutdownMode::Drain
+    // everything queued before it is still processed
+    Stop,
+    // sent by the chaos thread. the task exits right away, as if it had crashed
+    Kill,
+    // sent by the worker to make room for a more important task, see ServerConfig::preemption.
+    // done_tx hears back once the task has exited and given up its slot
+    Preempt {
+        pause: bool,
+        done_tx: Sender<()>,
+    },
+    // the task answers with its state on reply_tx, see AdminCommand::Snapshot. tenant is only passed through
+    Snapshot {
+        tenant: TenantId,
+        reply_tx: Sender<TaskSnapshot>,
+    },
+    // the task answers with its counters on reply_tx, see AdminCommand::TaskStatus
+    Status {
+        reply_tx: Sender<TaskStats>,
+    },
+    // several instructions the worker handled in the same turn, see ServerConfig::instruction_batch.
+    // the task runs them in order, ahead of anything queued behind the batch, and answers all of them with one
+    // TaskResult::Batch once it is done
+    Batch {
+        instructions: Vec<TaskInstruction>,
+    },
+}
+
+impl TaskInstruction {
+    pub fn req_id(&self) -> Option<RequestId> {
+        match self {
+            TaskInstruction::Query { req_id, .. }
+            | TaskInstruction::QueryBytes { req_id, .. }
+            | TaskInstruction::QueryMany { req_id, .. }
+            | TaskInstruction::QueryMatching { req_id, .. }
+            | TaskInstruction::QueryPath { req_id, .. }
+            | TaskInstruction::Update { req_id, .. }
+            | TaskInstruction::Set { req_id, .. }
+            | TaskInstruction::UpdateIfVersion { req_id, .. }
+            | TaskInstruction::Publish { req_id, .. }
+            | TaskInstruction::Subscribe { req_id, .. } => Some(*req_id),
+            TaskInstruction::Deliver { .. }
+            | TaskInstruction::Stop
+            | TaskInstruction::Kill
+            | TaskInstruction::Preempt { .. }
+            | TaskInstruction::Snapshot { .. }
+            | TaskInstruction::Status { .. }
+            | TaskInstruction::Batch { .. } => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TaskInstruction::Query { .. } => "query",
+            TaskInstruction::QueryBytes { .. } => "query_bytes",
+            TaskInstruction::QueryMany { .. } => "query_many",
+            TaskInstruction::QueryMatching { .. } => "query_matching",
+            TaskInstruction::QueryPath { .. } => "query_path",
+            TaskInstruction::Update { .. } => "update",
+            TaskInstruction::Set { .. } => "set",
+            TaskInstruction::UpdateIfVersion { .. } => "update_if_version",
+            TaskInstruction::Publish { .. } => "publish",
+            TaskInstruction::Subscribe { .. } => "subscribe",
+            TaskInstruction::Deliver { .. } => "deliver",
+            TaskInstruction::Stop => "stop",
+            TaskInstruction::Kill => "kill",
+            TaskInstruction::Preempt { .. } => "preempt",
+            TaskInstruction::Snapshot { .. } => "snapshot",
+            TaskInstruction::Status { .. } => "status",
+            TaskInstruction::Batch { .. } => "batch",
+        }
+    }
+
+    fn result_tx(&self) -> Option<&Sender<TaskResult>> {
+        match self {
+            TaskInstruction::Query { result_tx, .. }
+            | TaskInstruction::QueryBytes { result_tx, .. }
+            | TaskInstruction::QueryMany { result_tx, .. }
+            | TaskInstruction::QueryMatching { result_tx, .. }
+            | TaskInstruction::QueryPath { result_tx, .. }
+            | TaskInstruction::Update { result_tx, .. }
+            | TaskInstruction::Set { result_tx, .. }
+            | TaskInstruction::UpdateIfVersion { result_tx, .. }
+            | TaskInstruction::Publish { result_tx, .. }
+            | TaskInstruction::Subscribe { result_tx, .. } => Some(result_tx),
+            TaskInstruction::Deliver { .. }
+            | TaskInstruction::Stop
+            | TaskInstruction::Kill
+            | TaskInstruction::Preempt { .. }
+            | TaskInstruction::Snapshot { .. }
+            | TaskInstruction::Status { .. }
+            | TaskInstruction::Batch { .. } => None,
+        }
+    }
+}
+
+// an instruction the worker could not hand to its task
+// this happens when the task thread has already exited but has not yet removed itself from task_map
+#[derive(Debug, PartialEq, Clone)]
+pub struct DeadLetter {
+    pub req_id: RequestId,
+    pub id: TaskId,
+    pub kind: &'static str,
 }
 
 // thread running task
 pub struct TaskThread {
     pub task: Task,
-    pub rx: Receiver<TaskInstruction>,
+    pub rx: CountingReceiver<TaskInstruction>,
+    pub events: EventBus,
+    pub update_timeout: Duration,
+    pub task_timeout: Duration, // exits after this long without an instruction
+    pub abort: Arc<AtomicBool>, // set by the worker on ShutdownMode::Immediate, queued instructions are dropped
+    pub killed: Arc<AtomicBool>, // this task's alone, set by AdminCommand::KillTask, queued instructions are dropped
+    pub clock: Arc<dyn Clock>,
+    pub tracer: Tracer,
+    pub queue_waits: QueueWaits,
+    pub lifecycle: LifecycleTable,
+    pub access_metadata: bool, // keep task.access and send it with every QueryOk
+    pub stats: TaskStats,      // what this run of the task has answered so far
+    pub deadlines: DeadlineTable,
+    pub probe: Probe, // what the task is doing, for the profiler
+    pub preempted: Option<Sender<()>>, // set by TaskInstruction::Preempt, told once the task has given up its slot
+    pub backlog: VecDeque<TaskInstruction>, // what is left of a TaskInstruction::Batch, run before the next receive
+    pub held: Option<Vec<(Sender<TaskResult>, TaskResult)>>, // answers while a batch runs, sent once it is done
+    pub chunk_size: Option<usize>, // query values longer than this are sent in pieces, see ServerConfig::query_chunk_size
+    pub(crate) replica: Option<ReplicaPublisher>, // publishes copies of the values, see ServerConfig::replica_interval
+}
+
+// why a task thread stopped running, see ServerEvent::TaskFinished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskExit {
+    Stopped, // shut down, disconnected or terminated over its quota
+    Expired, // no instruction for the task timeout
+    Killed,  // killed by chaos, it leaves everything behind
+    Preempted, // made room for a more important task and was forgotten
+    Paused,    // made room for a more important task and is kept to be started again, state and all
 }
 
 impl TaskThread {
-    fn run(mut self) {
-        let timeout_duration = Duration::from_secs(TASK_TIMEOUT);
+    fn run(&mut self) -> TaskExit {
+        let timeout_duration = self.task_timeout;
+        let mut exit = TaskExit::Stopped;
+        // clock time the task started waiting, kept while it wakes up only to publish its replica
+        let mut idle_since = None;
         loop {
-            println!("[Task {}] Waiting for instruction...", self.task.id);
-            match self.rx.recv_timeout(timeout_duration) {
+            let received = match self.backlog.pop_front() {
+                Some(msg) => Ok(msg),
+                None => {
+                    self.release_held();
+                    let now = self.clock.now();
+                    self.publish_replica(now);
+                    let idle_left = timeout_duration.saturating_sub(now - *idle_since.get_or_insert(now));
+                    let wait = match self.replica.as_ref().and_then(|replica| replica.due(self.task.version)) {
+                        Some(due) => idle_left.min(due.saturating_sub(now)),
+                        None => idle_left,
+                    };
+                    println!("[Task {}] Waiting for instruction...", self.task.id);
+                    self.probe.enter(Activity::Waiting);
+                    let received = self.rx.recv_timeout(&*self.clock, wait);
+                    self.probe.enter(Activity::Handling);
+                    // woken up to publish, not idle for long enough to expire
+                    if matches!(received, Err(mpsc::RecvTimeoutError::Timeout)) && wait < idle_left {
+                        continue;
+                    }
+                    received
+                }
+            };
+            match received {
                 Ok(msg) => {
+                    idle_since = None;
+                    if self.abort.load(Ordering::Relaxed) {
+                        println!("[Task {}] Worker shut down immediately. Dropping queued instructions.", self.task.id);
+                        break;
+                    }
+                    if self.killed.load(Ordering::Relaxed) {
+                        println!("[Task {}] Killed. Dropping queued instructions. {:?}", self.task.id, self.stats);
+                        return TaskExit::Killed;
+                    }
+                    if let TaskInstruction::Batch { instructions } = msg {
+                        println!("[Task {}] Received a batch of {} instructions", self.task.id, instructions.len());
+                        self.backlog.extend(instructions);
+                        self.held.get_or_insert_with(Vec::new);
+                        continue;
+                    }
                     println!("[Task {}] Received instruction: {:?}", self.task.id, msg);
+                    if let Some(req_id) = msg.req_id() {
+                        self.tracer.mark(req_id, self.task.id, Hop::Received);
+                        self.queue_waits.taken(QueueHop::Task, req_id);
+                    }
+                    let started = self.clock.now();
+                    if let (Some(req_id), Some(result_tx)) = (msg.req_id(), msg.result_tx()) {
+                        if self.deadlines.expired(req_id, started) {
+                            println!("[Task {}] Deadline of req {req_id} passed while it was queued", self.task.id);
+                            let result_tx = result_tx.clone();
+                            let stage = DeadlineStage::Task;
+                            self.reply(&result_tx, started, TaskResult::TimedOut { req_id, id: self.task.id, stage });
+                            continue;
+                        }
+                    }
+                    if let Some(meter) = &self.task.meter {
+                        meter.begin();
+                    }
+                    // a compare-and-swap update runs as a plain one once its version check passes
+                    let msg = match msg {
+                        TaskInstruction::UpdateIfVersion { req_id, update_id, expected_version, result_tx } => {
+                            if expected_version != self.task.version {
+                                let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                                self.reply(&result_tx, started, TaskResult::VersionConflict {
+                                    req_id,
+                                    id: self.task.id,
+                                    expected: expected_version,
+                                    actual: self.task.version,
+                                });
+                                continue;
+                            }
+                            TaskInstruction::Update { req_id, update_id, result_tx }
+                        }
+                        msg => msg,
+                    };
+                    // handler tasks answer queries and updates through their handler instead of the maps
+                    let msg = match self.task.handler.as_mut() {
+                        Some(handler) => match Instruction::split(msg, self.task.id) {
+                            Ok((instruction, result_tx)) => {
+                                let req_id = instruction.req_id();
+                                let update_id = match &instruction {
+                                    Instruction::Update { update_id, .. } => Some(update_id.clone()),
+                                    _ => None,
+                                };
+                                let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                                let result = handler.handle(instruction);
+                                if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                    if terminated {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                                // successful updates are published the same as map updates
+                                if let (Some(update_id), TaskResult::UpdateOk { value, .. }) = (update_id, &result) {
+                                    self.task.version += 1;
+                                    self.events.publish(ServerEvent::Published {
+                                        topic: format!("update/{update_id}"),
+                                        id: self.task.id,
+                                        payload: value.clone(),
+                                    });
+                                }
+                                self.reply(&result_tx, started, result);
+                                continue;
+                            }
+                            Err(msg) => msg,
+                        },
+                        None => msg,
+                    };
                     // receives a TaskInstruction which it processes
                     match msg {
                         // gets value from a query_map for some query_id
                         TaskInstruction::Query { req_id, query_id, result_tx } => {
-                            let _ = result_tx.send(TaskResult::ReceivedRequest);
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
                             // result_tx is shared directly to TaskThread via ServerThread so that it can transmit result
                             // messages directly back to ServerThread
+                            if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                if terminated {
+                                    break;
+                                }
+                                continue;
+                            }
                             match self.task.query_map.get(&query_id) {
                                 Some(value) => {
-                                    let _ = result_tx.send(TaskResult::QueryOk {
+                                    let access = self
+                                        .access_metadata
+                                        .then(|| self.task.access.entry(query_id).or_default().queried(self.clock.now()));
+                                    self.reply(&result_tx, started, TaskResult::QueryOk {
+                                        req_id,
+                                        id: self.task.id,
+                                        value,
+                                        access,
+                                    });
+                                }
+                                // a plain query gets a binary value as base64 text
+                                None if self.task.blobs.contains_key(&query_id) => {
+                                    let value = base64_encode(&self.task.blobs[&query_id]);
+                                    self.reply(&result_tx, started, TaskResult::QueryOk {
                                         req_id,
                                         id: self.task.id,
-                                        value: value.clone(),
+                                        value,
+                                        access: None,
                                     });
                                 }
                                 None => {
-                                    let _ = result_tx.send(TaskResult::QueryError {
+                                    self.reply(&result_tx, started, TaskResult::QueryError {
                                         req_id,
                                         id: self.task.id,
-                                        msg: format!("Query ID '{}' not found", query_id),
+                                        code: ErrorCode::KeyNotFound,
+                                        detail: Some(format!("Query ID '{}' not found", query_id)),
                                     });
                                 }
                             }
                         }
+                        TaskInstruction::QueryBytes { req_id, key, encoding, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                if terminated {
+                                    break;
+                                }
+                                continue;
+                            }
+                            // a text value goes out as its utf-8
+                            let value = match self.task.blobs.get(&key) {
+                                Some(bytes) => Some(Value::encoded(bytes, encoding)),
+                                None => self.task.query_map.get(&key).map(|text| Value::encoded(text.as_bytes(), encoding)),
+                            };
+                            let result = match value {
+                                Some(value) => TaskResult::BytesOk { req_id, id: self.task.id, value },
+                                None => TaskResult::QueryError {
+                                    req_id,
+                                    id: self.task.id,
+                                    code: ErrorCode::KeyNotFound,
+                                    detail: Some(format!("Query ID '{}' not found", key)),
+                                },
+                            };
+                            self.reply(&result_tx, started, result);
+                        }
+                        // one answer for all keys, whichever of them exist
+                        TaskInstruction::QueryMany { req_id, keys, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                if terminated {
+                                    break;
+                                }
+                                continue;
+                            }
+                            let mut values = HashMap::new();
+                            let mut missing = vec![];
+                            for key in keys {
+                                match self.task.query_map.get(&key) {
+                                    Some(value) => {
+                                        values.insert(key, value);
+                                    }
+                                    None => missing.push(key),
+                                }
+                            }
+                            self.reply(&result_tx, started, TaskResult::QueryManyOk { req_id, id: self.task.id, values, missing });
+                        }
+                        TaskInstruction::QueryMatching { req_id, pattern, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                if terminated {
+                                    break;
+                                }
+                                continue;
+                            }
+                            let values = self
+                                .task
+                                .query_map
+                                .iter()
+                                .filter(|(key, _)| pattern.matches(key))
+                                .map(|(key, value)| (key.to_string(), value.to_string()))
+                                .collect();
+                            self.reply(&result_tx, started, TaskResult::QueryManyOk {
+                                req_id,
+                                id: self.task.id,
+                                values,
+                                missing: vec![],
+                            });
+                        }
+                        // parses the document on every query, nothing is kept between them
+                        TaskInstruction::QueryPath { req_id, key, path, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                if terminated {
+                                    break;
+                                }
+                                continue;
+                            }
+                            let error = |code, detail| TaskResult::QueryError { req_id, id: self.task.id, code, detail: Some(detail) };
+                            let result = match self.task.query_map.get(&key).map(|text| Json::parse(&text)) {
+                                None => error(ErrorCode::KeyNotFound, format!("Query ID '{}' not found", key)),
+                                Some(Err(err)) => error(ErrorCode::PathNotFound, format!("'{key}' is not a JSON document: {err}")),
+                                Some(Ok(document)) => match path.select(&document) {
+                                    Some(fragment) => TaskResult::QueryOk { req_id, id: self.task.id, value: fragment.to_string(), access: None },
+                                    None => error(ErrorCode::PathNotFound, format!("{path} selects nothing in '{key}'")),
+                                },
+                            };
+                            self.reply(&result_tx, started, result);
+                        }
                         // over here, this does not actually update any values
                         // for the sake of simplicity, it just runs some function without any parameters
                         // we assume that update_fn would alter some value (which we expect to be queried using QueryRequest)
                         TaskInstruction::Update { req_id, update_id, result_tx } => {
-                            let _ = result_tx.send(TaskResult::ReceivedRequest);
-                            if let Some(update_fn) = self.task.update_map.get_mut(&update_id) {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            if self.deadlines.expired(req_id, self.clock.now()) {
+                                println!("[Task {}] Deadline of req {req_id} passed before its update ran", self.task.id);
+                                self.reply(&result_tx, started, TaskResult::TimedOut {
+                                    req_id,
+                                    id: self.task.id,
+                                    stage: DeadlineStage::Update,
+                                });
+                                continue;
+                            }
+                            if let Some(mut update_fn) = self.task.update_map.remove(&update_id) {
                                 println!("[Task {}] Running update function", self.task.id);
-                                let value = update_fn();
-                                let _ = result_tx.send(TaskResult::UpdateOk {
+                                // the update runs on a helper thread so one that never returns can't hang the task.
+                                // the closure and its writes travel there and back, if they don't come back in time they are lost.
+                                // the store is lent to it for reading and taken back whatever happens
+                                let store = task_state::lend(&mut self.task.query_map);
+                                let mut state = TaskState::new(
+                                    Arc::clone(&store),
+                                    self.task.memory.clone(),
+                                    self.task.version,
+                                    self.task.context.clone(),
+                                );
+                                let (done_tx, done_rx) = mpsc::channel();
+                                thread::spawn(move || {
+                                    let value = update_fn(&mut state);
+                                    let _ = done_tx.send((value, update_fn, state));
+                                });
+                                // the task thread only waits on the helper, but that wait is the update as far as the profile goes
+                                self.probe.enter(Activity::ExecutingUpdate);
+                                let done = clock::recv_timeout(&*self.clock, &done_rx, self.update_timeout);
+                                self.probe.enter(Activity::Handling);
+                                // an update still running past its timeout reads an empty store from here on
+                                self.task.query_map = task_state::take_back(&store);
+                                let (value, update_fn, state) = match done {
+                                    Ok(done) => done,
+                                    // the helper thread dropped done_tx without sending, the closure went down with it
+                                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                                        println!("[Task {}] Update '{update_id}' panicked", self.task.id);
+                                        self.reply(&result_tx, started, TaskResult::UpdateError {
+                                            req_id,
+                                            id: self.task.id,
+                                            code: ErrorCode::Panicked,
+                                            detail: Some(format!("Update ID '{}' panicked", update_id)),
+                                        });
+                                        continue;
+                                    }
+                                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                                        println!(
+                                            "[Task {}] Update '{update_id}' exceeded {:?}. Task is degraded.",
+                                            self.task.id, self.update_timeout
+                                        );
+                                        self.task.timed_out_updates.insert(update_id.clone());
+                                        self.events.publish(ServerEvent::TaskDegraded { id: self.task.id, update_id });
+                                        self.reply(&result_tx, started, TaskResult::UpdateTimedOut { req_id, id: self.task.id });
+                                        continue;
+                                    }
+                                };
+                                self.task.update_map.insert(update_id.clone(), update_fn);
+                                let writes = state.take_writes();
+                                if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                    if terminated {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                                let value = match value {
+                                    Ok(value) => value,
+                                    Err(msg) => {
+                                        self.reply(&result_tx, started, TaskResult::UpdateError {
+                                            req_id,
+                                            id: self.task.id,
+                                            code: ErrorCode::UpdateFailed,
+                                            detail: Some(msg),
+                                        });
+                                        continue;
+                                    }
+                                };
+                                // an update named after a schema key produces a value for it, and whatever it set has to fit too
+                                let checked = self.task.schema.check(&update_id, &value).and_then(|()| {
+                                    writes.iter().try_for_each(|(key, value)| match value {
+                                        Some(value) => self.task.schema.check(key, value),
+                                        None => Ok(()),
+                                    })
+                                });
+                                if let Err(violation) = checked {
+                                    self.reply(&result_tx, started, TaskResult::UpdateError {
+                                        req_id,
+                                        id: self.task.id,
+                                        code: ErrorCode::SchemaViolation,
+                                        detail: Some(violation.to_string()),
+                                    });
+                                    continue;
+                                }
+                                // every successful update is a state change other components may care about
+                                self.task.version += 1;
+                                for (key, written) in writes {
+                                    if self.access_metadata {
+                                        self.task.access.entry(key.clone()).or_default().written(self.task.version);
+                                    }
+                                    match written {
+                                        Some(written) => self.task.query_map.set(key, written),
+                                        None => {
+                                            self.task.query_map.remove(&key);
+                                        }
+                                    }
+                                }
+                                self.events.publish(ServerEvent::Published {
+                                    topic: format!("update/{update_id}"),
+                                    id: self.task.id,
+                                    payload: value.clone(),
+                                });
+                                self.reply(&result_tx, started, TaskResult::UpdateOk {
                                     req_id,
                                     id: self.task.id,
                                     value,
                                 });
+                            } else if self.task.timed_out_updates.contains(&update_id) {
+                                self.reply(&result_tx, started, TaskResult::UpdateError {
+                                    req_id,
+                                    id: self.task.id,
+                                    code: ErrorCode::TimedOut,
+                                    detail: Some(format!("Update ID '{}' timed out earlier and is unavailable", update_id)),
+                                });
                             } else {
-                                let _ = result_tx.send(TaskResult::UpdateError {
+                                self.reply(&result_tx, started, TaskResult::UpdateError {
+                                    req_id,
+                                    id: self.task.id,
+                                    code: ErrorCode::UpdateNotFound,
+                                    detail: Some(format!("Update ID '{}' not found", update_id)),
+                                });
+                            }
+                        }
+                        TaskInstruction::Set { req_id, key, value, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            if let Some(terminated) = self.over_quota(req_id, started, &result_tx) {
+                                if terminated {
+                                    break;
+                                }
+                                continue;
+                            }
+                            if let Err(violation) = self.task.schema.check(&key, &value) {
+                                self.reply(&result_tx, started, TaskResult::UpdateError {
                                     req_id,
                                     id: self.task.id,
-                                    msg: format!("Update ID '{}' not found", update_id),
+                                    code: ErrorCode::SchemaViolation,
+                                    detail: Some(violation.to_string()),
                                 });
+                                continue;
+                            }
+                            self.task.version += 1;
+                            if self.access_metadata {
+                                self.task.access.entry(key.clone()).or_default().written(self.task.version);
+                            }
+                            self.task.query_map.set(key, value.clone());
+                            self.reply(&result_tx, started, TaskResult::UpdateOk { req_id, id: self.task.id, value });
+                        }
+                        // turned into a plain Update or answered with VersionConflict before this match
+                        TaskInstruction::UpdateIfVersion { .. } => {}
+                        TaskInstruction::Publish { req_id, topic, payload, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            let delivered = self.events.publish(ServerEvent::Published {
+                                topic: topic.clone(),
+                                id: self.task.id,
+                                payload,
+                            });
+                            self.reply(&result_tx, started, TaskResult::Published {
+                                req_id,
+                                id: self.task.id,
+                                topic,
+                                delivered,
+                            });
+                        }
+                        TaskInstruction::Subscribe { req_id, topic, result_tx } => {
+                            let _ = result_tx.send(TaskResult::ReceivedRequest { req_id, id: self.task.id });
+                            self.reply(&result_tx, started, TaskResult::Subscribed {
+                                req_id,
+                                id: self.task.id,
+                                topic,
+                            });
+                        }
+                        // the latest payload per topic is exposed through the query_map so it can be queried like any other value
+                        // events without a payload of their own are stored in their debug form
+                        TaskInstruction::Deliver { event } => {
+                            if let Some(handler) = self.task.handler.as_mut() {
+                                handler.deliver(&event);
+                                continue;
+                            }
+                            let payload = match &event {
+                                ServerEvent::Published { payload, .. } => payload.clone(),
+                                other => format!("{other:?}"),
+                            };
+                            let key = format!("event/{}", event.topic());
+                            if let Some(replica) = self.replica.as_mut() {
+                                replica.touch();
+                            }
+                            if self.access_metadata {
+                                self.task.access.entry(key.clone()).or_default().written(self.task.version);
+                            }
+                            self.task.query_map.set(key, payload);
+                        }
+                        TaskInstruction::Stop => {
+                            println!("[Task {}] Worker is shutting down. Exiting task loop.", self.task.id);
+                            break;
+                        }
+                        TaskInstruction::Kill => {
+                            println!("[Task {}] Killed by chaos. Exiting without cleanup. {:?}", self.task.id, self.stats);
+                            return TaskExit::Killed;
+                        }
+                        TaskInstruction::Preempt { pause, done_tx } => {
+                            println!("[Task {}] Preempted by a more important task", self.task.id);
+                            self.preempted = Some(done_tx);
+                            return if pause { TaskExit::Paused } else { TaskExit::Preempted };
+                        }
+                        // a handler's state is its own, there is nothing to snapshot
+                        TaskInstruction::Snapshot { tenant, reply_tx } => {
+                            if self.task.handler.is_none() {
+                                let _ = reply_tx.send(TaskSnapshot::of(&self.task, tenant));
                             }
                         }
+                        TaskInstruction::Status { reply_tx } => {
+                            let _ = reply_tx.send(self.stats);
+                        }
+                        // unpacked into the backlog before this match
+                        TaskInstruction::Batch { .. } => {}
                     }
                 }
     
@@ -150,6 +1462,7 @@ impl TaskThread {
                         "[Task {}] No instruction received for {:?}. Exiting due to inactivity.",
                         self.task.id, timeout_duration
                     );
+                    exit = TaskExit::Expired;
                     break;
                 }
     
@@ -162,316 +1475,2513 @@ impl TaskThread {
                 }
             }
         }
+        // a batch cut short still answers what it got to
+        self.release_held();
     
-        println!("[Task {}] Task loop terminated.", self.task.id);
+        println!("[Task {}] Task loop terminated. {:?}", self.task.id, self.stats);
+        exit
+    }
+
+    // a copy of the values to the replica store, if one is due by now
+    fn publish_replica(&mut self, now: Duration) {
+        if let Some(replica) = self.replica.as_mut() {
+            replica.publish_if_due(self.task.id, self.task.version, now, self.task.query_map.iter());
+        }
+    }
+
+    // sends the answer to an instruction that started at started, after noting how long the task spent on it
+    fn reply(&mut self, result_tx: &Sender<TaskResult>, started: Duration, result: TaskResult) {
+        let busy = self.clock.now().saturating_sub(started);
+        if let Some(req_id) = result.req_id() {
+            self.lifecycle.executed(req_id, busy);
+        }
+        self.stats.count(&result, busy);
+        let results = match self.chunk_size {
+            Some(size) => chunk::split(result, size),
+            None => vec![result],
+        };
+        for result in results {
+            match &mut self.held {
+                Some(held) => held.push((result_tx.clone(), result)),
+                None => {
+                    let _ = result_tx.send(result);
+                }
+            }
+        }
+    }
+
+    // sends the answers held back while a batch ran as one TaskResult::Batch, on the channel of the first of them
+    fn release_held(&mut self) {
+        let mut held = self.held.take().unwrap_or_default();
+        let Some((result_tx, _)) = held.first() else { return };
+        let result_tx = result_tx.clone();
+        let result = match held.len() {
+            1 => held.remove(0).1,
+            _ => {
+                let req_ids = held.iter().filter_map(|(_, result)| result.req_id()).collect();
+                TaskResult::Batch { req_ids, results: held.into_iter().map(|(_, result)| result).collect() }
+            }
+        };
+        let _ = result_tx.send(result);
+    }
+
+    // closes the metered instruction that started at started. if it went over its quota, answers it with
+    // QuotaExceeded and returns whether the task should exit
+    fn over_quota(&mut self, req_id: RequestId, started: Duration, result_tx: &Sender<TaskResult>) -> Option<bool> {
+        let meter = self.task.meter.as_ref()?;
+        let resource = meter.finish(self.clock.now().saturating_sub(started))?;
+        let terminated = meter.quota().terminate;
+        println!(
+            "[Task {}] Instruction exceeded its {resource:?} quota{}",
+            self.task.id,
+            if terminated { ". Terminating task." } else { "" }
+        );
+        self.reply(result_tx, started, TaskResult::QuotaExceeded { req_id, id: self.task.id, resource, terminated });
+        Some(terminated)
     }
     
 }
 
 // thread that runs worker
 pub struct WorkerThread {
-    task_map: Arc<Mutex<HashMap<TaskId, Sender<TaskInstruction>>>>, // maps a Task to a transmitter that transmits from worker to task
+    task_map: TaskSenders,                                          // maps a Task to a transmitter that transmits from worker to task
+    expired: Arc<Mutex<HashMap<TaskId, Task>>>,                     // tasks kept after expiring, see ServerConfig::respawn_expired
+    paused_tasks: Arc<Mutex<HashMap<TaskId, Task>>>,                // tasks preempted with Preemption::Pause, see live_task
     active_tasks: Arc<AtomicUsize>,                                 // number of active tasks (used for throttling)
+    presence: TaskPresence,                                         // a bit per running task, read without locking task_map
+    max_concurrent_tasks: Arc<AtomicUsize>,                         // creates are throttled once active_tasks reaches this
+    events: EventBus,                                               // handed to every task so it can publish and be subscribed
+    dead_letters: SharedDeadLetters,                                // instructions that could not be delivered to their task
+    watchdog: Watchdog,                                             // told about every instruction handed to a task
+    abort: Arc<AtomicBool>,                                         // shared with every task, see ShutdownMode::Immediate
+    kill_switches: Arc<Mutex<HashMap<TaskId, Arc<AtomicBool>>>>,    // each running task's own, see AdminCommand::KillTask
+    faults: FaultInjector,                                          // applied to every instruction sent to a task
+    tracer: Tracer,                                                 // marks requests as they pass the worker and tasks
+    queue_waits: QueueWaits,                                        // stamped and noted on both channels a request waits on
+    lifecycle: LifecycleTable,                                      // told when a request is dequeued and how long its task took
+    tenants: TenantTable,                                           // which tenant each task belongs to
+    groups: GroupTable,                                             // running tasks per task group
+    priorities: Arc<Mutex<HashMap<TaskId, Priority>>>,              // priority of every running task, see preempt_for
+    deadlines: DeadlineTable,                                       // checked when a request is dequeued, here and by its task
+    finished: Arc<Mutex<TaskStats>>,                                // counters of every task run that has ended
+    admin_tx: AdminSender,                                          // handed to the server, see admin_sender
+    admin_rx: Arc<Mutex<Receiver<AdminCommand>>>,                   // checked before every request the worker handles
+    bell: Doorbell,                                                 // rung by both channels, the worker sleeps on it
+    intake: IntakeGauge,                                            // queue length and pause, published for the server
+    hedges: Option<Hedges>,                                         // queries to send again, see ServerConfig::hedge_after
+    replicas: Option<ReplicaStore>,                                 // copies of the tasks' values, see ServerConfig::replica_interval
+    summary: Arc<Mutex<WorkerSummary>>,                             // filled as the worker goes, returned by run
+    heartbeat_tx: Option<Sender<Duration>>,                         // clock time of every beat, see heartbeats
+    #[cfg(feature = "profiler")]
+    profiler: Option<Profiler>,                                     // hands out every thread's probe, see ServerConfig::profiler
+    probe: Probe,                                                   // what the worker is doing, for the profiler
+    outbox: Mutex<Option<Outbox>>,                                  // a turn's instructions, see flush
+    config: ServerConfig,
 }
 
 impl WorkerThread {
-    pub fn new() -> Self {
+    pub fn new(events: EventBus, config: ServerConfig) -> Self {
+        let (admin_tx, admin_rx) = mpsc::channel();
+        let bell = Doorbell::default();
+        #[cfg(feature = "profiler")]
+        let profiler = config.profiler.map(Profiler::new);
+        #[cfg(feature = "profiler")]
+        let probe = profiler.as_ref().map(|profiler| profiler.probe(Component::Worker)).unwrap_or_default();
+        #[cfg(not(feature = "profiler"))]
+        let probe = Probe::default();
         Self {
             task_map: Arc::new(Mutex::new(HashMap::new())),
+            expired: Arc::new(Mutex::new(HashMap::new())),
+            paused_tasks: Arc::new(Mutex::new(HashMap::new())),
             active_tasks: Arc::new(AtomicUsize::new(0)),
+            presence: TaskPresence::new(),
+            max_concurrent_tasks: Arc::new(AtomicUsize::new(MAX_CONCURRENT_TASKS)),
+            events,
+            dead_letters: Arc::new(Mutex::new(Vec::new())),
+            watchdog: Watchdog::new(Arc::clone(&config.clock)),
+            abort: Arc::new(AtomicBool::new(false)),
+            kill_switches: Arc::new(Mutex::new(HashMap::new())),
+            faults: FaultInjector::new(config.faults, Arc::clone(&config.clock)),
+            tracer: Tracer::new(config.tracing, Arc::clone(&config.clock)),
+            queue_waits: QueueWaits::new(Arc::clone(&config.clock)),
+            lifecycle: LifecycleTable::new(Arc::clone(&config.clock)),
+            tenants: TenantTable::new(),
+            groups: GroupTable::new(),
+            priorities: Arc::new(Mutex::new(HashMap::new())),
+            deadlines: DeadlineTable::new(),
+            finished: Arc::new(Mutex::new(TaskStats::default())),
+            admin_tx: AdminSender::new(admin_tx, bell.clone()),
+            admin_rx: Arc::new(Mutex::new(admin_rx)),
+            bell,
+            intake: IntakeGauge::default(),
+            // a slower answer comes from a task that was busy or held up, one that took longer than it may idle is lost
+            hedges: config.hedge_after.map(|_| Hedges::new(config.timeouts.task)),
+            replicas: config.replica_interval.map(|_| ReplicaStore::new()),
+            summary: Arc::new(Mutex::new(WorkerSummary::default())),
+            heartbeat_tx: None,
+            #[cfg(feature = "profiler")]
+            profiler,
+            probe,
+            outbox: Mutex::new(None),
+            config,
         }
     }
 
-    pub fn run(
-        &self,
-        rx: Receiver<TaskRequest>,
-        shutdown_flag: Arc<AtomicBool>,
-    ) {
-        let task_map = Arc::clone(&self.task_map);
-        let active_tasks = Arc::clone(&self.active_tasks);
+    // a worker sharing every channel, task and table with this one, to take over once it is gone, see supervisor.rs
+    pub(crate) fn successor(&self) -> Self {
+        Self {
+            task_map: Arc::clone(&self.task_map),
+            expired: Arc::clone(&self.expired),
+            paused_tasks: Arc::clone(&self.paused_tasks),
+            active_tasks: Arc::clone(&self.active_tasks),
+            presence: self.presence.clone(),
+            max_concurrent_tasks: Arc::clone(&self.max_concurrent_tasks),
+            events: self.events.clone(),
+            dead_letters: Arc::clone(&self.dead_letters),
+            watchdog: self.watchdog.clone(),
+            abort: Arc::clone(&self.abort),
+            kill_switches: Arc::clone(&self.kill_switches),
+            faults: self.faults.clone(),
+            tracer: self.tracer.clone(),
+            queue_waits: self.queue_waits.clone(),
+            lifecycle: self.lifecycle.clone(),
+            tenants: self.tenants.clone(),
+            groups: self.groups.clone(),
+            priorities: Arc::clone(&self.priorities),
+            deadlines: self.deadlines.clone(),
+            finished: Arc::clone(&self.finished),
+            admin_tx: self.admin_tx.clone(),
+            admin_rx: Arc::clone(&self.admin_rx),
+            bell: self.bell.clone(),
+            intake: self.intake.clone(),
+            hedges: self.hedges.clone(),
+            replicas: self.replicas.clone(),
+            summary: Arc::clone(&self.summary),
+            heartbeat_tx: self.heartbeat_tx.clone(),
+            #[cfg(feature = "profiler")]
+            profiler: self.profiler.clone(),
+            probe: self.probe.clone(),
+            outbox: Mutex::new(None),
+            config: self.config.clone(),
+        }
+    }
+
+    // clears the poison a panicking predecessor left on the shared locks. live tasks stay in the task map as they were
+    pub(crate) fn recover(&self) {
+        self.task_map.clear_poison();
+        self.expired.clear_poison();
+        self.paused_tasks.clear_poison();
+        self.priorities.clear_poison();
+        self.kill_switches.clear_poison();
+        self.dead_letters.clear_poison();
+        self.finished.clear_poison();
+        self.admin_rx.clear_poison();
+        self.summary.clear_poison();
+        self.summary.lock().unwrap().killed = false;
+    }
+
+    // the worker beats on the returned channel every ServerConfig::heartbeat interval while it runs.
+    // without a heartbeat configured it never does
+    pub fn heartbeats(&mut self) -> Receiver<Duration> {
+        let (heartbeat_tx, heartbeat_rx) = mpsc::channel();
+        self.heartbeat_tx = Some(heartbeat_tx);
+        heartbeat_rx
+    }
+
+    // sender for the worker's admin channel. a command wakes the worker, it is handled before the next request
+    pub fn admin_sender(&self) -> AdminSender {
+        self.admin_tx.clone()
+    }
+
+    // rung by whatever wants the worker awake. the request channel has to ring it on every send, see
+    // backpressure::channel_with_doorbell, or the worker only notices requests once its wait times out
+    pub(crate) fn doorbell(&self) -> Doorbell {
+        self.bell.clone()
+    }
+
+    // handle to the in-flight table the worker fills, the listener drains and the watchdog thread scans
+    pub fn watchdog(&self) -> Watchdog {
+        self.watchdog.clone()
+    }
+
+    // handle to the copies the tasks publish and the worker reads. None without ServerConfig::replica_interval
+    pub fn replicas(&self) -> Option<ReplicaStore> {
+        self.replicas.clone()
+    }
+
+    // handle to the queries the worker may hedge, shared with the listener. None without ServerConfig::hedge_after
+    pub fn hedges(&self) -> Option<Hedges> {
+        self.hedges.clone()
+    }
+
+    // handle to what the worker has queued and whether it is paused, see ServerThread::try_query_task
+    pub fn intake(&self) -> IntakeGauge {
+        self.intake.clone()
+    }
+
+    // handle to the active task counter so the server can read it while the worker runs
+    pub fn active_tasks(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.active_tasks)
+    }
+
+    // handle to the running tasks' bits so the server can check for a task without going through the worker
+    pub fn presence(&self) -> TaskPresence {
+        self.presence.clone()
+    }
+
+    // handle to the concurrency cap so the server can move it while the worker runs
+    pub fn max_concurrent_tasks(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.max_concurrent_tasks)
+    }
+
+    // handle to the fault injector so the server and listener share its generators and stats
+    pub fn faults(&self) -> FaultInjector {
+        self.faults.clone()
+    }
+
+    // handle to the tracer so the server and listener mark the same traces
+    pub fn tracer(&self) -> Tracer {
+        self.tracer.clone()
+    }
+
+    // handle to the per-hop queue waits, stamped by the server and the worker and noted by the worker and tasks
+    pub fn queue_waits(&self) -> QueueWaits {
+        self.queue_waits.clone()
+    }
+
+    // handle to the profiler so the server can sample and report it, None when ServerConfig::profiler is not set
+    #[cfg(feature = "profiler")]
+    pub fn profiler(&self) -> Option<Profiler> {
+        self.profiler.clone()
+    }
+
+    // a probe for a new thread, one that notes nothing unless the server is profiled
+    #[cfg(feature = "profiler")]
+    pub(crate) fn probe(&self, component: Component) -> Probe {
+        self.profiler.as_ref().map(|profiler| profiler.probe(component)).unwrap_or_default()
+    }
+
+    #[cfg(not(feature = "profiler"))]
+    pub(crate) fn probe(&self, _component: Component) -> Probe {
+        Probe::default()
+    }
+
+    // the task map, with the time spent waiting for it showing up as LockingTaskMap in the profile
+    fn lock_task_map(&self) -> MutexGuard<'_, HashMap<TaskId, CountingSender<TaskInstruction>>> {
+        self.probe.enter(Activity::LockingTaskMap);
+        let task_map = self.task_map.lock().unwrap();
+        self.probe.enter(Activity::Handling);
+        task_map
+    }
+
+    // handle to the lifecycle table the server, worker, tasks and listener fill in together
+    pub fn lifecycle(&self) -> LifecycleTable {
+        self.lifecycle.clone()
+    }
+
+    // handle to the tenant table so the server can report per tenant
+    pub fn tenants(&self) -> TenantTable {
+        self.tenants.clone()
+    }
+
+    // handle to the per-group counters so the server can report them
+    pub fn groups(&self) -> GroupTable {
+        self.groups.clone()
+    }
+
+    // handle to the deadline table so the server can fill it in as it sends requests
+    pub fn deadlines(&self) -> DeadlineTable {
+        self.deadlines.clone()
+    }
+
+    // handle to the finished tasks' counters so the server can read them after the worker has exited
+    pub fn finished_tasks(&self) -> Arc<Mutex<TaskStats>> {
+        Arc::clone(&self.finished)
+    }
+
+    // handle to the task map so the chaos thread can pick tasks to kill
+    pub(crate) fn task_senders(&self) -> TaskSenders {
+        Arc::clone(&self.task_map)
+    }
+
+    // handle to the dead-letter queue so the server can inspect it while the worker runs
+    pub fn dead_letters(&self) -> Arc<Mutex<Vec<DeadLetter>>> {
+        Arc::clone(&self.dead_letters)
+    }
+
+    // send an instruction to a task. the send only fails if the task's receiver is gone,
+    // in which case the instruction goes to the dead-letter queue and the requester is told it was undeliverable
+    // a task whose queue is at capacity or that is at its in-flight limit gets nothing more, the requester is told it is busy
+    fn dispatch(&self, id: TaskId, tx: &CountingSender<TaskInstruction>, instruction: TaskInstruction) {
+        // events delivered to the task sit in the same queue and count against the capacity, but are never
+        // turned away themselves, so they are the only thing that can push a queue past it
+        let mut outbox = self.outbox.lock().unwrap();
+        let batched = outbox.as_ref().map_or(0, |outbox| outbox.iter().filter(|(task, _, _)| *task == id).count());
+        let queue_full = self.config.task_queue_capacity.is_some_and(|capacity| tx.depth() + batched >= capacity);
+        let at_limit = self.config.max_in_flight_per_task.is_some_and(|max| self.watchdog.in_flight_for(id) >= max);
+        if queue_full || at_limit {
+            if let (Some(req_id), Some(result_tx)) = (instruction.req_id(), instruction.result_tx()) {
+                let why = if queue_full { "its queue is full" } else { "it has too many instructions in flight" };
+                println!("[req:{req_id}] [WorkerThread] Task {id} is busy, {why}");
+                let _ = result_tx.send(TaskResult::Busy { req_id, id });
+            }
+            return;
+        }
+        if let Some(req_id) = instruction.req_id() {
+            self.watchdog.track(req_id, id);
+            self.tracer.mark(req_id, id, Hop::Dispatched);
+            self.queue_waits.stamp(QueueHop::Task, req_id);
+        }
+        match outbox.as_mut() {
+            Some(outbox) => outbox.push((id, tx.clone(), instruction)),
+            None => self.deliver(id, tx, instruction),
+        }
+    }
+
+    // puts instruction on the task's channel. a task that is gone gets it dead-lettered, every instruction of a batch
+    fn deliver(&self, id: TaskId, tx: &CountingSender<TaskInstruction>, instruction: TaskInstruction) {
+        let sent = self.faults.send(FaultChannel::Instructions, tx, instruction, |i| Some(i.clone()));
+        let Err(mpsc::SendError(instruction)) = sent else { return };
+        let instructions = match instruction {
+            TaskInstruction::Batch { instructions } => instructions,
+            instruction => vec![instruction],
+        };
+        for instruction in instructions {
+            let Some(req_id) = instruction.req_id() else { continue };
+            self.watchdog.complete(req_id);
+            println!("[req:{req_id}] [WorkerThread] Task {id} exited before {} could be delivered", instruction.kind());
+            self.dead_letters.lock().unwrap().push(DeadLetter { req_id, id, kind: instruction.kind() });
+            if let Some(result_tx) = instruction.result_tx() {
+                let _ = result_tx.send(TaskResult::Undeliverable { req_id, id });
+            }
+        }
+    }
+
+    // sends what dispatch collected over a turn, one TaskInstruction::Batch per task that got several instructions,
+    // tasks in the order they first got one
+    fn flush(&self) {
+        let Some(outbox) = self.outbox.lock().unwrap().take() else { return };
+        let mut tasks: Vec<(TaskId, CountingSender<TaskInstruction>, Vec<TaskInstruction>)> = vec![];
+        for (id, tx, instruction) in outbox {
+            match tasks.iter_mut().find(|(task, _, _)| *task == id) {
+                Some((_, _, instructions)) => instructions.push(instruction),
+                None => tasks.push((id, tx, vec![instruction])),
+            }
+        }
+        for (id, tx, mut instructions) in tasks {
+            let instruction = match instructions.len() {
+                1 => instructions.remove(0),
+                _ => {
+                    self.summary.lock().unwrap().batches += 1;
+                    TaskInstruction::Batch { instructions }
+                }
+            };
+            self.deliver(id, &tx, instruction);
+        }
+    }
+
+    // a copy of every watched query that is still unanswered by now, to the same task
+    fn send_hedges(&self, hedges: &Hedges, now: Duration) {
+        for (req_id, id, instruction) in hedges.take_due(now) {
+            let Some(tx) = self.lock_task_map().get(&id).cloned() else {
+                hedges.unsent(req_id);
+                continue;
+            };
+            println!("[req:{req_id}] [WorkerThread] No answer from Task {id} yet, hedging the query");
+            self.dispatch(id, &tx, instruction);
+        }
+    }
+
+    // returns what the worker did once it exits
+    pub fn run(
+        &self,
+        rx: &CountingReceiver<Envelope>,
+        shutdown_flag: Arc<AtomicBool>,
+    ) -> WorkerSummary {
+        // requests are pulled off the channel into per-class queues so interactive work can overtake batch work
+        let mut queues = QosQueues::with_weights(self.config.batch_share, self.config.tenant_weights.clone());
+        // set once a TaskRequest::Shutdown arrives. from then on nothing new is accepted,
+        // and the loop ends as soon as the queues are empty
+        let mut stopping = false;
+        // set by TaskRequest::Kill, the loop ends right away and nothing is cleaned up
+        let mut killed = false;
+        // set by AdminCommand::PauseIntake, requests pile up in the queues until it is cleared
+        let mut paused = false;
+        // a successor starts with empty queues and takes requests again
+        self.intake.set_queued(0);
+        self.intake.set_paused(false);
+
+        // clock time the next heartbeat is due
+        let mut next_beat = self.config.clock.now();
+
+        // while no shutdown noted. whoever raises the flag also rings the doorbell, see ShutdownSignal,
+        // so the timeout below only matters for heartbeats and a flag raised some other way
+        while !shutdown_flag.load(Ordering::Relaxed) {
+            let mut wait = self.config.timeouts.worker;
+            if let (Some(heartbeat_tx), Some(heartbeat)) = (&self.heartbeat_tx, self.config.heartbeat) {
+                let now = self.config.clock.now();
+                if now >= next_beat {
+                    let _ = heartbeat_tx.send(now);
+                    next_beat = now + heartbeat.interval;
+                }
+                wait = wait.min(next_beat - now);
+            }
+            if let Some(hedges) = &self.hedges {
+                let now = self.config.clock.now();
+                self.send_hedges(hedges, now);
+                if let Some(due) = hedges.next_due() {
+                    wait = wait.min(due.saturating_sub(now));
+                }
+            }
+            // a shutdown ends a pause, a drain has to get through the queues
+            let idle = queues.is_empty() || (paused && !stopping);
+            // only block on the channel when there is nothing to do locally
+            if idle {
+                if stopping {
+                    break;
+                }
+                // asleep until a request or an admin command comes in, whichever is first
+                self.probe.enter(Activity::Waiting);
+                let admin_rx = self.admin_rx.lock().unwrap();
+                let selected = select::select(&*self.config.clock, &self.bell, rx, &admin_rx, wait);
+                drop(admin_rx);
+                self.probe.enter(Activity::Handling);
+                match selected {
+                    Selected::Request(envelope) => self.enqueue(&mut queues, envelope, &mut stopping, &mut killed),
+                    // a pause or a resume counts right away, the rest of the turn decides what to do next
+                    Selected::Control(command) => {
+                        self.admin(command, &queues, &mut paused);
+                        continue;
+                    }
+                    // a raised flag, or a message another selection took first
+                    Selected::Woken => continue,
+                    // woken up for the next heartbeat
+                    Selected::TimedOut if wait < self.config.timeouts.worker => continue,
+                    // a request channel whose senders are gone looks the same, the server may be done sending for now
+                    Selected::TimedOut => {
+                        println!("[WorkerThread] timed out waiting on channel");
+                        continue;
+                    }
+                }
+            }
+
+            // drain whatever else is already waiting so the scheduler sees both classes
+            // a kill stops the draining too, whatever is still on the channel is dropped with it
+            while !killed {
+                let Ok(envelope) = rx.try_recv() else { break };
+                self.enqueue(&mut queues, envelope, &mut stopping, &mut killed);
+            }
+            self.intake.set_queued(queues.len());
+            if killed {
+                println!("[WorkerThread] Killed by chaos. Exiting without stopping tasks.");
+                let mut summary = self.summary.lock().unwrap();
+                summary.killed = true;
+                return summary.clone();
+            }
+
+            // admin commands before the next request, however much is queued
+            while let Ok(command) = self.admin_rx.lock().unwrap().try_recv() {
+                self.admin(command, &queues, &mut paused);
+            }
+            if paused && !stopping {
+                continue;
+            }
+            let turn = self.config.instruction_batch.unwrap_or(1);
+            if turn > 1 {
+                *self.outbox.lock().unwrap() = Some(vec![]);
+            }
+            for _ in 0..turn.max(1) {
+                let Some(msg) = queues.pop() else { break };
+                self.tenants.note_served(msg.opts.tenant);
+                self.handle(msg);
+            }
+            self.intake.set_queued(queues.len());
+            self.flush();
+        }
+
+        if stopping {
+            // tasks get Stop behind whatever they already have queued
+            for tx in self.lock_task_map().values() {
+                let _ = tx.send(TaskInstruction::Stop);
+            }
+            println!("[WorkerThread] Shutdown requested. Worker exiting.");
+        } else {
+            println!("[WorkerThread] Shutdown flag detected. Worker exiting.");
+        }
+        self.summary.lock().unwrap().clone()
+    }
+
+    // pings and shutdowns are handled right away, everything else waits its turn in the QoS queues
+    fn enqueue(&self, queues: &mut QosQueues<Envelope>, envelope: Envelope, stopping: &mut bool, killed: &mut bool) {
+        let Envelope { opts, request } = envelope;
+        if let Some(req_id) = request.req_id() {
+            self.queue_waits.taken(QueueHop::Worker, req_id);
+        }
+        match request {
+            TaskRequest::Ping { reply_tx } => {
+                let _ = reply_tx.send(WorkerStatus {
+                    active_tasks: self.active_tasks.load(Ordering::Acquire),
+                    queue_depth: queues.len(),
+                });
+            }
+            TaskRequest::Shutdown { mode } => {
+                println!("[WorkerThread] Shutdown requested ({mode:?})");
+                *stopping = true;
+                if mode == ShutdownMode::Immediate {
+                    self.abort.store(true, Ordering::Relaxed);
+                    while let Some(envelope) = queues.pop() {
+                        Self::reject_shutting_down(envelope.request);
+                    }
+                }
+            }
+            TaskRequest::Kill => *killed = true,
+            request if *stopping => Self::reject_shutting_down(request),
+            request if self.should_shed(opts.qos, queues.len()) => {
+                if let Some((req_id, id, result_tx)) = request.reply_to() {
+                    println!("[req:{req_id}] [WorkerThread] Overloaded, shedding the request");
+                    let _ = result_tx.send(TaskResult::Shed { req_id, id });
+                }
+            }
+            request => {
+                if let Some((req_id, id, _)) = request.reply_to() {
+                    self.tracer.mark(req_id, id, Hop::Dequeued);
+                }
+                queues.push_for(opts.qos, opts.tenant, Envelope { opts, request })
+            }
+        }
+    }
+
+    // whether a request of class arriving now is dropped under ServerConfig::load_shedding
+    fn should_shed(&self, class: QosClass, queue_depth: usize) -> bool {
+        self.config.load_shedding.is_some_and(|shedding| {
+            shedding.sheds(class) && shedding.overloaded(queue_depth, self.active_tasks.load(Ordering::Acquire))
+        })
+    }
+
+    fn admin(&self, command: AdminCommand, queues: &QosQueues<Envelope>, paused: &mut bool) {
+        println!("[WorkerThread] Admin command: {command:?}");
+        match command {
+            AdminCommand::DumpStats { reply_tx } => {
+                let _ = reply_tx.send(WorkerStats {
+                    active_tasks: self.active_tasks.load(Ordering::Acquire),
+                    queue_depth: queues.len(),
+                    paused: *paused,
+                    tasks: self.task_ids(),
+                    dead_letters: self.dead_letters.lock().unwrap().len(),
+                    finished_tasks: *self.finished.lock().unwrap(),
+                });
+            }
+            AdminCommand::ListTasks { reply_tx } => {
+                let _ = reply_tx.send(self.task_ids());
+            }
+            AdminCommand::KillTask { id, reply_tx } => {
+                // out of task_map first, so nothing new is sent its way. the switch stops it at its next
+                // instruction, the Kill behind whatever it has queued only wakes a task that is waiting for one
+                let tx = self.lock_task_map().remove(&id);
+                if let Some(killed) = self.kill_switches.lock().unwrap().get(&id) {
+                    killed.store(true, Ordering::Relaxed);
+                }
+                let _ = reply_tx.send(tx.is_some_and(|tx| tx.send(TaskInstruction::Kill).is_ok()));
+            }
+            // a task that isn't running drops reply_tx unanswered
+            AdminCommand::TaskStatus { id, reply_tx } => {
+                if let Some(tx) = self.lock_task_map().get(&id) {
+                    let _ = tx.send(TaskInstruction::Status { reply_tx });
+                }
+            }
+            AdminCommand::PauseIntake => {
+                *paused = true;
+                self.intake.set_paused(true);
+            }
+            AdminCommand::ResumeIntake => {
+                *paused = false;
+                self.intake.set_paused(false);
+            }
+            AdminCommand::Snapshot { reply_tx } => {
+                let mut pending = vec![];
+                for (id, tx) in self.lock_task_map().iter() {
+                    let (snapshot_tx, snapshot_rx) = mpsc::channel();
+                    let tenant = self.tenants.owner(*id).unwrap_or_default();
+                    if tx.send(TaskInstruction::Snapshot { tenant, reply_tx: snapshot_tx }).is_ok() {
+                        pending.push((*id, snapshot_rx));
+                    }
+                }
+                for kept in [&self.expired, &self.paused_tasks] {
+                    for task in kept.lock().unwrap().values().filter(|task| task.handler.is_none()) {
+                        let (snapshot_tx, snapshot_rx) = mpsc::channel();
+                        let _ = snapshot_tx.send(TaskSnapshot::of(task, self.tenants.owner(task.id).unwrap_or_default()));
+                        pending.push((task.id, snapshot_rx));
+                    }
+                }
+                let _ = reply_tx.send(pending);
+            }
+        }
+    }
+
+    fn task_ids(&self) -> Vec<TaskId> {
+        let mut ids: Vec<TaskId> = self.lock_task_map().keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn reject_shutting_down(request: TaskRequest) {
+        if let Some((req_id, id, result_tx)) = request.reply_to() {
+            println!("[req:{req_id}] [WorkerThread] Rejected, worker is shutting down");
+            let _ = result_tx.send(TaskResult::ShuttingDown { req_id, id });
+        }
+    }
+
+    // starts the thread for a newly created task, unless it is too large or the concurrency cap, the tenant's cap
+    // or its group's cap is reached
+    fn spawn_task(&self, req_id: RequestId, task: Task, tenant: TenantId, result_tx: Sender<TaskResult>) {
+        let id = task.id;
+        if let Err(reason) = self.config.task_limits.check(&*task.query_map, task.update_map.len()) {
+            println!("[req:{req_id}] [WorkerThread] Task {id} rejected, {reason}");
+            let _ = result_tx.send(TaskResult::RejectedTooLarge { req_id, id, reason });
+            return;
+        }
+        if !self.admit(req_id, &task, tenant, &result_tx, true) {
+            return;
+        }
+
+        println!("[req:{req_id}] [WorkerThread] Initializing task thread for Task {id}");
+        self.start_task(task, tenant);
+        self.events.publish(ServerEvent::TaskCreated { id, tenant });
+        let _ = result_tx.send(TaskResult::Created { req_id, id });
+    }
+
+    // whether task may start now: under the concurrency cap or with room made by preempt_for, and under its tenant's
+    // and group's caps. one that may not is answered Throttled. without preempt nothing is stopped to make room
+    fn admit(
+        &self,
+        req_id: RequestId,
+        task: &Task,
+        tenant: TenantId,
+        result_tx: &Sender<TaskResult>,
+        preempt: bool,
+    ) -> bool {
+        let id = task.id;
+
+        // if active tasks are at the concurrency cap, throttle the oncoming tasks
+        // these are assumed to be handled by the server (via a buffer)
+        // worker thread does not buffer oncoming tasks when it is throttled
+
+        // if the worker sees a lower value, Acquire ensures it also sees all 
+        // memory writes that were made by the task thread before its Release-ordered fetch_sub.
+        // the cap is read fresh for every create, so a change applies from the next one on
+        if self.active_tasks.load(Ordering::Acquire) >= self.max_concurrent_tasks.load(Ordering::Relaxed)
+            && !(preempt && self.preempt_for(req_id, task, result_tx))
+        {
+            println!("[req:{req_id}] [WorkerThread] Task {id} rejected due to throttling");
+            self.summary.lock().unwrap().throttled += 1;
+            self.events.publish(ServerEvent::Throttled { req_id, id });
+            let _ = result_tx.send(TaskResult::Throttled { req_id, id, group: None });
+            return false;
+        }
+        if self.config.tenant_caps.get(&tenant).is_some_and(|cap| self.tenants.active(tenant) >= *cap) {
+            println!("[req:{req_id}] [WorkerThread] Task {id} rejected, tenant {tenant} is at its cap");
+            self.summary.lock().unwrap().throttled += 1;
+            self.events.publish(ServerEvent::Throttled { req_id, id });
+            let _ = result_tx.send(TaskResult::Throttled { req_id, id, group: None });
+            return false;
+        }
+        if let Some(group) = task.group.as_ref().filter(|group| {
+            self.config.group_caps.get(*group).is_some_and(|cap| self.groups.active(group) >= *cap)
+        }) {
+            println!("[req:{req_id}] [WorkerThread] Task {id} rejected, group '{group}' is at its cap");
+            self.summary.lock().unwrap().throttled += 1;
+            self.events.publish(ServerEvent::Throttled { req_id, id });
+            let _ = result_tx.send(TaskResult::Throttled { req_id, id, group: Some(group.clone()) });
+            return false;
+        }
+        true
+    }
+
+    // with ServerConfig::preemption, stops the least important idle task below task's priority and waits for it to give up
+    // its slot. the victim's Preempted goes out on result_tx ahead of the create's own answer. false if nothing was stopped
+    fn preempt_for(&self, req_id: RequestId, task: &Task, result_tx: &Sender<TaskResult>) -> bool {
+        let Some(preemption) = self.config.preemption else { return false };
+        let victim = {
+            let task_map = self.lock_task_map();
+            let priorities = self.priorities.lock().unwrap();
+            // idle means nothing queued and nothing being worked on, so stopping it loses no request
+            let idle = task_map
+                .iter()
+                .filter(|(id, tx)| tx.depth() == 0 && self.watchdog.in_flight_for(**id) == 0)
+                .filter_map(|(id, _)| Some((*id, *priorities.get(id)?)));
+            preemption::victim(idle, task.priority)
+        };
+        let Some(victim) = victim else { return false };
+        let pause = preemption == Preemption::Pause;
+        // a terminated task is out of reach right away, a paused one moves over to the paused map on its own
+        let tx = if pause { self.lock_task_map().get(&victim).cloned() } else { self.lock_task_map().remove(&victim) };
+        let Some(tx) = tx else { return false };
+        let (done_tx, done_rx) = mpsc::channel();
+        if tx.send(TaskInstruction::Preempt { pause, done_tx }).is_err() {
+            return false;
+        }
+        if clock::recv_timeout(&*self.config.clock, &done_rx, self.config.timeouts.task).is_err() {
+            println!("[req:{req_id}] [WorkerThread] Task {victim} did not give up its slot in time");
+            return false;
+        }
+        println!("[req:{req_id}] [WorkerThread] Task {victim} preempted for Task {}", task.id);
+        let _ = result_tx.send(TaskResult::Preempted { req_id, id: victim, by: task.id, paused: pause });
+        true
+    }
+
+    // runs a task on its own thread and returns its sender, which is in task_map by then
+    fn start_task(&self, task: Task, tenant: TenantId) -> CountingSender<TaskInstruction> {
+        let id = task.id;
+        let task_map = Arc::clone(&self.task_map);
+        let active_tasks = Arc::clone(&self.active_tasks);
+
+        let (task_tx, task_rx) = backpressure::channel();
+
+        // owned before it can be found, so no request of another tenant gets through in between
+        self.tenants.started(id, tenant);
+        self.priorities.lock().unwrap().insert(id, task.priority);
+        let killed = Arc::new(AtomicBool::new(false));
+        self.kill_switches.lock().unwrap().insert(id, Arc::clone(&killed));
+        if let Some(group) = &task.group {
+            self.groups.started(group);
+        }
+        self.summary.lock().unwrap().tasks_spawned += 1;
+
+        self.presence.insert(id);
+        self.lock_task_map().insert(id, task_tx.clone());
+
+        // a task is created
+        // no other thread depends on seeing the increment instantly
+        // just bumping a counter — atomicity is enough, ordering doesn't matter here.
+        active_tasks.fetch_add(1, Ordering::Relaxed);
+
+        let task_map_cloned = Arc::clone(&task_map);
+        let active_tasks_cloned = Arc::clone(&active_tasks);
+        let tenants = self.tenants.clone();
+        let groups = self.groups.clone();
+        let expired = self.config.respawn_expired.then(|| Arc::clone(&self.expired));
+        let paused = Arc::clone(&self.paused_tasks);
+        let priorities = Arc::clone(&self.priorities);
+        let kill_switches = Arc::clone(&self.kill_switches);
+        let presence = self.presence.clone();
+        let task_timeout = task.idle_timeout.unwrap_or(self.config.timeouts.task);
+        // a handler has no values to copy
+        let replica = match (&self.replicas, self.config.replica_interval) {
+            (Some(store), Some(interval)) if task.handler.is_none() => Some(ReplicaPublisher::new(store.clone(), interval)),
+            _ => None,
+        };
+        let replicas = self.replicas.clone();
+        let mut task_thread = TaskThread {
+            task,
+            rx: task_rx,
+            events: self.events.clone(),
+            update_timeout: self.config.update_timeout,
+            task_timeout,
+            abort: Arc::clone(&self.abort),
+            killed,
+            clock: Arc::clone(&self.config.clock),
+            tracer: self.tracer.clone(),
+            queue_waits: self.queue_waits.clone(),
+            lifecycle: self.lifecycle.clone(),
+            access_metadata: self.config.access_metadata,
+            stats: TaskStats::default(),
+            deadlines: self.deadlines.clone(),
+            probe: self.probe(Component::Task),
+            preempted: None,
+            backlog: VecDeque::new(),
+            held: None,
+            chunk_size: self.config.query_chunk_size,
+            replica,
+        };
+        let finished = Arc::clone(&self.finished);
+        let events = self.events.clone();
+
+
+        thread::spawn(move || {
+            let exit = task_thread.run();
+            finished.lock().unwrap().add(&task_thread.stats);
+            if let Some(group) = &task_thread.task.group {
+                groups.exited(group);
+            }
+
+            // task is completed
+            // a killed task leaves its sender behind the way a crash would,
+            // so later instructions for it end up in the dead-letter queue
+            priorities.lock().unwrap().remove(&id);
+            kill_switches.lock().unwrap().remove(&id);
+            if let Some(replicas) = &replicas {
+                replicas.remove(id);
+            }
+            // cleared before a paused or expired task can be found to respawn, which sets it again
+            presence.remove(id);
+            match (exit, expired) {
+                (TaskExit::Killed, _) => tenants.exited(id, false),
+                // kept apart from the expired ones, whether or not respawn_expired is on, see live_task
+                (TaskExit::Paused, _) => {
+                    let mut task_map = task_map_cloned.lock().unwrap();
+                    task_map.remove(&id);
+                    paused.lock().unwrap().insert(id, task_thread.task);
+                    tenants.exited(id, false);
+                }
+                (TaskExit::Expired, Some(expired)) => {
+                    // moved over while task_map is locked, so the worker finds the task in one map or the other
+                    let mut task_map = task_map_cloned.lock().unwrap();
+                    task_map.remove(&id);
+                    expired.lock().unwrap().insert(id, task_thread.task);
+                    tenants.exited(id, false);
+                }
+                _ => {
+                    task_map_cloned.lock().unwrap().remove(&id);
+                    tenants.exited(id, true);
+                }
+            }
+            
+            // Ordering::Release says: "all memory writes before this (like removing from task_map) 
+            // must be visible to other threads that later do an Acquire load on this atomic."
+            active_tasks_cloned.fetch_sub(1, Ordering::Release);
+            if let Some(done_tx) = task_thread.preempted.take() {
+                let _ = done_tx.send(());
+            }
+
+            println!("[WorkerThread] Task {id} finished and removed.");
+            events.publish(ServerEvent::TaskFinished { id, exit, stats: task_thread.stats });
+        });
+        task_tx
+    }
+
+    // the sender of a running task. with respawn_expired a task that expired is started again first, with the state
+    // it expired with, and the requester is sent Respawned. subscriptions are not carried over.
+    // a respawn is admitted the way a create is, one that isn't is answered Throttled and the task stays expired.
+    // a paused task is started again the same way, but only into a free slot. making room for it would pause another
+    // task in its place, or undo the preemption that paused it
+    // a task that isn't there is answered NotFound with ctx, one of another tenant is treated as if it did not exist
+    fn live_task(
+        &self,
+        req_id: RequestId,
+        id: TaskId,
+        tenant: TenantId,
+        result_tx: &Sender<TaskResult>,
+        ctx: &'static str,
+    ) -> Option<CountingSender<TaskInstruction>> {
+        if self.tenants.owner(id) == Some(tenant) {
+            if let Some(tx) = self.lock_task_map().get(&id) {
+                return Some(tx.clone());
+            }
+            for (kept, preempt) in [(&self.expired, true), (&self.paused_tasks, false)] {
+                let Some(task) = kept.lock().unwrap().remove(&id) else { continue };
+                if !self.admit(req_id, &task, tenant, result_tx, preempt) {
+                    kept.lock().unwrap().insert(id, task);
+                    return None;
+                }
+                println!("[req:{req_id}] [WorkerThread] Respawning Task {id}");
+                let tx = self.start_task(task, tenant);
+                let _ = result_tx.send(TaskResult::Respawned { req_id, id });
+                return Some(tx);
+            }
+        }
+        let _ = result_tx.send(TaskResult::NotFound { req_id, id, ctx });
+        None
+    }
+
+    fn handle(&self, envelope: Envelope) {
+        let Envelope { opts: RequestOptions { tenant, stale_ok, .. }, request: msg } = envelope;
+        *self.summary.lock().unwrap().handled.entry(msg.kind()).or_insert(0) += 1;
+        if let Some((req_id, id, result_tx)) = msg.reply_to() {
+            self.lifecycle.dequeue(req_id);
+            if self.deadlines.expired(req_id, self.config.clock.now()) {
+                println!("[req:{req_id}] [WorkerThread] Deadline passed while the request was queued");
+                let _ = result_tx.send(TaskResult::TimedOut { req_id, id, stage: DeadlineStage::Worker });
+                return;
+            }
+        }
+        match msg {
+            TaskRequest::CreateTask {
+                req_id,
+                id,
+                query_map,
+                blobs,
+                update_map,
+                schema,
+                meter,
+                version,
+                idle_timeout,
+                group,
+                priority,
+                result_tx,
+            } => {
+                let task = Task {
+                    id,
+                    query_map,
+                    blobs,
+                    update_map,
+                    schema,
+                    memory: TaskMemory::default(),
+                    context: self.config.context.clone(),
+                    timed_out_updates: HashSet::new(),
+                    meter,
+                    handler: None,
+                    version,
+                    idle_timeout,
+                    access: HashMap::new(),
+                    group,
+                    priority,
+                };
+                self.spawn_task(req_id, task, tenant, result_tx);
+            }
+
+            TaskRequest::CreateHandlerTask { req_id, id, handler, meter, result_tx } => {
+                let task = Task {
+                    id,
+                    query_map: Box::new(HashMap::new()),
+                    blobs: HashMap::new(),
+                    update_map: HashMap::new(),
+                    schema: Schema::new(),
+                    memory: TaskMemory::default(),
+                    context: self.config.context.clone(),
+                    timed_out_updates: HashSet::new(),
+                    meter,
+                    handler: Some(handler),
+                    version: 0,
+                    idle_timeout: None,
+                    access: HashMap::new(),
+                    group: None,
+                    priority: 0,
+                };
+                self.spawn_task(req_id, task, tenant, result_tx);
+            }
+
+            TaskRequest::QueryTask { req_id, id, query_id, result_tx } => {
+                // get specific task
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for query") {
+                    // a replica that has the key answers right away, anything else goes to the task as usual
+                    let replicated = self.replicas.as_ref().filter(|_| stale_ok).and_then(|replicas| replicas.read(id, &query_id));
+                    if let Some(value) = replicated {
+                        println!("[req:{req_id}] [WorkerThread] Query task {id} answered from its replica");
+                        let _ = result_tx.send(TaskResult::QueryOk { req_id, id, value, access: None });
+                        return;
+                    }
+                    let instruction = TaskInstruction::Query { req_id, query_id, result_tx };
+                    if let (Some(hedges), Some(after)) = (&self.hedges, self.config.hedge_after) {
+                        hedges.watch(req_id, id, self.config.clock.now() + after, instruction.clone());
+                    }
+                    // send subset of the TaskRequest onto the specified task
+                    self.dispatch(id, &tx, instruction);
+                }
+            }
+
+            TaskRequest::QueryBytesTask { req_id, id, key, encoding, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for query") {
+                    self.dispatch(id, &tx, TaskInstruction::QueryBytes { req_id, key, encoding, result_tx });
+                }
+            }
+
+            TaskRequest::QueryManyTask { req_id, id, keys, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for query") {
+                    self.dispatch(id, &tx, TaskInstruction::QueryMany { req_id, keys, result_tx });
+                }
+            }
+
+            TaskRequest::QueryMatchingTask { req_id, id, pattern, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for query") {
+                    self.dispatch(id, &tx, TaskInstruction::QueryMatching { req_id, pattern, result_tx });
+                }
+            }
+
+            TaskRequest::QueryPathTask { req_id, id, key, path, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for query") {
+                    self.dispatch(id, &tx, TaskInstruction::QueryPath { req_id, key, path, result_tx });
+                }
+            }
+
+            TaskRequest::UpdateTask { req_id, id, update_id, result_tx } => {
+                // get specific task
+
+                // this unwrap will trigger if mutex lock is poisoned.
+                // but if mutex is poisoned the task_map is lost.
+                // it will be poisoned when a task thread panics.
+                // if it panics after removal from task_map, we are good. but otherwise no.
+                // currently no code exists in TaskThread that can panic so no impl against poisoned locks has been written
+                // if it panics, its fine. the task_map was in a dangerous state anyway
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for update") {
+                    // send subset of the TaskRequest onto the specified task
+                    self.dispatch(id, &tx, TaskInstruction::Update { req_id, update_id, result_tx });
+                }
+            }
+
+            TaskRequest::SetTask { req_id, id, key, value, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for update") {
+                    self.dispatch(id, &tx, TaskInstruction::Set { req_id, key, value, result_tx });
+                }
+            }
+
+            TaskRequest::UpdateIfVersionTask { req_id, id, update_id, expected_version, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for update") {
+                    self.dispatch(id, &tx, TaskInstruction::UpdateIfVersion { req_id, update_id, expected_version, result_tx });
+                }
+            }
+
+            TaskRequest::PublishTask { req_id, id, topic, payload, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for publish") {
+                    self.dispatch(id, &tx, TaskInstruction::Publish { req_id, topic, payload, result_tx });
+                }
+            }
+
+            TaskRequest::SubscribeTask { req_id, id, topic, result_tx } => {
+                if let Some(tx) = self.live_task(req_id, id, tenant, &result_tx, "Task not found for subscribe") {
+                    // registering here rather than in the task means the subscription is in place
+                    // before the worker handles any request sent after this one
+                    self.events.subscribe_task(&topic, id, tx.clone());
+                    self.dispatch(id, &tx, TaskInstruction::Subscribe { req_id, topic, result_tx });
+                }
+            }
+
+            // handled in enqueue, never queued
+            TaskRequest::Ping { .. } | TaskRequest::Shutdown { .. } | TaskRequest::Kill => {}
+        }
+    }
+}
+
+// thread that collects every TaskResult into the shared results store
+// with ServerConfig::listener_shards there is one per shard, each draining its own result channel.
+// the shards share everything else and only go idle together
+pub struct ListenerThread {
+    shard: usize,
+    results: ResultStore,
+    last_activity: Arc<AtomicU64>,   // clock time of the last result any shard received, in nanoseconds
+    live_shards: Arc<AtomicUsize>,   // shards still running, the last one out raises shutdown
+    watchdog: Watchdog,
+    lifecycle: LifecycleTable,
+    tracer: Tracer,
+    autoscaler: Option<Autoscaler>,
+    sinks: SinkChain,
+    events: EventBus,
+    faults: FaultInjector,
+    shutdown: ShutdownSignal,
+    shutdown_hooks: ShutdownHooks,
+    clock: Arc<dyn Clock>,
+    idle_timeout: Duration,
+    run_mode: RunMode,
+    probe: Probe,
+    chunks: RefCell<ChunkAssembler>, // query values streamed in pieces, by req_id
+    hedges: Option<Hedges>,          // the slower answer of a hedged query is dropped, see ServerConfig::hedge_after
+}
+
+impl ListenerThread {
+    fn run(self, rx: Receiver<TaskResult>) {
+        let idle_timeout = self.idle_timeout;
+        // results held back by the fault injector, recorded once their due time (clock time) has passed
+        let mut delayed: Vec<(Duration, TaskResult)> = vec![];
+        // idle time counts from the last result received by any shard, not from when we got around to waiting again,
+        // so a clock that jumps while a result is being recorded still expires the listener
+        self.touch();
+
+        loop {
+            let now = self.clock.now();
+            let (due, pending): (Vec<_>, Vec<_>) = delayed.into_iter().partition(|(due, _)| *due <= now);
+            delayed = pending;
+            for (_, result) in due {
+                self.record(result);
+            }
+            // wake up in time for the next delayed result, otherwise wait out the idle timeout.
+            // a persistent listener has no idle deadline and waits whole periods
+            let idle_left = match self.run_mode {
+                RunMode::Simulation => (self.last_activity() + idle_timeout).saturating_sub(now),
+                RunMode::Persistent => idle_timeout,
+            };
+            let wait = delayed
+                .iter()
+                .map(|(due, _)| *due - now)
+                .min()
+                .map_or(idle_left, |d| d.min(idle_left));
+
+            self.probe.enter(Activity::Waiting);
+            let received = clock::recv_timeout(&*self.clock, &rx, wait);
+            self.probe.enter(Activity::Handling);
+            match received {
+                Ok(result) => {
+                    // recieved some output from a TaskThread
+                    println!("[Listener {}] {:?}", self.shard, result);
+                    self.touch();
+                    match self.faults.decide(FaultChannel::Results) {
+                        Fault::None => self.record(result),
+                        Fault::Drop => println!("[FaultInjector] Dropped a message on Results"),
+                        Fault::Delay(delay) => delayed.push((self.clock.now() + delay, result)),
+                        Fault::Duplicate => {
+                            self.record(result.clone());
+                            self.record(result);
+                        }
+                    }
+                }
+                // only a real idle period counts, not waking up for a delayed result
+                Err(mpsc::RecvTimeoutError::Timeout) if !delayed.is_empty() => continue,
+                // only a disconnect after shutdown_with ends a persistent server
+                Err(mpsc::RecvTimeoutError::Timeout) if self.run_mode == RunMode::Persistent => continue,
+                // another shard got something in the meantime
+                Err(mpsc::RecvTimeoutError::Timeout) if self.clock.now() < self.last_activity() + idle_timeout => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => {             // shutdown condition: idle time has reached LISTENER_TIMEOUT
+                    println!("[Listener {}] No activity. Shutting down...", self.shard);
+                    break;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {       // shutdown condition: channel has already been severed
+                    println!("[Listener {}] Channel disconnected. Shutting down...", self.shard);
+                    for (_, result) in delayed {
+                        self.record(result);
+                    }
+                    break;
+                }
+            }
+        }
+        // a shard that is done leaves the flag alone while the others may still be recording
+        if self.live_shards.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // the worker leaves right away rather than at its next timeout
+            self.shutdown.raise();
+            // wake anyone in wait_idle, nothing else is going to be recorded
+            self.results.notify();
+            // before join_listener returns
+            self.shutdown_hooks.run();
+        }
+    }
+
+    fn touch(&self) {
+        self.last_activity.fetch_max(self.clock.now().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn last_activity(&self) -> Duration {
+        Duration::from_nanos(self.last_activity.load(Ordering::Relaxed))
+    }
+
+    fn record(&self, result: TaskResult) {
+        self.probe.enter(Activity::Recording);
+        self.record_result(result);
+        self.probe.enter(Activity::Handling);
+    }
+
+    fn record_result(&self, result: TaskResult) {
+        match result {
+            TaskResult::ReceivedRequest { req_id, .. } => return self.lifecycle.acknowledge(req_id),
+            TaskResult::Respawned { req_id, .. } => return self.lifecycle.respawn(req_id),
+            // only a note, the create it rides on is still waiting for its answer
+            TaskResult::Preempted { req_id, .. } => return self.sinks.accept(req_id, &result),
+            TaskResult::Batch { results, .. } => {
+                for result in results {
+                    self.record_result(result);
+                }
+                return;
+            }
+            TaskResult::QueryChunk { req_id, id, seq, last, bytes } => {
+                let Some(result) = self.chunks.borrow_mut().add(req_id, id, seq, last, bytes) else { return };
+                return self.record_result(result);
+            }
+            _ => {}
+        }
+        let Some(req_id) = result.req_id() else { return };
+        if self.hedges.as_ref().is_some_and(|hedges| !hedges.answered(req_id, self.clock.now())) {
+            println!("[req:{req_id}] [Listener {}] Dropping the slower answer of a hedged query", self.shard);
+            // the copy may have been tracked again after the first answer completed it
+            self.watchdog.complete(req_id);
+            return;
+        }
+        if let Some(autoscaler) = &self.autoscaler {
+            match result {
+                TaskResult::Created { .. } => autoscaler.observe(false),
+                TaskResult::Throttled { .. } => autoscaler.observe(true),
+                _ => {}
+            }
+        }
+        self.watchdog.complete(req_id);
+        self.lifecycle.complete(req_id);
+        if self.tracer.is_enabled() {
+            self.tracer.mark(req_id, result.id(), Hop::Recorded);
+            for span in self.tracer.spans(req_id) {
+                self.events.publish(ServerEvent::Span(span));
+            }
+        }
+        self.sinks.accept(req_id, &result);
+    }
+}
+
+pub struct ServerThread {
+    pub worker_tx: CountingSender<Envelope>,     // transmitter from server to worker, so it has to own it. counts what the worker hasn't taken yet
+    pub admin_tx: AdminSender,                   // the worker's admin channel, see admin
+    pub result_txs: Vec<mpsc::Sender<TaskResult>>, // one per listener shard, a request's TaskThread gets the one for its req_id
+
+    // both are AtomicUsize to ensure any operations are atomic.
+    pub request_counter: usize,
+    pub task_id_counter: usize,
+
+    pub results: ResultStore,
+    pub listener_handles: Vec<JoinHandle<()>>,   // join handles for the listener shards
+    pub worker_handle: Option<JoinHandle<WorkerSummary>>, // taken by join_worker, or when the server is dropped
+    pub worker_summary: Option<WorkerSummary>,   // what the worker did, once join_worker has joined it
+    pub events: EventBus,                        // shared with the worker and every task
+    pub dead_letter_queue: SharedDeadLetters,    // filled by the worker, see DeadLetter
+    pub rate_limiter: Option<RateLimiter>,       // per-client token buckets, None when rate limiting is off
+    pub request_tenants: HashMap<RequestId, TenantId>, // tenant of every request sent, for accounting
+    pub tenants: TenantTable,                    // shared with the worker, which tenant each task belongs to
+    pub groups: GroupTable,                      // shared with the worker, running tasks per task group
+    pub finished_tasks: Arc<Mutex<TaskStats>>,   // shared with the worker, counters of every task run that has ended
+    pub deadlines: DeadlineTable,                // shared with the worker and every task, see RequestOptions::deadline
+    pub active_tasks: Arc<AtomicUsize>,          // shared with the worker, read by health()
+    pub presence: TaskPresence,                  // shared with the worker, see task_exists
+    pub max_concurrent_tasks: Arc<AtomicUsize>,  // shared with the worker, see set_max_concurrent_tasks
+    pub accepting: bool,                         // false once shutdown_with has been called
+    pub shutdown_flag: Arc<AtomicBool>,          // set by the listener when it exits
+    pub shutdown_hooks: ShutdownHooks,           // run by the listener once it has set shutdown_flag, see on_shutdown
+    pub faults: FaultInjector,                   // shared with the worker and listener
+    pub clock: Arc<dyn Clock>,                   // the configured clock, shared with every other thread
+    pub timeouts: Timeouts,                      // the configured idle timeouts, see ServerConfig::timeouts
+    pub recorder: Option<Recorder>,              // notes every request while recording, see start_recording
+    pub lifecycle: LifecycleTable,               // acknowledgements and completions, filled by the listener
+    pub tracer: Tracer,                          // per-request hop marks, only filled when ServerConfig::tracing is on
+    pub queue_waits: QueueWaits,                 // how long requests sat on the worker's and the tasks' channels
+    pub history: Option<TaskHistory>,            // last results per task, only kept when ServerConfig::task_history is set
+    pub result_subscribers: ResultSubscribers,   // see subscribe_results, shared with the listener
+    pub sinks: SinkChain,                        // where every recorded result goes, shared with the listener. ends in results
+    pub result_counts: ResultCounts,             // one of the sinks, counts results by kind for stats()
+    pub started_at: Duration,                    // clock time the server was created at
+    pub interceptors: InterceptorChain,          // every request passes these on its way to the worker, see add_interceptor
+    pub circuit_breaker: Option<CircuitBreaker>, // asked before every request is sent, None when it is not configured
+    pub heartbeat: Option<HeartbeatMonitor>,     // fed by the worker's heartbeats, None when they are not configured
+    pub ready_queue_depth: Option<usize>,        // see ServerConfig::ready_queue_depth
+    pub intake: IntakeGauge,                     // shared with the worker, see try_query_task
+    pub slow_requests: Option<SlowRequests>,     // one of the sinks, None when ServerConfig::slow_requests is not set
+    pub query_cache: Option<QueryCache>,         // one of the sinks, None when ServerConfig::query_cache is off
+    pub negative_cache: Option<NegativeCache>,   // one of the sinks, None when ServerConfig::negative_cache is not set
+    pub hedges: Option<Hedges>,                  // shared with the worker and listener, None when ServerConfig::hedge_after is not set
+    pub coalescer: Option<QueryCoalescer>,       // one of the sinks, None when ServerConfig::coalesce_queries is off
+    pub replicas: Option<ReplicaStore>,          // shared with the worker and every task, None when ServerConfig::replica_interval is not set
+    #[cfg(feature = "profiler")]
+    pub profiler: Option<Profiler>,              // samples every thread's probe, None when ServerConfig::profiler is not set
+    pub updates: UpdateRegistry,                 // update functions by name, for templates, see register_update
+    pub templates: HashMap<String, TaskTemplate>, // see register_template
+    pub checkpoint_chain: Option<CheckpointChain>, // the incremental checkpoint being written, see checkpoint_incremental
+}
+
+impl Default for ServerThread {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerThread {
+    pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+
+    pub fn with_config(config: ServerConfig) -> Self {
+        // shutdown behaviour is based on idle time
+        // if server does not send a task in a span of LISTENER_TIMEOUT idle time, listener thread shuts down as well as the worker
+        // idle time gets reset every time we have confirmation of a new TaskRequest because of the behaviour of recv_timeout
+        let shutdown_flag = Arc::new(AtomicBool::new(false)); // shutdown flag to be shared between listener and worker
+
+        // one shard of the store per listener shard
+        let results = ResultStore::new(config.listener_shards);
+
+        let events = EventBus::new();
+
+        let mut worker = WorkerThread::new(events.clone(), config.clone());
+        // channel for server-worker comm, it wakes the worker through the same doorbell as the admin channel
+        let doorbell = worker.doorbell();
+        let (worker_tx, worker_rx) = backpressure::channel_with_doorbell(doorbell.clone());
+        let dead_letter_queue = worker.dead_letters();
+        let active_tasks = worker.active_tasks();
+        let presence = worker.presence();
+        let max_concurrent_tasks = worker.max_concurrent_tasks();
+        let watchdog = worker.watchdog();
+        let faults = worker.faults();
+        let task_senders = worker.task_senders();
+        let tracer = worker.tracer();
+        let queue_waits = worker.queue_waits();
+        let lifecycle = worker.lifecycle();
+        let admin_tx = worker.admin_sender();
+        let intake = worker.intake();
+        let hedges = worker.hedges();
+        let replicas = worker.replicas();
+        let tenants = worker.tenants();
+        let groups = worker.groups();
+        let finished_tasks = worker.finished_tasks();
+        let deadlines = worker.deadlines();
+        #[cfg(feature = "profiler")]
+        let profiler = worker.profiler();
+        let listener_probes: Vec<Probe> =
+            (0..results.shard_count()).map(|_| worker.probe(Component::Listener)).collect();
+
+        let heartbeat = config.heartbeat.map(HeartbeatMonitor::new);
+        let beats = heartbeat.is_some().then(|| worker.heartbeats());
+        // the monitor tells the supervisor about a dead worker, only when asked to
+        let (revive_tx, revive_rx) = match config.heartbeat {
+            Some(heartbeat) if heartbeat.restart => {
+                let (revive_tx, revive_rx) = mpsc::channel();
+                (Some(revive_tx), Some(revive_rx))
+            }
+            _ => (None, None),
+        };
+
+        // worker thread, under a supervisor when configured
+        let worker_handle = if config.supervise_worker {
+            supervisor::spawn_supervised(worker, worker_rx, revive_rx, Arc::clone(&shutdown_flag), events.clone())
+        } else {
+            thread::spawn({
+                let shutdown = Arc::clone(&shutdown_flag);
+                move || worker.run(&worker_rx, shutdown)
+            })
+        };
+
+        // heartbeat monitor thread, only when heartbeats are configured
+        if let (Some(heartbeat), Some(beats)) = (&heartbeat, beats) {
+            heartbeat.spawn(beats, Arc::clone(&config.clock), revive_tx, Arc::clone(&shutdown_flag));
+        }
+
+        // sampler thread, only when the server is profiled
+        #[cfg(feature = "profiler")]
+        if let Some(profiler) = &profiler {
+            profiler.spawn(Arc::clone(&shutdown_flag));
+        }
+
+        // watchdog thread, only when a slow task threshold is configured
+        if let Some(threshold) = config.slow_task_threshold {
+            watchdog.spawn(threshold, events.clone(), Arc::clone(&shutdown_flag));
+        }
+
+        // chaos thread, only when chaos is configured
+        if let Some(chaos) = config.chaos {
+            Chaos::new(chaos, Arc::clone(&task_senders), worker_tx.clone(), events.clone(), Arc::clone(&config.clock))
+                .spawn(Arc::clone(&shutdown_flag));
+        }
+
+        // failure thread, only when failures are scheduled
+        if let Some(schedule) = config.failures.clone() {
+            FailureRunner::new(schedule, task_senders, worker_tx.clone(), faults.clone(), events.clone(), Arc::clone(&config.clock))
+                .spawn(Arc::clone(&shutdown_flag));
+        }
+
+        // autoscaler thread, only when autoscaling is configured
+        let autoscaler = config.autoscale.map(|autoscale| Autoscaler::new(autoscale, Arc::clone(&config.clock)));
+        if let Some(autoscaler) = &autoscaler {
+            autoscaler.spawn(
+                Arc::clone(&max_concurrent_tasks),
+                Arc::clone(&active_tasks),
+                worker_tx.clone(),
+                events.clone(),
+                Arc::clone(&shutdown_flag),
+            );
+        }
+
+        let history = config.task_history.map(TaskHistory::new);
+        let result_subscribers = ResultSubscribers::default();
+        // the configured sinks first, then the built-in ones, the store last
+        let mut sinks = config.result_sinks.clone();
+        if let Some(history) = &history {
+            sinks = sinks.then(history.clone());
+        }
+        let result_counts = ResultCounts::new(lifecycle.clone());
+        let circuit_breaker = config.circuit_breaker.map(|breaker| CircuitBreaker::new(breaker, Arc::clone(&config.clock)));
+        if let Some(breaker) = &circuit_breaker {
+            sinks = sinks.then(breaker.clone());
+        }
+        let slow_requests = config.slow_requests.map(|slow| SlowRequests::new(slow, lifecycle.clone(), events.clone()));
+        if let Some(slow_requests) = &slow_requests {
+            sinks = sinks.then(slow_requests.clone());
+        }
+        let query_cache = config.query_cache.then(QueryCache::new);
+        if let Some(query_cache) = &query_cache {
+            sinks = sinks.then(query_cache.clone());
+        }
+        let negative_cache = config.negative_cache.map(|negative| NegativeCache::new(negative, Arc::clone(&config.clock)));
+        if let Some(negative_cache) = &negative_cache {
+            sinks = sinks.then(negative_cache.clone());
+        }
+        // a query that takes longer than its task may idle is lost, see assumption 1
+        let coalescer = config
+            .coalesce_queries
+            .then(|| QueryCoalescer::new(Arc::clone(&config.clock), config.timeouts.task));
+        if let Some(coalescer) = &coalescer {
+            sinks = sinks.then(coalescer.clone());
+        }
+        let sinks = sinks.then(result_subscribers.clone()).then(result_counts.clone()).then(results.clone());
+
+        // listener threads, one per shard, each with its own channel for task-server comm for results
+        let last_activity = Arc::new(AtomicU64::new(0));
+        let shutdown_hooks = ShutdownHooks::new();
+        let live_shards = Arc::new(AtomicUsize::new(results.shard_count()));
+        let (result_txs, listener_handles) = listener_probes
+            .into_iter()
+            .enumerate()
+            .map(|(shard, probe)| {
+                let (result_tx, result_rx) = mpsc::channel::<TaskResult>();
+                let listener = ListenerThread {
+                    shard,
+                    results: results.clone(),
+                    last_activity: Arc::clone(&last_activity),
+                    live_shards: Arc::clone(&live_shards),
+                    watchdog: watchdog.clone(),
+                    lifecycle: lifecycle.clone(),
+                    tracer: tracer.clone(),
+                    autoscaler: autoscaler.clone(),
+                    sinks: sinks.clone(),
+                    events: events.clone(),
+                    faults: faults.clone(),
+                    shutdown: ShutdownSignal::new(Arc::clone(&shutdown_flag), doorbell.clone()),
+                    shutdown_hooks: shutdown_hooks.clone(),
+                    clock: Arc::clone(&config.clock),
+                    idle_timeout: config.timeouts.listener,
+                    run_mode: config.run_mode,
+                    probe,
+                    chunks: RefCell::new(ChunkAssembler::default()),
+                    hedges: hedges.clone(),
+                };
+                (result_tx, thread::spawn(move || listener.run(result_rx)))
+            })
+            .unzip();
+
+        let started_at = config.clock.now();
+        Self {
+            worker_tx,
+            admin_tx,
+            result_txs,
+            request_counter: 0,
+            task_id_counter: 0,
+            results,
+            listener_handles,
+            worker_handle: Some(worker_handle),
+            worker_summary: None,
+            events,
+            dead_letter_queue,
+            rate_limiter: config.rate_limit.map(|limit| RateLimiter::new(limit, Arc::clone(&config.clock))),
+            request_tenants: HashMap::new(),
+            tenants,
+            groups,
+            finished_tasks,
+            deadlines,
+            active_tasks,
+            presence,
+            max_concurrent_tasks,
+            accepting: true,
+            shutdown_flag,
+            shutdown_hooks,
+            faults,
+            clock: config.clock,
+            timeouts: config.timeouts,
+            recorder: None,
+            lifecycle,
+            tracer,
+            queue_waits,
+            history,
+            result_subscribers,
+            sinks,
+            result_counts,
+            started_at,
+            interceptors: InterceptorChain::new(),
+            circuit_breaker,
+            heartbeat,
+            ready_queue_depth: config.ready_queue_depth,
+            intake,
+            slow_requests,
+            query_cache,
+            negative_cache,
+            hedges,
+            coalescer,
+            replicas,
+            #[cfg(feature = "profiler")]
+            profiler,
+            updates: UpdateRegistry::new(),
+            templates: HashMap::new(),
+            checkpoint_chain: None,
+        }
+    }
+
+    // current issue with these two is that they are 64 bit unsigned integers and at some point they will overflow
+    // for a large system, we will need better handling of uuids than this
+    // one solution could be to maintain a pool of active tasks and TaskRequests and make sure any new generated id
+    // is not already in the pool
+    // not implemented here
+
+    // where the answer to req_id goes, the channel of the listener shard that owns its slot in the results store
+    pub fn result_tx(&self, req_id: RequestId) -> mpsc::Sender<TaskResult> {
+        self.result_txs[self.results.shard_of(req_id)].clone()
+    }
+
+    // unique TaskRequest identifier
+    pub fn next_req_id(&mut self) -> RequestId {
+        let id = self.request_counter;
+        self.request_counter += 1;
+        id
+    }
+
+    // unique task identifier
+    pub fn next_task_id(&mut self) -> TaskId {
+        let id = self.task_id_counter;
+        self.task_id_counter += 1;
+        id
+    }
+
+    // rate limiting happens here, before anything reaches the worker
+    // a rejected request is answered with RateLimited through the regular result channel so the listener records it
+    fn admit(&mut self, opts: &RequestOptions, req_id: RequestId, id: TaskId) -> bool {
+        // noted up front, so requests turned away here still count for their tenant
+        self.request_tenants.insert(req_id, opts.tenant);
+        // the listener may already be gone, so this is recorded directly instead of going through result_tx
+        if !self.accepting {
+            println!("[req:{req_id}] [ServerThread] Rejected, server is shutting down");
+            self.record(TaskResult::ShuttingDown { req_id, id });
+            return false;
+        }
+        if self.worker_liveness() == Some(Liveness::Dead) {
+            println!("[req:{req_id}] [ServerThread] Worker missed its heartbeats, failing fast");
+            let _ = self.result_tx(req_id).send(TaskResult::Undeliverable { req_id, id });
+            return false;
+        }
+        if self.circuit_breaker.as_ref().is_some_and(|breaker| !breaker.allow(req_id)) {
+            println!("[req:{req_id}] [ServerThread] Circuit is open, failing fast");
+            let _ = self.result_tx(req_id).send(TaskResult::CircuitOpen { req_id, id });
+            return false;
+        }
+        let Some(limiter) = self.rate_limiter.as_mut() else { return true };
+        if limiter.try_acquire(opts.client) {
+            return true;
+        }
+        println!("[req:{req_id}] [ServerThread] Client {} is rate limited", opts.client);
+        let _ = self.result_tx(req_id).send(TaskResult::RateLimited { req_id, id, client: opts.client });
+        false
+    }
+
+    // hands the request to the recorder, if one is attached. only builds the entry when it is needed
+    fn note(&mut self, req_id: RequestId, opts: &RequestOptions, request: impl FnOnce() -> RecordedRequest) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(req_id, opts, request());
+        }
+    }
+
+    // starts capturing every request issued from here on, with its timing on the server's clock
+    // restarts the recording if one was already running
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(Recorder::new(Arc::clone(&self.clock)));
+    }
+
+    // stops recording and returns what was captured, see Replayer
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recorder.take().map(Recorder::finish)
+    }
+
+    // every request headed for the worker goes through here, and through the interceptors first
+    // the request itself is dropped on failure, the worker is gone anyway
+    fn send(&mut self, mut opts: RequestOptions, mut request: TaskRequest) -> Result<(), mpsc::SendError<()>> {
+        let verdict = match request.reply_to() {
+            Some(_) => self.interceptors.intercept(&mut opts, &mut request),
+            None => Verdict::Continue,
+        };
+        if let Verdict::Reject(reason) = verdict {
+            if let Some((req_id, id, result_tx)) = request.reply_to() {
+                println!("[req:{req_id}] [ServerThread] Rejected by an interceptor: {reason}");
+                let _ = result_tx.send(TaskResult::Rejected { req_id, id, reason });
+            }
+            return Ok(());
+        }
+        if let Some(coalescer) = &self.coalescer {
+            if let TaskRequest::UpdateTask { id, .. }
+            | TaskRequest::SetTask { id, .. }
+            | TaskRequest::UpdateIfVersionTask { id, .. }
+            | TaskRequest::PublishTask { id, .. } = &request
+            {
+                coalescer.close(*id);
+            }
+        }
+        if let Some((req_id, id, _)) = request.reply_to() {
+            if let Some(deadline) = opts.deadline {
+                self.deadlines.set(req_id, deadline);
+            }
+            self.lifecycle.submit(req_id, id, opts.qos);
+            self.tracer.mark(req_id, id, Hop::Sent);
+            self.queue_waits.stamp(QueueHop::Worker, req_id);
+        }
+        let envelope = Envelope { opts, request };
+        if let Verdict::Delay(delay) = verdict {
+            let (faults, worker_tx, clock) = (self.faults.clone(), self.worker_tx.clone(), Arc::clone(&self.clock));
+            thread::spawn(move || {
+                clock.sleep(delay);
+                let _ = faults.send(FaultChannel::Requests, &worker_tx, envelope, Envelope::try_clone);
+            });
+            return Ok(());
+        }
+        self.faults
+            .send(FaultChannel::Requests, &self.worker_tx, envelope, Envelope::try_clone)
+            .map_err(|_| mpsc::SendError(()))
+    }
+
+    // runs every request sent from here on through interceptor, after the ones added before it
+    pub fn add_interceptor(&mut self, interceptor: impl RequestInterceptor + 'static) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub fn create_task(
+        &mut self,
+        query_map: HashMap<String, String>,
+        update_map: HashMap<String, UpdateFn>
+    ) -> TaskId {
+        self.create_task_with(RequestOptions::default(), query_map, update_map)
+    }
+
+    pub fn create_task_with(
+        &mut self,
+        opts: RequestOptions,
+        query_map: HashMap<String, String>,
+        update_map: HashMap<String, UpdateFn>
+    ) -> TaskId {
+        self.create_task_metered(opts, query_map, update_map, None)
+    }
+
+    // the task thread times every query and update of the task against meter's quota,
+    // and the update functions can report their steps to the same meter
+    pub fn create_task_metered(
+        &mut self,
+        opts: RequestOptions,
+        query_map: HashMap<String, String>,
+        update_map: HashMap<String, UpdateFn>,
+        meter: Option<Meter>,
+    ) -> TaskId {
+        self.create_task_with_store(opts, Box::new(query_map), update_map, meter)
+    }
+
+    // like create_task_metered, with the task's values kept in store instead of a HashMap
+    // a recording notes what the store holds when the task is created, a replay starts from a HashMap with the same entries
+    pub fn create_task_with_store(
+        &mut self,
+        opts: RequestOptions,
+        store: Box<dyn KvStore>,
+        update_map: HashMap<String, UpdateFn>,
+        meter: Option<Meter>,
+    ) -> TaskId {
+        self.create_task_at_version(opts, store, update_map, meter, 0, TaskSettings::default())
+    }
+
+    // a task checked by TaskBuilder::build
+    pub fn create_task_from(&mut self, spec: TaskSpec) -> TaskId {
+        self.create_task_from_with(RequestOptions::default(), spec)
+    }
+
+    pub fn create_task_from_with(&mut self, opts: RequestOptions, spec: TaskSpec) -> TaskId {
+        let TaskSpec { query_map, blobs, update_map, idle_timeout, group, priority, schema } = spec;
+        let settings = TaskSettings { blobs, schema, idle_timeout, group, priority };
+        self.create_task_at_version(opts, Box::new(query_map), update_map, None, 0, settings)
+    }
+
+    // a task that starts out at version instead of 0, for resuming one from a checkpoint
+    fn create_task_at_version(
+        &mut self,
+        opts: RequestOptions,
+        store: Box<dyn KvStore>,
+        update_map: HashMap<String, UpdateFn>,
+        meter: Option<Meter>,
+        version: u64,
+        settings: TaskSettings,
+    ) -> TaskId {
+        let req_id = self.next_req_id();
+        let id = self.next_task_id();
+        self.note(req_id, &opts, || {
+            let mut query_pairs: Vec<_> = store.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            let mut update_ids: Vec<_> = update_map.keys().cloned().collect();
+            query_pairs.sort();
+            update_ids.sort();
+            RecordedRequest::Create { id, query_map: query_pairs, update_ids }
+        });
+        if !self.admit(&opts, req_id, id) {
+            return id;
+        }
+        println!("[req:{req_id}] [ServerThread] Sending create task to worker for Task {id}");
+        let _ = self
+            .send(opts, TaskRequest::CreateTask {
+                req_id,
+                id,
+                query_map: store,
+                blobs: settings.blobs,
+                update_map,
+                schema: settings.schema,
+                meter,
+                version,
+                idle_timeout: settings.idle_timeout,
+                group: settings.group,
+                priority: settings.priority,
+                result_tx: self.result_tx(req_id),
+            });
+
+        id
+    }
+
+    // makes factory available to templates as update_id. replaces whatever was registered under that name before
+    pub fn register_update(&mut self, update_id: &str, factory: impl Fn() -> UpdateFn + Send + 'static) {
+        self.updates.insert(update_id, factory);
+    }
+
+    // every task created from the template starts with a copy of query_map and a fresh closure per update id.
+    // the update functions have to be registered first. replaces an earlier template of the same name
+    pub fn register_template(
+        &mut self,
+        name: &str,
+        query_map: HashMap<String, String>,
+        update_ids: &[&str],
+    ) -> Result<(), TemplateError> {
+        if let Some(update_id) = update_ids.iter().find(|update_id| !self.updates.contains(update_id)) {
+            return Err(TemplateError::UnknownUpdate { template: name.to_string(), update_id: update_id.to_string() });
+        }
+        let update_ids = update_ids.iter().map(|update_id| update_id.to_string()).collect();
+        self.templates.insert(name.to_string(), TaskTemplate { query_map, update_ids });
+        Ok(())
+    }
+
+    pub fn create_task_from_template(&mut self, name: &str) -> Result<TaskId, TemplateError> {
+        self.create_task_from_template_with(RequestOptions::default(), name)
+    }
+
+    pub fn create_task_from_template_with(&mut self, opts: RequestOptions, name: &str) -> Result<TaskId, TemplateError> {
+        let template = self.templates.get(name).ok_or_else(|| TemplateError::UnknownTemplate(name.to_string()))?;
+        let query_map = template.query_map.clone();
+        let update_map = template
+            .update_ids
+            .iter()
+            .filter_map(|update_id| Some((update_id.clone(), self.updates.build(update_id)?)))
+            .collect();
+        Ok(self.create_task_with(opts, query_map, update_map))
+    }
+
+    // a task whose queries and updates are answered by handler, see TaskHandler
+    pub fn create_handler_task(&mut self, handler: impl TaskHandler + 'static) -> TaskId {
+        self.create_handler_task_with(RequestOptions::default(), Box::new(handler), None)
+    }
+
+    // a handler can't be written to a recording, it is noted as a create with empty maps,
+    // so a replay makes a task that answers nothing but keeps the ids lined up
+    pub fn create_handler_task_with(&mut self, opts: RequestOptions, handler: Box<dyn TaskHandler>, meter: Option<Meter>) -> TaskId {
+        let req_id = self.next_req_id();
+        let id = self.next_task_id();
+        self.note(req_id, &opts, || RecordedRequest::Create { id, query_map: vec![], update_ids: vec![] });
+        if !self.admit(&opts, req_id, id) {
+            return id;
+        }
+        println!("[req:{req_id}] [ServerThread] Sending create handler task to worker for Task {id}");
+        let _ = self.send(opts, TaskRequest::CreateHandlerTask { req_id, id, handler, meter, result_tx: self.result_tx(req_id) });
+        id
+    }
+
+    pub fn query_task(&mut self, id: TaskId, query_id: &str) {
+        self.query_task_with(RequestOptions::default(), id, query_id)
+    }
+
+    pub fn query_task_with(&mut self, opts: RequestOptions, id: TaskId, query_id: &str) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::Query { id, query_id: query_id.to_string() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        if let Some(cache) = &self.query_cache {
+            if let Some(value) = cache.get(opts.tenant, id, query_id) {
+                println!("[req:{req_id}] [ServerThread] Query task {id} answered from the cache");
+                let _ = self.result_tx(req_id).send(TaskResult::QueryOk { req_id, id, value, access: None });
+                return;
+            }
+            cache.expect(req_id, opts.tenant, id, query_id);
+        }
+        if let Some(negative) = &self.negative_cache {
+            if let Some(ctx) = negative.get(opts.tenant, id) {
+                println!("[req:{req_id}] [ServerThread] Task {id} was just not found, answered from the negative cache");
+                let _ = self.result_tx(req_id).send(TaskResult::NotFound { req_id, id, ctx });
+                return;
+            }
+            negative.expect(req_id, opts.tenant);
+        }
+        // a stale_ok query may be answered from a replica, a query joining it would get that answer too
+        if let Some(coalescer) = self.coalescer.as_ref().filter(|_| !opts.stale_ok) {
+            if coalescer.join(opts.tenant, id, query_id, req_id, self.result_tx(req_id)) {
+                println!("[req:{req_id}] [ServerThread] Query task {id} joined an identical query in flight");
+                return;
+            }
+        }
+        match self.send(opts, TaskRequest::QueryTask {
+            req_id,
+            id,
+            query_id: query_id.to_string(),
+            result_tx: self.result_tx(req_id),
+        }) {
+            Ok(()) => {
+                println!("[req:{req_id}] [ServerThread] Query task {id} sent to worker.");
+            }
+            Err(err) => {
+                println!(
+                    "[req:{req_id}] [ServerThread] Failed to send query task {id} to worker: {err:?}"
+                );
+                if let Some(coalescer) = &self.coalescer {
+                    coalescer.failed(req_id);
+                }
+            }
+        }
+    }
+
+    // fetches key as bytes, answered with TaskResult::BytesOk. works on text values too, bypasses the query caches
+    pub fn query_bytes(&mut self, id: TaskId, key: &str, encoding: Encoding) {
+        self.query_bytes_with(RequestOptions::default(), id, key, encoding)
+    }
+
+    pub fn query_bytes_with(&mut self, opts: RequestOptions, id: TaskId, key: &str, encoding: Encoding) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::QueryBytes { id, key: key.to_string(), encoding });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        let request = TaskRequest::QueryBytesTask { req_id, id, key: key.to_string(), encoding, result_tx: self.result_tx(req_id) };
+        if let Err(err) = self.send(opts, request) {
+            println!("[req:{req_id}] [ServerThread] Failed to send bytes query to task {id}: {err:?}");
+        }
+    }
+
+    // fetches several keys in one request, answered with a single TaskResult::QueryManyOk
+    pub fn query_many(&mut self, id: TaskId, keys: &[&str]) {
+        self.query_many_with(RequestOptions::default(), id, keys)
+    }
+
+    pub fn query_many_with(&mut self, opts: RequestOptions, id: TaskId, keys: &[&str]) {
+        let req_id = self.next_req_id();
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        self.note(req_id, &opts, || RecordedRequest::QueryMany { id, keys: keys.clone() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        if let Err(err) = self.send(opts, TaskRequest::QueryManyTask { req_id, id, keys, result_tx: self.result_tx(req_id) }) {
+            println!("[req:{req_id}] [ServerThread] Failed to send multi-key query to task {id}: {err:?}");
+        }
+    }
+
+    // fetches every entry whose key matches, e.g. KeyPattern::Prefix("metrics/".into()) for a task's metrics.
+    // answered with a single TaskResult::QueryManyOk
+    pub fn query_matching(&mut self, id: TaskId, pattern: KeyPattern) {
+        self.query_matching_with(RequestOptions::default(), id, pattern)
+    }
+
+    pub fn query_matching_with(&mut self, opts: RequestOptions, id: TaskId, pattern: KeyPattern) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::QueryMatching { id, pattern: pattern.clone() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        if let Err(err) = self.send(opts, TaskRequest::QueryMatchingTask { req_id, id, pattern, result_tx: self.result_tx(req_id) }) {
+            println!("[req:{req_id}] [ServerThread] Failed to send pattern query to task {id}: {err:?}");
+        }
+    }
+
+    // the part of the JSON document under key that path points at, e.g. "$.a.b[0]".parse()?. evaluated by the task,
+    // answered with a QueryOk holding only the fragment, compact JSON. bypasses the query caches
+    pub fn query_path(&mut self, id: TaskId, key: &str, path: JsonPath) {
+        self.query_path_with(RequestOptions::default(), id, key, path)
+    }
+
+    pub fn query_path_with(&mut self, opts: RequestOptions, id: TaskId, key: &str, path: JsonPath) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::QueryPath { id, key: key.to_string(), path: path.clone() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        let request = TaskRequest::QueryPathTask { req_id, id, key: key.to_string(), path, result_tx: self.result_tx(req_id) };
+        if let Err(err) = self.send(opts, request) {
+            println!("[req:{req_id}] [ServerThread] Failed to send path query to task {id}: {err:?}");
+        }
+    }
 
-        // while no shutdown noted
-        while !shutdown_flag.load(Ordering::Relaxed) {
-            match rx.recv_timeout(Duration::from_secs(WORKER_TIMEOUT)) {
-                Ok(msg) => match msg {
-                    TaskRequest::CreateTask {
-                        req_id,
-                        id,
-                        query_map,
-                        update_map,
-                        result_tx,
-                    } => {
-                        // if active tasks are more than MAX_CONCURRENT_TASKS, throttle the oncoming tasks
-                        // these are assumed to be handled by the server (via a buffer)
-                        // worker thread does not buffer oncoming tasks when it is throttled
-
-                        // if the worker sees a lower value, Acquire ensures it also sees all 
-                        // memory writes that were made by the task thread before its Release-ordered fetch_sub.
-                        if active_tasks.load(Ordering::Acquire) >= MAX_CONCURRENT_TASKS {
-                            println!("[req:{req_id}] [WorkerThread] Task {id} rejected due to throttling");
-                            let _ = result_tx.send(TaskResult::Throttled { req_id, id });
-                            continue;
-                        }
+    pub fn update_task(&mut self, id: TaskId, update_id: &str) {
+        self.update_task_with(RequestOptions::default(), id, update_id)
+    }
 
-                        let (task_tx, task_rx) = std::sync::mpsc::channel();
-                        let task = Task { id, query_map, update_map };
+    pub fn update_task_with(&mut self, opts: RequestOptions, id: TaskId, update_id: &str) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::Update { id, update_id: update_id.to_string() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        self
+            .send(opts, TaskRequest::UpdateTask {
+                req_id,
+                id,
+                update_id: update_id.to_string(),
+                result_tx: self.result_tx(req_id),
+            })
+            .unwrap();
+    }
 
-                        task_map.lock().unwrap().insert(id, task_tx.clone());
+    // query_task, unless the worker is paused or ServerConfig::ready_queue_depth requests are already waiting for it.
+    // then it fails right away instead of queueing behind them, nothing is sent or recorded.
+    // the request id the query went out with otherwise, its result comes back like any other
+    pub fn try_query_task(&mut self, id: TaskId, query_id: &str) -> Result<RequestId, WouldBlock> {
+        self.try_query_task_with(RequestOptions::default(), id, query_id)
+    }
 
-                        // a task is created
-                        // no other thread depends on seeing the increment instantly
-                        // just bumping a counter — atomicity is enough, ordering doesn't matter here.
-                        active_tasks.fetch_add(1, Ordering::Relaxed);
+    pub fn try_query_task_with(&mut self, opts: RequestOptions, id: TaskId, query_id: &str) -> Result<RequestId, WouldBlock> {
+        self.would_block()?;
+        let req_id = self.request_counter;
+        self.query_task_with(opts, id, query_id);
+        Ok(req_id)
+    }
 
-                        println!("[req:{req_id}] [WorkerThread] Initializing task thread for Task {id}");
+    // update_task, failing right away the way try_query_task does
+    pub fn try_update_task(&mut self, id: TaskId, update_id: &str) -> Result<RequestId, WouldBlock> {
+        self.try_update_task_with(RequestOptions::default(), id, update_id)
+    }
 
-                        let task_map_cloned = Arc::clone(&task_map);
-                        let active_tasks_cloned = Arc::clone(&active_tasks);
-                        let task_thread = TaskThread { task, rx: task_rx };
+    pub fn try_update_task_with(&mut self, opts: RequestOptions, id: TaskId, update_id: &str) -> Result<RequestId, WouldBlock> {
+        self.would_block()?;
+        let req_id = self.request_counter;
+        self.update_task_with(opts, id, update_id);
+        Ok(req_id)
+    }
 
-                        thread::spawn(move || {
-                            task_thread.run();
+    // what the worker has on its channel counts as queued too, it just hasn't taken it yet
+    fn would_block(&self) -> Result<(), WouldBlock> {
+        let depth = self.worker_tx.depth() + self.intake.queued();
+        match self.ready_queue_depth {
+            Some(capacity) if depth >= capacity => Err(WouldBlock::QueueFull { depth, capacity }),
+            _ if self.intake.paused() => Err(WouldBlock::Paused),
+            _ => Ok(()),
+        }
+    }
 
-                            // task is completed
-                            task_map_cloned.lock().unwrap().remove(&id);
-                            
-                            // Ordering::Release says: "all memory writes before this (like removing from task_map) 
-                            // must be visible to other threads that later do an Acquire load on this atomic."
-                            active_tasks_cloned.fetch_sub(1, Ordering::Release);
+    // stores value under key on task id, answered with UpdateOk like an update and counted as one in the task's version.
+    // a value the task's schema doesn't allow is answered with UpdateError SchemaViolation and not stored
+    pub fn set_value(&mut self, id: TaskId, key: &str, value: &str) {
+        self.set_value_with(RequestOptions::default(), id, key, value)
+    }
 
-                            println!("[WorkerThread] Task {id} finished and removed.");
-                        });
-                    }
+    pub fn set_value_with(&mut self, opts: RequestOptions, id: TaskId, key: &str, value: &str) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::Set { id, key: key.to_string(), value: value.to_string() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        let request =
+            TaskRequest::SetTask { req_id, id, key: key.to_string(), value: value.to_string(), result_tx: self.result_tx(req_id) };
+        if let Err(err) = self.send(opts, request) {
+            println!("[req:{req_id}] [ServerThread] Failed to send set to task {id}: {err:?}");
+        }
+    }
 
-                    TaskRequest::QueryTask { req_id, id, query_id, result_tx } => {
-                        // get specific task
-                        if let Some(tx) = task_map.lock().unwrap().get(&id) {
-                            // send subset of the TaskRequest onto the specified task
-                            tx.send(TaskInstruction::Query { req_id, query_id, result_tx }).ok();
-                        } else {
-                            let _ = result_tx.send(TaskResult::NotFound {
-                                req_id,
-                                id,
-                                ctx: "Task not found for query",
-                            });
-                        }
-                    }
+    // optimistic concurrency: the update only runs if nothing else updated the task since it was at expected_version.
+    // a task starts at version 0, so a caller that saw UpdateOk knows the task moved to expected_version + 1,
+    // and one that got VersionConflict finds the current version in it
+    pub fn update_task_if_version(&mut self, id: TaskId, update_id: &str, expected_version: u64) {
+        self.update_task_if_version_with(RequestOptions::default(), id, update_id, expected_version)
+    }
 
-                    TaskRequest::UpdateTask { req_id, id, update_id, result_tx } => {
-                        // get specific task
-
-                        // this unwrap will trigger if mutex lock is poisoned.
-                        // but if mutex is poisoned the task_map is lost.
-                        // it will be poisoned when a task thread panics.
-                        // if it panics after removal from task_map, we are good. but otherwise no.
-                        // currently no code exists in TaskThread that can panic so no impl against poisoned locks has been written
-                        // if it panics, its fine. the task_map was in a dangerous state anyway
-                        if let Some(tx) = task_map.lock().unwrap().get(&id) {
-                            // send subset of the TaskRequest onto the specified task
-                            tx.send(TaskInstruction::Update { req_id, update_id, result_tx }).ok();
-                        } else {
-                            let _ = result_tx.send(TaskResult::NotFound {
-                                req_id,
-                                id,
-                                ctx: "Task not found for update",
-                            });
-                        }
-                    }
-                },
-                Err(mpsc::RecvTimeoutError::Disconnected) => {
-                    // commented this println statement out so as not to overwhlem the logs
-                    // happens often as server thread will close the sender as soon as all tasks are sent
-                    // there might be some time in between all tasks being sent and all tasks being completed.
-                    // uncommenting this would just cause a lot of annoying log messages.
+    pub fn update_task_if_version_with(&mut self, opts: RequestOptions, id: TaskId, update_id: &str, expected_version: u64) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::UpdateIfVersion {
+            id,
+            update_id: update_id.to_string(),
+            expected_version,
+        });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        let _ = self.send(opts, TaskRequest::UpdateIfVersionTask {
+            req_id,
+            id,
+            update_id: update_id.to_string(),
+            expected_version,
+            result_tx: self.result_tx(req_id),
+        });
+    }
 
-                    // println!("[WorkerThread] channel is empty and sending half is closed. Exiting.");
-                    // ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
-                    continue;
-                }
-                Err(e) => {
-                    println!("[WorkerThread] {e}");
-                    continue;
-                }
-            }
+    pub fn publish_task(&mut self, id: TaskId, topic: &str, payload: &str) {
+        self.publish_task_with(RequestOptions::default(), id, topic, payload)
+    }
+
+    pub fn publish_task_with(&mut self, opts: RequestOptions, id: TaskId, topic: &str, payload: &str) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::Publish {
+            id,
+            topic: topic.to_string(),
+            payload: payload.to_string(),
+        });
+        if !self.admit(&opts, req_id, id) {
+            return;
         }
+        let _ = self.send(opts, TaskRequest::PublishTask {
+            req_id,
+            id,
+            topic: topic.to_string(),
+            payload: payload.to_string(),
+            result_tx: self.result_tx(req_id),
+        });
+    }
 
-        println!("[WorkerThread] Shutdown flag detected. Worker exiting.");
+    pub fn subscribe_task(&mut self, id: TaskId, topic: &str) {
+        self.subscribe_task_with(RequestOptions::default(), id, topic)
     }
-}
 
-pub struct ServerThread {
-    pub worker_tx: Sender<TaskRequest>,          // transmitter from server to worker, so it has to own it
-    pub result_tx: mpsc::Sender<TaskResult>,     // owns it so it can clone the mpsc::Sender and sends it to a TaskThread
+    pub fn subscribe_task_with(&mut self, opts: RequestOptions, id: TaskId, topic: &str) {
+        let req_id = self.next_req_id();
+        self.note(req_id, &opts, || RecordedRequest::Subscribe { id, topic: topic.to_string() });
+        if !self.admit(&opts, req_id, id) {
+            return;
+        }
+        let _ = self.send(opts, TaskRequest::SubscribeTask {
+            req_id,
+            id,
+            topic: topic.to_string(),
+            result_tx: self.result_tx(req_id),
+        });
+    }
 
-    // both are AtomicUsize to ensure any operations are atomic.
-    pub request_counter: usize,
-    pub task_id_counter: usize,
+    // snapshot of every instruction the worker failed to deliver so far
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letter_queue.lock().unwrap().clone()
+    }
 
-    pub results: Arc<Mutex<Vec<Option<TaskResult>>>>,
-    pub listener_handle: Option<JoinHandle<()>>, // join handle for the listener thread
-}
+    // runs f with the id and tenant of every task the worker starts. hooks run on their own thread, see hooks.rs
+    pub fn on_task_created(&self, mut f: impl FnMut(TaskId, TenantId) + Send + 'static) {
+        hooks::spawn_hook(&self.events, "task_created", move |event| {
+            if let ServerEvent::TaskCreated { id, tenant } = event {
+                f(id, tenant)
+            }
+        });
+    }
 
-impl ServerThread {
-    pub fn new() -> Self {
-        let (worker_tx, worker_rx) = mpsc::channel(); // channel for server-worker comm
-        let (result_tx, result_rx) = mpsc::channel(); // channel for task-server comm for results
-        
-        // shutdown behaviour is based on idle time
-        // if server does not send a task in a span of LISTENER_TIMEOUT idle time, listener thread shuts down as well as the worker
-        // idle time gets reset every time we have confirmation of a new TaskRequest because of the behaviour of recv_timeout
-        let shutdown_flag = Arc::new(AtomicBool::new(false)); // shutdown flag to be shared between listener and worker
-        let shutdown_flag_for_listener = Arc::clone(&shutdown_flag);
-
-        // chance for index overflow here
-        // we are trusting that the server knows not to overflow its own vector
-        let mut results_vec = Vec::with_capacity(MAX_REQ_ID);
-        results_vec.resize_with(MAX_REQ_ID, || None);
-        let results: SharedResults = Arc::new(Mutex::new(results_vec));
-        let results_for_listener = Arc::clone(&results);
-
-        // worker thread
-        thread::spawn({
-            let shutdown = Arc::clone(&shutdown_flag);
-            move || {
-                let worker = WorkerThread::new();
-                worker.run(worker_rx, shutdown);
+    // runs f whenever a task thread exits, with why and what it did during that run
+    pub fn on_task_finished(&self, mut f: impl FnMut(TaskId, TaskExit, TaskStats) + Send + 'static) {
+        hooks::spawn_hook(&self.events, "task_finished", move |event| {
+            if let ServerEvent::TaskFinished { id, exit, stats } = event {
+                f(id, exit, stats)
             }
         });
+    }
 
-        // listener thread
-        let listener_handle = thread::spawn(move || {
-            loop {
-                match result_rx.recv_timeout(Duration::from_secs(LISTENER_TIMEOUT)) {
-                    Ok(result) => {
-                        // recieved some output from a TaskThread
-                        println!("[Listener] {:?}", result);
-        
-                        if let Some(req_id) = match &result {
-                            TaskResult::QueryOk { req_id, .. }
-                            | TaskResult::QueryError { req_id, .. }
-                            | TaskResult::UpdateOk { req_id, .. }
-                            | TaskResult::UpdateError { req_id, .. }
-                            | TaskResult::NotFound { req_id, .. }
-                            | TaskResult::Throttled { req_id, .. } => Some(*req_id),
-                            TaskResult::ReceivedRequest => None,
-                        } {
-                            let mut results = results_for_listener.lock().unwrap();
-                            if results.len() <= req_id {
-                                results.resize(req_id + 1, None);
-                            }
-                            results[req_id] = Some(result);
-                        }
-                    }
-                    Err(mpsc::RecvTimeoutError::Timeout) => {             // shutdown condition: idle time has reached LISTENER_TIMEOUT
-                        println!("[Listener] No activity. Shutting down...");
-                        shutdown_flag_for_listener.store(true, Ordering::Relaxed);
-                        break;
-                    }
-                    Err(mpsc::RecvTimeoutError::Disconnected) => {       // shutdown condition: channel has already been severed
-                        println!("[Listener] Channel disconnected. Shutting down...");
-                        shutdown_flag_for_listener.store(true, Ordering::Relaxed);
-                        break;
-                    }
-                }
+    // runs f with the req_id and task id of every create the worker throttles, e.g. to queue it for a retry
+    pub fn on_throttled(&self, mut f: impl FnMut(RequestId, TaskId) + Send + 'static) {
+        hooks::spawn_hook(&self.events, "throttled", move |event| {
+            if let ServerEvent::Throttled { req_id, id } = event {
+                f(req_id, id)
             }
         });
+    }
 
-        Self {
-            worker_tx,
-            result_tx: result_tx.clone(),
-            request_counter: 0,
-            task_id_counter: 0,
-            results,
-            listener_handle: Some(listener_handle)
+    // runs f once the server has shut down, from an idle timeout or shutdown_with alike, e.g. to flush sinks or export a report.
+    // hooks run in the order they were added on the last listener thread, after every result is recorded and before
+    // join_listener returns. f runs right away if the server has shut down already
+    pub fn on_shutdown(&self, f: impl FnOnce() + Send + 'static) {
+        self.shutdown_hooks.add(f);
+    }
+
+    // subscribe to events published on the server's EventBus (use ALL_TOPICS for everything)
+    pub fn subscribe(&self, topic: &str) -> Receiver<ServerEvent> {
+        self.events.subscribe(topic)
+    }
+
+    // every result recorded from now on that matches filter, in the order they are recorded.
+    // the filter runs in the listener, so a subscriber only ever sees what it asked for
+    pub fn subscribe_results(&self, filter: ResultFilter) -> Receiver<TaskResult> {
+        self.result_subscribers.subscribe(filter)
+    }
+
+    // pings the worker and checks on the listener
+    // the worker counts as alive if it answers the ping within HEALTH_TIMEOUT
+    pub fn health(&self) -> HealthReport {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let status = self
+            .worker_tx
+            .send(Envelope { opts: RequestOptions::default(), request: TaskRequest::Ping { reply_tx } })
+            .ok()
+            .and_then(|_| reply_rx.recv_timeout(HEALTH_TIMEOUT).ok());
+
+        let active_tasks = self.active_tasks.load(Ordering::Acquire);
+        let queue_depth = status.map_or(0, |s| s.queue_depth);
+        let readiness = if !self.accepting {
+            Readiness::Draining
+        } else if status.is_none() {
+            Readiness::WorkerDown
+        } else if active_tasks >= self.max_concurrent_tasks() {
+            Readiness::AtCapacity
+        } else if self.ready_queue_depth.is_some_and(|threshold| queue_depth >= threshold) {
+            Readiness::QueueFull
+        } else {
+            Readiness::Ready
+        };
+        HealthReport {
+            worker_alive: status.is_some(),
+            listener_alive: self.listener_handles.iter().any(|h| !h.is_finished()),
+            active_tasks,
+            queue_depth,
+            readiness,
         }
     }
 
-    // current issue with these two is that they are 64 bit unsigned integers and at some point they will overflow
-    // for a large system, we will need better handling of uuids than this
-    // one solution could be to maintain a pool of active tasks and TaskRequests and make sure any new generated id
-    // is not already in the pool
-    // not implemented here
+    // on SIGINT or SIGTERM the worker is drained, the listener gets to record what comes back, the result sinks are
+    // flushed and the process exits. meant for long interactive runs that shouldn't lose their results to a ctrl-c
+    #[cfg(feature = "signals")]
+    pub fn shutdown_on_signal(&self) -> std::io::Result<()> {
+        signals::shutdown_on_signal(
+            self.worker_tx.clone(),
+            Arc::clone(&self.shutdown_flag),
+            self.sinks.clone(),
+            self.timeouts.listener,
+        )
+    }
 
-    // unique TaskRequest identifier
-    pub fn next_req_id(&mut self) -> RequestId {
-        let id = self.request_counter;
-        self.request_counter += 1;
-        id
+    // hands an operator command to the worker, ahead of whatever requests it has queued.
+    // a worker that is blocked waiting for requests wakes up for it
+    pub fn admin(&self, command: AdminCommand) {
+        let _ = self.admin_tx.send(command);
     }
 
-    // unique task identifier
-    pub fn next_task_id(&mut self) -> TaskId {
-        let id = self.task_id_counter;
-        self.task_id_counter += 1;
-        id
+    // None if the worker did not answer within HEALTH_TIMEOUT
+    pub fn worker_stats(&self) -> Option<WorkerStats> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.admin(AdminCommand::DumpStats { reply_tx });
+        reply_rx.recv_timeout(HEALTH_TIMEOUT).ok()
     }
 
-    pub fn create_task(
-        &mut self,
-        query_map: HashMap<String, String>,
-        update_map: HashMap<String, Box<dyn FnMut() -> String + Send + 'static>>
-    ) -> TaskId {
-        let req_id = self.next_req_id();
-        let id = self.next_task_id();
-        println!("[req:{req_id}] [ServerThread] Sending create task to worker for Task {id}");
-        let _ = self.worker_tx
-            .send(TaskRequest::CreateTask {
-                req_id,
-                id,
-                query_map,
-                update_map,
-                result_tx: self.result_tx.clone(),
-            });
+    // the counters of a running task, asked behind whatever it already has queued. like every admin command this
+    // overtakes requests still waiting in the worker's queues, those aren't counted yet.
+    // None if there is no such task or it did not answer within the task timeout
+    pub fn task_stats(&self, id: TaskId) -> Option<TaskStats> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.admin(AdminCommand::TaskStatus { id, reply_tx });
+        reply_rx.recv_timeout(self.timeouts.task).ok()
+    }
 
-        id
+    // whether the worker has been beating lately, as of the monitor's last look. None without ServerConfig::heartbeat
+    pub fn worker_liveness(&self) -> Option<Liveness> {
+        self.heartbeat.as_ref().map(HeartbeatMonitor::liveness)
     }
 
-    pub fn query_task(&mut self, id: TaskId, query_id: &str) {
-        let req_id = self.next_req_id();
-        match self.worker_tx.send(TaskRequest::QueryTask {
-            req_id,
-            id,
-            query_id: query_id.to_string(),
-            result_tx: self.result_tx.clone(),
-        }) {
-            Ok(()) => {
-                println!("[req:{req_id}] [ServerThread] Query task {id} sent to worker.");
+    // None when no circuit breaker is configured
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.circuit_breaker.as_ref().map(CircuitBreaker::state)
+    }
+
+    // totals since the server was created, read off the results the listener and the server have recorded so far
+    pub fn stats(&self) -> ServerStats {
+        ServerStats {
+            requests: self.request_counter,
+            results: self.result_counts.by_kind(),
+            active_tasks: self.active_tasks.load(Ordering::Acquire),
+            throttled: self.result_counts.count("throttled"),
+            average_latency: self.result_counts.average_latency(),
+            slow_requests: self.slow_requests.as_ref().map_or(0, SlowRequests::count),
+            uptime: self.clock.now().saturating_sub(self.started_at),
+            channel: self.worker_tx.stats(),
+            hedges: self.hedges.as_ref().map(Hedges::stats).unwrap_or_default(),
+            coalesced: self.coalescer.as_ref().map_or(0, QueryCoalescer::coalesced),
+            replica_reads: self.replicas.as_ref().map_or(0, ReplicaStore::reads),
+        }
+    }
+
+    // how long requests have waited on the worker's channel or their tasks' so far, to see which one backs up
+    pub fn queue_wait(&self, hop: QueueHop) -> WaitDistribution {
+        self.queue_waits.distribution(hop)
+    }
+
+    // the slowest requests over the ServerConfig::slow_requests threshold so far, slowest first. empty when it is not set
+    pub fn slowest_requests(&self) -> Vec<SlowRequest> {
+        self.slow_requests.as_ref().map_or_else(Vec::new, SlowRequests::slowest)
+    }
+
+    // what the worker, the tasks and the listener were seen doing so far. None unless ServerConfig::profiler is set
+    #[cfg(feature = "profiler")]
+    pub fn profile(&self) -> Option<ProfileReport> {
+        self.profiler.as_ref().map(Profiler::report)
+    }
+
+    // the counters of every task run that has ended, added up. unlike worker_stats this still works once the worker is gone
+    pub fn finished_task_stats(&self) -> TaskStats {
+        *self.finished_tasks.lock().unwrap()
+    }
+
+    // running tasks in id order, empty if the worker did not answer
+    pub fn list_tasks(&self) -> Vec<TaskId> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.admin(AdminCommand::ListTasks { reply_tx });
+        reply_rx.recv_timeout(HEALTH_TIMEOUT).unwrap_or_default()
+    }
+
+    // whether task id is running, of whichever tenant. answered from a bit the worker keeps per task, without a request
+    // or the task map's lock, so it is cheap enough to ask all the time. a task that expired and is kept for
+    // ServerConfig::respawn_expired doesn't exist until a request brings it back
+    pub fn task_exists(&self, id: TaskId) -> bool {
+        self.presence.contains(id)
+    }
+
+    // drops whatever the query cache has for task id, the next query of each key goes to the worker again
+    pub fn invalidate_cached(&self, id: TaskId) {
+        if let Some(cache) = &self.query_cache {
+            cache.invalidate(id);
+        }
+    }
+
+    // false if there was no such task or the worker did not answer
+    pub fn kill_task(&self, id: TaskId) -> bool {
+        self.invalidate_cached(id);
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.admin(AdminCommand::KillTask { id, reply_tx });
+        reply_rx.recv_timeout(HEALTH_TIMEOUT).unwrap_or(false)
+    }
+
+    // the worker keeps taking requests off its channel but handles none of them until resume_worker, so a burst can be
+    // staged in its queues and let go at once. pings, admin commands and shutdowns still get through.
+    // applies to every request sent after this returns. mind the listener timeout, a long pause looks idle to it
+    pub fn pause_worker(&self) {
+        self.admin(AdminCommand::PauseIntake);
+    }
+
+    // the worker works through whatever piled up while it was paused, in the usual QoS and fair queueing order
+    pub fn resume_worker(&self) {
+        self.admin(AdminCommand::ResumeIntake);
+    }
+
+    // writes the state of every task to path, see Checkpoint. each task is asked behind whatever it already has queued,
+    // so requests sent before this are reflected. tasks that don't answer within the task timeout are left out,
+    // so are handler tasks, and both are listed as skipped
+    pub fn checkpoint(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<CheckpointSummary> {
+        let (tasks, skipped) = self.snapshot_tasks();
+        let written = tasks.len();
+        Checkpoint { tasks, ..Default::default() }.save(path)?;
+        println!("[ServerThread] Checkpointed {written} task(s), skipped {skipped:?}");
+        Ok(CheckpointSummary { written, skipped })
+    }
+
+    // like checkpoint, but only the first one to path writes every task in full. the ones after it append what
+    // changed since the one before: values that are new or differ, keys that were removed, tasks that are gone.
+    // tasks that started in between are written in full. cheap for tasks with large stores that hardly change.
+    // checkpointing to another path starts a new chain there. written is how many tasks changed, a skipped task
+    // isn't taken for gone
+    pub fn checkpoint_incremental(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<CheckpointSummary> {
+        let (tasks, skipped) = self.snapshot_tasks();
+        let chain = match &mut self.checkpoint_chain {
+            Some(chain) if chain.path == path.as_ref() => chain,
+            chain => chain.insert(CheckpointChain::new(&path)),
+        };
+        let first = chain.links == 0;
+        let link = chain.next(tasks, &skipped);
+        if first {
+            link.save(&path)?;
+        } else {
+            link.append(&path)?;
+        }
+        let written = link.tasks.len() + link.deltas.len();
+        println!("[ServerThread] Checkpointed {written} changed task(s), skipped {skipped:?}");
+        Ok(CheckpointSummary { written, skipped })
+    }
+
+    // every task's snapshot by id, and the ids of the tasks that gave none, see checkpoint
+    fn snapshot_tasks(&self) -> (Vec<TaskSnapshot>, Vec<TaskId>) {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.admin(AdminCommand::Snapshot { reply_tx });
+        let pending = reply_rx.recv_timeout(HEALTH_TIMEOUT).unwrap_or_default();
+        let deadline = Instant::now() + self.timeouts.task;
+        let mut tasks = vec![];
+        let mut skipped = vec![];
+        for (id, rx) in pending {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(task) => tasks.push(task),
+                Err(_) => skipped.push(id),
             }
-            Err(err) => {
-                println!(
-                    "[req:{req_id}] [ServerThread] Failed to send query task {id} to worker: {err:?}"
-                );
+        }
+        tasks.sort_by_key(|task| task.id);
+        skipped.sort_unstable();
+        (tasks, skipped)
+    }
+
+    // creates every task of a checkpoint again, values, version, tenant and all, with update functions from registry.
+    // on a fresh server the tasks keep their ids, otherwise they get new ones the way a replay does.
+    // an incremental checkpoint is restored to its last link first.
+    // returns the ids in checkpoint order. nothing is created if the registry lacks one of the update functions
+    pub fn resume(&mut self, path: impl AsRef<std::path::Path>, registry: &UpdateRegistry) -> Result<Vec<TaskId>, CheckpointError> {
+        let tasks = Checkpoint::load(path)?.restore()?;
+        for task in &tasks {
+            if let Some(update_id) = task.update_ids.iter().find(|update_id| !registry.contains(update_id)) {
+                return Err(CheckpointError::UnknownUpdate { id: task.id, update_id: update_id.clone() });
+            }
+        }
+        let mut ids = vec![];
+        for task in tasks {
+            let update_map = task.update_ids.iter().filter_map(|u| Some((u.clone(), registry.build(u)?))).collect();
+            let store: HashMap<String, String> = task.values.into_iter().collect();
+            let opts = RequestOptions { tenant: task.tenant, ..Default::default() };
+            self.task_id_counter = self.task_id_counter.max(task.id);
+            let id = self.create_task_at_version(opts, Box::new(store), update_map, None, task.version, TaskSettings::default());
+            if id != task.id {
+                println!("[ServerThread] Task {} is resumed as Task {id}", task.id);
             }
+            ids.push(id);
         }
+        Ok(ids)
     }
 
-    pub fn update_task(&mut self, id: TaskId, update_id: &str) {
-        let req_id = self.next_req_id();
-        self.worker_tx
-            .send(TaskRequest::UpdateTask {
-                req_id,
-                id,
-                update_id: update_id.to_string(),
-                result_tx: self.result_tx.clone(),
-            })
-            .unwrap();
+    // moves the throttling limit on the live worker. lowering it below the number of running tasks stops nothing,
+    // new creates are throttled until enough tasks have exited
+    pub fn set_max_concurrent_tasks(&self, n: usize) {
+        self.max_concurrent_tasks.store(n, Ordering::Relaxed);
+    }
+
+    pub fn max_concurrent_tasks(&self) -> usize {
+        self.max_concurrent_tasks.load(Ordering::Relaxed)
+    }
+
+    // requests sent to the worker that it hasn't taken off its channel yet. unlike HealthReport::queue_depth
+    // this needs no ping, and it is what grows when the worker falls behind the server
+    pub fn queue_depth(&self) -> usize {
+        self.worker_tx.depth()
+    }
+
+    // calls callback with the depth every time queue_depth() climbs to mark, so a caller can back off
+    // before the worker's channel grows without bound. replaces any earlier mark
+    pub fn set_high_water_mark(&self, mark: usize, callback: impl Fn(usize) + Send + 'static) {
+        self.worker_tx.set_high_water(mark, Box::new(callback));
+    }
+
+    pub fn clear_high_water_mark(&self) {
+        self.worker_tx.clear_high_water();
+    }
+
+    fn record(&self, result: TaskResult) {
+        let Some(req_id) = result.req_id() else { return };
+        self.lifecycle.complete(req_id);
+        self.sinks.accept(req_id, &result);
+    }
+
+    // requests a task acknowledged with ReceivedRequest that have no result yet, in req_id order
+    // anything still in here after the listener has exited was accepted but never completed
+    pub fn unanswered_requests(&self) -> Vec<RequestId> {
+        self.lifecycle.unanswered()
+    }
+
+    // None for req_ids the server never sent or answered
+    pub fn request_state(&self, req_id: RequestId) -> Option<RequestState> {
+        self.lifecycle.state(req_id)
+    }
+
+    // the recorded result for req_id with its timing attached, None while there is no result yet
+    // the timing is None for results the server recorded itself without sending anything, e.g. RateLimited
+    pub fn result_envelope(&self, req_id: RequestId) -> Option<ResultEnvelope> {
+        let result = self.results.get(req_id)?;
+        let meta = self.lifecycle.get(req_id).and_then(|lifecycle| lifecycle.meta());
+        Some(ResultEnvelope { result, meta })
+    }
+
+    // the last results recorded for a task, oldest first. empty unless ServerConfig::task_history is set
+    pub fn task_history(&self, id: TaskId) -> Vec<TaskResult> {
+        self.history.as_ref().map_or_else(Vec::new, |history| history.get(id))
+    }
+
+    // writes a one-line-per-result summary of every recorded result to path, for looking at a run once it is over.
+    // call it after shutdown_with or join_listener to have everything in it
+    pub fn export_results(&self, format: ExportFormat, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let results = self.results.snapshot().into_iter().enumerate().filter_map(|(req_id, result)| {
+            Some((req_id, result?, self.lifecycle.get(req_id).and_then(|lifecycle| lifecycle.meta())))
+        });
+        export::write(format, results, &mut out)
+    }
+
+    // requests that have been waiting in Acknowledged for at least older_than (clock time)
+    pub fn stuck_requests(&self, older_than: Duration) -> Vec<StuckRequest> {
+        self.lifecycle.stuck(older_than)
+    }
+
+    // blocks until every request issued so far has a recorded result, so tests can assert without sleeping
+    // the timeout is real time even with a SimClock, it bounds how long the caller is willing to wait for the threads
+    // gives up when the timeout expires, or as soon as the listener has exited: whatever is still missing at that
+    // point was acknowledged (or not) but will never be answered
+    // on failure returns the req_ids that have no result
+    pub fn wait_idle(&self, timeout: Duration) -> Result<(), Vec<RequestId>> {
+        let deadline = Instant::now() + timeout;
+        let mut pending = vec![];
+        self.results.wait_until(deadline, || {
+            pending = (0..self.request_counter).filter(|req_id| !self.results.is_recorded(*req_id)).collect();
+            pending.is_empty() || self.shutdown_flag.load(Ordering::Relaxed)
+        });
+        if pending.is_empty() {
+            return Ok(());
+        }
+        println!("[ServerThread] wait_idle gave up with {} request(s) pending: {pending:?}", pending.len());
+        Err(pending)
+    }
+
+    // stops accepting requests and winds the worker and its tasks down according to mode,
+    // then waits for the listener to collect the last results. requests made afterwards are answered with ShuttingDown
+    // the listener exits as soon as every in-flight result has arrived instead of waiting out LISTENER_TIMEOUT:
+    // the server drops its own result_tx here, so the channel disconnects once the worker and tasks drop theirs
+    pub fn shutdown_with(&mut self, mode: ShutdownMode) {
+        self.begin_shutdown(mode);
+        self.join_listener();
+    }
+
+    // tells the worker and lets go of the result channels, the listeners go once the tasks have let go of theirs
+    fn begin_shutdown(&mut self, mode: ShutdownMode) {
+        if !self.accepting {
+            return;
+        }
+        self.accepting = false;
+        println!("[ServerThread] Shutting down ({mode:?})");
+        let _ = self.worker_tx.send(Envelope {
+            opts: RequestOptions::default(),
+            request: TaskRequest::Shutdown { mode },
+        });
+        for result_tx in &mut self.result_txs {
+            let (detached_tx, _) = mpsc::channel();
+            drop(std::mem::replace(result_tx, detached_tx));
+        }
     }
 
     // server thread exits early, so we let the listener handle join so it can finish executing and print its logs
+    // with RunMode::Persistent this only returns once shutdown_with has been called
     // for a system without timeouts and one with an infinitely running server thread, we can use std::thread::park
     pub fn join_listener(&mut self) {
-        if let Some(handle) = self.listener_handle.take() {
+        for handle in self.listener_handles.drain(..) {
             let _ = handle.join();
         }
     }
+
+    // blocks until the worker has exited and returns what it did, e.g. after shutdown_with or join_listener.
+    // the summary is kept in worker_summary, later calls return it again. None if the worker panicked
+    pub fn join_worker(&mut self) -> Option<WorkerSummary> {
+        if let Some(handle) = self.worker_handle.take() {
+            self.worker_summary = handle.join().ok();
+        }
+        self.worker_summary.clone()
+    }
+}
+
+
+// a server going out of scope drains like shutdown_with(ShutdownMode::Drain), but its listeners and worker only get
+// DROP_TIMEOUT to finish. whatever is still running then, e.g. a handler task that never returns, is left behind
+impl Drop for ServerThread {
+    fn drop(&mut self) {
+        self.begin_shutdown(ShutdownMode::Drain);
+        let deadline = Instant::now() + DROP_TIMEOUT;
+        for handle in std::mem::take(&mut self.listener_handles) {
+            if !join_by(handle, deadline) {
+                println!("[ServerThread] Dropped with a listener still running, leaving it behind");
+                return;
+            }
+        }
+        if let Some(handle) = self.worker_handle.take() {
+            if !join_by(handle, deadline) {
+                println!("[ServerThread] Dropped with the worker still running, leaving it behind");
+            }
+        }
+    }
 }
 
+// joins the thread unless it is still running at deadline (real time), false then
+fn join_by<T>(handle: JoinHandle<T>, deadline: Instant) -> bool {
+    while !handle.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    if !handle.is_finished() {
+        return false;
+    }
+    let _ = handle.join();
+    true
+}
 
 // this block is for testing purposes
 impl ServerThread {
     pub fn expect(&self, req_id: usize, expected: &TaskResult) -> bool {
-        let results = self.results.lock().unwrap();
-        match &results[req_id] {
+        match &self.results.get(req_id) {
             Some(actual) if actual == expected => {
                 println!("[EXPECT] req:{req_id} matched expected result.");
                 true
@@ -487,8 +3997,81 @@ impl ServerThread {
         }
     }
 
+    // like expect, but waits up to timeout (real time) for the result to be recorded instead of racing its delivery
+    // returns as soon as a result is there, a wrong one is not going to change. also gives up early once the listener has exited
+    pub fn expect_eventually(&self, req_id: RequestId, expected: &TaskResult, timeout: Duration) -> Result<(), ExpectError> {
+        let started = Instant::now();
+        let deadline = started + timeout;
+        self.results.wait_until(deadline, || self.results.is_recorded(req_id) || self.shutdown_flag.load(Ordering::Relaxed));
+        match self.results.get(req_id) {
+            Some(actual) if actual == *expected => {
+                println!("[EXPECT] req:{req_id} matched expected result.");
+                Ok(())
+            }
+            Some(actual) => {
+                let err = ExpectError::Mismatch { req_id, expected: Box::new(expected.clone()), actual: Box::new(actual) };
+                println!("[EXPECT] {err}");
+                Err(err)
+            }
+            None => {
+                let err = ExpectError::Missing { req_id, expected: Box::new(expected.clone()), waited: started.elapsed() };
+                println!("[EXPECT] {err}");
+                Err(err)
+            }
+        }
+    }
+
+    // running tasks of the task group, see ServerConfig::group_caps
+    pub fn group_active_tasks(&self, group: &str) -> usize {
+        self.groups.active(group)
+    }
+
+    // QoS class the request was sent with, kept with its lifecycle and in its ResultMeta once answered
+    pub fn class_of(&self, req_id: RequestId) -> Option<QosClass> {
+        self.lifecycle.get(req_id).map(|lifecycle| lifecycle.class)
+    }
+
+    // tenant the request was sent for
+    pub fn tenant_of(&self, req_id: RequestId) -> Option<TenantId> {
+        self.request_tenants.get(&req_id).copied()
+    }
+
+    // every recorded result for requests of the given tenant, in req_id order
+    pub fn results_for_tenant(&self, tenant: TenantId) -> Vec<TaskResult> {
+        self.results
+            .snapshot()
+            .into_iter()
+            .enumerate()
+            .filter(|(req_id, _)| self.tenant_of(*req_id) == Some(tenant))
+            .filter_map(|(_, result)| result)
+            .collect()
+    }
+
+    // requests, answers and running tasks of one tenant
+    pub fn tenant_stats(&self, tenant: TenantId) -> TenantStats {
+        let results = self.results_for_tenant(tenant);
+        TenantStats {
+            requests: self.request_tenants.values().filter(|t| **t == tenant).count(),
+            answered: results.len(),
+            errors: results.iter().filter(|result| result.error_code().is_some()).count(),
+            active_tasks: self.tenants.active(tenant),
+            served: self.tenants.served(tenant),
+        }
+    }
+
+    // every recorded result for requests of the given class, in req_id order
+    pub fn results_for_class(&self, class: QosClass) -> Vec<TaskResult> {
+        self.results
+            .snapshot()
+            .into_iter()
+            .enumerate()
+            .filter(|(req_id, _)| self.class_of(*req_id) == Some(class))
+            .filter_map(|(_, result)| result)
+            .collect()
+    }
+
     pub fn expect_none(&self, req_id: usize) -> bool {
-        self.results.lock().unwrap()[req_id].is_none()
+        !self.results.is_recorded(req_id)
     }
     
 }
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Clock, QosClass, RequestId, TaskId, TaskResult};
+
+// completed requests a LifecycleTable keeps by default, the oldest completion makes room for the next
+pub const COMPLETED_REQUESTS_KEPT: usize = 10_000;
+
+// where a request is in its lifecycle. it only ever moves forward: Submitted -> Acknowledged -> Completed,
+// and requests that never reach a task (creates, rejections) go straight from Submitted to Completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestState {
+    Submitted,    // the server sent it to the worker
+    Acknowledged, // a task sent ReceivedRequest for it
+    Completed,    // its result was recorded
+}
+
+// what has been seen of one request so far, times are clock times
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestLifecycle {
+    pub id: TaskId,
+    pub submitted_at: Option<Duration>,
+    pub dequeued_at: Option<Duration>, // the worker took it out of its QoS queues to handle it
+    pub acknowledged_at: Option<Duration>,
+    pub execution: Option<Duration>,   // how long its task spent on it, measured by the task
+    pub completed_at: Option<Duration>,
+    pub respawned: bool, // its task had expired and was brought back for it
+    pub class: QosClass, // the QoS class it was sent with
+}
+
+impl RequestLifecycle {
+    // a late acknowledgement, e.g. one delayed by the fault injector, doesn't move a completed request back
+    pub fn state(&self) -> RequestState {
+        if self.completed_at.is_some() {
+            RequestState::Completed
+        } else if self.acknowledged_at.is_some() {
+            RequestState::Acknowledged
+        } else {
+            RequestState::Submitted
+        }
+    }
+
+    // None until the request has been both sent and answered
+    pub fn meta(&self) -> Option<ResultMeta> {
+        Some(ResultMeta {
+            enqueued_at: self.submitted_at?,
+            dequeued_at: self.dequeued_at,
+            execution: self.execution,
+            completed_at: self.completed_at?,
+            respawned: self.respawned,
+            class: self.class,
+        })
+    }
+}
+
+// timing of a completed request, attached to its result by ServerThread::result_envelope. times are clock times
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResultMeta {
+    pub enqueued_at: Duration,         // the server sent it to the worker
+    pub dequeued_at: Option<Duration>, // the worker picked it up, None if it was turned away before that
+    pub execution: Option<Duration>,   // time its task spent on it, None for requests no task handled
+    pub completed_at: Duration,        // the listener recorded the result
+    pub respawned: bool,               // its task had expired and was respawned to take it
+    pub class: QosClass,               // the QoS class it was sent with
+}
+
+impl ResultMeta {
+    // time spent waiting on the worker channel and in the worker's queues
+    pub fn queueing(&self) -> Option<Duration> {
+        Some(self.dequeued_at?.saturating_sub(self.enqueued_at))
+    }
+
+    pub fn total(&self) -> Duration {
+        self.completed_at.saturating_sub(self.enqueued_at)
+    }
+}
+
+// a recorded result and, for requests that went through the worker, its timing
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultEnvelope {
+    pub result: TaskResult,
+    pub meta: Option<ResultMeta>,
+}
+
+// a request a task acknowledged and has not answered for a while, see LifecycleTable::stuck
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StuckRequest {
+    pub req_id: RequestId,
+    pub id: TaskId,
+    pub acknowledged_for: Duration, // clock time since the acknowledgement
+}
+
+#[derive(Default)]
+struct Entries {
+    by_req_id: HashMap<RequestId, RequestLifecycle>,
+    // completed requests, in the order they completed
+    completed: VecDeque<RequestId>,
+}
+
+impl Entries {
+    // None for a request never submitted, or one completed long enough ago to have been dropped. a late duplicate
+    // of its acknowledgement or result must not bring it back, it would sit there never to complete
+    fn entry(&mut self, req_id: RequestId) -> Option<&mut RequestLifecycle> {
+        self.by_req_id.get_mut(&req_id)
+    }
+}
+
+// per-request lifecycle, filled in by the server as it sends requests and by the listener as acknowledgements
+// and results come in. requests still on their way are all kept, completed ones only up to a capacity like
+// TaskHistory, so a persistent server doesn't grow it forever. meta() and class_of are gone for the ones dropped.
+// only submit adds a request, the transitions after it leave one they don't know alone.
+// cloning is cheap, every clone shares the same table
+#[derive(Clone)]
+pub struct LifecycleTable {
+    entries: Arc<Mutex<Entries>>,
+    clock: Arc<dyn Clock>,
+    completed_kept: usize,
+}
+
+impl LifecycleTable {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self::with_capacity(clock, COMPLETED_REQUESTS_KEPT)
+    }
+
+    // keeps the last completed_kept completed requests
+    pub fn with_capacity(clock: Arc<dyn Clock>, completed_kept: usize) -> Self {
+        Self { entries: Arc::new(Mutex::new(Entries::default())), clock, completed_kept }
+    }
+
+    pub fn submit(&self, req_id: RequestId, id: TaskId, class: QosClass) {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.by_req_id.entry(req_id).or_insert_with(|| empty(id));
+        entry.submitted_at.get_or_insert(now);
+        entry.class = class;
+    }
+
+    pub fn dequeue(&self, req_id: RequestId) {
+        let now = self.clock.now();
+        if let Some(entry) = self.entries.lock().unwrap().entry(req_id) {
+            entry.dequeued_at.get_or_insert(now);
+        }
+    }
+
+    pub fn executed(&self, req_id: RequestId, execution: Duration) {
+        if let Some(entry) = self.entries.lock().unwrap().entry(req_id) {
+            entry.execution.get_or_insert(execution);
+        }
+    }
+
+    pub fn acknowledge(&self, req_id: RequestId) {
+        let now = self.clock.now();
+        if let Some(entry) = self.entries.lock().unwrap().entry(req_id) {
+            entry.acknowledged_at.get_or_insert(now);
+        }
+    }
+
+    pub fn respawn(&self, req_id: RequestId) {
+        if let Some(entry) = self.entries.lock().unwrap().entry(req_id) {
+            entry.respawned = true;
+        }
+    }
+
+    pub fn complete(&self, req_id: RequestId) {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.entry(req_id).filter(|entry| entry.completed_at.is_none()) else { return };
+        entry.completed_at = Some(now);
+        entries.completed.push_back(req_id);
+        while entries.completed.len() > self.completed_kept {
+            let Some(oldest) = entries.completed.pop_front() else { break };
+            entries.by_req_id.remove(&oldest);
+        }
+    }
+
+    pub fn get(&self, req_id: RequestId) -> Option<RequestLifecycle> {
+        self.entries.lock().unwrap().by_req_id.get(&req_id).copied()
+    }
+
+    pub fn state(&self, req_id: RequestId) -> Option<RequestState> {
+        self.get(req_id).map(|entry| entry.state())
+    }
+
+    // requests a task acknowledged that have no result yet, in req_id order
+    // once the listener has exited these are the ones that were accepted but will never complete
+    pub fn unanswered(&self) -> Vec<RequestId> {
+        self.stuck(Duration::ZERO).into_iter().map(|stuck| stuck.req_id).collect()
+    }
+
+    // requests that have been in Acknowledged for at least older_than, in req_id order
+    pub fn stuck(&self, older_than: Duration) -> Vec<StuckRequest> {
+        let now = self.clock.now();
+        let entries = self.entries.lock().unwrap();
+        let mut stuck: Vec<StuckRequest> = entries
+            .by_req_id
+            .iter()
+            .filter(|(_, entry)| entry.state() == RequestState::Acknowledged)
+            .filter_map(|(req_id, entry)| {
+                let acknowledged_for = now.saturating_sub(entry.acknowledged_at?);
+                (acknowledged_for >= older_than).then_some(StuckRequest { req_id: *req_id, id: entry.id, acknowledged_for })
+            })
+            .collect();
+        stuck.sort_by_key(|stuck| stuck.req_id);
+        stuck
+    }
+}
+
+fn empty(id: TaskId) -> RequestLifecycle {
+    RequestLifecycle {
+        id,
+        submitted_at: None,
+        dequeued_at: None,
+        acknowledged_at: None,
+        execution: None,
+        completed_at: None,
+        respawned: false,
+        class: QosClass::default(),
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+
+use crate::KvStore;
+
+// caps on how big a single task may be. None leaves that dimension unbounded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskLimits {
+    pub max_query_keys: Option<usize>,
+    pub max_update_fns: Option<usize>,
+    // length in bytes of any one value in the query map
+    pub max_value_bytes: Option<usize>,
+}
+
+// the first limit a task went over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Oversize {
+    QueryKeys { count: usize, max: usize },
+    UpdateFns { count: usize, max: usize },
+    ValueBytes { key: String, bytes: usize, max: usize },
+}
+
+impl fmt::Display for Oversize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Oversize::QueryKeys { count, max } => write!(f, "{count} query keys, at most {max} are allowed"),
+            Oversize::UpdateFns { count, max } => write!(f, "{count} update functions, at most {max} are allowed"),
+            Oversize::ValueBytes { key, bytes, max } => {
+                write!(f, "the value of '{key}' is {bytes} bytes, at most {max} are allowed")
+            }
+        }
+    }
+}
+
+impl TaskLimits {
+    pub fn check(&self, query_map: &dyn KvStore, update_fns: usize) -> Result<(), Oversize> {
+        if let Some(max) = self.max_query_keys {
+            let count = query_map.iter().count();
+            if count > max {
+                return Err(Oversize::QueryKeys { count, max });
+            }
+        }
+        if let Some(max) = self.max_update_fns.filter(|max| update_fns > *max) {
+            return Err(Oversize::UpdateFns { count: update_fns, max });
+        }
+        if let Some(max) = self.max_value_bytes {
+            // the smallest offending key, so the answer does not depend on the store's iteration order
+            let oversized = query_map.iter().filter(|(_, value)| value.len() > max).min_by_key(|(key, _)| *key);
+            if let Some((key, value)) = oversized {
+                return Err(Oversize::ValueBytes { key: key.to_string(), bytes: value.len(), max });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::fault::Rng;
+use crate::{Recording, ReplayError, RequestId, ServerThread, TaskId, TaskResult, UpdateFn, update_fn};
+
+// the key every generated task answers queries for, and the update every generated task runs
+pub const LOADGEN_QUERY: &str = "value";
+pub const LOADGEN_UPDATE: &str = "bump";
+// the template the generated tasks are created from, registered on the server by the first run
+pub const LOADGEN_TEMPLATE: &str = "loadgen";
+
+// exponentially distributed with the given mean. 1 - u is never 0, so the log is finite
+fn exponential(mean: f64, rng: &mut Rng) -> f64 {
+    -(1.0 - rng.next_f64()).ln() * mean
+}
+
+// pareto distributed with the given mean, heavier tailed the closer shape gets to 1. a shape of 1 or less has no mean,
+// it is taken as just above 1
+fn pareto(mean: f64, shape: f64, rng: &mut Rng) -> f64 {
+    let shape = shape.max(1.0 + f64::EPSILON);
+    let scale = mean * (shape - 1.0) / shape;
+    scale / (1.0 - rng.next_f64()).powf(1.0 / shape)
+}
+
+fn secs(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+// when the next request goes out. rates are requests per second of clock time
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arrival {
+    // evenly spaced
+    Constant { rate: f64 },
+    // exponentially distributed gaps with the given mean rate
+    Poisson { rate: f64 },
+    // burst requests back to back, with the bursts spaced so the average is still rate
+    Bursty { rate: f64, burst: usize },
+    // pareto distributed gaps with the given mean rate: mostly short, now and then a very long one
+    Pareto { rate: f64, shape: f64 },
+    // the gaps of a recorded run, one after the other, starting over once they run out. see Arrival::trace
+    Trace { gaps: Arc<[Duration]> },
+}
+
+impl Arrival {
+    // the gaps between the requests of a recording, ignoring what they were
+    pub fn trace(recording: &Recording) -> Self {
+        let gaps = recording.entries.windows(2).map(|pair| pair[1].at.saturating_sub(pair[0].at)).collect();
+        Arrival::Trace { gaps }
+    }
+
+    // the gaps of a recording saved with Recording::save, see trace
+    pub fn load_trace(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Ok(Arrival::trace(&Recording::load(path)?))
+    }
+
+    // clock time until the request after the n-th one (counting from 0), None if there is none
+    fn gap(&self, n: usize, rng: &mut Rng) -> Option<Duration> {
+        let gap = match self {
+            Arrival::Constant { rate } => secs(1.0 / rate),
+            Arrival::Poisson { rate } => secs(exponential(1.0 / rate, rng)),
+            Arrival::Bursty { rate, burst } => {
+                let burst = (*burst).max(1);
+                secs(if (n + 1).is_multiple_of(burst) { burst as f64 / rate } else { 0.0 })
+            }
+            Arrival::Pareto { rate, shape } => secs(pareto(1.0 / rate, *shape, rng)),
+            // a trace with fewer than two requests has no gaps, it sends one request and stops
+            Arrival::Trace { gaps } if gaps.is_empty() => return None,
+            Arrival::Trace { gaps } => gaps[n % gaps.len()],
+        };
+        Some(gap)
+    }
+}
+
+// how long a generated task lives before it is killed and another one takes its place, in clock time
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TaskLifetime {
+    // the same tasks for the whole run
+    #[default]
+    Forever,
+    Constant { lifetime: Duration },
+    Exponential { mean: Duration },
+    Pareto { mean: Duration, shape: f64 },
+}
+
+impl TaskLifetime {
+    // None for a task that is never retired
+    fn sample(&self, rng: &mut Rng) -> Option<Duration> {
+        match *self {
+            TaskLifetime::Forever => None,
+            TaskLifetime::Constant { lifetime } => Some(lifetime),
+            TaskLifetime::Exponential { mean } => Some(secs(exponential(mean.as_secs_f64(), rng))),
+            TaskLifetime::Pareto { mean, shape } => Some(secs(pareto(mean.as_secs_f64(), shape, rng))),
+        }
+    }
+}
+
+// what a load generator sends. the same seed sends the same sequence of requests
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadProfile {
+    // how many tasks are created up front, every request goes to one of them at random
+    pub tasks: usize,
+    // share of requests that are updates, the rest are queries. 0.0..=1.0
+    pub update_share: f64,
+    pub arrival: Arrival,
+    // how long requests keep being sent, in clock time
+    pub duration: Duration,
+    pub seed: u64,
+    // tasks past their lifetime are killed before the next request goes out and replaced with new ones.
+    // a replacement can be throttled while the task it replaces winds down, so keep tasks below the concurrency cap
+    pub task_lifetime: TaskLifetime,
+}
+
+impl Default for LoadProfile {
+    fn default() -> Self {
+        Self {
+            tasks: 4,
+            update_share: 0.2,
+            arrival: Arrival::Constant { rate: 50.0 },
+            duration: Duration::from_secs(1),
+            seed: 0,
+            task_lifetime: TaskLifetime::Forever,
+        }
+    }
+}
+
+// what a load generator run achieved. latencies are from the server sending a request to the listener recording it
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadReport {
+    pub sent: usize,
+    // answered with QueryOk or UpdateOk
+    pub succeeded: usize,
+    // answered with anything else
+    pub failed: usize,
+    // never answered before the run gave up waiting
+    pub missing: usize,
+    // tasks killed for reaching their lifetime, see LoadProfile::task_lifetime
+    pub retired: usize,
+    // clock time from the first request to the last answer
+    pub elapsed: Duration,
+    // answered requests per second of elapsed time
+    pub throughput: f64,
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sent {} ok {} failed {} missing {} in {:?} ({:.1} req/s)",
+            self.sent, self.succeeded, self.failed, self.missing, self.elapsed, self.throughput
+        )?;
+        if self.retired > 0 {
+            writeln!(f, "retired {} tasks", self.retired)?;
+        }
+        write!(f, "latency p50 {:?} p90 {:?} p99 {:?} max {:?}", self.p50, self.p90, self.p99, self.max)
+    }
+}
+
+// nearest-rank percentile of sorted latencies, p in 0.0..=100.0
+pub fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+// drives a synthetic workload against a server: creates the profile's tasks, then sends queries and updates
+// at the profile's arrival rate for its duration, and waits for the answers
+// pacing uses the server's clock. with a SimClock something else has to advance it or the run never ends
+#[derive(Debug, Clone)]
+pub struct LoadGenerator {
+    profile: LoadProfile,
+}
+
+impl LoadGenerator {
+    pub fn new(profile: LoadProfile) -> Self {
+        Self { profile }
+    }
+
+    pub fn profile(&self) -> &LoadProfile {
+        &self.profile
+    }
+
+    // wait bounds (in real time) how long to wait for the last answers once sending is done
+    pub fn run(&self, s: &mut ServerThread, wait: Duration) -> LoadReport {
+        let profile = &self.profile;
+        let mut rng = Rng(profile.seed);
+        let clock = Arc::clone(&s.clock);
+
+        if !s.templates.contains_key(LOADGEN_TEMPLATE) {
+            s.register_update(LOADGEN_UPDATE, || -> UpdateFn { update_fn(|_| Ok("bumped".to_string())) });
+            s.register_template(LOADGEN_TEMPLATE, [(LOADGEN_QUERY.to_string(), "0".to_string())].into(), &[LOADGEN_UPDATE])
+                .expect("the update was just registered");
+        }
+        let started = clock.now();
+        // every task with the clock time it is retired at, if it ever is
+        let spawn = |s: &mut ServerThread, rng: &mut Rng| -> (TaskId, Option<Duration>) {
+            let id = s.create_task_from_template(LOADGEN_TEMPLATE).expect("the template was just registered");
+            // one that would outlive the clock is never retired
+            (id, profile.task_lifetime.sample(rng).and_then(|lifetime| clock.now().checked_add(lifetime)))
+        };
+        let mut tasks: Vec<_> = (0..profile.tasks.max(1)).map(|_| spawn(s, &mut rng)).collect();
+        let mut retired = 0;
+
+        let mut sent: Vec<RequestId> = vec![];
+        let mut due = started;
+        let end = started.saturating_add(profile.duration);
+        while due < end {
+            let now = clock.now();
+            if due > now {
+                clock.sleep(due - now);
+            }
+            for task in tasks.iter_mut() {
+                if task.1.is_some_and(|retire_at| retire_at <= clock.now()) {
+                    s.kill_task(task.0);
+                    retired += 1;
+                    *task = spawn(s, &mut rng);
+                }
+            }
+            let id = tasks[(rng.next_u64() % tasks.len() as u64) as usize].0;
+            sent.push(s.request_counter);
+            if rng.next_f64() < profile.update_share {
+                s.update_task(id, LOADGEN_UPDATE);
+            } else {
+                s.query_task(id, LOADGEN_QUERY);
+            }
+            // a gap that runs past the end of the clock, a rate of 0 among them, leaves nothing more to send
+            match profile.arrival.gap(sent.len() - 1, &mut rng).and_then(|gap| due.checked_add(gap)) {
+                Some(next) => due = next,
+                None => break,
+            }
+        }
+
+        let deadline = Instant::now() + wait;
+        s.results.wait_until(deadline, || sent.iter().all(|req_id| s.results.is_recorded(*req_id)));
+        self.report(s, &sent, started, retired)
+    }
+
+    fn report(&self, s: &ServerThread, sent: &[RequestId], started: Duration, retired: usize) -> LoadReport {
+        let (mut succeeded, mut failed, mut missing) = (0, 0, 0);
+        let mut latencies = vec![];
+        let mut finished = started;
+        for req_id in sent {
+            match s.results.get(*req_id) {
+                Some(TaskResult::QueryOk { .. } | TaskResult::UpdateOk { .. }) => succeeded += 1,
+                Some(_) => failed += 1,
+                None => {
+                    missing += 1;
+                    continue;
+                }
+            }
+            if let Some(meta) = s.lifecycle.get(*req_id).and_then(|lifecycle| lifecycle.meta()) {
+                latencies.push(meta.total());
+                finished = finished.max(meta.completed_at);
+            }
+        }
+        latencies.sort();
+        let elapsed = finished.saturating_sub(started);
+        let answered = succeeded + failed;
+        LoadReport {
+            sent: sent.len(),
+            succeeded,
+            failed,
+            missing,
+            retired,
+            elapsed,
+            throughput: if elapsed.is_zero() { 0.0 } else { answered as f64 / elapsed.as_secs_f64() },
+            p50: percentile(&latencies, 50.0),
+            p90: percentile(&latencies, 90.0),
+            p99: percentile(&latencies, 99.0),
+            max: latencies.last().copied(),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Clock, RequestId, ResultSink, TaskId, TaskResult, TenantId};
+
+// how long a NotFound is remembered and for how many tasks at most, see ServerConfig::negative_cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegativeCacheConfig {
+    pub ttl: Duration,
+    pub capacity: usize,
+}
+
+impl Default for NegativeCacheConfig {
+    fn default() -> Self {
+        Self { ttl: Duration::from_secs(1), capacity: 1024 }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Misses {
+    // clock time each entry stops counting, and the ctx of the NotFound it answers with
+    entries: HashMap<(TenantId, TaskId), (Duration, &'static str)>,
+    // oldest first, the one to go when the cache is full
+    order: VecDeque<(TenantId, TaskId)>,
+    // queries on their way to the worker and whose they are
+    pending: HashMap<RequestId, TenantId>,
+    hits: usize,
+}
+
+// answers queries for a task that was just answered with NotFound the same way, without the worker and its task map,
+// until the ttl runs out. a Created for the task drops it again. the oldest entry goes once capacity is reached.
+// cloning is cheap, every clone shares the same entries
+#[derive(Clone)]
+pub struct NegativeCache {
+    config: NegativeCacheConfig,
+    misses: Arc<Mutex<Misses>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl NegativeCache {
+    pub fn new(config: NegativeCacheConfig, clock: Arc<dyn Clock>) -> Self {
+        Self { config, misses: Arc::new(Mutex::new(Misses::default())), clock }
+    }
+
+    pub fn config(&self) -> NegativeCacheConfig {
+        self.config
+    }
+
+    // the ctx of a NotFound for task id that is still fresh
+    pub fn get(&self, tenant: TenantId, id: TaskId) -> Option<&'static str> {
+        let mut misses = self.misses.lock().unwrap();
+        let now = self.clock.now();
+        let (until, ctx) = *misses.entries.get(&(tenant, id))?;
+        if until <= now {
+            misses.entries.remove(&(tenant, id));
+            misses.order.retain(|key| *key != (tenant, id));
+            return None;
+        }
+        misses.hits += 1;
+        Some(ctx)
+    }
+
+    // a query sent to the worker as req_id, a NotFound for it is remembered
+    pub(crate) fn expect(&self, req_id: RequestId, tenant: TenantId) {
+        self.misses.lock().unwrap().pending.insert(req_id, tenant);
+    }
+
+    // tasks with an entry, fresh or not
+    pub fn len(&self) -> usize {
+        self.misses.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hits(&self) -> usize {
+        self.misses.lock().unwrap().hits
+    }
+}
+
+impl fmt::Debug for NegativeCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegativeCache").field("config", &self.config).field("entries", &self.len()).finish_non_exhaustive()
+    }
+}
+
+impl ResultSink for NegativeCache {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        let mut misses = self.misses.lock().unwrap();
+        if let TaskResult::Created { id, .. } = result {
+            misses.entries.retain(|(_, task), _| task != id);
+            misses.order.retain(|(_, task)| task != id);
+            return;
+        }
+        let Some(tenant) = misses.pending.remove(&req_id) else { return };
+        let TaskResult::NotFound { id, ctx, .. } = result else { return };
+        if self.config.capacity == 0 {
+            return;
+        }
+        let Misses { entries, order, .. } = &mut *misses;
+        while entries.len() >= self.config.capacity && !entries.contains_key(&(tenant, *id)) {
+            let Some(oldest) = order.pop_front() else { break };
+            entries.remove(&oldest);
+        }
+        if entries.insert((tenant, *id), (self.clock.now() + self.config.ttl, ctx)).is_none() {
+            order.push_back((tenant, *id));
+        }
+    }
+}
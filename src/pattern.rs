@@ -0,0 +1,46 @@
+// selects keys of a task's query_map, see ServerThread::query_matching
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyPattern {
+    // every key starting with the prefix, e.g. "metrics/" for "metrics/cpu" and "metrics/mem"
+    Prefix(String),
+    // '*' matches any run of characters, '/' included, and '?' exactly one. everything else matches itself
+    Glob(String),
+}
+
+impl KeyPattern {
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            KeyPattern::Prefix(prefix) => key.starts_with(prefix.as_str()),
+            KeyPattern::Glob(glob) => glob_matches(glob.as_bytes(), key.as_bytes()),
+        }
+    }
+}
+
+// iterative matcher that backtracks to the last '*' on a mismatch, linear in practice and never recursive
+// works on bytes, so '?' matches one byte of a multi-byte character
+fn glob_matches(glob: &[u8], key: &[u8]) -> bool {
+    let (mut g, mut k) = (0, 0);
+    let mut star: Option<(usize, usize)> = None; // position after the last '*', and where in key it started matching
+    while k < key.len() {
+        match glob.get(g) {
+            Some(b'*') => {
+                star = Some((g + 1, k));
+                g += 1;
+            }
+            Some(&c) if c == b'?' || c == key[k] => {
+                g += 1;
+                k += 1;
+            }
+            _ => match star {
+                // let the last '*' swallow one more character and try again from there
+                Some((after, from)) => {
+                    star = Some((after, from + 1));
+                    g = after;
+                    k = from + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == b'*')
+}
@@ -0,0 +1,36 @@
+use crate::TaskId;
+
+// how much a task matters when capacity runs out, higher wins. tasks start at 0, see TaskBuilder::priority
+pub type Priority = u8;
+
+// what happens to the task that makes room for a more important one, see ServerConfig::preemption
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preemption {
+    // the task exits and is forgotten, later requests for it are answered with NotFound
+    Terminate,
+    // the task exits but keeps its state, the next request for it starts it again the way respawn_expired does.
+    // like any respawn that one isn't throttled
+    Pause,
+}
+
+impl std::str::FromStr for Preemption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "terminate" => Ok(Preemption::Terminate),
+            "pause" => Ok(Preemption::Pause),
+            other => Err(format!("unknown preemption '{other}'")),
+        }
+    }
+}
+
+// the task to preempt for one of the given priority: the least important of the candidates below it,
+// the oldest of those on a tie
+pub(crate) fn victim(candidates: impl IntoIterator<Item = (TaskId, Priority)>, priority: Priority) -> Option<TaskId> {
+    candidates
+        .into_iter()
+        .filter(|(_, candidate)| *candidate < priority)
+        .min_by_key(|(id, candidate)| (*candidate, *id))
+        .map(|(id, _)| id)
+}
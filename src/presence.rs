@@ -0,0 +1,65 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use crate::TaskId;
+
+// ids the first segment covers, every segment after it covers twice as many as the one before
+const FIRST_SEGMENT: usize = 4096;
+// enough segments for FIRST_SEGMENT * (2^32 - 1) ids
+const SEGMENTS: usize = 32;
+
+// a bit per task id, set while the task's thread runs. segments are allocated as ids reach them and never freed,
+// so reading a bit is a couple of atomic loads and never waits on the worker or the task map.
+// cloning is cheap, every clone shares the same bits
+#[derive(Clone)]
+pub struct TaskPresence {
+    segments: Arc<[OnceLock<Box<[AtomicU64]>>]>,
+}
+
+impl Default for TaskPresence {
+    fn default() -> Self {
+        Self { segments: (0..SEGMENTS).map(|_| OnceLock::new()).collect() }
+    }
+}
+
+// which segment id falls into, and its bit within the segment
+fn locate(id: TaskId) -> (usize, usize) {
+    let n = id / FIRST_SEGMENT + 1;
+    let segment = (usize::BITS - 1 - n.leading_zeros()) as usize;
+    (segment, id - FIRST_SEGMENT * ((1 << segment) - 1))
+}
+
+impl TaskPresence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // whether task id is running right now. a task of any tenant counts
+    pub fn contains(&self, id: TaskId) -> bool {
+        let (segment, bit) = locate(id);
+        let Some(words) = self.segments.get(segment).and_then(OnceLock::get) else { return false };
+        words[bit / 64].load(Ordering::Acquire) & (1 << (bit % 64)) != 0
+    }
+
+    pub(crate) fn insert(&self, id: TaskId) {
+        let (segment, bit) = locate(id);
+        let words = self.segments[segment]
+            .get_or_init(|| (0..(FIRST_SEGMENT << segment) / 64).map(|_| AtomicU64::new(0)).collect());
+        words[bit / 64].fetch_or(1 << (bit % 64), Ordering::Release);
+    }
+
+    pub(crate) fn remove(&self, id: TaskId) {
+        let (segment, bit) = locate(id);
+        if let Some(words) = self.segments[segment].get() {
+            words[bit / 64].fetch_and(!(1 << (bit % 64)), Ordering::Release);
+        }
+    }
+}
+
+impl fmt::Debug for TaskPresence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let allocated = self.segments.iter().filter(|segment| segment.get().is_some()).count();
+        f.debug_struct("TaskPresence").field("segments", &allocated).finish_non_exhaustive()
+    }
+}
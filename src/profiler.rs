@@ -0,0 +1,210 @@
+#[cfg(feature = "profiler")]
+use std::collections::HashMap;
+#[cfg(feature = "profiler")]
+use std::fmt;
+#[cfg(feature = "profiler")]
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+#[cfg(feature = "profiler")]
+use std::sync::{Arc, Mutex, Weak};
+#[cfg(feature = "profiler")]
+use std::thread;
+#[cfg(feature = "profiler")]
+use std::time::Duration;
+
+// what a thread is doing when the profiler looks at it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Activity {
+    Waiting,         // blocked on its channel for the next message
+    Handling,        // working on a message, outside of anything more specific below
+    LockingTaskMap,  // waiting to get hold of the worker's task map
+    ExecutingUpdate, // running a task's update function
+    Recording,       // passing a result through the sinks
+}
+
+impl Activity {
+    pub const ALL: [Activity; 5] =
+        [Activity::Waiting, Activity::Handling, Activity::LockingTaskMap, Activity::ExecutingUpdate, Activity::Recording];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Activity::Waiting => "waiting",
+            Activity::Handling => "handling",
+            Activity::LockingTaskMap => "locking task_map",
+            Activity::ExecutingUpdate => "executing update",
+            Activity::Recording => "recording",
+        }
+    }
+}
+
+// the kinds of thread the profiler samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Component {
+    Worker,
+    Task,
+    Listener,
+}
+
+impl Component {
+    pub const ALL: [Component; 3] = [Component::Worker, Component::Task, Component::Listener];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Worker => "worker",
+            Component::Task => "task",
+            Component::Listener => "listener",
+        }
+    }
+}
+
+// one thread's instrumentation point, it notes what the thread is doing for the sampler to read.
+// without the profiler feature, or for a server that isn't profiled, entering an activity does nothing
+#[derive(Debug, Clone, Default)]
+pub struct Probe {
+    #[cfg(feature = "profiler")]
+    slot: Option<Arc<AtomicU8>>,
+}
+
+impl Probe {
+    #[cfg(feature = "profiler")]
+    pub fn enter(&self, activity: Activity) {
+        if let Some(slot) = &self.slot {
+            slot.store(activity as u8, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(not(feature = "profiler"))]
+    pub fn enter(&self, _activity: Activity) {}
+}
+
+// how often the sampler looks at every probe
+#[cfg(feature = "profiler")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfilerConfig {
+    pub interval: Duration,
+}
+
+#[cfg(feature = "profiler")]
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self { interval: Duration::from_millis(1) }
+    }
+}
+
+#[cfg(feature = "profiler")]
+#[derive(Default)]
+struct Samples {
+    // a probe drops out once its thread has let go of it
+    probes: Vec<(Component, Weak<AtomicU8>)>,
+    rounds: usize,
+    counts: HashMap<(Component, Activity), usize>,
+}
+
+// hands out probes and counts what they say every interval, see ServerConfig::profiler.
+// samples are taken in real time whatever the configured clock, it is the threads' cpu that is being looked at.
+// cloning is cheap, every clone shares the same samples
+#[cfg(feature = "profiler")]
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    samples: Arc<Mutex<Samples>>,
+    config: ProfilerConfig,
+}
+
+#[cfg(feature = "profiler")]
+impl Profiler {
+    pub fn new(config: ProfilerConfig) -> Self {
+        Self { samples: Arc::new(Mutex::new(Samples::default())), config }
+    }
+
+    pub fn config(&self) -> ProfilerConfig {
+        self.config
+    }
+
+    // a probe for a new thread of the given kind, starting out Handling
+    pub fn probe(&self, component: Component) -> Probe {
+        let slot = Arc::new(AtomicU8::new(Activity::Handling as u8));
+        self.samples.lock().unwrap().probes.push((component, Arc::downgrade(&slot)));
+        Probe { slot: Some(slot) }
+    }
+
+    // one round, every live probe counts once
+    pub fn sample(&self) {
+        let mut samples = self.samples.lock().unwrap();
+        let Samples { probes, rounds, counts } = &mut *samples;
+        *rounds += 1;
+        probes.retain(|(component, slot)| {
+            let Some(slot) = slot.upgrade() else { return false };
+            let activity = Activity::ALL[slot.load(Ordering::Relaxed) as usize];
+            *counts.entry((*component, activity)).or_insert(0) += 1;
+            true
+        });
+    }
+
+    // samples every interval until the flag is raised
+    pub(crate) fn spawn(&self, shutdown_flag: Arc<AtomicBool>) {
+        let profiler = self.clone();
+        thread::spawn(move || {
+            while !shutdown_flag.load(Ordering::Relaxed) {
+                thread::sleep(profiler.config.interval);
+                profiler.sample();
+            }
+        });
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let samples = self.samples.lock().unwrap();
+        ProfileReport { rounds: samples.rounds, samples: samples.counts.clone() }
+    }
+}
+
+#[cfg(feature = "profiler")]
+impl fmt::Debug for Samples {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Samples").field("rounds", &self.rounds).field("counts", &self.counts).finish()
+    }
+}
+
+// how often each kind of thread was seen doing what, over every sampling round so far
+#[cfg(feature = "profiler")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProfileReport {
+    pub rounds: usize,
+    pub samples: HashMap<(Component, Activity), usize>,
+}
+
+#[cfg(feature = "profiler")]
+impl ProfileReport {
+    pub fn count(&self, component: Component, activity: Activity) -> usize {
+        self.samples.get(&(component, activity)).copied().unwrap_or(0)
+    }
+
+    // every sample of the component's threads, several tasks count several times per round
+    pub fn total(&self, component: Component) -> usize {
+        Activity::ALL.iter().map(|activity| self.count(component, *activity)).sum()
+    }
+
+    // fraction of the component's samples spent on activity, 0 without samples
+    pub fn share(&self, component: Component, activity: Activity) -> f64 {
+        match self.total(component) {
+            0 => 0.0,
+            total => self.count(component, activity) as f64 / total as f64,
+        }
+    }
+}
+
+// a line per component and activity that was seen, with its share of the component's samples
+#[cfg(feature = "profiler")]
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "profile over {} rounds", self.rounds)?;
+        for component in Component::ALL {
+            for activity in Activity::ALL {
+                let count = self.count(component, activity);
+                if count > 0 {
+                    let share = self.share(component, activity) * 100.0;
+                    writeln!(f, "  {:<9} {:<17} {count:>7} {share:>5.1}%", component.name(), activity.name())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
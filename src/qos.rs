@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use crate::wfq::FairQueue;
+use crate::TenantId;
+
+// quality-of-service class of a request
+// interactive requests are always preferred by the worker, batch requests get a reserved minimum share
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum QosClass {
+    #[default]
+    Interactive,
+    Batch,
+}
+
+// default for ServerConfig::batch_share
+pub const DEFAULT_BATCH_SHARE: usize = 4;
+
+// the worker's two request queues
+// pop() prefers interactive items, except that while batch items are waiting at least one out of every
+// `batch_share` pops goes to batch, so a steady stream of interactive work cannot starve batch work completely
+// within a class tenants take turns by weight, see FairQueue
+pub struct QosQueues<T> {
+    interactive: FairQueue<T>,
+    batch: FairQueue<T>,
+    batch_share: usize,
+    since_batch: usize, // pops since batch was last served
+}
+
+impl<T> QosQueues<T> {
+    // a batch_share of 0 is treated as 1 (batch and interactive alternate)
+    pub fn new(batch_share: usize) -> Self {
+        Self::with_weights(batch_share, HashMap::new())
+    }
+
+    // with the tenants' weights for fair queueing within each class
+    pub fn with_weights(batch_share: usize, weights: HashMap<TenantId, u32>) -> Self {
+        Self {
+            interactive: FairQueue::new(weights.clone()),
+            batch: FairQueue::new(weights),
+            batch_share: batch_share.max(1),
+            since_batch: 0,
+        }
+    }
+
+    // on behalf of tenant 0
+    pub fn push(&mut self, class: QosClass, item: T) {
+        self.push_for(class, 0, item)
+    }
+
+    pub fn push_for(&mut self, class: QosClass, tenant: TenantId, item: T) {
+        match class {
+            QosClass::Interactive => self.interactive.push(tenant, item),
+            QosClass::Batch => self.batch.push(tenant, item),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let batch_due = self.since_batch + 1 >= self.batch_share;
+        if !self.batch.is_empty() && (self.interactive.is_empty() || batch_due) {
+            self.since_batch = 0;
+            return self.batch.pop().map(|(_, item)| item);
+        }
+        let item = self.interactive.pop().map(|(_, item)| item);
+        if item.is_some() {
+            self.since_batch += 1;
+        }
+        item
+    }
+
+    pub fn len(&self) -> usize {
+        self.interactive.len() + self.batch.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
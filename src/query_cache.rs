@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::{RequestId, ResultSink, TaskId, TaskResult, TenantId};
+
+// how a cached value came to be, a tenant only ever sees what it queried itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    tenant: TenantId,
+    value: String,
+}
+
+#[derive(Debug, Default)]
+struct Cached {
+    entries: HashMap<(TaskId, String), Entry>,
+    // queries on their way to the worker, with the generation of their task when they were sent
+    pending: HashMap<RequestId, (TaskId, String, TenantId, u64)>,
+    // goes up with every invalidation of a task, a QueryOk sent before it is too old to be cached
+    generations: HashMap<TaskId, u64>,
+    hits: usize,
+    misses: usize,
+}
+
+// answers repeated queries on the server, without the worker, from the QueryOk of an earlier identical one.
+// a task's entries are dropped by any UpdateOk for it and by ServerThread::invalidate_cached, not by anything else:
+// a value a delivery changed or a task that expired keeps being answered from the cache, the way a caching tier
+// in front of a service goes stale. see ServerConfig::query_cache.
+// cloning is cheap, every clone shares the same entries
+#[derive(Clone, Default)]
+pub struct QueryCache {
+    cached: Arc<Mutex<Cached>>,
+}
+
+impl QueryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the cached value of query_id on task id, counted as a hit or a miss
+    pub fn get(&self, tenant: TenantId, id: TaskId, query_id: &str) -> Option<String> {
+        let mut cached = self.cached.lock().unwrap();
+        let value = cached
+            .entries
+            .get(&(id, query_id.to_string()))
+            .filter(|entry| entry.tenant == tenant)
+            .map(|entry| entry.value.clone());
+        match value {
+            Some(_) => cached.hits += 1,
+            None => cached.misses += 1,
+        }
+        value
+    }
+
+    // a query that missed is sent to the worker as req_id, its QueryOk fills the entry
+    pub(crate) fn expect(&self, req_id: RequestId, tenant: TenantId, id: TaskId, query_id: &str) {
+        let mut cached = self.cached.lock().unwrap();
+        let generation = cached.generations.get(&id).copied().unwrap_or(0);
+        cached.pending.insert(req_id, (id, query_id.to_string(), tenant, generation));
+    }
+
+    // drops every entry of task id
+    pub fn invalidate(&self, id: TaskId) {
+        let mut cached = self.cached.lock().unwrap();
+        cached.entries.retain(|(task, _), _| *task != id);
+        *cached.generations.entry(id).or_insert(0) += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.cached.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hits(&self) -> usize {
+        self.cached.lock().unwrap().hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.cached.lock().unwrap().misses
+    }
+}
+
+impl fmt::Debug for QueryCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cached = self.cached.lock().unwrap();
+        f.debug_struct("QueryCache")
+            .field("entries", &cached.entries.len())
+            .field("hits", &cached.hits)
+            .field("misses", &cached.misses)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResultSink for QueryCache {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        if let TaskResult::UpdateOk { id, .. } = result {
+            return self.invalidate(*id);
+        }
+        let mut cached = self.cached.lock().unwrap();
+        let Some((id, query_id, tenant, generation)) = cached.pending.remove(&req_id) else { return };
+        let TaskResult::QueryOk { value, .. } = result else { return };
+        // an update got in between, the value may be from before it
+        if cached.generations.get(&id).copied().unwrap_or(0) != generation {
+            return;
+        }
+        cached.entries.insert((id, query_id), Entry { tenant, value: value.clone() });
+    }
+}
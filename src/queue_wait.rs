@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::loadgen::percentile;
+use crate::{Clock, RequestId};
+
+// the channels a request waits on before someone takes it off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueHop {
+    Worker, // from the server sending it until the worker takes it off its request channel
+    Task,   // from the worker dispatching its instruction until the task takes it off its instruction channel
+}
+
+// how long requests waited on one hop, over every request that has passed it so far. None without samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WaitDistribution {
+    pub samples: usize,
+    pub mean: Option<Duration>,
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Waits {
+    // clock time a request was put on a channel, until it is taken off again
+    stamped: HashMap<(QueueHop, RequestId), Duration>,
+    samples: HashMap<QueueHop, Vec<Duration>>,
+}
+
+// every request is stamped when it is put on a channel and the wait is noted when it is taken off, per hop.
+// always on, unlike the tracer. a duplicated message only counts once, from the first time it is taken off.
+// cloning is cheap, every clone shares the same samples
+#[derive(Clone)]
+pub struct QueueWaits {
+    waits: Arc<Mutex<Waits>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl QueueWaits {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { waits: Arc::new(Mutex::new(Waits::default())), clock }
+    }
+
+    pub(crate) fn stamp(&self, hop: QueueHop, req_id: RequestId) {
+        let now = self.clock.now();
+        self.waits.lock().unwrap().stamped.insert((hop, req_id), now);
+    }
+
+    pub(crate) fn taken(&self, hop: QueueHop, req_id: RequestId) {
+        let now = self.clock.now();
+        let mut waits = self.waits.lock().unwrap();
+        if let Some(stamped) = waits.stamped.remove(&(hop, req_id)) {
+            waits.samples.entry(hop).or_default().push(now.saturating_sub(stamped));
+        }
+    }
+
+    pub fn distribution(&self, hop: QueueHop) -> WaitDistribution {
+        let mut samples = self.waits.lock().unwrap().samples.get(&hop).cloned().unwrap_or_default();
+        if samples.is_empty() {
+            return WaitDistribution::default();
+        }
+        samples.sort_unstable();
+        WaitDistribution {
+            samples: samples.len(),
+            mean: Some(samples.iter().sum::<Duration>() / samples.len() as u32),
+            p50: percentile(&samples, 50.0),
+            p90: percentile(&samples, 90.0),
+            p99: percentile(&samples, 99.0),
+            max: samples.last().copied(),
+        }
+    }
+}
+
+impl fmt::Debug for QueueWaits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueueWaits")
+            .field("worker", &self.distribution(QueueHop::Worker))
+            .field("task", &self.distribution(QueueHop::Task))
+            .finish()
+    }
+}
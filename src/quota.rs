@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// limits on what a single instruction of a metered task may use. None means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quota {
+    pub max_wall_time: Option<Duration>, // measured on the server's clock, from receiving the instruction to its result
+    pub max_steps: Option<u64>,          // interpreter steps for scripts, fuel for wasm modules
+    pub terminate: bool,                 // the task exits after its first breach instead of carrying on
+}
+
+// which limit of a Quota an instruction went over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaResource {
+    WallTime,
+    Steps,
+}
+
+// what a metered task has used so far, summed over all of its metered instructions
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub instructions: u64,
+    pub wall_time: Duration,
+    pub steps: u64,
+}
+
+#[derive(Default)]
+struct MeterState {
+    total: Usage,
+    steps: u64, // steps of the instruction currently running
+}
+
+// resource accounting for one task, shared between the task thread, which times every query and update,
+// and the task's code, which reports the steps it takes
+#[derive(Clone, Default)]
+pub struct Meter {
+    quota: Quota,
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl Meter {
+    pub fn new(quota: Quota) -> Self {
+        Self { quota, state: Arc::default() }
+    }
+
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    pub fn usage(&self) -> Usage {
+        self.state.lock().unwrap().total
+    }
+
+    // reports n steps of the running instruction. fails once the instruction is over its step quota,
+    // so code that can stop early doesn't have to run to completion first
+    pub fn step(&self, n: u64) -> Result<(), QuotaResource> {
+        let mut state = self.state.lock().unwrap();
+        state.steps = state.steps.saturating_add(n);
+        match self.quota.max_steps {
+            Some(max) if state.steps > max => Err(QuotaResource::Steps),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn begin(&self) {
+        self.state.lock().unwrap().steps = 0;
+    }
+
+    // adds the finished instruction to the totals and says which limit it went over, steps first
+    pub(crate) fn finish(&self, wall_time: Duration) -> Option<QuotaResource> {
+        let mut state = self.state.lock().unwrap();
+        let steps = std::mem::take(&mut state.steps);
+        state.total.instructions += 1;
+        state.total.wall_time += wall_time;
+        state.total.steps = state.total.steps.saturating_add(steps);
+        if self.quota.max_steps.is_some_and(|max| steps > max) {
+            Some(QuotaResource::Steps)
+        } else if self.quota.max_wall_time.is_some_and(|max| wall_time > max) {
+            Some(QuotaResource::WallTime)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Clock, ClientId};
+
+// token bucket parameters applied to every client independently
+// a client can burst up to `capacity` requests, after which it gets `refill_per_sec` requests per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Duration, // clock time
+}
+
+// sits in front of the worker, inside ServerThread
+// buckets are created lazily the first time a client sends a request and start out full
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: HashMap<ClientId, TokenBucket>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit, clock: Arc<dyn Clock>) -> Self {
+        Self { limit, buckets: HashMap::new(), clock }
+    }
+
+    // takes a token from the client's bucket. returns false if the bucket is empty
+    pub fn try_acquire(&mut self, client: ClientId) -> bool {
+        let now = self.clock.now();
+        let limit = self.limit;
+        let bucket = self.buckets.entry(client).or_insert(TokenBucket {
+            tokens: limit.capacity as f64,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_sub(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.refill_per_sec).min(limit.capacity as f64);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Clock, Encoding, JsonPath, KeyPattern, QosClass, RequestId, RequestOptions, ServerThread, TaskId, UpdateFn, update_fn};
+
+// a request as it was issued against a ServerThread
+// update functions can't be written to a file, so only their ids are kept and the Replayer supplies stand-ins
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedRequest {
+    Create { id: TaskId, query_map: Vec<(String, String)>, update_ids: Vec<String> },
+    Query { id: TaskId, query_id: String },
+    QueryBytes { id: TaskId, key: String, encoding: Encoding },
+    QueryMany { id: TaskId, keys: Vec<String> },
+    QueryMatching { id: TaskId, pattern: KeyPattern },
+    QueryPath { id: TaskId, key: String, path: JsonPath },
+    Set { id: TaskId, key: String, value: String },
+    Update { id: TaskId, update_id: String },
+    UpdateIfVersion { id: TaskId, update_id: String, expected_version: u64 },
+    Publish { id: TaskId, topic: String, payload: String },
+    Subscribe { id: TaskId, topic: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedEntry {
+    pub at: Duration, // clock time since recording started
+    pub req_id: RequestId,
+    pub opts: RequestOptions,
+    pub request: RecordedRequest,
+}
+
+// why a recording could not be loaded
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse { line: usize, msg: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{err}"),
+            ReplayError::Parse { line, msg } => write!(f, "line {line}: {msg}"),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+// attached to a ServerThread by start_recording, notes every request the server is asked to make,
+// including the ones that end up rejected
+pub struct Recorder {
+    started: Duration,
+    clock: Arc<dyn Clock>,
+    entries: Vec<RecordedEntry>,
+}
+
+impl Recorder {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { started: clock.now(), clock, entries: vec![] }
+    }
+
+    pub fn record(&mut self, req_id: RequestId, opts: &RequestOptions, request: RecordedRequest) {
+        let at = self.clock.now().saturating_sub(self.started);
+        self.entries.push(RecordedEntry { at, req_id, opts: opts.clone(), request });
+    }
+
+    pub fn finish(self) -> Recording {
+        Recording { entries: self.entries }
+    }
+}
+
+// the file format is plain text, one request per line, tab separated:
+//   <at in nanos> <req_id> <client>[@<tenant>] <interactive|batch> <kind> <fields...>
+// the tenant is left out for tenant 0
+// with these fields per kind:
+//   create    <id> <number of query pairs> <key> <value>... <update_id>...
+//   query     <id> <query_id>
+//   query_bytes <id> <key> <raw|base64>
+//   query_many <id> <key>...
+//   query_matching <id> <prefix|glob> <pattern>
+//   query_path <id> <key> <path>
+//   set       <id> <key> <value>
+//   update    <id> <update_id>
+//   update_if_version <id> <update_id> <expected_version>
+//   publish   <id> <topic> <payload>
+//   subscribe <id> <topic>
+// tabs, newlines and backslashes inside fields are escaped as \t, \n and \\
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub entries: Vec<RecordedEntry>,
+}
+
+impl Recording {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, ReplayError> {
+        Recording::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Recording, ReplayError> {
+        let entries = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(i, line)| parse_entry(line).map_err(|msg| ReplayError::Parse { line: i + 1, msg }))
+            .collect::<Result<_, _>>()?;
+        Ok(Recording { entries })
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            let qos = match entry.opts.qos {
+                QosClass::Interactive => "interactive",
+                QosClass::Batch => "batch",
+            };
+            let mut fields = vec![
+                entry.at.as_nanos().to_string(),
+                entry.req_id.to_string(),
+                match entry.opts.tenant {
+                    0 => entry.opts.client.to_string(),
+                    tenant => format!("{}@{tenant}", entry.opts.client),
+                },
+                qos.to_string(),
+            ];
+            match &entry.request {
+                RecordedRequest::Create { id, query_map, update_ids } => {
+                    fields.extend(["create".to_string(), id.to_string(), query_map.len().to_string()]);
+                    for (key, value) in query_map {
+                        fields.extend([escape(key), escape(value)]);
+                    }
+                    fields.extend(update_ids.iter().map(|u| escape(u)));
+                }
+                RecordedRequest::Query { id, query_id } => {
+                    fields.extend(["query".to_string(), id.to_string(), escape(query_id)]);
+                }
+                RecordedRequest::QueryBytes { id, key, encoding } => {
+                    fields.extend(["query_bytes".to_string(), id.to_string(), escape(key), encoding.to_string()]);
+                }
+                RecordedRequest::QueryMany { id, keys } => {
+                    fields.extend(["query_many".to_string(), id.to_string()]);
+                    fields.extend(keys.iter().map(|k| escape(k)));
+                }
+                RecordedRequest::QueryMatching { id, pattern } => {
+                    let (mode, pattern) = match pattern {
+                        KeyPattern::Prefix(prefix) => ("prefix", prefix),
+                        KeyPattern::Glob(glob) => ("glob", glob),
+                    };
+                    fields.extend(["query_matching".to_string(), id.to_string(), mode.to_string(), escape(pattern)]);
+                }
+                RecordedRequest::QueryPath { id, key, path } => {
+                    fields.extend(["query_path".to_string(), id.to_string(), escape(key), escape(&path.to_string())]);
+                }
+                RecordedRequest::Set { id, key, value } => {
+                    fields.extend(["set".to_string(), id.to_string(), escape(key), escape(value)]);
+                }
+                RecordedRequest::Update { id, update_id } => {
+                    fields.extend(["update".to_string(), id.to_string(), escape(update_id)]);
+                }
+                RecordedRequest::UpdateIfVersion { id, update_id, expected_version } => {
+                    fields.extend([
+                        "update_if_version".to_string(),
+                        id.to_string(),
+                        escape(update_id),
+                        expected_version.to_string(),
+                    ]);
+                }
+                RecordedRequest::Publish { id, topic, payload } => {
+                    fields.extend(["publish".to_string(), id.to_string(), escape(topic), escape(payload)]);
+                }
+                RecordedRequest::Subscribe { id, topic } => {
+                    fields.extend(["subscribe".to_string(), id.to_string(), escape(topic)]);
+                }
+            }
+            writeln!(f, "{}", fields.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+pub(crate) fn unescape(field: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            other => return Err(format!("bad escape \\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(out)
+}
+
+fn parse_entry(line: &str) -> Result<RecordedEntry, String> {
+    let fields: Vec<String> = line.split('\t').map(unescape).collect::<Result<_, _>>()?;
+    let number = |i: usize, what: &str| -> Result<usize, String> {
+        let field = fields.get(i).ok_or(format!("missing {what}"))?;
+        field.parse().map_err(|_| format!("{what} '{field}' is not a number"))
+    };
+    let text = |i: usize, what: &str| fields.get(i).cloned().ok_or(format!("missing {what}"));
+
+    let at = Duration::from_nanos(number(0, "time")? as u64);
+    let req_id = number(1, "req_id")?;
+    let (client, tenant) = match text(2, "client")?.split_once('@') {
+        Some((client, tenant)) => (
+            client.parse().map_err(|_| format!("client '{client}' is not a number"))?,
+            tenant.parse().map_err(|_| format!("tenant '{tenant}' is not a number"))?,
+        ),
+        None => (number(2, "client")?, 0),
+    };
+    let qos = match text(3, "qos class")?.as_str() {
+        "interactive" => QosClass::Interactive,
+        "batch" => QosClass::Batch,
+        other => return Err(format!("unknown qos class '{other}'")),
+    };
+    let id = number(5, "task id")?;
+    let (request, used) = match text(4, "request kind")?.as_str() {
+        "create" => {
+            let pairs = number(6, "query pair count")?;
+            let start = pairs.checked_mul(2).and_then(|fields| fields.checked_add(7));
+            let Some(start) = start.filter(|start| *start <= fields.len()) else {
+                return Err(format!("expected {pairs} query pairs"));
+            };
+            let query_map = fields[7..start].chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect();
+            let update_ids = fields[start..].to_vec();
+            (RecordedRequest::Create { id, query_map, update_ids }, fields.len())
+        }
+        "query" => (RecordedRequest::Query { id, query_id: text(6, "query id")? }, 7),
+        "query_bytes" => {
+            let encoding = text(7, "encoding")?.parse()?;
+            (RecordedRequest::QueryBytes { id, key: text(6, "key")?, encoding }, 8)
+        }
+        "query_many" => (RecordedRequest::QueryMany { id, keys: fields[6..].to_vec() }, fields.len()),
+        "query_matching" => {
+            let pattern = match text(6, "pattern mode")?.as_str() {
+                "prefix" => KeyPattern::Prefix(text(7, "pattern")?),
+                "glob" => KeyPattern::Glob(text(7, "pattern")?),
+                other => return Err(format!("unknown pattern mode '{other}'")),
+            };
+            (RecordedRequest::QueryMatching { id, pattern }, 8)
+        }
+        "query_path" => {
+            let path = text(7, "path")?.parse()?;
+            (RecordedRequest::QueryPath { id, key: text(6, "key")?, path }, 8)
+        }
+        "set" => (RecordedRequest::Set { id, key: text(6, "key")?, value: text(7, "value")? }, 8),
+        "update" => (RecordedRequest::Update { id, update_id: text(6, "update id")? }, 7),
+        "update_if_version" => {
+            let update_id = text(6, "update id")?;
+            let expected_version = number(7, "expected version")? as u64;
+            (RecordedRequest::UpdateIfVersion { id, update_id, expected_version }, 8)
+        }
+        "publish" => (RecordedRequest::Publish { id, topic: text(6, "topic")?, payload: text(7, "payload")? }, 8),
+        "subscribe" => (RecordedRequest::Subscribe { id, topic: text(6, "topic")? }, 7),
+        other => return Err(format!("unknown request kind '{other}'")),
+    };
+    if fields.len() > used {
+        return Err(format!("{} unexpected trailing field(s)", fields.len() - used));
+    }
+
+    // deadlines are clock times of the recorded run and are not written, a replayed request has none
+    Ok(RecordedEntry { at, req_id, opts: RequestOptions { client, qos, tenant, deadline: None, stale_ok: false }, request })
+}
+
+// stands in for a recorded update function, gets the update id and returns the closure to install
+pub type UpdateFactory = Box<dyn Fn(&str) -> UpdateFn>;
+
+// re-issues a recording against a server, keeping the original spacing between requests on the server's clock
+// the recorded task ids and req_ids line up with the fresh server's as long as it starts out fresh
+pub struct Replayer {
+    recording: Recording,
+    update_fn: UpdateFactory,
+}
+
+impl Replayer {
+    // by default every update function returns its own update id
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            update_fn: Box::new(|update_id| {
+                let value = update_id.to_string();
+                update_fn(move |_| Ok(value.clone()))
+            }),
+        }
+    }
+
+    pub fn with_update_fn(mut self, update_fn: UpdateFactory) -> Self {
+        self.update_fn = update_fn;
+        self
+    }
+
+    pub fn run(&self, server: &mut ServerThread) {
+        let clock = Arc::clone(&server.clock);
+        let started = clock.now();
+        for entry in &self.recording.entries {
+            let elapsed = clock.now().saturating_sub(started);
+            if entry.at > elapsed {
+                clock.sleep(entry.at - elapsed);
+            }
+            if server.request_counter != entry.req_id {
+                println!(
+                    "[Replayer] req:{} is replayed as req:{}, the server was not fresh",
+                    entry.req_id, server.request_counter
+                );
+            }
+            let opts = entry.opts.clone();
+            match &entry.request {
+                RecordedRequest::Create { id, query_map, update_ids } => {
+                    let update_map: HashMap<_, _> = update_ids.iter().map(|u| (u.clone(), (self.update_fn)(u))).collect();
+                    let new_id = server.create_task_with(opts, query_map.iter().cloned().collect(), update_map);
+                    if new_id != *id {
+                        println!("[Replayer] Task {id} is replayed as Task {new_id}");
+                    }
+                }
+                RecordedRequest::Query { id, query_id } => server.query_task_with(opts, *id, query_id),
+                RecordedRequest::QueryBytes { id, key, encoding } => server.query_bytes_with(opts, *id, key, *encoding),
+                RecordedRequest::QueryMany { id, keys } => {
+                    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+                    server.query_many_with(opts, *id, &keys)
+                }
+                RecordedRequest::QueryMatching { id, pattern } => server.query_matching_with(opts, *id, pattern.clone()),
+                RecordedRequest::QueryPath { id, key, path } => server.query_path_with(opts, *id, key, path.clone()),
+                RecordedRequest::Set { id, key, value } => server.set_value_with(opts, *id, key, value),
+                RecordedRequest::Update { id, update_id } => server.update_task_with(opts, *id, update_id),
+                RecordedRequest::UpdateIfVersion { id, update_id, expected_version } => {
+                    server.update_task_if_version_with(opts, *id, update_id, *expected_version)
+                }
+                RecordedRequest::Publish { id, topic, payload } => server.publish_task_with(opts, *id, topic, payload),
+                RecordedRequest::Subscribe { id, topic } => server.subscribe_task_with(opts, *id, topic),
+            }
+        }
+    }
+}
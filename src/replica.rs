@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::TaskId;
+
+// a task's values as it last published them. the map is shared, never changed: a newer copy replaces it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replica {
+    pub values: Arc<HashMap<String, String>>,
+    pub version: u64,          // the task's version when it published
+    pub published_at: Duration, // clock time
+}
+
+// copies of the running tasks' values, published by each task at most once every ServerConfig::replica_interval and
+// read by the worker for queries sent with RequestOptions::stale_ok, which then never reach the task. a copy can be up
+// to an interval behind its task, binary values and handler tasks are never in it. a task's copy goes when it stops.
+// cloning is cheap, every clone shares the same copies
+#[derive(Debug, Clone, Default)]
+pub struct ReplicaStore {
+    replicas: Arc<RwLock<HashMap<TaskId, Replica>>>,
+    reads: Arc<AtomicUsize>,
+}
+
+impl ReplicaStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: TaskId) -> Option<Replica> {
+        self.replicas.read().unwrap().get(&id).cloned()
+    }
+
+    // the value of key in task id's copy, counted as a read when there is one
+    pub fn read(&self, id: TaskId, key: &str) -> Option<String> {
+        let value = self.replicas.read().unwrap().get(&id)?.values.get(key).cloned()?;
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    // queries answered from a copy instead of by their task
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.replicas.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn publish(&self, id: TaskId, replica: Replica) {
+        self.replicas.write().unwrap().insert(id, replica);
+    }
+
+    pub(crate) fn remove(&self, id: TaskId) {
+        self.replicas.write().unwrap().remove(&id);
+    }
+}
+
+// what a task thread keeps to know when to publish next
+#[derive(Debug)]
+pub(crate) struct ReplicaPublisher {
+    pub(crate) store: ReplicaStore,
+    pub(crate) interval: Duration,
+    published: Option<(u64, Duration)>, // version and clock time of the last copy
+    // changed without a version bump, e.g. by a delivered event
+    dirty: bool,
+}
+
+impl ReplicaPublisher {
+    pub(crate) fn new(store: ReplicaStore, interval: Duration) -> Self {
+        Self { store, interval, published: None, dirty: false }
+    }
+
+    pub(crate) fn touch(&mut self) {
+        self.dirty = true;
+    }
+
+    // clock time the next copy is due at, None when the last one is still current
+    pub(crate) fn due(&self, version: u64) -> Option<Duration> {
+        match self.published {
+            None => Some(Duration::ZERO),
+            Some((published, at)) if published != version || self.dirty => Some(at + self.interval),
+            Some(_) => None,
+        }
+    }
+
+    // publishes values if a copy is due by now
+    pub(crate) fn publish_if_due<'a>(
+        &mut self,
+        id: TaskId,
+        version: u64,
+        now: Duration,
+        values: impl Iterator<Item = (&'a str, &'a str)>,
+    ) {
+        if self.due(version).is_none_or(|due| due > now) {
+            return;
+        }
+        let values = values.map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        self.store.publish(id, Replica { values: Arc::new(values), version, published_at: now });
+        self.published = Some((version, now));
+        self.dirty = false;
+    }
+}
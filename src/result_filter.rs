@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}};
+
+use crate::{ErrorCode, RequestId, ResultSink, TaskId, TaskResult};
+
+// which recorded results a result subscriber gets, see ServerThread::subscribe_results
+// every field narrows it down further, the default lets everything through
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResultFilter {
+    pub task: Option<TaskId>,       // only results about this task
+    pub kinds: Vec<&'static str>,   // only results of these kinds (see TaskResult::kind), empty for any kind
+    pub errors_only: bool,          // only results with an error code
+    pub codes: Vec<ErrorCode>,      // only results with one of these error codes, empty for any
+}
+
+impl ResultFilter {
+    pub fn task(id: TaskId) -> Self {
+        Self { task: Some(id), ..Self::default() }
+    }
+
+    pub fn errors() -> Self {
+        Self { errors_only: true, ..Self::default() }
+    }
+
+    pub fn matches(&self, result: &TaskResult) -> bool {
+        let code = result.error_code();
+        self.task.is_none_or(|id| result.id() == id)
+            && (self.kinds.is_empty() || self.kinds.contains(&result.kind()))
+            && (!self.errors_only || code.is_some())
+            && (self.codes.is_empty() || code.is_some_and(|code| self.codes.contains(&code)))
+    }
+}
+
+type Subscriber = (ResultFilter, Sender<TaskResult>);
+
+// every result subscriber with its filter. the listener hands each result it records to the ones it matches
+// cloning is cheap, every clone shares the same list
+#[derive(Debug, Clone, Default)]
+pub struct ResultSubscribers {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl ResultSubscribers {
+    pub fn subscribe(&self, filter: ResultFilter) -> Receiver<TaskResult> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push((filter, tx));
+        rx
+    }
+
+    // subscribers whose receiver is gone are pruned on the way
+    pub fn publish(&self, result: &TaskResult) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|(filter, tx)| !filter.matches(result) || tx.send(result.clone()).is_ok());
+    }
+}
+
+impl ResultSink for ResultSubscribers {
+    fn accept(&self, _req_id: RequestId, result: &TaskResult) {
+        self.publish(result);
+    }
+}
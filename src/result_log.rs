@@ -0,0 +1,96 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Clock, RequestId, ResultSink, TaskResult};
+
+// appends every recorded result to a file as one JSON object per line, for looking at long runs afterwards:
+// {"req_id":1,"id":0,"kind":"query_ok","recorded_at_ms":12.5,"error_code":null,"result":"QueryOk { .. }"}
+// recorded_at_ms is clock time, result is the Debug form of the whole TaskResult
+// lines are buffered and written out once flush_every (clock time) has passed since the last flush,
+// and whatever is left when the sink is dropped
+#[derive(Debug)]
+pub struct JsonlSink {
+    out: Mutex<Output>,
+    clock: Arc<dyn Clock>,
+    flush_every: Duration,
+}
+
+#[derive(Debug)]
+struct Output {
+    writer: BufWriter<File>,
+    flushed_at: Duration,
+}
+
+impl JsonlSink {
+    // appends to path, creating it if needed
+    pub fn create(path: impl AsRef<Path>, clock: Arc<dyn Clock>, flush_every: Duration) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let flushed_at = clock.now();
+        Ok(Self { out: Mutex::new(Output { writer: BufWriter::new(file), flushed_at }), clock, flush_every })
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        out.flushed_at = self.clock.now();
+        out.writer.flush()
+    }
+}
+
+impl ResultSink for JsonlSink {
+    // a failed write is reported and otherwise ignored, the run goes on without it
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        let now = self.clock.now();
+        let line = json_line(req_id, result, now);
+        let mut out = self.out.lock().unwrap();
+        let mut written = writeln!(out.writer, "{line}");
+        if written.is_ok() && now.saturating_sub(out.flushed_at) >= self.flush_every {
+            out.flushed_at = now;
+            written = out.writer.flush();
+        }
+        if let Err(err) = written {
+            println!("[JsonlSink] Could not write req:{req_id}: {err}");
+        }
+    }
+
+    fn flush(&self) {
+        if let Err(err) = JsonlSink::flush(self) {
+            println!("[JsonlSink] Could not flush: {err}");
+        }
+    }
+}
+
+fn json_line(req_id: RequestId, result: &TaskResult, now: Duration) -> String {
+    let error_code = result.error_code().map_or("null".to_string(), |code| json_string(&format!("{code:?}")));
+    format!(
+        "{{\"req_id\":{req_id},\"id\":{},\"kind\":{},\"recorded_at_ms\":{},\"error_code\":{error_code},\"result\":{}}}",
+        result.id(),
+        json_string(result.kind()),
+        now.as_secs_f64() * 1000.0,
+        json_string(&format!("{result:?}")),
+    )
+}
+
+// s as a JSON string literal, quotes included
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
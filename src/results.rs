@@ -0,0 +1,105 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use crate::{RequestId, ResultSink, TaskResult};
+
+// the result of every request, by req_id. split into shards by req_id so each listener shard
+// writes to its own mutex, see ServerConfig::listener_shards
+// cloning is cheap, every clone shares the same shards
+#[derive(Debug, Clone)]
+pub struct ResultStore {
+    shards: Arc<Vec<Mutex<Vec<Option<TaskResult>>>>>,
+    // bumped after every write and notified, so a waiter hears about results landing in any shard
+    changes: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl ResultStore {
+    // at least one shard, whatever is asked for
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: Arc::new((0..shards.max(1)).map(|_| Mutex::new(Vec::new())).collect()),
+            changes: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    // consecutive req_ids go to consecutive shards
+    pub fn shard_of(&self, req_id: RequestId) -> usize {
+        req_id % self.shards.len()
+    }
+
+    pub fn get(&self, req_id: RequestId) -> Option<TaskResult> {
+        let shard = self.shards[self.shard_of(req_id)].lock().unwrap();
+        shard.get(req_id / self.shards.len()).cloned().flatten()
+    }
+
+    pub fn is_recorded(&self, req_id: RequestId) -> bool {
+        let shard = self.shards[self.shard_of(req_id)].lock().unwrap();
+        shard.get(req_id / self.shards.len()).is_some_and(Option::is_some)
+    }
+
+    // replaces whatever was recorded for req_id before
+    pub fn set(&self, req_id: RequestId, result: TaskResult) {
+        {
+            let mut shard = self.shards[self.shard_of(req_id)].lock().unwrap();
+            let slot = req_id / self.shards.len();
+            if shard.len() <= slot {
+                shard.resize(slot + 1, None);
+            }
+            shard[slot] = Some(result);
+        }
+        self.notify();
+    }
+
+    // every slot in req_id order, up to the highest req_id with a result
+    pub fn snapshot(&self) -> Vec<Option<TaskResult>> {
+        let shards: Vec<_> = self.shards.iter().map(|shard| shard.lock().unwrap().clone()).collect();
+        let len = shards
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shard)| Some(shard.iter().rposition(Option::is_some)? * shards.len() + index + 1))
+            .max()
+            .unwrap_or(0);
+        (0..len).map(|req_id| shards[req_id % shards.len()].get(req_id / shards.len()).cloned().flatten()).collect()
+    }
+
+    // wakes everyone in wait_until without recording anything, e.g. when the listener exits
+    pub fn notify(&self) {
+        let (changes, changed) = &*self.changes;
+        *changes.lock().unwrap() += 1;
+        changed.notify_all();
+    }
+
+    // blocks until ready returns true or deadline (real time) passes, returns what ready said last
+    // ready is checked again after every write, it may read the store but must not write to it
+    pub fn wait_until(&self, deadline: Instant, mut ready: impl FnMut() -> bool) -> bool {
+        let (changes, changed) = &*self.changes;
+        // held while ready runs, so a write that ready missed can only notify once this is waiting
+        let mut seen = changes.lock().unwrap();
+        loop {
+            if ready() {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            seen = changed.wait_timeout(seen, deadline - now).unwrap().0;
+        }
+    }
+}
+
+// the default sink, always last in the server's chain so everything before it has seen a result
+// by the time wait_idle and friends see it here.
+// a Preempted is about another task than the request's, it is left to the sinks before this one
+impl ResultSink for ResultStore {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        if let TaskResult::Preempted { .. } = result {
+            return;
+        }
+        self.set(req_id, result.clone());
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{Json, KeyPattern};
+
+// what a value under a schema key has to parse as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Text, // anything goes, for keys that only need a pattern
+    Integer,
+    Number,
+    Bool, // "true" or "false"
+    Json, // a JSON document, see ServerThread::query_path
+}
+
+impl ValueType {
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            ValueType::Text => true,
+            ValueType::Integer => value.parse::<i64>().is_ok(),
+            ValueType::Number => value.parse::<f64>().is_ok_and(f64::is_finite),
+            ValueType::Bool => value == "true" || value == "false",
+            ValueType::Json => Json::parse(value).is_ok(),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ValueType::Text => "text",
+            ValueType::Integer => "integer",
+            ValueType::Number => "number",
+            ValueType::Bool => "bool",
+            ValueType::Json => "json",
+        })
+    }
+}
+
+// a value a schema turned away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub key: String,
+    pub reason: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' {}", self.key, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Rule {
+    kind: Option<ValueType>,
+    pattern: Option<KeyPattern>,
+}
+
+// what the values of a task have to look like, key by key, see TaskBuilder::schema. keys the schema doesn't name
+// take any value. checked at creation, by ServerThread::set_value and on the value of an update whose id is a schema key
+//   Schema::new().typed("count", ValueType::Integer).pattern("region", KeyPattern::Prefix("eu-".into()))
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    rules: HashMap<String, Rule>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn typed(mut self, key: &str, kind: ValueType) -> Self {
+        self.rules.entry(key.to_string()).or_default().kind = Some(kind);
+        self
+    }
+
+    // the value has to match pattern, the same way query_matching matches keys
+    pub fn pattern(mut self, key: &str, pattern: KeyPattern) -> Self {
+        self.rules.entry(key.to_string()).or_default().pattern = Some(pattern);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn check(&self, key: &str, value: &str) -> Result<(), SchemaViolation> {
+        let Some(rule) = self.rules.get(key) else { return Ok(()) };
+        let violation = |reason| Err(SchemaViolation { key: key.to_string(), reason });
+        if let Some(kind) = rule.kind.filter(|kind| !kind.accepts(value)) {
+            return violation(format!("has to be {kind}, got '{value}'"));
+        }
+        match &rule.pattern {
+            Some(pattern) if !pattern.matches(value) => violation(format!("has to match {pattern:?}, got '{value}'")),
+            _ => Ok(()),
+        }
+    }
+
+    // every entry of values, the first violation by key order
+    pub fn check_all<'a>(&self, values: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<(), SchemaViolation> {
+        let mut values: Vec<_> = values.into_iter().collect();
+        values.sort();
+        values.into_iter().try_for_each(|(key, value)| self.check(key, value))
+    }
+}
@@ -0,0 +1,760 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::quota::Meter;
+use crate::{TaskState, UpdateFn};
+
+// scripts describe what a hypervisor task exposes, in terms of the integer args it is created with
+//
+//   script     := (signature ' '+)? item (' '+ item)*
+//   signature  := 'args(' (type (',' type)*)? ')'
+//   type       := 'int' | 'nat' | 'pos'             any integer, >= 0, > 0
+//   item       := code | definition
+//   code       := index op?
+//   index      := digit+                      position in args, 0-based
+//   op         := 'a' | 's' | 'm'             add, subtract, multiply
+//   definition := name '{' body '}'
+//   name       := [a-z_] [a-z0-9_]*
+//
+// a bare index becomes a query: query id "<index>" answers args[index]
+// an index followed by an op becomes an update: update id "<index><op>" applies the op with args[index]
+// to the task's running total and returns the new total
+// a definition becomes an update with id "<name>" that runs its body and returns the running total afterwards
+// the total starts at 0 and is shared by all updates of a task
+//
+// a script with a signature only accepts exactly the args it declares, checked before the task is created,
+// and may only refer to those. without one any args work as long as every referenced index is there
+//
+// definition bodies are a small statement language over the args:
+//
+//   body   := stmt (';' stmt)*
+//   stmt   := var '=' expr                        assignment. 'total' is the running total, anything else is local to one run
+//           | 'if' expr '{' body '}' ('else' '{' body '}')?
+//           | 'repeat' expr '{' body '}'          runs body expr times, at most MAX_LOOP_ITERATIONS
+//           | expr
+//   expr   := sum (('==' | '!=' | '<' | '<=' | '>' | '>=') sum)?      comparisons give 1 or 0
+//   sum    := term (('+' | '-') term)*
+//   term   := unary (('*' | '/' | '%') unary)*
+//   unary  := '-' unary | atom
+//   atom   := integer | '$' index | var | '(' expr ')'
+//
+// conditions are true when non-zero. a variable has to be assigned before it is read. arithmetic wraps on overflow,
+// a division by zero or an oversized loop stops the run, leaves the total as it was and makes the update
+// fail with the reason, which the task answers as UpdateError
+//
+// e.g. "args(int, pos) 0 1a 1m" with args [3, 4] gives query "0" -> "3", and updates "1a" (total + 4) and "1m" (total * 4)
+// and "grow{repeat $0 {total = total + $1}; if total > 100 {total = 100}}" with args [3, 50] gives update "grow",
+// which adds 50 three times and caps the total at 100
+
+// upper bound on the iterations of a single repeat
+pub const MAX_LOOP_ITERATIONS: i64 = 10_000;
+
+// the update functions a script turns into, same shape as Task::update_map
+pub type UpdateMap = HashMap<String, UpdateFn>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl Op {
+    fn from_char(c: char) -> Option<Op> {
+        match c {
+            'a' => Some(Op::Add),
+            's' => Some(Op::Sub),
+            'm' => Some(Op::Mul),
+            _ => None,
+        }
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Op::Add => 'a',
+            Op::Sub => 's',
+            Op::Mul => 'm',
+        }
+    }
+
+    fn apply(self, total: i64, arg: i64) -> i64 {
+        match self {
+            Op::Add => total.wrapping_add(arg),
+            Op::Sub => total.wrapping_sub(arg),
+            Op::Mul => total.wrapping_mul(arg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Int(i64),
+    // args[index], pos is where the reference starts in the script
+    Arg { index: usize, pos: usize },
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Repeat(Expr, Vec<Stmt>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Code {
+    Query { index: usize },
+    Update { index: usize, op: Op },
+    Define { name: String, body: Vec<Stmt> },
+}
+
+impl Code {
+    // the query or update id the code is exposed under
+    pub fn name(&self) -> String {
+        match self {
+            Code::Query { index } => index.to_string(),
+            Code::Update { index, op } => format!("{index}{}", op.as_char()),
+            Code::Define { name, .. } => name.clone(),
+        }
+    }
+
+    // every arg the code refers to, with the position of the reference
+    fn arg_refs(&self, pos: usize) -> Vec<(usize, usize)> {
+        fn walk_expr(expr: &Expr, refs: &mut Vec<(usize, usize)>) {
+            match expr {
+                Expr::Arg { index, pos } => refs.push((*index, *pos)),
+                Expr::Neg(inner) => walk_expr(inner, refs),
+                Expr::Binary(_, lhs, rhs) => {
+                    walk_expr(lhs, refs);
+                    walk_expr(rhs, refs);
+                }
+                Expr::Int(_) | Expr::Var(_) => {}
+            }
+        }
+        fn walk(body: &[Stmt], refs: &mut Vec<(usize, usize)>) {
+            for stmt in body {
+                match stmt {
+                    Stmt::Assign(_, expr) | Stmt::Expr(expr) => walk_expr(expr, refs),
+                    Stmt::If(cond, then, otherwise) => {
+                        walk_expr(cond, refs);
+                        walk(then, refs);
+                        walk(otherwise, refs);
+                    }
+                    Stmt::Repeat(count, body) => {
+                        walk_expr(count, refs);
+                        walk(body, refs);
+                    }
+                }
+            }
+        }
+
+        match self {
+            Code::Query { index } | Code::Update { index, .. } => vec![(*index, pos)],
+            Code::Define { body, .. } => {
+                let mut refs = vec![];
+                walk(body, &mut refs);
+                refs
+            }
+        }
+    }
+}
+
+// what a script's signature accepts in one position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgType {
+    Int,
+    Nat,
+    Pos,
+}
+
+impl ArgType {
+    fn from_name(name: &str) -> Option<ArgType> {
+        match name {
+            "int" => Some(ArgType::Int),
+            "nat" => Some(ArgType::Nat),
+            "pos" => Some(ArgType::Pos),
+            _ => None,
+        }
+    }
+
+    pub fn accepts(self, value: i64) -> bool {
+        match self {
+            ArgType::Int => true,
+            ArgType::Nat => value >= 0,
+            ArgType::Pos => value > 0,
+        }
+    }
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgType::Int => write!(f, "int"),
+            ArgType::Nat => write!(f, "nat"),
+            ArgType::Pos => write!(f, "pos"),
+        }
+    }
+}
+
+// why the args a task was created with don't match its script's signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgError {
+    WrongArity { expected: usize, got: usize },
+    WrongType { index: usize, expected: ArgType, value: i64 },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::WrongArity { expected, got } => write!(f, "expected {expected} arg(s), got {got}"),
+            ArgError::WrongType { index, expected, value } => write!(f, "arg {index} is {value}, expected {expected}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptErrorKind {
+    Empty,
+    UnexpectedChar(char),
+    UnexpectedEnd,
+    UnknownOp(char),
+    IndexTooLarge,
+    Duplicate(String),
+    UnknownVariable(String),
+    ArgOutOfRange { index: usize, args: usize },
+    UnknownType(String),
+    // the args don't match the script's signature
+    Args(ArgError),
+    // a registered script was asked for by name, see Hypervisor::register_script. version None means the latest
+    UnknownScript { name: String, version: Option<u32> },
+    // a WASM module could not be compiled or instantiated, see wasm.rs. only produced with the "wasm" feature
+    Wasm(String),
+}
+
+// what is wrong with a script and where. pos is the char offset into the script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub pos: usize,
+    pub kind: ScriptErrorKind,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ScriptErrorKind::Empty => write!(f, "script is empty"),
+            ScriptErrorKind::UnexpectedChar(c) => write!(f, "unexpected '{c}' at {}", self.pos),
+            ScriptErrorKind::UnexpectedEnd => write!(f, "script ends unexpectedly at {}", self.pos),
+            ScriptErrorKind::UnknownOp(c) => write!(f, "unknown op '{c}' at {}, expected a, s or m", self.pos),
+            ScriptErrorKind::IndexTooLarge => write!(f, "number at {} is too large", self.pos),
+            ScriptErrorKind::Duplicate(name) => write!(f, "'{name}' at {} is defined twice", self.pos),
+            ScriptErrorKind::UnknownVariable(name) => {
+                write!(f, "variable '{name}' at {} is read before it is assigned", self.pos)
+            }
+            ScriptErrorKind::ArgOutOfRange { index, args } => {
+                write!(f, "index {index} at {} is out of range for {args} arg(s)", self.pos)
+            }
+            ScriptErrorKind::UnknownType(name) => write!(f, "unknown arg type '{name}' at {}, expected int, nat or pos", self.pos),
+            ScriptErrorKind::Args(err) => write!(f, "{err}"),
+            ScriptErrorKind::UnknownScript { name, version: Some(version) } => {
+                write!(f, "no script '{name}' with version {version} is registered")
+            }
+            ScriptErrorKind::UnknownScript { name, version: None } => write!(f, "no script '{name}' is registered"),
+            ScriptErrorKind::Wasm(msg) => write!(f, "wasm module rejected: {msg}"),
+        }
+    }
+}
+
+// why a definition's run stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeError {
+    DivisionByZero,
+    LoopTooLong(i64),
+    StepQuotaExceeded(u64),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::LoopTooLong(n) => write!(f, "repeat {n} exceeds {MAX_LOOP_ITERATIONS} iterations"),
+            RuntimeError::StepQuotaExceeded(max) => write!(f, "step quota of {max} exceeded"),
+        }
+    }
+}
+
+// a parsed script, each code with the position it starts at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub signature: Option<Vec<ArgType>>,
+    pub codes: Vec<(usize, Code)>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, ScriptError> {
+        let mut parser = Parser { chars: source.chars().collect(), pos: 0, assigned: HashSet::new() };
+        let mut codes: Vec<(usize, Code)> = vec![];
+        let signature = parser.signature()?;
+        if let Some(c) = parser.peek().filter(|c| signature.is_some() && *c != ' ') {
+            return Err(ScriptError { pos: parser.pos, kind: ScriptErrorKind::UnexpectedChar(c) });
+        }
+
+        while parser.pos < parser.chars.len() {
+            if parser.peek() == Some(' ') {
+                parser.pos += 1;
+                continue;
+            }
+            let start = parser.pos;
+            let code = parser.item()?;
+            // an item has to end at a space or the end of the script, "1a!" and "1ab" are both rejected here
+            if let Some(c) = parser.peek().filter(|c| *c != ' ') {
+                return Err(ScriptError { pos: parser.pos, kind: ScriptErrorKind::UnexpectedChar(c) });
+            }
+            if codes.iter().any(|(_, other)| other.name() == code.name()) {
+                return Err(ScriptError { pos: start, kind: ScriptErrorKind::Duplicate(code.name()) });
+            }
+            if let Some(signature) = &signature {
+                if let Some(&(index, pos)) = code.arg_refs(start).iter().find(|(index, _)| *index >= signature.len()) {
+                    return Err(ScriptError { pos, kind: ScriptErrorKind::ArgOutOfRange { index, args: signature.len() } });
+                }
+            }
+            codes.push((start, code));
+        }
+
+        if codes.is_empty() {
+            return Err(ScriptError { pos: 0, kind: ScriptErrorKind::Empty });
+        }
+        Ok(Script { signature, codes })
+    }
+
+    // checks args against the signature, scripts without one accept anything here
+    pub fn check_args(&self, args: &[i64]) -> Result<(), ArgError> {
+        let Some(signature) = &self.signature else { return Ok(()) };
+        if args.len() != signature.len() {
+            return Err(ArgError::WrongArity { expected: signature.len(), got: args.len() });
+        }
+        match signature.iter().zip(args).position(|(ty, value)| !ty.accepts(*value)) {
+            Some(index) => Err(ArgError::WrongType { index, expected: signature[index], value: args[index] }),
+            None => Ok(()),
+        }
+    }
+
+    // turns the script into a task's query and update maps. every arg reference has to point into args
+    pub fn build(&self, args: &[i64]) -> Result<(HashMap<String, String>, UpdateMap), ScriptError> {
+        self.build_metered(args, &Meter::default())
+    }
+
+    // like build, but every update reports its steps to meter: one for an op code, and one per statement and
+    // per expression node evaluated for a definition. a run that goes over the step quota stops there
+    // and leaves the total as it was
+    pub fn build_metered(&self, args: &[i64], meter: &Meter) -> Result<(HashMap<String, String>, UpdateMap), ScriptError> {
+        self.check_args(args).map_err(|err| ScriptError { pos: 0, kind: ScriptErrorKind::Args(err) })?;
+        let mut query_map = HashMap::new();
+        let mut update_map: UpdateMap = HashMap::new();
+        let total = Arc::new(Mutex::new(0i64));
+
+        for (pos, code) in &self.codes {
+            if let Some(&(index, pos)) = code.arg_refs(*pos).iter().find(|(index, _)| *index >= args.len()) {
+                return Err(ScriptError { pos, kind: ScriptErrorKind::ArgOutOfRange { index, args: args.len() } });
+            }
+            match code {
+                Code::Query { index } => {
+                    query_map.insert(code.name(), args[*index].to_string());
+                }
+                Code::Update { index, op } => {
+                    let (arg, op, total, meter) = (args[*index], *op, Arc::clone(&total), meter.clone());
+                    update_map.insert(
+                        code.name(),
+                        Box::new(move |_: &mut TaskState| {
+                            step(&meter).map_err(|err| err.to_string())?;
+                            let mut total = total.lock().unwrap();
+                            *total = op.apply(*total, arg);
+                            Ok(total.to_string())
+                        }),
+                    );
+                }
+                Code::Define { body, .. } => {
+                    let (body, args, total, meter) = (body.clone(), args.to_vec(), Arc::clone(&total), meter.clone());
+                    update_map.insert(
+                        code.name(),
+                        Box::new(move |_: &mut TaskState| {
+                            let mut total = total.lock().unwrap();
+                            let mut run = Run { args: &args, total: *total, vars: HashMap::new(), meter: &meter };
+                            match run.body(&body) {
+                                Ok(()) => {
+                                    *total = run.total;
+                                    Ok(total.to_string())
+                                }
+                                Err(err) => Err(err.to_string()),
+                            }
+                        }),
+                    );
+                }
+            }
+        }
+        Ok((query_map, update_map))
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    // variables assigned so far in the current definition, reading anything else is an error
+    assigned: HashSet<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, kind: ScriptErrorKind) -> ScriptError {
+        ScriptError { pos: self.pos, kind }
+    }
+
+    // the char at pos, or UnexpectedEnd
+    fn current(&self) -> Result<char, ScriptError> {
+        self.peek().ok_or(self.error(ScriptErrorKind::UnexpectedEnd))
+    }
+
+    fn unexpected(&self) -> ScriptError {
+        match self.peek() {
+            Some(c) => self.error(ScriptErrorKind::UnexpectedChar(c)),
+            None => self.error(ScriptErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    // skips spaces, then consumes token if it is next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        let matches = token.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c));
+        if matches {
+            self.pos += token.chars().count();
+        }
+        matches
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ScriptError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, ScriptError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.unexpected());
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().map_err(|_| ScriptError { pos: start, kind: ScriptErrorKind::IndexTooLarge })
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        self.skip_spaces();
+        let start = self.pos;
+        if !self.peek().is_some_and(|c| c.is_ascii_lowercase() || c == '_') {
+            return None;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    // a keyword only counts as a whole word, so a variable called "iffy" is still a variable
+    fn keyword(&mut self, word: &str) -> bool {
+        let start = self.pos;
+        match self.ident() {
+            Some(ident) if ident == word => true,
+            _ => {
+                self.pos = start;
+                false
+            }
+        }
+    }
+
+    // the optional signature at the start of a script
+    fn signature(&mut self) -> Result<Option<Vec<ArgType>>, ScriptError> {
+        if !self.eat("args(") {
+            return Ok(None);
+        }
+        let mut signature = vec![];
+        if self.eat(")") {
+            return Ok(Some(signature));
+        }
+        loop {
+            self.skip_spaces();
+            let start = self.pos;
+            let Some(name) = self.ident() else { return Err(self.unexpected()) };
+            let ty = ArgType::from_name(&name).ok_or(ScriptError { pos: start, kind: ScriptErrorKind::UnknownType(name) })?;
+            signature.push(ty);
+            if self.eat(")") {
+                return Ok(Some(signature));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn item(&mut self) -> Result<Code, ScriptError> {
+        let c = self.current()?;
+        if c.is_ascii_digit() {
+            let index = self.number()?;
+            return match self.peek() {
+                Some(c) if c.is_ascii_lowercase() => {
+                    let op = Op::from_char(c).ok_or(self.error(ScriptErrorKind::UnknownOp(c)))?;
+                    self.pos += 1;
+                    Ok(Code::Update { index, op })
+                }
+                _ => Ok(Code::Query { index }),
+            };
+        }
+        let Some(name) = self.ident() else { return Err(self.unexpected()) };
+        if self.peek() != Some('{') {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        self.assigned = HashSet::from(["total".to_string()]);
+        let body = self.body()?;
+        self.expect("}")?;
+        Ok(Code::Define { name, body })
+    }
+
+    // statements up to (not including) the closing brace
+    fn body(&mut self) -> Result<Vec<Stmt>, ScriptError> {
+        let mut body = vec![self.stmt()?];
+        while self.eat(";") {
+            body.push(self.stmt()?);
+        }
+        Ok(body)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ScriptError> {
+        self.expect("{")?;
+        let body = self.body()?;
+        self.expect("}")?;
+        Ok(body)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, ScriptError> {
+        self.skip_spaces();
+        if self.keyword("if") {
+            let cond = self.expr()?;
+            let then = self.block()?;
+            let otherwise = if self.keyword("else") { self.block()? } else { vec![] };
+            return Ok(Stmt::If(cond, then, otherwise));
+        }
+        if self.keyword("repeat") {
+            let count = self.expr()?;
+            return Ok(Stmt::Repeat(count, self.block()?));
+        }
+
+        // an assignment starts with "<var> =", anything else is an expression
+        let start = self.pos;
+        if let Some(var) = self.ident() {
+            self.skip_spaces();
+            if self.peek() == Some('=') && self.chars.get(self.pos + 1) != Some(&'=') {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.assigned.insert(var.clone());
+                return Ok(Stmt::Assign(var, expr));
+            }
+        }
+        self.pos = start;
+        Ok(Stmt::Expr(self.expr()?))
+    }
+
+    fn expr(&mut self) -> Result<Expr, ScriptError> {
+        let lhs = self.sum()?;
+        // longer operators first so "<=" isn't read as "<"
+        let ops = [
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<=", BinOp::Le),
+            (">=", BinOp::Ge),
+            ("<", BinOp::Lt),
+            (">", BinOp::Gt),
+        ];
+        for (token, op) in ops {
+            if self.eat(token) {
+                return Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.sum()?)));
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn sum(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat("+") {
+                BinOp::Add
+            } else if self.eat("-") {
+                BinOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                BinOp::Mul
+            } else if self.eat("/") {
+                BinOp::Div
+            } else if self.eat("%") {
+                BinOp::Rem
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScriptError> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ScriptError> {
+        self.skip_spaces();
+        let start = self.pos;
+        match self.current()? {
+            '(' => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            '$' => {
+                self.pos += 1;
+                Ok(Expr::Arg { index: self.number()?, pos: start })
+            }
+            c if c.is_ascii_digit() => {
+                let value = self.number()?;
+                let value = i64::try_from(value).map_err(|_| ScriptError { pos: start, kind: ScriptErrorKind::IndexTooLarge })?;
+                Ok(Expr::Int(value))
+            }
+            _ => {
+                let Some(var) = self.ident() else { return Err(self.unexpected()) };
+                if !self.assigned.contains(&var) {
+                    return Err(ScriptError { pos: start, kind: ScriptErrorKind::UnknownVariable(var) });
+                }
+                Ok(Expr::Var(var))
+            }
+        }
+    }
+}
+
+fn step(meter: &Meter) -> Result<(), RuntimeError> {
+    meter
+        .step(1)
+        .map_err(|_| RuntimeError::StepQuotaExceeded(meter.quota().max_steps.unwrap_or_default()))
+}
+
+// state of one run of a definition. the total is only written back if the run finishes
+struct Run<'a> {
+    args: &'a [i64],
+    total: i64,
+    vars: HashMap<String, i64>,
+    meter: &'a Meter,
+}
+
+impl Run<'_> {
+    fn body(&mut self, body: &[Stmt]) -> Result<(), RuntimeError> {
+        for stmt in body {
+            step(self.meter)?;
+            match stmt {
+                Stmt::Assign(var, expr) => {
+                    let value = self.eval(expr)?;
+                    if var == "total" {
+                        self.total = value;
+                    } else {
+                        self.vars.insert(var.clone(), value);
+                    }
+                }
+                Stmt::If(cond, then, otherwise) => {
+                    if self.eval(cond)? != 0 {
+                        self.body(then)?;
+                    } else {
+                        self.body(otherwise)?;
+                    }
+                }
+                Stmt::Repeat(count, body) => {
+                    let count = self.eval(count)?;
+                    if count > MAX_LOOP_ITERATIONS {
+                        return Err(RuntimeError::LoopTooLong(count));
+                    }
+                    for _ in 0..count {
+                        self.body(body)?;
+                    }
+                }
+                Stmt::Expr(expr) => {
+                    self.eval(expr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i64, RuntimeError> {
+        step(self.meter)?;
+        Ok(match expr {
+            Expr::Int(value) => *value,
+            Expr::Arg { index, .. } => self.args[*index],
+            Expr::Var(var) if var == "total" => self.total,
+            // only reachable after an assignment in a branch that didn't run, reads as 0 then
+            Expr::Var(var) => self.vars.get(var).copied().unwrap_or(0),
+            Expr::Neg(inner) => self.eval(inner)?.wrapping_neg(),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+                match op {
+                    BinOp::Add => lhs.wrapping_add(rhs),
+                    BinOp::Sub => lhs.wrapping_sub(rhs),
+                    BinOp::Mul => lhs.wrapping_mul(rhs),
+                    BinOp::Div | BinOp::Rem if rhs == 0 => return Err(RuntimeError::DivisionByZero),
+                    BinOp::Div => lhs.wrapping_div(rhs),
+                    BinOp::Rem => lhs.wrapping_rem(rhs),
+                    BinOp::Eq => (lhs == rhs) as i64,
+                    BinOp::Ne => (lhs != rhs) as i64,
+                    BinOp::Lt => (lhs < rhs) as i64,
+                    BinOp::Le => (lhs <= rhs) as i64,
+                    BinOp::Gt => (lhs > rhs) as i64,
+                    BinOp::Ge => (lhs >= rhs) as i64,
+                }
+            }
+        })
+    }
+}
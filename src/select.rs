@@ -0,0 +1,80 @@
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::backpressure::CountingReceiver;
+use crate::clock::{Blocked, Clock};
+
+// rung by every send on the channels a thread selects over, so it can sleep on all of them at once.
+// cloning is cheap, every clone rings the same bell
+#[derive(Clone, Default)]
+pub(crate) struct Doorbell {
+    rings: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl Doorbell {
+    pub(crate) fn ring(&self) {
+        let (rings, rung) = &*self.rings;
+        *rings.lock().unwrap() += 1;
+        rung.notify_all();
+    }
+
+    // how often it has been rung so far, for wait
+    pub(crate) fn rings(&self) -> u64 {
+        *self.rings.0.lock().unwrap()
+    }
+
+    // until it has been rung more than seen times or timeout passes on clock. whether it was rung
+    pub(crate) fn wait(&self, clock: &dyn Clock, seen: u64, timeout: Duration) -> bool {
+        let deadline = clock.now() + timeout;
+        let _blocked = Blocked::new(clock, deadline);
+        let (rings, rung) = &*self.rings;
+        let mut count = rings.lock().unwrap();
+        while *count == seen {
+            let now = clock.now();
+            if now >= deadline {
+                return false;
+            }
+            count = rung.wait_timeout(count, clock.poll_interval(deadline - now)).unwrap().0;
+        }
+        true
+    }
+}
+
+// what select found
+pub(crate) enum Selected<R, C> {
+    Request(R),
+    Control(C),
+    // the bell rang without a message, e.g. for a raised shutdown flag
+    Woken,
+    TimedOut,
+}
+
+// the next message on either channel, control first, waiting up to timeout on clock for one to come in.
+// both channels have to ring bell on every send. a request channel whose senders are all gone counts as empty
+pub(crate) fn select<R, C>(
+    clock: &dyn Clock,
+    bell: &Doorbell,
+    requests: &CountingReceiver<R>,
+    control: &Receiver<C>,
+    timeout: Duration,
+) -> Selected<R, C> {
+    let take = || {
+        if let Ok(command) = control.try_recv() {
+            return Some(Selected::Control(command));
+        }
+        match requests.try_recv() {
+            Ok(request) => Some(Selected::Request(request)),
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => None,
+        }
+    };
+    // read before looking, so a message that comes in after the look still ends the wait
+    let seen = bell.rings();
+    if let Some(selected) = take() {
+        return selected;
+    }
+    if !bell.wait(clock, seen, timeout) {
+        return Selected::TimedOut;
+    }
+    take().unwrap_or(Selected::Woken)
+}
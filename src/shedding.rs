@@ -0,0 +1,34 @@
+use crate::QosClass;
+
+// which requests the worker drops while it is overloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShedPolicy {
+    // batch requests are dropped, interactive ones are still queued
+    #[default]
+    BatchOnly,
+    // every request that comes in is dropped until the worker has caught up
+    Everything,
+}
+
+// when the worker counts as overloaded and what it drops then, see ServerConfig::load_shedding.
+// a request that arrives while either threshold is reached is answered with TaskResult::Shed instead of being queued.
+// what is queued already stays queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadShedding {
+    pub queue_depth: Option<usize>,  // requests waiting in the worker's QoS queues
+    pub active_tasks: Option<usize>, // running tasks
+    pub policy: ShedPolicy,
+}
+
+impl LoadShedding {
+    pub fn overloaded(&self, queue_depth: usize, active_tasks: usize) -> bool {
+        self.queue_depth.is_some_and(|max| queue_depth >= max) || self.active_tasks.is_some_and(|max| active_tasks >= max)
+    }
+
+    pub fn sheds(&self, class: QosClass) -> bool {
+        match self.policy {
+            ShedPolicy::BatchOnly => class == QosClass::Batch,
+            ShedPolicy::Everything => true,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::select::Doorbell;
+
+// the shutdown flag together with the worker's doorbell, so a worker blocked waiting for requests leaves as soon as
+// the flag goes up instead of noticing it once its wait times out, up to the worker timeout later
+#[derive(Clone)]
+pub(crate) struct ShutdownSignal {
+    flag: Arc<AtomicBool>,
+    bell: Doorbell,
+}
+
+impl ShutdownSignal {
+    pub(crate) fn new(flag: Arc<AtomicBool>, bell: Doorbell) -> Self {
+        Self { flag, bell }
+    }
+
+    // sets the flag, then wakes the worker so it sees it. a worker that is gone already doesn't mind
+    pub(crate) fn raise(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+        self.bell.ring();
+    }
+}
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+// cleanup to run once the server has shut down, after an idle timeout and after shutdown_with alike.
+// see ServerThread::on_shutdown. cloning is cheap, every clone shares the same hooks
+#[derive(Clone)]
+pub struct ShutdownHooks {
+    // None once the hooks have run
+    hooks: Arc<Mutex<Option<Vec<Hook>>>>,
+}
+
+impl ShutdownHooks {
+    pub fn new() -> Self {
+        Self { hooks: Arc::new(Mutex::new(Some(Vec::new()))) }
+    }
+
+    // f runs right away, on the calling thread, if the server has shut down already
+    pub fn add(&self, f: impl FnOnce() + Send + 'static) {
+        let mut hooks = self.hooks.lock().unwrap();
+        match hooks.as_mut() {
+            Some(pending) => pending.push(Box::new(f)),
+            None => {
+                drop(hooks);
+                f();
+            }
+        }
+    }
+
+    pub fn has_run(&self) -> bool {
+        self.hooks.lock().unwrap().is_none()
+    }
+
+    // every hook added so far, in the order they were added. later calls do nothing
+    pub(crate) fn run(&self) {
+        let Some(hooks) = self.hooks.lock().unwrap().take() else { return };
+        for hook in hooks {
+            hook();
+        }
+    }
+}
+
+impl Default for ShutdownHooks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ShutdownHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hooks.lock().unwrap().as_ref() {
+            Some(pending) => f.debug_struct("ShutdownHooks").field("pending", &pending.len()).finish(),
+            None => f.debug_struct("ShutdownHooks").field("has_run", &true).finish(),
+        }
+    }
+}
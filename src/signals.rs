@@ -0,0 +1,96 @@
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::SigId;
+
+use crate::backpressure::CountingSender;
+use crate::{Envelope, RequestOptions, ResultSink, ShutdownMode, SinkChain, TaskRequest};
+
+// how often the watcher looks at the signal flag, real time
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// exit status after a signal-triggered shutdown, what a shell reports for a process ended by SIGINT
+const SIGNAL_EXIT_CODE: i32 = 130;
+
+// the servers currently watching for signals. a handler can't be taken back out of the process, see
+// signal_hook::low_level::unregister, so while none is watching `unwatched` makes a signal do its default again
+struct Watchers {
+    count: usize,
+    unwatched: Option<Arc<AtomicBool>>,
+}
+
+static WATCHERS: Mutex<Watchers> = Mutex::new(Watchers { count: 0, unwatched: None });
+
+fn watch() -> io::Result<()> {
+    let mut watchers = WATCHERS.lock().unwrap();
+    if watchers.unwatched.is_none() {
+        let unwatched = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register_conditional_default(SIGINT, Arc::clone(&unwatched))?;
+        signal_hook::flag::register_conditional_default(SIGTERM, Arc::clone(&unwatched))?;
+        watchers.unwatched = Some(unwatched);
+    }
+    watchers.count += 1;
+    watchers.unwatched.as_ref().unwrap().store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+// drops the watcher's own handlers, the last one gone hands the signals back to their default
+fn unwatch(ids: &[SigId]) {
+    for id in ids {
+        signal_hook::low_level::unregister(*id);
+    }
+    let mut watchers = WATCHERS.lock().unwrap();
+    watchers.count -= 1;
+    if watchers.count == 0 {
+        watchers.unwatched.as_ref().unwrap().store(true, Ordering::SeqCst);
+    }
+}
+
+// on SIGINT or SIGTERM: drain the worker, wait for the listener to record what comes back, flush the sinks and exit.
+// the watcher gives up quietly once the server's listener has exited on its own, removing its handlers
+pub(crate) fn shutdown_on_signal(
+    worker_tx: CountingSender<Envelope>,
+    shutdown_flag: Arc<AtomicBool>,
+    sinks: SinkChain,
+    listener_timeout: Duration,
+) -> io::Result<()> {
+    let received = Arc::new(AtomicBool::new(false));
+    watch()?;
+    let mut ids = Vec::new();
+    for signal in [SIGINT, SIGTERM] {
+        match signal_hook::flag::register(signal, Arc::clone(&received)) {
+            Ok(id) => ids.push(id),
+            Err(err) => {
+                unwatch(&ids);
+                return Err(err);
+            }
+        }
+    }
+    thread::spawn(move || {
+        while !received.load(Ordering::Relaxed) {
+            if shutdown_flag.load(Ordering::Relaxed) {
+                unwatch(&ids);
+                return;
+            }
+            thread::sleep(SIGNAL_POLL_INTERVAL);
+        }
+        println!("[Signals] Caught a termination signal. Draining before exit...");
+        let _ = worker_tx.send(Envelope {
+            opts: RequestOptions::default(),
+            request: TaskRequest::Shutdown { mode: ShutdownMode::Drain },
+        });
+        // the server still holds its result channels, so the listener goes once it has been idle for listener_timeout
+        let deadline = Instant::now() + listener_timeout + Duration::from_secs(1);
+        while !shutdown_flag.load(Ordering::Relaxed) && Instant::now() < deadline {
+            thread::sleep(SIGNAL_POLL_INTERVAL);
+        }
+        sinks.flush();
+        println!("[Signals] Results flushed. Exiting.");
+        process::exit(SIGNAL_EXIT_CODE);
+    });
+    Ok(())
+}
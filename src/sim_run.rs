@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Clock, ServerThread, SimClock};
+
+// how long (real time) nothing may start or stop waiting on the clock before the server counts as settled
+pub const DEFAULT_SETTLE: Duration = Duration::from_millis(5);
+
+type Action = Box<dyn FnOnce(&mut ServerThread)>;
+
+// what a time-compressed run covered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimRunReport {
+    pub simulated: Duration, // clock time the run moved through
+    pub wall: Duration,      // real time it took
+    pub advances: usize,     // how often the clock was moved on
+    pub fired: usize,        // scheduled actions that ran
+}
+
+impl SimRunReport {
+    // simulated time per real time
+    pub fn compression(&self) -> f64 {
+        if self.wall.is_zero() { 0.0 } else { self.simulated.as_secs_f64() / self.wall.as_secs_f64() }
+    }
+}
+
+impl fmt::Display for SimRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "simulated {:?} in {:?} ({:.0}x), {} advances, {} actions",
+            self.simulated,
+            self.wall,
+            self.compression(),
+            self.advances,
+            self.fired
+        )
+    }
+}
+
+// runs a server on a SimClock through hours of scheduled requests and idle expiries in seconds of real time.
+// once every thread has settled, the clock jumps straight to whatever comes first: the next scheduled action or
+// the earliest deadline a thread is blocked until. it never jumps past one, so timeouts fire one at a time and in
+// order, each seeing everything that happened before it.
+// settled means no thread started or stopped waiting on the clock for the settle period. a thread that computes for
+// longer than that without touching the clock, e.g. a slow update function, gets the clock moved under it
+pub struct SimRun {
+    clock: Arc<SimClock>,
+    settle: Duration,
+    // by clock time since the run started, in the order they were added
+    actions: BTreeMap<Duration, Vec<Action>>,
+}
+
+impl SimRun {
+    // clock has to be the one the server was configured with
+    pub fn new(clock: Arc<SimClock>) -> Self {
+        Self { clock, settle: DEFAULT_SETTLE, actions: BTreeMap::new() }
+    }
+
+    pub fn with_settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    // runs action on the server at clock time offset since the run started
+    pub fn at(mut self, offset: Duration, action: impl FnOnce(&mut ServerThread) + 'static) -> Self {
+        self.actions.entry(offset).or_default().push(Box::new(action));
+        self
+    }
+
+    // moves the clock through until (since the run started), or until nothing is scheduled and no thread waits
+    // on the clock anymore, whichever comes first
+    pub fn run(mut self, s: &mut ServerThread, until: Duration) -> SimRunReport {
+        let wall = Instant::now();
+        let started = self.clock.now();
+        let end = started + until;
+        let (mut advances, mut fired) = (0, 0);
+        loop {
+            self.settle();
+            let now = self.clock.now();
+            // everything due, then settle again before looking for the next event
+            let due: Vec<_> = self.actions.range(..=now.saturating_sub(started)).map(|(offset, _)| *offset).collect();
+            if !due.is_empty() {
+                for offset in due {
+                    for action in self.actions.remove(&offset).unwrap_or_default() {
+                        action(s);
+                        fired += 1;
+                    }
+                }
+                continue;
+            }
+            let next_action = self.actions.keys().next().map(|offset| started + *offset);
+            let next = match (next_action, self.clock.next_deadline()) {
+                (Some(action), Some(deadline)) => action.min(deadline),
+                (Some(next), None) | (None, Some(next)) => next,
+                (None, None) => break,
+            };
+            if now >= end {
+                break;
+            }
+            self.clock.advance_to(next.min(end));
+            advances += 1;
+        }
+        SimRunReport { simulated: self.clock.now() - started, wall: wall.elapsed(), advances, fired }
+    }
+
+    // waits (real time) until no thread has started or stopped waiting on the clock for the settle period
+    fn settle(&self) {
+        let mut seen = self.clock.activity();
+        loop {
+            thread::sleep(self.settle);
+            let activity = self.clock.activity();
+            if activity == seen {
+                return;
+            }
+            seen = activity;
+        }
+    }
+}
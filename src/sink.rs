@@ -0,0 +1,66 @@
+use std::fmt::Debug;
+use std::sync::{Arc, mpsc::Sender};
+
+use crate::{RequestId, TaskResult};
+
+// somewhere a recorded result goes. the listener shards and the server hand every result they record
+// to the server's SinkChain, which ends in the results store
+pub trait ResultSink: Debug + Send + Sync {
+    // once per recorded result, from whichever thread recorded it
+    fn accept(&self, req_id: RequestId, result: &TaskResult);
+
+    // push out anything buffered, e.g. before the process exits. nothing to do for most sinks
+    fn flush(&self) {}
+}
+
+// sinks run one after the other, in the order they were added
+// cloning is cheap, every clone shares the same sinks
+#[derive(Debug, Clone, Default)]
+pub struct SinkChain {
+    sinks: Vec<Arc<dyn ResultSink>>,
+}
+
+impl SinkChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, sink: impl ResultSink + 'static) -> Self {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+
+    pub fn then_shared(mut self, sink: Arc<dyn ResultSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+impl ResultSink for SinkChain {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        for sink in &self.sinks {
+            sink.accept(req_id, result);
+        }
+    }
+
+    fn flush(&self) {
+        for sink in &self.sinks {
+            sink.flush();
+        }
+    }
+}
+
+// forwards a copy of every result, a receiver that has gone away is ignored
+impl ResultSink for Sender<TaskResult> {
+    fn accept(&self, _req_id: RequestId, result: &TaskResult) {
+        let _ = self.send(result.clone());
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{EventBus, LifecycleTable, RequestId, ResultSink, ServerEvent, TaskId, TaskResult};
+
+// requests taking longer than threshold from being sent to having their result recorded are reported.
+// the keep slowest of them are kept for after the run, see ServerConfig::slow_requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowRequestConfig {
+    pub threshold: Duration,
+    pub keep: usize,
+}
+
+impl Default for SlowRequestConfig {
+    fn default() -> Self {
+        Self { threshold: Duration::from_millis(500), keep: 10 }
+    }
+}
+
+// a request that went over the threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowRequest {
+    pub req_id: RequestId,
+    pub id: TaskId,
+    pub kind: &'static str, // its result's TaskResult::kind
+    pub latency: Duration,  // clock time from sending it to recording its result
+}
+
+#[derive(Default)]
+struct Slowest {
+    count: usize,
+    // slowest first, at most keep of them
+    kept: Vec<SlowRequest>,
+}
+
+// reports every result that took too long as ServerEvent::SlowRequest and keeps the slowest.
+// only requests that went through the worker have a latency, like ResultCounts::average_latency.
+// cloning is cheap, every clone shares the same requests
+#[derive(Clone)]
+pub struct SlowRequests {
+    config: SlowRequestConfig,
+    slowest: Arc<Mutex<Slowest>>,
+    lifecycle: LifecycleTable,
+    events: EventBus,
+}
+
+impl SlowRequests {
+    pub fn new(config: SlowRequestConfig, lifecycle: LifecycleTable, events: EventBus) -> Self {
+        Self { config, slowest: Arc::new(Mutex::new(Slowest::default())), lifecycle, events }
+    }
+
+    pub fn config(&self) -> SlowRequestConfig {
+        self.config
+    }
+
+    // every request that went over the threshold, not just the kept ones
+    pub fn count(&self) -> usize {
+        self.slowest.lock().unwrap().count
+    }
+
+    // slowest first. ties keep the one recorded first ahead
+    pub fn slowest(&self) -> Vec<SlowRequest> {
+        self.slowest.lock().unwrap().kept.clone()
+    }
+}
+
+impl fmt::Debug for SlowRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowRequests").field("config", &self.config).field("count", &self.count()).finish_non_exhaustive()
+    }
+}
+
+impl ResultSink for SlowRequests {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        let Some(meta) = self.lifecycle.get(req_id).and_then(|lifecycle| lifecycle.meta()) else { return };
+        let latency = meta.total();
+        if latency <= self.config.threshold {
+            return;
+        }
+        let slow = SlowRequest { req_id, id: result.id(), kind: result.kind(), latency };
+        {
+            let mut slowest = self.slowest.lock().unwrap();
+            slowest.count += 1;
+            let at = slowest.kept.partition_point(|kept| kept.latency >= latency);
+            if at < self.config.keep {
+                slowest.kept.insert(at, slow);
+                slowest.kept.truncate(self.config.keep);
+            }
+        }
+        println!("[req:{req_id}] [SlowRequests] Took {latency:?}");
+        self.events.publish(ServerEvent::SlowRequest { req_id, id: slow.id, latency });
+    }
+}
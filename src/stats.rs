@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{ChannelStats, HedgeStats, LifecycleTable, RequestId, ResultSink, TaskResult};
+
+// a server-wide snapshot, see ServerThread::stats. everything is counted off the results as they are recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerStats {
+    pub requests: usize,                      // req_ids handed out, requests the server turned away included
+    pub results: HashMap<&'static str, usize>, // recorded results by TaskResult::kind
+    pub active_tasks: usize,
+    pub throttled: usize,                     // creates turned away by the concurrency cap or a tenant's cap
+    // from sending a request to recording its result, over every result that went through the worker. None before the first
+    pub average_latency: Option<Duration>,
+    pub slow_requests: usize,                 // over the ServerConfig::slow_requests threshold, 0 when it is not set
+    pub uptime: Duration,                     // clock time since the server was created
+    pub channel: ChannelStats,                // sends on the worker's request channel, to compare the channel backends
+    pub hedges: HedgeStats,                   // queries sent twice and answers dropped, see ServerConfig::hedge_after
+    pub coalesced: usize,                     // queries answered along with an identical one, see ServerConfig::coalesce_queries
+    pub replica_reads: usize,                 // stale_ok queries answered from a replica, see ServerConfig::replica_interval
+}
+
+#[derive(Default)]
+struct Counts {
+    by_kind: HashMap<&'static str, usize>,
+    latency_total: Duration,
+    latency_samples: u32,
+}
+
+// counts every recorded result by kind and adds up how long they took, for ServerThread::stats.
+// the lifecycle table is completed before results reach the sinks, so a result's timing is there by the time it is counted
+// cloning is cheap, every clone shares the same counts
+#[derive(Clone)]
+pub struct ResultCounts {
+    counts: Arc<Mutex<Counts>>,
+    lifecycle: LifecycleTable,
+}
+
+impl ResultCounts {
+    pub fn new(lifecycle: LifecycleTable) -> Self {
+        Self { counts: Arc::new(Mutex::new(Counts::default())), lifecycle }
+    }
+
+    pub fn by_kind(&self) -> HashMap<&'static str, usize> {
+        self.counts.lock().unwrap().by_kind.clone()
+    }
+
+    pub fn count(&self, kind: &str) -> usize {
+        self.counts.lock().unwrap().by_kind.get(kind).copied().unwrap_or(0)
+    }
+
+    pub fn average_latency(&self) -> Option<Duration> {
+        let counts = self.counts.lock().unwrap();
+        (counts.latency_samples > 0).then(|| counts.latency_total / counts.latency_samples)
+    }
+}
+
+impl fmt::Debug for ResultCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResultCounts").field("by_kind", &self.by_kind()).finish_non_exhaustive()
+    }
+}
+
+impl ResultSink for ResultCounts {
+    fn accept(&self, req_id: RequestId, result: &TaskResult) {
+        let latency = self.lifecycle.get(req_id).and_then(|lifecycle| lifecycle.meta()).map(|meta| meta.total());
+        let mut counts = self.counts.lock().unwrap();
+        *counts.by_kind.entry(result.kind()).or_default() += 1;
+        if let Some(latency) = latency {
+            counts.latency_total += latency;
+            counts.latency_samples += 1;
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+// where a task keeps the values its queries read and its subscriptions write
+// a HashMap by default, see ServerThread::create_task_with_store for plugging in anything else:
+// a persistent store, a bounded one that evicts, or one that counts its reads
+pub trait KvStore: Send {
+    // takes &mut self so a store can note the read, e.g. to keep recency for eviction
+    fn get(&mut self, key: &str) -> Option<String>;
+    fn set(&mut self, key: String, value: String);
+    fn remove(&mut self, key: &str) -> Option<String>;
+    // every entry, in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_>;
+}
+
+impl KvStore for HashMap<String, String> {
+    fn get(&mut self, key: &str) -> Option<String> {
+        HashMap::get(self, key).cloned()
+    }
+
+    fn set(&mut self, key: String, value: String) {
+        self.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<String> {
+        HashMap::remove(self, key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        Box::new(HashMap::iter(self).map(|(key, value)| (key.as_str(), value.as_str())))
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use crate::backpressure::CountingReceiver;
+use crate::{Envelope, EventBus, ServerEvent, WorkerSummary, WorkerThread};
+
+// runs the worker on a thread of its own and watches its JoinHandle. when the worker panics, e.g. on a poisoned lock,
+// a successor sharing its tasks and tables takes over the same request channel and ServerEvent::WorkerRestarted goes out.
+// the request the worker was handling and whatever it had pulled into its queues are lost with it.
+// a worker that was killed is only replaced once the heartbeat monitor has declared it dead over revive_rx,
+// see HeartbeatConfig::restart. returns the summary of the whole run, restarts included, once a worker is done for good
+pub(crate) fn spawn_supervised(
+    worker: WorkerThread,
+    rx: CountingReceiver<Envelope>,
+    revive_rx: Option<Receiver<()>>,
+    shutdown_flag: Arc<AtomicBool>,
+    events: EventBus,
+) -> JoinHandle<WorkerSummary> {
+    // the receiver outlives every worker thread, a panic only poisons the lock around it
+    let rx = Arc::new(Mutex::new(rx));
+    thread::spawn(move || {
+        let mut worker = worker;
+        let mut restarts = 0;
+        loop {
+            let successor = worker.successor();
+            let handle = thread::spawn({
+                let rx = Arc::clone(&rx);
+                let shutdown_flag = Arc::clone(&shutdown_flag);
+                move || worker.run(&rx.lock().unwrap_or_else(PoisonError::into_inner), shutdown_flag)
+            });
+            match handle.join() {
+                Ok(summary) if summary.killed && revive_rx.as_ref().is_some_and(revived) => {
+                    println!("[Supervisor] Worker was declared dead");
+                }
+                Ok(mut summary) => {
+                    summary.restarts = restarts;
+                    return summary;
+                }
+                Err(_) => println!("[Supervisor] Worker panicked"),
+            }
+            restarts += 1;
+            println!("[Supervisor] Starting replacement #{restarts}");
+            rx.clear_poison();
+            successor.recover();
+            events.publish(ServerEvent::WorkerRestarted { restarts });
+            worker = successor;
+        }
+    })
+}
+
+// waits for the monitor to declare the worker that just exited dead. false once the monitor is gone, e.g. on shutdown
+fn revived(revive_rx: &Receiver<()>) -> bool {
+    // anything sent before is about an earlier worker
+    while revive_rx.try_recv().is_ok() {}
+    revive_rx.recv().is_ok()
+}
@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{KvStore, ServerContext, UpdateFn, UpdateOutcome};
+
+// the task's store while an update runs, lent to the helper thread and taken back once the update is over
+pub(crate) type LentStore = Arc<Mutex<Box<dyn KvStore>>>;
+
+// moves store out for an update to read, leaving an empty one in its place
+pub(crate) fn lend(store: &mut Box<dyn KvStore>) -> LentStore {
+    Arc::new(Mutex::new(std::mem::replace(store, Box::new(HashMap::new()))))
+}
+
+// the store back from an update, done or not. one still running reads an empty store from here on
+pub(crate) fn take_back(lent: &LentStore) -> Box<dyn KvStore> {
+    std::mem::replace(&mut *lock(lent), Box::new(HashMap::new()))
+}
+
+// boxes f as an update function. a closure passed straight to Box::new needs its argument spelled out as
+// |state: &mut TaskState|, going through here lets it be |_| or |state|
+pub fn update_fn(f: impl FnMut(&mut TaskState) -> UpdateOutcome + Send + 'static) -> UpdateFn {
+    Box::new(f)
+}
+
+// a lock that a panicking update left poisoned still guards data that is whole, every change under it is one call
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// counters and extensions the update functions keep between runs. they stay with the task and every update reaches
+// them through its TaskState, so one that panics or times out doesn't take them down with it, though one that times
+// out and keeps running can still change them. none of it is part of checkpoints. cloning is cheap, clones share
+#[derive(Clone, Default)]
+pub struct TaskMemory {
+    counters: Arc<Mutex<HashMap<String, i64>>>,
+    extensions: Arc<Mutex<HashMap<String, Box<dyn Any + Send>>>>,
+}
+
+impl fmt::Debug for TaskMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut extensions: Vec<_> = lock(&self.extensions).keys().cloned().collect();
+        extensions.sort();
+        f.debug_struct("TaskMemory")
+            .field("counters", &*lock(&self.counters))
+            .field("extensions", &extensions)
+            .finish()
+    }
+}
+
+// what an update function gets to work with: the task's values, counters and extensions of its own choosing,
+// and the version the task is at. values are read from the task's store as they are asked for, what the update
+// sets or removes is kept aside and written back once it returns Ok, left out when it returns Err, panics or times
+// out. the server's context is there for every update
+pub struct TaskState {
+    store: LentStore,
+    // keys set (Some) or removed (None) by the running update, read before the store
+    writes: HashMap<String, Option<String>>,
+    memory: TaskMemory,
+    version: u64,
+    context: Option<ServerContext>,
+}
+
+// an empty task's at version 0, for running an update function on its own
+impl Default for TaskState {
+    fn default() -> Self {
+        Self::new(Arc::new(Mutex::new(Box::new(HashMap::new()))), TaskMemory::default(), 0, None)
+    }
+}
+
+impl TaskState {
+    pub(crate) fn new(store: LentStore, memory: TaskMemory, version: u64, context: Option<ServerContext>) -> Self {
+        Self { store, writes: HashMap::new(), memory, version, context }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        match self.writes.get(key) {
+            Some(written) => written.clone(),
+            None => lock(&self.store).get(key),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.writes.insert(key.to_string(), Some(value.into()));
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let old = self.get(key)?;
+        self.writes.insert(key.to_string(), None);
+        Some(old)
+    }
+
+    // 0 for a counter never bumped
+    pub fn counter(&self, name: &str) -> i64 {
+        lock(&self.memory.counters).get(name).copied().unwrap_or(0)
+    }
+
+    // adds by to the counter and gives its new value
+    pub fn bump(&mut self, name: &str, by: i64) -> i64 {
+        let mut counters = lock(&self.memory.counters);
+        let counter = counters.entry(name.to_string()).or_insert(0);
+        *counter += by;
+        *counter
+    }
+
+    // a copy of the extension stored under name, None if there is none or it isn't a T
+    pub fn extension<T: Any + Clone>(&self, name: &str) -> Option<T> {
+        lock(&self.memory.extensions).get(name)?.downcast_ref().cloned()
+    }
+
+    // runs f on the extension stored under name in place, None if there is none or it isn't a T
+    pub fn with_extension<T: Any, R>(&mut self, name: &str, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        lock(&self.memory.extensions).get_mut(name)?.downcast_mut().map(f)
+    }
+
+    // replaces whatever was stored under name, of any type
+    pub fn insert_extension<T: Any + Send>(&mut self, name: &str, value: T) {
+        lock(&self.memory.extensions).insert(name.to_string(), Box::new(value));
+    }
+
+    // the task's version when the update started, an update that returns Ok moves it one up
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // the server's context, see ServerConfig::context. None without one or if it holds something other than a T
+    pub fn context<T: Any>(&self) -> Option<&T> {
+        self.context.as_ref()?.get()
+    }
+
+    // what the update set and removed, sorted by key
+    pub(crate) fn take_writes(self) -> Vec<(String, Option<String>)> {
+        let mut writes: Vec<_> = self.writes.into_iter().collect();
+        writes.sort();
+        writes
+    }
+}
+
+impl fmt::Debug for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskState")
+            .field("writes", &self.writes.len())
+            .field("memory", &self.memory)
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
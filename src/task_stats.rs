@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use crate::TaskResult;
+
+// what one run of a task thread has done, counted as it answers. a task that is respawned starts again from zero.
+// see ServerThread::task_stats for a running task and WorkerStats::finished_tasks for the ones that have exited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    pub queries: u64, // query instructions answered, single key, many keys and pattern alike
+    pub updates: u64, // update instructions answered, compare-and-swap ones included
+    pub errors: u64,  // answers that report a failure, see TaskResult::error_code, quotas and version conflicts
+    pub busy: Duration, // clock time spent on instructions
+}
+
+impl TaskStats {
+    pub fn add(&mut self, other: &TaskStats) {
+        self.queries += other.queries;
+        self.updates += other.updates;
+        self.errors += other.errors;
+        self.busy += other.busy;
+    }
+
+    // counts an answer that took busy to produce
+    pub(crate) fn count(&mut self, result: &TaskResult, busy: Duration) {
+        match result {
+            TaskResult::QueryOk { .. } | TaskResult::QueryError { .. } | TaskResult::QueryManyOk { .. } => self.queries += 1,
+            TaskResult::UpdateOk { .. }
+            | TaskResult::UpdateError { .. }
+            | TaskResult::UpdateTimedOut { .. }
+            | TaskResult::VersionConflict { .. } => self.updates += 1,
+            _ => {}
+        }
+        if result.error_code().is_some()
+            || matches!(result, TaskResult::QuotaExceeded { .. } | TaskResult::VersionConflict { .. })
+        {
+            self.errors += 1;
+        }
+        self.busy += busy;
+    }
+}
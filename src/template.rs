@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// the spec of a task that can be created any number of times, see ServerThread::register_template
+// update functions are referenced by name and built fresh for every task from the server's UpdateRegistry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskTemplate {
+    pub query_map: HashMap<String, String>,
+    pub update_ids: Vec<String>,
+}
+
+// why a template could not be registered or used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnknownTemplate(String),
+    // the template names an update function that is not registered with ServerThread::register_update
+    UnknownUpdate { template: String, update_id: String },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownTemplate(name) => write!(f, "no template named '{name}'"),
+            TemplateError::UnknownUpdate { template, update_id } => {
+                write!(f, "template '{template}' uses update '{update_id}', which is not registered")
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::TaskId;
+
+// who a task belongs to. requests carry one in RequestOptions::tenant and only reach tasks of the same tenant,
+// so tenants sharing a server can't see each other's tasks. tenant 0 is where everything goes by default
+pub type TenantId = usize;
+
+#[derive(Debug, Default)]
+struct Tenants {
+    owners: HashMap<TaskId, TenantId>, // every task the worker still knows, running or kept after expiring
+    active: HashMap<TenantId, usize>,  // running task threads per tenant
+    served: HashMap<TenantId, usize>,  // requests the worker took off its queues per tenant
+}
+
+// the worker's per-tenant view of its tasks, used for isolation and for the per-tenant caps in ServerConfig::tenant_caps
+// cloning is cheap, every clone shares the same table
+#[derive(Debug, Clone, Default)]
+pub struct TenantTable {
+    inner: Arc<Mutex<Tenants>>,
+}
+
+impl TenantTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn owner(&self, id: TaskId) -> Option<TenantId> {
+        self.inner.lock().unwrap().owners.get(&id).copied()
+    }
+
+    // the tenant's tasks in id order
+    pub fn tasks_of(&self, tenant: TenantId) -> Vec<TaskId> {
+        let tenants = self.inner.lock().unwrap();
+        let mut ids: Vec<TaskId> =
+            tenants.owners.iter().filter(|(_, owner)| **owner == tenant).map(|(id, _)| *id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    // running task threads of the tenant
+    pub fn active(&self, tenant: TenantId) -> usize {
+        self.inner.lock().unwrap().active.get(&tenant).copied().unwrap_or(0)
+    }
+
+    // requests of the tenant the worker has taken off its queues, its throughput under fair queueing
+    pub fn served(&self, tenant: TenantId) -> usize {
+        self.inner.lock().unwrap().served.get(&tenant).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn note_served(&self, tenant: TenantId) {
+        *self.inner.lock().unwrap().served.entry(tenant).or_default() += 1;
+    }
+
+    pub(crate) fn started(&self, id: TaskId, tenant: TenantId) {
+        let mut tenants = self.inner.lock().unwrap();
+        tenants.owners.insert(id, tenant);
+        *tenants.active.entry(tenant).or_default() += 1;
+    }
+
+    // forget is false for a task the worker still knows about after its thread is gone, e.g. one kept for a respawn
+    pub(crate) fn exited(&self, id: TaskId, forget: bool) {
+        let mut tenants = self.inner.lock().unwrap();
+        let owner = if forget { tenants.owners.remove(&id) } else { tenants.owners.get(&id).copied() };
+        if let Some(active) = owner.and_then(|tenant| tenants.active.get_mut(&tenant)) {
+            *active = active.saturating_sub(1);
+        }
+    }
+}
+
+// what one tenant has sent and got back, see ServerThread::tenant_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantStats {
+    pub requests: usize,
+    // requests with a recorded result
+    pub answered: usize,
+    // answered with a result that has an error code, see TaskResult::error_code
+    pub errors: usize,
+    pub active_tasks: usize,
+    // requests the worker has taken off its queues
+    pub served: usize,
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use crate::config_file::ConfigError;
+use crate::{LISTENER_TIMEOUT, TASK_TIMEOUT, WORKER_TIMEOUT};
+
+// the idle timeouts of the three kinds of threads. the defaults are the constants of the same names,
+// and the assumptions written down next to them apply to whatever is configured here, see ServerConfig::validate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    // a task exits after this long without an instruction
+    pub task: Duration,
+    // the listener shuts everything down after this long without a result
+    pub listener: Duration,
+    // how long the worker blocks on its channel before looking at the shutdown flag again
+    pub worker: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            task: Duration::from_secs(TASK_TIMEOUT),
+            listener: Duration::from_secs(LISTENER_TIMEOUT),
+            worker: Duration::from_secs(WORKER_TIMEOUT),
+        }
+    }
+}
+
+impl Timeouts {
+    // rejects timeouts that break the assumptions, see validate
+    pub fn new(task: Duration, listener: Duration, worker: Duration) -> Result<Self, ConfigError> {
+        let timeouts = Self { task, listener, worker };
+        timeouts.validate()?;
+        Ok(timeouts)
+    }
+
+    // all three from the task timeout, in the same proportions as the defaults: the listener and the worker wait
+    // two and a half times as long as a task. derived(2s) is the default. base has to be longer than zero.
+    // the update timeout isn't one of them and has to stay shorter than base, the base_timeout key halves it
+    pub fn derived(base: Duration) -> Self {
+        let listener = base * 5 / 2;
+        Self { task: base, listener, worker: listener }
+    }
+
+    // every timeout longer than zero and assumption 2, the listener outlasting any task
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let Timeouts { task, listener, worker } = *self;
+        if task.is_zero() || listener.is_zero() || worker.is_zero() {
+            return Err(ConfigError::Invalid("timeouts have to be longer than zero".to_string()));
+        }
+        // assumption 2
+        if listener <= task {
+            return Err(ConfigError::Invalid(format!(
+                "listener timeout ({listener:?}) has to be longer than the task timeout ({task:?})"
+            )));
+        }
+        Ok(())
+    }
+}
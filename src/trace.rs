@@ -0,0 +1,178 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Clock, RequestId, TaskId};
+
+// traces of recorded requests a Tracer keeps by default, the oldest recorded one makes room for the next
+pub const RECORDED_TRACES_KEPT: usize = 10_000;
+
+// the points a request passes on its way through the system, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hop {
+    Sent,       // the server put it on the worker channel
+    Dequeued,   // the worker took it off the channel into its QoS queues
+    Dispatched, // the worker handed it to its task. creates and rejected requests skip this and Received
+    Received,   // the task took it off its instruction channel
+    Recorded,   // the listener recorded its result
+}
+
+impl Hop {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hop::Sent => "sent",
+            Hop::Dequeued => "dequeued",
+            Hop::Dispatched => "dispatched",
+            Hop::Received => "received",
+            Hop::Recorded => "recorded",
+        }
+    }
+}
+
+// one leg of a request's trip, between two consecutive hops it passed. times are clock times
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub req_id: RequestId,
+    pub id: TaskId,
+    pub from: Hop,
+    pub to: Hop,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl Span {
+    pub fn duration(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+struct Trace {
+    id: TaskId,
+    hops: Vec<(Hop, Duration)>,
+}
+
+#[derive(Default)]
+struct Traces {
+    by_req_id: HashMap<RequestId, Trace>,
+    // requests that reached Hop::Recorded, in that order
+    recorded: VecDeque<RequestId>,
+}
+
+// the trace context of every request is its req_id: the server, worker, tasks and listener each mark the hop
+// they are at against it, and the spans are the gaps between those marks. the traces of requests still on their way
+// are all kept, recorded ones only up to a capacity, their spans have been published as ServerEvent::Span by then.
+// cloning is cheap, every clone shares the same traces. a disabled tracer ignores every mark
+#[derive(Clone)]
+pub struct Tracer {
+    enabled: bool,
+    traces: Arc<Mutex<Traces>>,
+    recorded_kept: usize,
+    clock: Arc<dyn Clock>,
+    // unix time at clock time zero, so exported spans line up with wall time
+    epoch: Duration,
+}
+
+impl Tracer {
+    pub fn new(enabled: bool, clock: Arc<dyn Clock>) -> Self {
+        Self::with_capacity(enabled, clock, RECORDED_TRACES_KEPT)
+    }
+
+    // keeps the traces of the last recorded_kept recorded requests
+    pub fn with_capacity(enabled: bool, clock: Arc<dyn Clock>, recorded_kept: usize) -> Self {
+        let unix_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let epoch = unix_now.saturating_sub(clock.now());
+        Self { enabled, traces: Arc::new(Mutex::new(Traces::default())), recorded_kept, clock, epoch }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // only the first time a request passes a hop counts, a duplicated message doesn't move it
+    pub fn mark(&self, req_id: RequestId, id: TaskId, hop: Hop) {
+        if !self.enabled {
+            return;
+        }
+        let now = self.clock.now();
+        let mut traces = self.traces.lock().unwrap();
+        let trace = traces.by_req_id.entry(req_id).or_insert_with(|| Trace { id, hops: vec![] });
+        if trace.hops.iter().any(|(h, _)| *h == hop) {
+            return;
+        }
+        trace.hops.push((hop, now));
+        trace.hops.sort_by_key(|(h, _)| *h);
+        if hop == Hop::Recorded {
+            traces.recorded.push_back(req_id);
+            while traces.recorded.len() > self.recorded_kept {
+                let Some(oldest) = traces.recorded.pop_front() else { break };
+                traces.by_req_id.remove(&oldest);
+            }
+        }
+    }
+
+    // the legs of a request's trip so far, in hop order
+    pub fn spans(&self, req_id: RequestId) -> Vec<Span> {
+        let traces = self.traces.lock().unwrap();
+        let Some(trace) = traces.by_req_id.get(&req_id) else { return vec![] };
+        trace
+            .hops
+            .windows(2)
+            .map(|pair| Span {
+                req_id,
+                id: trace.id,
+                from: pair[0].0,
+                to: pair[1].0,
+                start: pair[0].1,
+                end: pair[1].1,
+            })
+            .collect()
+    }
+
+    // every trace in OpenTelemetry's OTLP/JSON format, ready to be posted to a collector's /v1/traces
+    // or loaded into a trace viewer. each request is one trace with a root span covering its whole trip
+    // and a child span per leg
+    pub fn export_otlp_json(&self) -> String {
+        let mut req_ids: Vec<RequestId> = self.traces.lock().unwrap().by_req_id.keys().copied().collect();
+        req_ids.sort();
+
+        let mut spans = vec![];
+        for req_id in req_ids {
+            let legs = self.spans(req_id);
+            let (Some(first), Some(last)) = (legs.first(), legs.last()) else { continue };
+            // trace and span ids have to be non-zero
+            let trace_id = format!("{:032x}", req_id + 1);
+            let root = format!("{:016x}", 1);
+            let whole = Span { to: last.to, end: last.end, ..first.clone() };
+            spans.push(self.otlp_span(&trace_id, &root, None, &format!("request {req_id}"), &whole));
+            for (i, leg) in legs.iter().enumerate() {
+                let name = format!("{} -> {}", leg.from.name(), leg.to.name());
+                let span_id = format!("{:016x}", i + 2);
+                spans.push(self.otlp_span(&trace_id, &span_id, Some(&root), &name, leg));
+            }
+        }
+        format!(
+            "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{{\"key\":\"service.name\",\"value\":{{\"stringValue\":\"server_worker_sim\"}}}}]}},\
+             \"scopeSpans\":[{{\"scope\":{{\"name\":\"server_worker_sim\"}},\"spans\":[{}]}}]}}]}}",
+            spans.join(",")
+        )
+    }
+
+    fn otlp_span(&self, trace_id: &str, span_id: &str, parent: Option<&str>, name: &str, span: &Span) -> String {
+        let mut json = format!("{{\"traceId\":\"{trace_id}\",\"spanId\":\"{span_id}\"");
+        if let Some(parent) = parent {
+            let _ = write!(json, ",\"parentSpanId\":\"{parent}\"");
+        }
+        let _ = write!(
+            json,
+            ",\"name\":\"{name}\",\"kind\":1,\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\
+             \"attributes\":[{{\"key\":\"req_id\",\"value\":{{\"intValue\":\"{}\"}}}},\
+             {{\"key\":\"task_id\",\"value\":{{\"intValue\":\"{}\"}}}}]}}",
+            (self.epoch + span.start).as_nanos(),
+            (self.epoch + span.end).as_nanos(),
+            span.req_id,
+            span.id
+        );
+        json
+    }
+}
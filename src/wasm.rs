@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use wasmi::{Caller, Config, Engine, Error, ExternType, Linker, Module, Store};
+
+use crate::quota::Meter;
+use crate::script::{ScriptError, ScriptErrorKind, UpdateMap};
+use crate::TaskState;
+
+// fuel a single exported function gets per call unless the caller picks something else
+// one unit is roughly one wasm instruction
+pub const DEFAULT_WASM_FUEL: u64 = 100_000;
+
+// a compiled WASM module the hypervisor can create tasks from, the sandboxed alternative to a script
+//
+// the module sees the task's args through one import:
+//   (import "env" "arg" (func (param i32) (result i64)))      args[index], traps if index is out of range
+// and exposes the task through its exports:
+//   query_<name>  : () -> i64      run once when the task is created, the value becomes query "<name>"
+//   update_<name> : (i64) -> i64   update "<name>", called with the running total and returning the new one
+// every other export is ignored
+//
+// every call runs with a fresh allotment of fuel, so a function that loops forever traps instead of hanging the task.
+// a trapped update leaves the total as it was and fails with the trap, like a failed script run
+//
+// the fuel an update burns is reported to the task's meter as its steps. with a step quota below fuel,
+// an update only gets one unit more than the quota, enough to trap once it goes over
+pub struct WasmModule {
+    engine: Engine,
+    module: Module,
+}
+
+impl WasmModule {
+    pub fn compile(bytes: &[u8]) -> Result<WasmModule, ScriptError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, bytes).map_err(wasm_error)?;
+        Ok(WasmModule { engine, module })
+    }
+
+    // instantiates the module for one task. all of the task's updates share the instance and the running total
+    pub fn build(&self, args: &[i64], fuel: u64, meter: &Meter) -> Result<(HashMap<String, String>, UpdateMap), ScriptError> {
+        let mut store = Store::new(&self.engine, args.to_vec());
+        let mut linker = Linker::<Vec<i64>>::new(&self.engine);
+        linker
+            .func_wrap("env", "arg", |caller: Caller<'_, Vec<i64>>, index: i32| -> Result<i64, Error> {
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| caller.data().get(index).copied())
+                    .ok_or_else(|| Error::new(format!("arg {index} is out of range")))
+            })
+            .map_err(wasm_error)?;
+        store.set_fuel(fuel).map_err(wasm_error)?;
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(wasm_error)?;
+
+        let mut query_map = HashMap::new();
+        let mut updates = vec![];
+        for export in self.module.exports() {
+            if !matches!(export.ty(), ExternType::Func(_)) {
+                continue;
+            }
+            if let Some(name) = export.name().strip_prefix("query_") {
+                let query = instance.get_typed_func::<(), i64>(&store, export.name()).map_err(wasm_error)?;
+                store.set_fuel(fuel).map_err(wasm_error)?;
+                let value = query.call(&mut store, ()).map_err(wasm_error)?;
+                query_map.insert(name.to_string(), value.to_string());
+            } else if let Some(name) = export.name().strip_prefix("update_") {
+                let update = instance.get_typed_func::<i64, i64>(&store, export.name()).map_err(wasm_error)?;
+                updates.push((name.to_string(), update));
+            }
+        }
+
+        let update_fuel = meter.quota().max_steps.map_or(fuel, |max| fuel.min(max.saturating_add(1)));
+        let sandbox = Arc::new(Mutex::new((store, 0i64)));
+        let mut update_map: UpdateMap = HashMap::new();
+        for (name, update) in updates {
+            let (sandbox, meter) = (Arc::clone(&sandbox), meter.clone());
+            update_map.insert(
+                name,
+                Box::new(move |_: &mut TaskState| {
+                    let mut sandbox = sandbox.lock().unwrap();
+                    let (store, total) = &mut *sandbox;
+                    store.set_fuel(update_fuel).map_err(|err| err.to_string())?;
+                    let result = update.call(&mut *store, *total);
+                    let _ = meter.step(update_fuel - store.get_fuel().unwrap_or(0));
+                    match result {
+                        Ok(value) => {
+                            *total = value;
+                            Ok(value.to_string())
+                        }
+                        Err(err) => Err(err.to_string()),
+                    }
+                }),
+            );
+        }
+        Ok((query_map, update_map))
+    }
+}
+
+fn wasm_error(err: impl std::fmt::Display) -> ScriptError {
+    ScriptError { pos: 0, kind: ScriptErrorKind::Wasm(err.to_string()) }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{Clock, EventBus, RequestId, ServerEvent, TaskId};
+
+struct InFlight {
+    id: TaskId,
+    dispatched_at: Duration, // clock time
+    flagged: bool, // SlowTask already emitted, so it is only reported once
+}
+
+// tracks instructions between the worker handing them to a task and the listener receiving their result
+// cloning is cheap, every clone shares the same in-flight table
+#[derive(Clone)]
+pub struct Watchdog {
+    in_flight: Arc<Mutex<HashMap<RequestId, InFlight>>>,
+    per_task: Arc<Mutex<HashMap<TaskId, usize>>>, // in-flight count per task, kept in step with in_flight
+    clock: Arc<dyn Clock>,
+}
+
+impl Watchdog {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { in_flight: Arc::new(Mutex::new(HashMap::new())), per_task: Arc::new(Mutex::new(HashMap::new())), clock }
+    }
+
+    // called by the worker when an instruction is handed to a task
+    // a duplicated instruction is tracked once, from its latest dispatch
+    pub fn track(&self, req_id: RequestId, id: TaskId) {
+        let dispatched_at = self.clock.now();
+        let previous = self.in_flight.lock().unwrap().insert(req_id, InFlight { id, dispatched_at, flagged: false });
+        let mut per_task = self.per_task.lock().unwrap();
+        if let Some(previous) = previous {
+            release(&mut per_task, previous.id);
+        }
+        *per_task.entry(id).or_default() += 1;
+    }
+
+    // called by the listener when the result for req_id arrives
+    pub fn complete(&self, req_id: RequestId) {
+        if let Some(done) = self.in_flight.lock().unwrap().remove(&req_id) {
+            release(&mut self.per_task.lock().unwrap(), done.id);
+        }
+    }
+
+    // instructions handed to task id that it has not answered yet
+    pub fn in_flight_for(&self, id: TaskId) -> usize {
+        self.per_task.lock().unwrap().get(&id).copied().unwrap_or(0)
+    }
+
+    // number of instructions dispatched but not yet answered
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+
+    // starts the watchdog thread. every threshold/4 it looks for instructions that have been in flight for longer
+    // than threshold and publishes a ServerEvent::SlowTask for each of them on the bus
+    // the threshold has to be shorter than the task timeout, anything longer would break assumption 1 before it fires,
+    // ServerConfig::validate turns it down
+    // the thread exits once the shutdown flag is set
+    pub fn spawn(&self, threshold: Duration, events: EventBus, shutdown_flag: Arc<AtomicBool>) -> JoinHandle<()> {
+        let in_flight = Arc::clone(&self.in_flight);
+        let clock = Arc::clone(&self.clock);
+        let tick = (threshold / 4).max(Duration::from_millis(1));
+
+        thread::spawn(move || {
+            while !shutdown_flag.load(Ordering::Relaxed) {
+                clock.sleep(tick);
+
+                // collect first and publish after releasing the lock, subscribers may be slow
+                let mut slow = vec![];
+                let now = clock.now();
+                for (req_id, entry) in in_flight.lock().unwrap().iter_mut() {
+                    let elapsed = now.saturating_sub(entry.dispatched_at);
+                    if !entry.flagged && elapsed > threshold {
+                        entry.flagged = true;
+                        slow.push(ServerEvent::SlowTask { id: entry.id, req_id: *req_id, elapsed });
+                    }
+                }
+                for event in slow {
+                    println!("[Watchdog] {:?}", event);
+                    events.publish(event);
+                }
+            }
+            println!("[Watchdog] Shutdown flag detected. Watchdog exiting.");
+        })
+    }
+}
+
+fn release(per_task: &mut HashMap<TaskId, usize>, id: TaskId) {
+    if let Some(count) = per_task.get_mut(&id) {
+        *count -= 1;
+        if *count == 0 {
+            per_task.remove(&id);
+        }
+    }
+}
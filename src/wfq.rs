@@ -0,0 +1,63 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::TenantId;
+
+// weighted fair queueing across tenants, see ServerConfig::tenant_weights
+// every item gets a virtual finish time when it is pushed: where its tenant's previous item finished (or the current
+// virtual time if the tenant was idle) plus 1/weight. pop takes the smallest finish time, so a tenant with weight 2
+// gets twice the turns of a tenant with weight 1 while both have work waiting, however fast either of them pushes.
+// an idle tenant does not bank turns for later
+// with a single tenant it is plain FIFO
+pub struct FairQueue<T> {
+    weights: HashMap<TenantId, u32>,
+    // ordered by tenant so ties go to the lower tenant id, the same pushes always pop in the same order
+    tenants: BTreeMap<TenantId, VecDeque<(f64, T)>>,
+    last_finish: HashMap<TenantId, f64>,
+    virtual_time: f64,
+    len: usize,
+}
+
+impl<T> FairQueue<T> {
+    // tenants without a weight get 1, a weight of 0 is treated as 1
+    pub fn new(weights: HashMap<TenantId, u32>) -> Self {
+        Self { weights, tenants: BTreeMap::new(), last_finish: HashMap::new(), virtual_time: 0.0, len: 0 }
+    }
+
+    pub fn weight(&self, tenant: TenantId) -> u32 {
+        self.weights.get(&tenant).copied().unwrap_or(1).max(1)
+    }
+
+    pub fn push(&mut self, tenant: TenantId, item: T) {
+        let start = self.last_finish.get(&tenant).copied().unwrap_or(0.0).max(self.virtual_time);
+        let finish = start + 1.0 / self.weight(tenant) as f64;
+        self.last_finish.insert(tenant, finish);
+        self.tenants.entry(tenant).or_default().push_back((finish, item));
+        self.len += 1;
+    }
+
+    // the item with the smallest finish time and its tenant
+    pub fn pop(&mut self) -> Option<(TenantId, T)> {
+        let tenant = self
+            .tenants
+            .iter()
+            .filter_map(|(tenant, queue)| Some((*tenant, queue.front()?.0)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))?
+            .0;
+        let queue = self.tenants.get_mut(&tenant)?;
+        let (finish, item) = queue.pop_front()?;
+        if queue.is_empty() {
+            self.tenants.remove(&tenant);
+        }
+        self.virtual_time = finish;
+        self.len -= 1;
+        Some((tenant, item))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+// what the worker did over its whole run, returned by WorkerThread::run. see ServerThread::join_worker
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerSummary {
+    pub handled: HashMap<&'static str, usize>, // requests taken out of the queues and acted on, by TaskRequest::kind
+    pub tasks_spawned: usize,                  // task threads started, respawns included
+    pub throttled: usize,                      // creates turned away at the concurrency cap or their tenant's cap
+    pub batches: usize,                        // TaskInstruction::Batch sent, see ServerConfig::instruction_batch
+    pub killed: bool,                          // ended by TaskRequest::Kill instead of a shutdown
+    pub restarts: usize,                       // workers that panicked and were replaced, see ServerConfig::supervise_worker
+}
+
+impl WorkerSummary {
+    // 0 for a kind that never came up
+    pub fn count(&self, kind: &str) -> usize {
+        self.handled.get(kind).copied().unwrap_or(0)
+    }
+
+    pub fn total_handled(&self) -> usize {
+        self.handled.values().sum()
+    }
+}
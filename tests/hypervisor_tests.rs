@@ -0,0 +1,341 @@
+use server_worker_sim::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_hypervisor_script_tasks() {
+    let mut h = Hypervisor::new();
+    let task_id = h.create_task("0 1a 1m", vec![3, 4]).unwrap();   // req_id: 0
+    h.query_task(task_id, "0");     // req_id: 1
+    h.update_task(task_id, "1a");   // req_id: 2
+    h.update_task(task_id, "1m");   // req_id: 3
+    h.query_task(task_id, "1");     // req_id: 4, not in the script
+
+    let err = h.create_task("1a!", vec![10, 20]).unwrap_err();     // req_id: 5
+    assert_eq!(err, ScriptError { pos: 2, kind: ScriptErrorKind::UnexpectedChar('!') });
+    let err = h.create_task("0 2", vec![10, 20]).unwrap_err();     // req_id: 6
+    assert_eq!(err.kind, ScriptErrorKind::ArgOutOfRange { index: 2, args: 2 });
+    assert_eq!(err.pos, 2);
+
+    assert_eq!(h.server().wait_idle(Duration::from_secs(1)), Ok(()));
+    h.listen_for_results();
+
+    let s = h.server();
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id: task_id, value: "3".into(), access: None }));
+    assert!(s.expect(2, &TaskResult::UpdateOk { req_id: 2, id: task_id, value: "4".into() }));
+    assert!(s.expect(3, &TaskResult::UpdateOk { req_id: 3, id: task_id, value: "16".into() }));
+    assert!(s.expect(4, &TaskResult::QueryError {
+        req_id: 4,
+        id: task_id,
+        code: ErrorCode::KeyNotFound,
+        detail: Some("Query ID '1' not found".into())
+    }));
+    assert!(s.expect(5, &TaskResult::InvalidScript {
+        req_id: 5,
+        id: 1,
+        error: ScriptError { pos: 2, kind: ScriptErrorKind::UnexpectedChar('!') }
+    }));
+
+    assert_eq!(Script::parse("").unwrap_err().kind, ScriptErrorKind::Empty);
+    assert_eq!(Script::parse("1x").unwrap_err(), ScriptError { pos: 1, kind: ScriptErrorKind::UnknownOp('x') });
+    assert_eq!(Script::parse("1a 1a").unwrap_err().kind, ScriptErrorKind::Duplicate("1a".into()));
+}
+
+#[test]
+fn test_hypervisor_script_expressions() {
+    let mut h = Hypervisor::new();
+    let task_id = h.create_task(
+        "0 1a grow{repeat $0 {total = total + $1}; if total > 100 {total = 100} else {total = total * 2}} \
+         parity{half = total / 2; if half * 2 == total {total = 0} else {total = -1}} zero{total = total / ($0 - 3)}",
+        vec![3, 50],
+    ).unwrap();                         // req_id: 0
+    h.update_task(task_id, "grow");     // req_id: 1, 3 * 50 capped at 100
+    h.update_task(task_id, "1a");       // req_id: 2, the total is shared with plain codes
+    h.update_task(task_id, "parity");   // req_id: 3
+    h.update_task(task_id, "zero");     // req_id: 4, division by zero leaves the total alone
+    h.update_task(task_id, "1a");       // req_id: 5
+
+    let mut small = Hypervisor::new();
+    let small_id = small.create_task("grow{repeat $0 {total = total + $1}; if total > 100 {total = 100} else {total = total * 2}}", vec![1, 7]).unwrap();
+    small.update_task(small_id, "grow");
+
+    for (req_id, value) in [(1, "100"), (2, "150"), (3, "0"), (5, "50")] {
+        assert_eq!(h.server().expect_eventually(req_id, &TaskResult::UpdateOk {
+            req_id,
+            id: task_id,
+            value: value.into()
+        }, Duration::from_secs(1)), Ok(()));
+    }
+    assert_eq!(h.server().expect_eventually(4, &TaskResult::UpdateError {
+        req_id: 4,
+        id: task_id,
+        code: ErrorCode::UpdateFailed,
+        detail: Some("division by zero".into())
+    }, Duration::from_secs(1)), Ok(()));
+    assert_eq!(small.server().expect_eventually(1, &TaskResult::UpdateOk {
+        req_id: 1,
+        id: small_id,
+        value: "14".into()
+    }, Duration::from_secs(1)), Ok(()));
+
+    // loops are bounded
+    let mut script = Script::parse("spin{repeat $0 {total = total + 1}}").unwrap().build(&[MAX_LOOP_ITERATIONS + 1]).unwrap().1;
+    let spin = script.get_mut("spin").unwrap();
+    assert_eq!(spin(&mut TaskState::default()), Err(format!("repeat {} exceeds {MAX_LOOP_ITERATIONS} iterations", MAX_LOOP_ITERATIONS + 1)));
+
+    assert_eq!(Script::parse("f{x = y}").unwrap_err(), ScriptError {
+        pos: 6,
+        kind: ScriptErrorKind::UnknownVariable("y".into())
+    });
+    assert_eq!(Script::parse("f{total = (1 + 2}").unwrap_err().kind, ScriptErrorKind::UnexpectedChar('}'));
+    assert_eq!(Script::parse("f{total = 1").unwrap_err().kind, ScriptErrorKind::UnexpectedEnd);
+    assert_eq!(h.create_task("f{total = $4}", vec![1]).unwrap_err(), ScriptError {
+        pos: 10,
+        kind: ScriptErrorKind::ArgOutOfRange { index: 4, args: 1 }
+    });
+}
+
+#[test]
+fn test_hypervisor_script_registry() {
+    let dir = std::env::temp_dir().join(format!("sws_scripts_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("counter.script"), "0 1a\n").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+    let mut h = Hypervisor::new();
+    assert_eq!(h.load_dir(&dir).unwrap(), vec![("counter".to_string(), 1)]);
+    let old = h.create_registered_task("counter", vec![5, 1]).unwrap();    // req_id: 0
+
+    // a new version only affects tasks created after it
+    assert_eq!(h.register_script("counter", "0 1m"), Ok(2));
+    let new = h.create_registered_task("counter", vec![5, 1]).unwrap();    // req_id: 1
+    let pinned = h.create_registered_task_version("counter", 1, vec![5, 1]).unwrap(); // req_id: 2
+    assert_eq!(h.task_script(old), Some(("counter".to_string(), 1)));
+    assert_eq!(h.task_script(new), Some(("counter".to_string(), 2)));
+    assert_eq!(h.task_script(pinned), Some(("counter".to_string(), 1)));
+
+    h.update_task(old, "1a");       // req_id: 3
+    h.update_task(new, "1a");       // req_id: 4, not in version 2
+    h.update_task(pinned, "1a");    // req_id: 5
+    assert_eq!(h.server().wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(h.server().expect(3, &TaskResult::UpdateOk { req_id: 3, id: old, value: "1".into() }));
+    assert!(h.server().expect(4, &TaskResult::UpdateError {
+        req_id: 4,
+        id: new,
+        code: ErrorCode::UpdateNotFound,
+        detail: Some("Update ID '1a' not found".into())
+    }));
+    assert!(h.server().expect(5, &TaskResult::UpdateOk { req_id: 5, id: pinned, value: "1".into() }));
+
+    assert_eq!(
+        h.create_registered_task_version("counter", 3, vec![]).unwrap_err().kind,
+        ScriptErrorKind::UnknownScript { name: "counter".into(), version: Some(3) }
+    );
+    assert_eq!(
+        h.create_registered_task("missing", vec![]).unwrap_err().kind,
+        ScriptErrorKind::UnknownScript { name: "missing".into(), version: None }
+    );
+    assert!(h.register_script("broken", "1a!").is_err());
+    assert_eq!(h.script_version("broken"), None);
+
+    std::fs::write(dir.join("zz_broken.script"), "1a!").unwrap();
+    assert!(matches!(h.load_dir(&dir), Err(LoadError::Script { ref name, .. }) if name == "zz_broken"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// (module
+//   (import "env" "arg" (func $arg (param i32) (result i64)))
+//   (func (export "query_first") (result i64) i32.const 0 call $arg)
+//   (func (export "update_add") (param i64) (result i64) local.get 0 i32.const 1 call $arg i64.add)
+//   (func (export "update_spin") (param i64) (result i64) (loop br 0) local.get 0)
+//   (func (export "update_trap") (param i64) (result i64) unreachable))
+#[cfg(feature = "wasm")]
+fn test_module() -> Vec<u8> {
+    fn section(id: u8, content: Vec<u8>) -> Vec<u8> {
+        [vec![id, content.len() as u8], content].concat()
+    }
+    fn export(name: &str, func: u8) -> Vec<u8> {
+        [vec![name.len() as u8], name.as_bytes().to_vec(), vec![0x00, func]].concat()
+    }
+    fn body(code: &[u8]) -> Vec<u8> {
+        [vec![code.len() as u8 + 1, 0x00], code.to_vec()].concat()
+    }
+    [
+        vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00],
+        section(1, vec![0x03, 0x60, 0x01, 0x7f, 0x01, 0x7e, 0x60, 0x00, 0x01, 0x7e, 0x60, 0x01, 0x7e, 0x01, 0x7e]),
+        section(2, vec![0x01, 0x03, b'e', b'n', b'v', 0x03, b'a', b'r', b'g', 0x00, 0x00]),
+        section(3, vec![0x04, 0x01, 0x02, 0x02, 0x02]),
+        section(7, [
+            vec![0x04],
+            export("query_first", 1),
+            export("update_add", 2),
+            export("update_spin", 3),
+            export("update_trap", 4),
+        ].concat()),
+        section(10, [
+            vec![0x04],
+            body(&[0x41, 0x00, 0x10, 0x00, 0x0b]),
+            body(&[0x20, 0x00, 0x41, 0x01, 0x10, 0x00, 0x7c, 0x0b]),
+            body(&[0x03, 0x40, 0x0c, 0x00, 0x0b, 0x20, 0x00, 0x0b]),
+            body(&[0x00, 0x0b]),
+        ].concat()),
+    ].concat()
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn test_hypervisor_wasm_tasks() {
+    let mut h = Hypervisor::new();
+    let task_id = h.create_wasm_task(&test_module(), vec![7, 5], 10_000).unwrap();  // req_id: 0
+    h.query_task(task_id, "first");     // req_id: 1
+    h.update_task(task_id, "add");      // req_id: 2
+    h.update_task(task_id, "spin");     // req_id: 3, runs out of fuel instead of hanging
+    h.update_task(task_id, "trap");     // req_id: 4
+    h.update_task(task_id, "add");      // req_id: 5, the task survived both
+
+    assert_eq!(h.server().wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(h.server().expect(1, &TaskResult::QueryOk { req_id: 1, id: task_id, value: "7".into(), access: None }));
+    assert!(h.server().expect(2, &TaskResult::UpdateOk { req_id: 2, id: task_id, value: "5".into() }));
+    assert!(h.server().expect(5, &TaskResult::UpdateOk { req_id: 5, id: task_id, value: "10".into() }));
+    let outcomes = h.collect_results(Duration::from_secs(1));
+    for outcome in &outcomes[3..=4] {
+        assert!(matches!(outcome, HypervisorOutcome::Failed { .. }));
+    }
+
+    // under a step quota the fuel an update burns counts as its steps
+    h.set_quota(Quota { max_steps: Some(100), ..Quota::default() });
+    let metered_id = h.create_wasm_task(&test_module(), vec![7, 5], 10_000).unwrap();  // req_id: 6
+    h.update_task(metered_id, "spin");  // req_id: 7
+    assert_eq!(h.server().expect_eventually(7, &TaskResult::QuotaExceeded {
+        req_id: 7,
+        id: metered_id,
+        resource: QuotaResource::Steps,
+        terminated: false
+    }, Duration::from_secs(1)), Ok(()));
+    assert_eq!(h.task_usage(metered_id).unwrap().steps, 101);
+
+    // reading an arg that isn't there traps while the queries run, so the task is never created
+    assert!(matches!(
+        h.create_wasm_task(&test_module(), vec![], DEFAULT_WASM_FUEL).unwrap_err().kind,
+        ScriptErrorKind::Wasm(_)
+    ));
+    assert!(matches!(h.create_wasm_task(b"not wasm", vec![], DEFAULT_WASM_FUEL).unwrap_err().kind, ScriptErrorKind::Wasm(_)));
+}
+
+#[test]
+fn test_hypervisor_quotas() {
+    let mut h = Hypervisor::new();
+    h.set_quota(Quota { max_steps: Some(50), ..Quota::default() });
+    let task_id = h.create_task("0 1a spin{repeat $0 {total = total + 1}}", vec![100, 2]).unwrap();  // req_id: 0
+    h.update_task(task_id, "1a");       // req_id: 1
+    h.update_task(task_id, "spin");     // req_id: 2, stops after 50 steps
+    h.update_task(task_id, "1a");       // req_id: 3, spin left the total alone
+
+    // any query takes longer than a nanosecond
+    h.set_quota(Quota { max_wall_time: Some(Duration::from_nanos(1)), terminate: true, ..Quota::default() });
+    let slow_id = h.create_task("0", vec![1]).unwrap();    // req_id: 4
+    h.query_task(slow_id, "0");         // req_id: 5
+
+    assert_eq!(h.server().expect_eventually(3, &TaskResult::UpdateOk { req_id: 3, id: task_id, value: "4".into() }, Duration::from_secs(1)), Ok(()));
+    assert_eq!(h.server().expect_eventually(5, &TaskResult::QuotaExceeded {
+        req_id: 5,
+        id: slow_id,
+        resource: QuotaResource::WallTime,
+        terminated: true
+    }, Duration::from_secs(1)), Ok(()));
+    assert!(h.server().expect(1, &TaskResult::UpdateOk { req_id: 1, id: task_id, value: "2".into() }));
+    assert!(h.server().expect(2, &TaskResult::QuotaExceeded {
+        req_id: 2,
+        id: task_id,
+        resource: QuotaResource::Steps,
+        terminated: false
+    }));
+
+    let usage = h.task_usage(task_id).unwrap();
+    assert_eq!(usage.instructions, 3);
+    assert_eq!(usage.steps, 1 + 51 + 1);
+    assert_eq!(h.task_usage(slow_id).unwrap().instructions, 1);
+    assert_eq!(h.task_usage(42), None);
+}
+
+#[test]
+fn test_hypervisor_collect_results() {
+    let mut h = Hypervisor::new();
+    let task_id = h.create_task("0 zero{total = 1 / total}", vec![3]).unwrap();   // req_id: 0
+    h.query_task(task_id, "0");         // req_id: 1
+    h.update_task(task_id, "zero");     // req_id: 2
+    h.query_task(task_id, "9");         // req_id: 3
+    let _ = h.create_task("1a!", vec![]);   // req_id: 4
+    for _ in 0..MAX_CONCURRENT_TASKS {
+        h.create_task("0", vec![1]).unwrap();   // req_ids: 5 to 8, the last one goes over the limit
+    }
+
+    let outcomes = h.collect_results(Duration::from_secs(1));
+    assert_eq!(outcomes.len(), 9);
+    assert_eq!(outcomes[0], HypervisorOutcome::Created { req_id: 0, id: task_id });
+    assert_eq!(outcomes[1], HypervisorOutcome::Value { req_id: 1, id: task_id, value: "3".into() });
+    assert_eq!(outcomes[2], HypervisorOutcome::Failed { req_id: 2, id: task_id, reason: "division by zero".into() });
+    assert!(matches!(&outcomes[3], HypervisorOutcome::Rejected { req_id: 3, result: TaskResult::QueryError { .. } }));
+    assert!(matches!(&outcomes[4], HypervisorOutcome::InvalidScript { req_id: 4, id: 1, .. }));
+    assert_eq!(outcomes[8], HypervisorOutcome::Throttled { req_id: 8, id: 5 });
+}
+
+#[test]
+fn test_hypervisor_script_signatures() {
+    let mut h = Hypervisor::new();
+    let task_id = h.create_task("args(int, pos) 0 1a", vec![-3, 4]).unwrap();   // req_id: 0
+    let err = h.create_task("args(int, pos) 1a", vec![10]).unwrap_err();        // req_id: 1
+    assert_eq!(err.kind, ScriptErrorKind::Args(ArgError::WrongArity { expected: 2, got: 1 }));
+    let err = h.create_task("args(int, pos) 1a", vec![10, 20, 30]).unwrap_err();    // req_id: 2
+    assert_eq!(err.kind, ScriptErrorKind::Args(ArgError::WrongArity { expected: 2, got: 3 }));
+    let err = h.create_task("args(nat, pos) 1a", vec![10, 0]).unwrap_err();     // req_id: 3
+    assert_eq!(err.kind, ScriptErrorKind::Args(ArgError::WrongType { index: 1, expected: ArgType::Pos, value: 0 }));
+    assert_eq!(err.to_string(), "arg 1 is 0, expected pos");
+    h.update_task(task_id, "1a");       // req_id: 4
+
+    // the rejected tasks are answered without reaching the worker
+    let outcomes = h.collect_results(Duration::from_secs(1));
+    assert!(matches!(&outcomes[1], HypervisorOutcome::InvalidScript { req_id: 1, id: 1, .. }));
+    assert_eq!(outcomes[4], HypervisorOutcome::Value { req_id: 4, id: task_id, value: "4".into() });
+
+    // the signature is checked against the script itself too
+    assert_eq!(Script::parse("args(int) 0 1a").unwrap_err(), ScriptError {
+        pos: 12,
+        kind: ScriptErrorKind::ArgOutOfRange { index: 1, args: 1 }
+    });
+    assert_eq!(Script::parse("args(int, float) 0").unwrap_err(), ScriptError {
+        pos: 10,
+        kind: ScriptErrorKind::UnknownType("float".into())
+    });
+    assert_eq!(Script::parse("args() f{total = 1}").unwrap().signature, Some(vec![]));
+    assert_eq!(Script::parse("0 1a").unwrap().signature, None);
+}
+
+#[test]
+fn test_hypervisor_tenants() {
+    let mut a = Hypervisor::new();
+    let mut b = a.attach();
+    let a_task = a.create_task("0", vec![1]).unwrap();    // server req_id: 0, server task 0
+    let b_task = b.create_task("0", vec![2]).unwrap();    // server req_id: 1, server task 1
+    let a_other = a.create_task("0", vec![3]).unwrap();   // server req_id: 2, server task 2
+    assert_eq!((a_task, b_task, a_other), (0, 0, 1));
+
+    b.query_task(b_task, "0");      // server req_id: 3
+    a.query_task(a_task, "0");      // server req_id: 4
+    b.query_task(1, "0");           // server req_id: 5, b has no task 1, so a's task 1 is out of reach
+
+    assert_eq!(a.collect_results(Duration::from_secs(1)), vec![
+        HypervisorOutcome::Created { req_id: 0, id: 0 },
+        HypervisorOutcome::Created { req_id: 1, id: 1 },
+        HypervisorOutcome::Value { req_id: 2, id: 0, value: "1".into() },
+    ]);
+    assert_eq!(b.collect_results(Duration::from_secs(1)), vec![
+        HypervisorOutcome::Created { req_id: 0, id: 0 },
+        HypervisorOutcome::Value { req_id: 1, id: 0, value: "2".into() },
+        HypervisorOutcome::NotFound { req_id: 2, id: 1 },
+    ]);
+
+    // both drive the same server
+    assert_eq!(a.server().request_counter, 6);
+    assert!(Arc::ptr_eq(&a.server, &b.server));
+}
@@ -16,7 +16,8 @@ fn test_simple_success() {
     assert!(s.expect(1, &TaskResult::QueryOk {
         req_id: 1,
         id: task_id,
-        value: "running".into()
+        value: "running".into(),
+        access: None
     }));
 }
 
@@ -60,7 +61,8 @@ fn test_query_missing_key_in_task() {
     assert!(s.expect(1, &TaskResult::QueryError {
         req_id: 1,
         id: task_id,
-        msg: "Query ID 'nonexistent_key' not found".into()
+        code: ErrorCode::KeyNotFound,
+        detail: Some("Query ID 'nonexistent_key' not found".into())
     }));
 }
 
@@ -74,7 +76,8 @@ fn test_update_missing_id_in_task() {
     assert!(s.expect(1, &TaskResult::UpdateError {
         req_id: 1,
         id: task_id,
-        msg: "Update ID 'bad_update_id' not found".into()
+        code: ErrorCode::UpdateNotFound,
+        detail: Some("Update ID 'bad_update_id' not found".into())
     }));
 }
 
@@ -112,7 +115,7 @@ fn test_task_throttling_behavior() {
     for i in 0..6 {
         let id = s.create_task(
             [("get_status".into(), "idle".into())].into(),
-            [("mark_done".into(), Box::new(|| "Done".to_string()) as Box<dyn FnMut() -> String + Send>)].into()
+            [("mark_done".into(), update_fn(|_| Ok("Done".to_string())))].into()
         );
         if i >= MAX_CONCURRENT_TASKS {
             throttled_ids.push((i , id));
@@ -121,18 +124,20 @@ fn test_task_throttling_behavior() {
 
     s.join_listener();
     for (req_id, id) in throttled_ids {
-        assert!(s.expect(req_id, &TaskResult::Throttled { req_id, id }));
+        assert!(s.expect(req_id, &TaskResult::Throttled { req_id, id, group: None }));
     }
 }
 
 #[test]
+// the baseline loop, kept as it was written
+#[allow(clippy::needless_range_loop)]
 fn test_queried_task_w_throttled_tasks() {
     let mut s = ServerThread::new();
     let mut task_id = [0; 6];
     for i in 0..6 {
         task_id[i] = s.create_task(
             [("get_status".into(), "idle".into())].into(),
-            [("mark_done".into(), Box::new(|| "done".to_string()) as Box<dyn FnMut() -> String + Send>)].into()
+            [("mark_done".into(), update_fn(|_| Ok("done".to_string())))].into()
         );
     }
 
@@ -147,16 +152,19 @@ fn test_queried_task_w_throttled_tasks() {
     assert!(s.expect(4, &TaskResult::Throttled {
         req_id: 4,
         id: task_id[4],
+        group: None,
     }));
     assert!(s.expect(5, &TaskResult::Throttled {
         req_id: 5,
         id: task_id[5],
+        group: None,
     }));
 
     assert!(s.expect(6, &TaskResult::QueryOk {
         req_id: 6,
         id: task_id[0],
-        value: "idle".into()
+        value: "idle".into(),
+        access: None
     }));
     assert!(s.expect(7, &TaskResult::UpdateOk {
         req_id: 7,
@@ -166,12 +174,14 @@ fn test_queried_task_w_throttled_tasks() {
     assert!(s.expect(8, &TaskResult::QueryOk {
         req_id: 8,
         id: task_id[2],
-        value: "idle".into()
+        value: "idle".into(),
+        access: None
     }));
     assert!(s.expect(9, &TaskResult::QueryError {
         req_id: 9,
         id: task_id[0],
-        msg: "Query ID 'invalid_query' not found".into()
+        code: ErrorCode::KeyNotFound,
+        detail: Some("Query ID 'invalid_query' not found".into())
     }));
 }
 
@@ -193,7 +203,8 @@ fn test_multiple_queries_in_quick_succession() {
         assert!(s.expect(i, &TaskResult::QueryOk {
             req_id: i,
             id: task_id,
-            value: "busy".into()
+            value: "busy".into(),
+            access: None
         }));
     }
 }
@@ -221,15 +232,3218 @@ fn test_throttling_recovery_after_timeout() {
 
     assert!(s.expect(4, &TaskResult::Throttled {
         req_id: 4,
-        id: throttled_id_1
+        id: throttled_id_1,
+        group: None,
     }));
     assert!(s.expect(5, &TaskResult::Throttled {
         req_id: 5,
-        id: throttled_id_2
+        id: throttled_id_2,
+        group: None,
     }));
     assert!(s.expect(7, &TaskResult::QueryOk {
         req_id: 7,
         id: retry_id,
-        value: "retry".into()
+        value: "retry".into(),
+        access: None
     }));
 }
+
+#[test]
+fn test_event_bus_update_and_task_to_task_delivery() {
+    let mut s = ServerThread::new();
+    let updates = s.subscribe("update/mark_done");
+    let everything = s.subscribe(ALL_TOPICS);
+
+    let publisher = s.create_task(
+        HashMap::new(),
+        [("mark_done".into(), update_fn(|_| Ok("done".to_string())))].into()
+    );                                                   // req_id: 0
+    let subscriber = s.create_task(HashMap::new(), HashMap::new()); // req_id: 1
+
+    s.subscribe_task(subscriber, "ping");                // req_id: 2
+    s.publish_task(publisher, "ping", "hello");          // req_id: 3
+    s.update_task(publisher, "mark_done");               // req_id: 4
+    // delivered to the subscribing task and to the ALL_TOPICS receiver
+    // once this is recorded the event is already queued for the subscriber, ahead of the query below
+    assert_eq!(s.expect_eventually(3, &TaskResult::Published {
+        req_id: 3,
+        id: publisher,
+        topic: "ping".into(),
+        delivered: 2
+    }, Duration::from_secs(1)), Ok(()));
+    s.query_task(subscriber, "event/ping");              // req_id: 5
+    s.join_listener();
+
+    assert!(s.expect(2, &TaskResult::Subscribed {
+        req_id: 2,
+        id: subscriber,
+        topic: "ping".into()
+    }));
+    assert!(s.expect(5, &TaskResult::QueryOk {
+        req_id: 5,
+        id: subscriber,
+        value: "hello".into(),
+        access: None
+    }));
+
+    assert_eq!(updates.try_recv(), Ok(ServerEvent::Published {
+        topic: "update/mark_done".into(),
+        id: publisher,
+        payload: "done".into()
+    }));
+    // next to each task's created and finished events
+    let everything: Vec<_> = everything.try_iter().collect();
+    assert_eq!(everything.iter().filter(|event| matches!(event, ServerEvent::Published { .. })).count(), 2);
+    assert_eq!(everything.len(), 6);
+}
+
+#[test]
+fn test_rate_limiting_is_per_client() {
+    let mut s = ServerThread::with_config(ServerConfig {
+        rate_limit: Some(RateLimit { capacity: 2, refill_per_sec: 0.0 }),
+        ..Default::default()
+    });
+    let chatty = RequestOptions { client: 1, ..Default::default() };
+    let quiet = RequestOptions { client: 2, ..Default::default() };
+
+    let task_id = s.create_task_with(chatty.clone(), [("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task_with(chatty.clone(), task_id, "status");   // req_id: 1
+    s.query_task_with(chatty.clone(), task_id, "status");   // req_id: 2, bucket is empty
+    s.query_task_with(quiet.clone(), task_id, "status");    // req_id: 3
+    s.join_listener();
+
+    assert!(s.expect(1, &TaskResult::QueryOk {
+        req_id: 1,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }));
+    assert!(s.expect(2, &TaskResult::RateLimited {
+        req_id: 2,
+        id: task_id,
+        client: 1
+    }));
+    assert!(s.expect(3, &TaskResult::QueryOk {
+        req_id: 3,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }));
+}
+
+#[test]
+fn test_qos_queues_prefer_interactive_with_batch_share() {
+    let mut queues = QosQueues::new(3);
+    for i in 1..=5 {
+        queues.push(QosClass::Interactive, format!("i{i}"));
+    }
+    queues.push(QosClass::Batch, "b1".to_string());
+    queues.push(QosClass::Batch, "b2".to_string());
+
+    let order: Vec<String> = std::iter::from_fn(|| queues.pop()).collect();
+    assert_eq!(order, ["i1", "i2", "b1", "i3", "i4", "b2", "i5"]);
+}
+
+#[test]
+fn test_qos_class_accounting() {
+    let mut s = ServerThread::new();
+    let batch = RequestOptions { qos: QosClass::Batch, ..Default::default() };
+
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task_with(batch, task_id, "status");    // req_id: 1
+    s.query_task(task_id, "status");                // req_id: 2
+    s.join_listener();
+
+    assert_eq!(s.class_of(1), Some(QosClass::Batch));
+    assert_eq!(s.class_of(2), Some(QosClass::Interactive));
+    assert_eq!(s.result_envelope(1).and_then(|envelope| envelope.meta).map(|meta| meta.class), Some(QosClass::Batch));
+    assert_eq!(s.results_for_class(QosClass::Batch), vec![TaskResult::QueryOk {
+        req_id: 1,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }]);
+}
+
+#[test]
+fn test_health_report() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.expect_eventually(0, &TaskResult::Created { req_id: 0, id: task_id }, Duration::from_secs(1)), Ok(()));
+
+    assert_eq!(s.health(), HealthReport {
+        worker_alive: true,
+        listener_alive: true,
+        active_tasks: 1,
+        queue_depth: 0,
+        readiness: Readiness::Ready
+    });
+
+    s.join_listener();
+    assert!(!s.health().listener_alive);
+}
+
+#[test]
+fn test_watchdog_flags_slow_update() {
+    // a threshold the task timeout would beat is turned down
+    let late = ServerConfig { slow_task_threshold: Some(Duration::from_secs(TASK_TIMEOUT)), ..Default::default() };
+    assert!(matches!(late.validate(), Err(ConfigError::Invalid(_))));
+
+    let mut s = ServerThread::with_config(ServerConfig {
+        slow_task_threshold: Some(Duration::from_millis(100)),
+        ..Default::default()
+    });
+    let slow_tasks = s.subscribe("slow_task");
+
+    let task_id = s.create_task(
+        HashMap::new(),
+        [("crunch".into(), update_fn(|_| {
+            thread::sleep(Duration::from_millis(400));
+            Ok("crunched".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "crunch");   // req_id: 1
+    s.join_listener();
+
+    assert!(s.expect(1, &TaskResult::UpdateOk {
+        req_id: 1,
+        id: task_id,
+        value: "crunched".into()
+    }));
+    let flagged: Vec<ServerEvent> = slow_tasks.try_iter().collect();
+    assert_eq!(flagged.len(), 1);
+    assert!(matches!(flagged[0], ServerEvent::SlowTask { id, req_id: 1, .. } if id == task_id));
+}
+
+#[test]
+fn test_update_timeout_degrades_task() {
+    let mut s = ServerThread::with_config(ServerConfig {
+        update_timeout: Duration::from_millis(200),
+        ..Default::default()
+    });
+    let degraded = s.subscribe("task_degraded");
+
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("hang".into(), update_fn(|_| {
+            thread::sleep(Duration::from_secs(3));
+            Ok("never seen".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "hang");     // req_id: 1
+    s.update_task(task_id, "hang");     // req_id: 2
+    s.query_task(task_id, "status");    // req_id: 3
+    s.join_listener();
+
+    assert!(s.expect(1, &TaskResult::UpdateTimedOut { req_id: 1, id: task_id }));
+    assert!(s.expect(2, &TaskResult::UpdateError {
+        req_id: 2,
+        id: task_id,
+        code: ErrorCode::TimedOut,
+        detail: Some("Update ID 'hang' timed out earlier and is unavailable".into())
+    }));
+    // the task itself keeps answering
+    assert!(s.expect(3, &TaskResult::QueryOk {
+        req_id: 3,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }));
+    assert_eq!(degraded.try_recv(), Ok(ServerEvent::TaskDegraded { id: task_id, update_id: "hang".into() }));
+}
+
+#[test]
+fn test_shutdown_drain_completes_queued_work() {
+    let mut s = ServerThread::new();
+    let started = std::time::Instant::now();
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    for _ in 0..3 {
+        s.query_task(task_id, "status");    // req_id: 1..=3
+    }
+    s.shutdown_with(ShutdownMode::Drain);
+    s.query_task(task_id, "status");        // req_id: 4
+
+    for req_id in 1..=3 {
+        assert!(s.expect(req_id, &TaskResult::QueryOk {
+            req_id,
+            id: task_id,
+            value: "running".into(),
+            access: None
+        }));
+    }
+    assert!(s.expect(4, &TaskResult::ShuttingDown { req_id: 4, id: task_id }));
+    assert!(started.elapsed() < Duration::from_secs(TASK_TIMEOUT));
+}
+
+#[test]
+fn test_shutdown_immediate_drops_queued_instructions() {
+    let mut s = ServerThread::new();
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("slow".into(), update_fn(move |_| {
+            let _ = started_tx.send(());
+            thread::sleep(Duration::from_millis(300));
+            Ok("done".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "slow");     // req_id: 1
+    // the queries below have to queue up behind an update that is already running
+    assert_eq!(started_rx.recv_timeout(Duration::from_secs(1)), Ok(()));
+    s.query_task(task_id, "status");    // req_id: 2
+    s.query_task(task_id, "status");    // req_id: 3
+    s.shutdown_with(ShutdownMode::Immediate);
+
+    // the update was already running and finishes, the queries queued behind it never run.
+    // depending on whether they were still in the worker or already with the task they are rejected or dropped
+    assert!(s.expect(1, &TaskResult::UpdateOk {
+        req_id: 1,
+        id: task_id,
+        value: "done".into()
+    }));
+    for req_id in 2..=3 {
+        assert!(matches!(s.results.get(req_id), None | Some(TaskResult::ShuttingDown { .. })));
+    }
+}
+
+#[test]
+fn test_wait_idle() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    for _ in 0..5 {
+        s.query_task(task_id, "status");    // req_id: 1..=5
+    }
+
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(0, &TaskResult::Created { req_id: 0, id: task_id }));
+    for req_id in 1..=5 {
+        assert!(s.expect(req_id, &TaskResult::QueryOk {
+            req_id,
+            id: task_id,
+            value: "running".into(),
+            access: None
+        }));
+    }
+
+    // once the listener is gone, anything still pending is reported right away
+    s.shutdown_with(ShutdownMode::Immediate);
+    s.query_task(task_id, "status");        // req_id: 6, recorded as ShuttingDown
+    s.next_req_id();                        // req_id: 7, never sent
+    let started = std::time::Instant::now();
+    assert_eq!(s.wait_idle(Duration::from_secs(10)), Err(vec![7]));
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_sim_clock_drives_task_expiry_and_listener_shutdown() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        ..Default::default()
+    });
+    let started = std::time::Instant::now();
+
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // real time passing doesn't expire anything
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(s.health().active_tasks, 1);
+
+    clock.advance(Duration::from_secs(TASK_TIMEOUT + 1));
+    while s.health().active_tasks > 0 {
+        assert!(started.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(5));
+    }
+    s.query_task(task_id, "status");    // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::NotFound {
+        req_id: 2,
+        id: task_id,
+        ctx: "Task not found for query"
+    }));
+
+    clock.advance(Duration::from_secs(LISTENER_TIMEOUT + 1));
+    s.join_listener();
+    assert!(started.elapsed() < Duration::from_secs(TASK_TIMEOUT));
+}
+
+#[test]
+fn test_fault_injection() {
+    // every instruction to a task is lost, so queries are acknowledged but never answered
+    let mut s = ServerThread::with_config(ServerConfig {
+        faults: Some(FaultConfig {
+            seed: 7,
+            instructions: FaultRates { drop: 1.0, ..Default::default() },
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+    s.query_task(task_id, "status");    // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_millis(500)), Err(vec![1, 2]));
+    assert_eq!(s.faults.stats(FaultChannel::Instructions).dropped, 2);
+    s.shutdown_with(ShutdownMode::Immediate);
+
+    // delayed and duplicated results still all end up recorded
+    let mut s = ServerThread::with_config(ServerConfig {
+        faults: Some(FaultConfig {
+            seed: 7,
+            results: FaultRates { delay: 0.5, duplicate: 0.5, ..Default::default() },
+            max_delay: Duration::from_millis(50),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    for _ in 0..10 {
+        s.query_task(task_id, "status");    // req_id: 1..=10
+    }
+    assert_eq!(s.wait_idle(Duration::from_secs(2)), Ok(()));
+    let stats = s.faults.stats(FaultChannel::Results);
+    assert!(stats.delayed > 0 && stats.duplicated > 0);
+    for req_id in 1..=10 {
+        assert!(s.expect(req_id, &TaskResult::QueryOk {
+            req_id,
+            id: task_id,
+            value: "running".into(),
+            access: None
+        }));
+    }
+
+    // same seed, same decisions
+    let decisions = |seed| {
+        let faults = FaultInjector::new(
+            Some(FaultConfig { seed, requests: FaultRates { drop: 0.3, delay: 0.3, duplicate: 0.3 }, ..Default::default() }),
+            std::sync::Arc::new(SystemClock::new()),
+        );
+        (0..20).map(|_| faults.decide(FaultChannel::Requests)).collect::<Vec<_>>()
+    };
+    assert_eq!(decisions(42), decisions(42));
+}
+
+#[test]
+fn test_dead_letters() {
+    // a task killed this way leaves its sender behind in the worker's task map
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        failures: Some(FailureSchedule::new().at(Duration::from_secs(1), FailureAction::KillTask(0))),
+        ..Default::default()
+    });
+    let finished = s.subscribe("task_finished");
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    clock.advance(Duration::from_secs(1));
+    assert!(matches!(
+        finished.recv_timeout(Duration::from_secs(1)),
+        Ok(ServerEvent::TaskFinished { id, exit: TaskExit::Killed, .. }) if id == task_id
+    ));
+
+    // the task's receiver goes with its thread a moment after the event, a query ahead of that would be lost with it
+    thread::sleep(Duration::from_millis(100));
+    // the task has exited, what is sent to it goes nowhere and is kept in the dead-letter queue
+    s.query_task(task_id, "status");    // req_id: 1
+    s.update_task(task_id, "bump");     // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::Undeliverable { req_id: 1, id: task_id }));
+    assert!(s.expect(2, &TaskResult::Undeliverable { req_id: 2, id: task_id }));
+    assert_eq!(s.dead_letters(), vec![
+        DeadLetter { req_id: 1, id: task_id, kind: "query" },
+        DeadLetter { req_id: 2, id: task_id, kind: "update" },
+    ]);
+}
+
+#[test]
+fn test_chaos_kills_tasks_and_worker() {
+    // a task kill on every tick
+    let mut s = ServerThread::with_config(ServerConfig {
+        chaos: Some(ChaosConfig {
+            seed: 1,
+            interval: Duration::from_millis(20),
+            task_kill_rate: 1.0,
+            worker_kill_rate: 0.0,
+        }),
+        ..Default::default()
+    });
+    let chaos = s.subscribe("chaos");
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(
+        chaos.recv_timeout(Duration::from_secs(1)),
+        Ok(ServerEvent::ChaosKill { target: ChaosTarget::Task(task_id) })
+    );
+
+    // the killed task is still in the worker's task map, so the query is dead-lettered
+    s.query_task(task_id, "status");    // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::Undeliverable { req_id: 1, id: task_id }));
+    assert_eq!(s.dead_letters(), vec![DeadLetter { req_id: 1, id: task_id, kind: "query" }]);
+    s.shutdown_with(ShutdownMode::Immediate);
+
+    // a worker kill on the first tick
+    let s = ServerThread::with_config(ServerConfig {
+        chaos: Some(ChaosConfig {
+            seed: 1,
+            interval: Duration::from_millis(20),
+            task_kill_rate: 0.0,
+            worker_kill_rate: 1.0,
+        }),
+        ..Default::default()
+    });
+    let chaos = s.subscribe("chaos");
+    assert_eq!(
+        chaos.recv_timeout(Duration::from_secs(1)),
+        Ok(ServerEvent::ChaosKill { target: ChaosTarget::Worker })
+    );
+    assert!(!s.health().worker_alive);
+}
+
+#[test]
+fn test_expect_eventually() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+
+    // no sleep needed, it waits for the result to arrive
+    assert_eq!(s.expect_eventually(1, &TaskResult::QueryOk {
+        req_id: 1,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }, Duration::from_secs(1)), Ok(()));
+
+    // a wrong result comes back with a diff pointing at the field that differs
+    let err = s.expect_eventually(1, &TaskResult::QueryOk {
+        req_id: 1,
+        id: task_id,
+        value: "stopped".into(),
+        access: None
+    }, Duration::from_secs(1)).unwrap_err();
+    assert!(matches!(err, ExpectError::Mismatch { req_id: 1, .. }));
+    let diff = err.to_string();
+    assert!(diff.contains("-     value: \"stopped\","), "{diff}");
+    assert!(diff.contains("+     value: \"running\","), "{diff}");
+    assert!(diff.contains("      req_id: 1,"), "{diff}");
+
+    // a request that was never sent is reported missing once the timeout expires
+    let req_id = s.next_req_id();
+    let started = std::time::Instant::now();
+    let err = s.expect_eventually(req_id, &TaskResult::Created { req_id, id: task_id }, Duration::from_millis(100));
+    assert!(matches!(err, Err(ExpectError::Missing { .. })));
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn test_record_and_replay() {
+    let config = || ServerConfig {
+        rate_limit: Some(RateLimit { capacity: 3, refill_per_sec: 0.0 }),
+        ..Default::default()
+    };
+    let batch = RequestOptions { client: 1, qos: QosClass::Batch, ..Default::default() };
+
+    let mut s = ServerThread::with_config(config());
+    s.start_recording();
+    let task_id = s.create_task(
+        [("status".into(), "tab\there".into())].into(),
+        [("mark_done".into(), update_fn(|_| Ok("done".to_string())))].into()
+    );                                          // req_id: 0
+    thread::sleep(Duration::from_millis(50));
+    s.query_task_with(batch.clone(), task_id, "status");    // req_id: 1
+    s.update_task(task_id, "mark_done");        // req_id: 2
+    s.query_task(task_id, "status");            // req_id: 3, rate limited
+    let recording = s.take_recording().unwrap();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert_eq!(recording.entries.len(), 4);
+    assert_eq!(recording.entries[1].opts, batch);
+    assert!(recording.entries[1].at >= Duration::from_millis(50));
+
+    // survives the trip through a file
+    let path = std::env::temp_dir().join(format!("sws_recording_{}.tsv", std::process::id()));
+    recording.save(&path).unwrap();
+    let loaded = Recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, recording);
+    assert!(matches!(Recording::parse("0\t0\t0\tinteractive\tcreate\tx"), Err(ReplayError::Parse { line: 1, .. })));
+    // a pair count no line could hold
+    let huge = format!("0\t0\t0\tinteractive\tcreate\t0\t{}\tk\tv", usize::MAX);
+    assert!(matches!(Recording::parse(&huge), Err(ReplayError::Parse { line: 1, msg }) if msg.contains("query pairs")));
+
+    // replayed against a fresh server with stand-in update functions, it produces the same results
+    let mut replayed = ServerThread::with_config(config());
+    let started = std::time::Instant::now();
+    Replayer::new(loaded)
+        .with_update_fn(Box::new(|_| update_fn(|_| Ok("done".to_string()))))
+        .run(&mut replayed);
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert_eq!(replayed.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(replayed.results.snapshot(), s.results.snapshot());
+    assert_eq!(replayed.class_of(1), Some(QosClass::Batch));
+}
+
+#[test]
+fn test_acknowledged_requests_are_tracked() {
+    let mut s = ServerThread::new();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let release_rx = std::sync::Mutex::new(release_rx);
+    let task_id = s.create_task(
+        HashMap::new(),
+        [("wait".into(), update_fn(move |_| {
+            let _ = release_rx.lock().unwrap().recv();
+            Ok("released".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "wait");     // req_id: 1
+
+    // the task acknowledges the update right away but can't answer it until it is released
+    let started = std::time::Instant::now();
+    while s.unanswered_requests().is_empty() && started.elapsed() < Duration::from_secs(1) {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(s.unanswered_requests(), vec![1]);
+    let pending = s.lifecycle.get(1).unwrap();
+    assert_eq!(pending.id, task_id);
+    assert!(pending.acknowledged_at.is_some() && pending.completed_at.is_none());
+    // creates are answered by the worker without an acknowledgement
+    assert!(s.lifecycle.get(0).is_some_and(|created| created.acknowledged_at.is_none()));
+
+    release_tx.send(()).unwrap();
+    assert_eq!(s.expect_eventually(1, &TaskResult::UpdateOk {
+        req_id: 1,
+        id: task_id,
+        value: "released".into()
+    }, Duration::from_secs(1)), Ok(()));
+    assert!(s.unanswered_requests().is_empty());
+    assert!(s.lifecycle.get(1).unwrap().completed_at.is_some());
+}
+
+#[test]
+fn test_request_state_machine() {
+    let mut s = ServerThread::new();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let release_rx = std::sync::Mutex::new(release_rx);
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("wait".into(), update_fn(move |_| {
+            let _ = release_rx.lock().unwrap().recv();
+            Ok("released".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "wait");     // req_id: 1
+    s.query_task(task_id, "status");    // req_id: 2, queued behind the update
+
+    let started = std::time::Instant::now();
+    while s.request_state(1) != Some(RequestState::Acknowledged) && started.elapsed() < Duration::from_secs(1) {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(s.request_state(0), Some(RequestState::Completed));
+    assert_eq!(s.request_state(1), Some(RequestState::Acknowledged));
+    assert_eq!(s.request_state(2), Some(RequestState::Submitted));
+    assert_eq!(s.request_state(3), None);
+
+    let stuck = s.stuck_requests(Duration::ZERO);
+    assert_eq!(stuck.len(), 1);
+    assert_eq!((stuck[0].req_id, stuck[0].id), (1, task_id));
+    assert!(s.stuck_requests(Duration::from_secs(60)).is_empty());
+
+    release_tx.send(()).unwrap();
+    assert_eq!(s.expect_eventually(2, &TaskResult::QueryOk {
+        req_id: 2,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }, Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.request_state(1), Some(RequestState::Completed));
+    assert_eq!(s.request_state(2), Some(RequestState::Completed));
+    assert!(s.stuck_requests(Duration::ZERO).is_empty());
+}
+
+#[test]
+fn test_tracing_spans() {
+    let mut s = ServerThread::with_config(ServerConfig { tracing: true, ..Default::default() });
+    let spans = s.subscribe("trace");
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+    assert_eq!(s.expect_eventually(1, &TaskResult::QueryOk {
+        req_id: 1,
+        id: task_id,
+        value: "running".into(),
+        access: None
+    }, Duration::from_secs(1)), Ok(()));
+
+    // the spans of a request are published before its result is recorded
+    let published: Vec<Span> = spans.try_iter().filter_map(|event| match event {
+        ServerEvent::Span(span) if span.req_id == 1 => Some(span),
+        _ => None,
+    }).collect();
+    let legs: Vec<(Hop, Hop)> = published.iter().map(|span| (span.from, span.to)).collect();
+    assert_eq!(legs, vec![
+        (Hop::Sent, Hop::Dequeued),
+        (Hop::Dequeued, Hop::Dispatched),
+        (Hop::Dispatched, Hop::Received),
+        (Hop::Received, Hop::Recorded),
+    ]);
+    assert!(published.windows(2).all(|pair| pair[0].end == pair[1].start));
+    assert_eq!(s.tracer.spans(1), published);
+    // a create never reaches a task
+    assert_eq!(s.tracer.spans(0).iter().map(|span| span.to).collect::<Vec<_>>(), vec![Hop::Dequeued, Hop::Recorded]);
+
+    let otlp = s.tracer.export_otlp_json();
+    assert!(otlp.starts_with("{\"resourceSpans\":["), "{otlp}");
+    assert!(otlp.contains("\"traceId\":\"00000000000000000000000000000002\""), "{otlp}");
+    assert!(otlp.contains("\"name\":\"dispatched -> received\""), "{otlp}");
+    assert_eq!(otlp.matches("\"spanId\"").count(), (1 + 2) + (1 + 4));
+
+    // off by default
+    let mut quiet = ServerThread::new();
+    quiet.create_task(HashMap::new(), HashMap::new());
+    assert_eq!(quiet.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(quiet.tracer.spans(0).is_empty());
+}
+
+#[test]
+fn test_completed_lifecycles_are_bounded() {
+    let clock = SimClock::new();
+    let lifecycle = LifecycleTable::with_capacity(clock.clone(), 2);
+    for req_id in 0..4 {
+        lifecycle.submit(req_id, 7, QosClass::Interactive);
+    }
+    for req_id in [2, 0, 1] {
+        clock.advance(Duration::from_millis(10));
+        lifecycle.complete(req_id);
+    }
+
+    // the oldest completion made room, the request still on its way is kept however old
+    assert_eq!(lifecycle.get(2), None);
+    assert_eq!(lifecycle.state(0), Some(RequestState::Completed));
+    assert_eq!(lifecycle.state(1), Some(RequestState::Completed));
+    assert_eq!(lifecycle.state(3), Some(RequestState::Submitted));
+
+    // a late duplicate doesn't bring back a dropped request, nor make up one never submitted
+    lifecycle.acknowledge(2);
+    lifecycle.acknowledge(9);
+    lifecycle.complete(9);
+    assert_eq!((lifecycle.get(2), lifecycle.get(9)), (None, None));
+    assert!(lifecycle.unanswered().is_empty());
+}
+
+#[test]
+fn test_recorded_traces_are_bounded() {
+    let tracer = Tracer::with_capacity(true, SimClock::new(), 2);
+    for req_id in 0..4 {
+        tracer.mark(req_id, 7, Hop::Sent);
+    }
+    for req_id in [2, 0, 1] {
+        tracer.mark(req_id, 7, Hop::Recorded);
+    }
+
+    // the oldest recorded trace made room, the request still on its way is kept however old
+    assert!(tracer.spans(2).is_empty());
+    assert_eq!(tracer.spans(0).len(), 1);
+    assert_eq!(tracer.spans(1).len(), 1);
+    tracer.mark(3, 7, Hop::Dequeued);
+    assert_eq!(tracer.spans(3).len(), 1);
+    let otlp = tracer.export_otlp_json();
+    assert!(otlp.contains("\"traceId\":\"00000000000000000000000000000001\""), "{otlp}");
+    assert!(!otlp.contains("\"traceId\":\"00000000000000000000000000000003\""), "{otlp}");
+}
+
+#[test]
+fn test_result_timing_metadata() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task(
+        HashMap::new(),
+        [("slow".into(), update_fn(|_| {
+            thread::sleep(Duration::from_millis(50));
+            Ok("done".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "slow");     // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    let update = s.result_envelope(1).unwrap();
+    assert_eq!(update.result, TaskResult::UpdateOk { req_id: 1, id: task_id, value: "done".into() });
+    let meta = update.meta.unwrap();
+    assert!(meta.execution.unwrap() >= Duration::from_millis(50));
+    assert!(meta.queueing().unwrap() <= meta.total());
+    assert!(meta.total() >= meta.execution.unwrap());
+
+    // the worker answers creates itself, no task spends time on them
+    let create = s.result_envelope(0).unwrap().meta.unwrap();
+    assert!(create.dequeued_at.is_some() && create.execution.is_none());
+    assert_eq!(s.result_envelope(2), None);
+}
+
+#[test]
+fn test_queue_depth_and_high_water_mark() {
+    // nothing takes messages off this channel until we do, so its depth is exact
+    let (tx, rx) = backpressure::channel::<usize>();
+    let crossings = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    tx.set_high_water(2, Box::new({
+        let crossings = std::sync::Arc::clone(&crossings);
+        move |depth| crossings.lock().unwrap().push(depth)
+    }));
+    for i in 0..3 {
+        tx.send(i).unwrap();
+    }
+    assert_eq!(tx.depth(), 3);
+    assert_eq!(rx.try_recv(), Ok(0));
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(tx.depth(), 1);
+    // climbing back to the mark fires again, staying above it doesn't
+    tx.send(3).unwrap();
+    tx.send(4).unwrap();
+    assert_eq!(*crossings.lock().unwrap(), vec![2, 2]);
+    drop(rx);
+    assert_eq!(tx.depth(), 0);
+    assert!(tx.send(5).is_err());
+    assert_eq!(tx.depth(), 0);
+
+    // every request the server sends passes through depth 1 at least once
+    let mut s = ServerThread::new();
+    let hits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    s.set_high_water_mark(1, {
+        let hits = std::sync::Arc::clone(&hits);
+        move |_| { hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed); }
+    });
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new());
+    for _ in 0..10 {
+        s.query_task(task_id, "status");
+    }
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.queue_depth(), 0);
+    assert!(hits.load(std::sync::atomic::Ordering::Relaxed) >= 1);
+}
+
+#[test]
+fn test_set_max_concurrent_tasks() {
+    let mut s = ServerThread::new();
+    assert_eq!(s.max_concurrent_tasks(), MAX_CONCURRENT_TASKS);
+
+    s.set_max_concurrent_tasks(2);
+    s.create_task(HashMap::new(), HashMap::new());                // req_id: 0
+    s.create_task(HashMap::new(), HashMap::new());                // req_id: 1
+    let throttled = s.create_task(HashMap::new(), HashMap::new()); // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::Throttled { req_id: 2, id: throttled, group: None }));
+
+    // scaling up lets the next create through without waiting for a task to exit
+    s.set_max_concurrent_tasks(3);
+    let admitted = s.create_task(HashMap::new(), HashMap::new()); // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(3, &TaskResult::Created { req_id: 3, id: admitted }));
+
+    // scaling down leaves running tasks alone
+    s.set_max_concurrent_tasks(1);
+    let throttled = s.create_task(HashMap::new(), HashMap::new()); // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(4, &TaskResult::Throttled { req_id: 4, id: throttled, group: None }));
+    assert_eq!(s.health().active_tasks, 3);
+}
+
+#[test]
+fn test_autoscaling_follows_throttle_rate() {
+    let config = ServerConfig {
+        autoscale: Some(AutoscaleConfig {
+            min_tasks: 2,
+            max_tasks: 6,
+            interval: Duration::from_millis(20),
+            window: Duration::from_millis(200),
+            ..AutoscaleConfig::default()
+        }),
+        ..ServerConfig::default()
+    };
+    assert_eq!(config.validate().ok(), Some(()));
+    for bounds in [AutoscaleConfig { min_tasks: 7, ..config.autoscale.unwrap() }, AutoscaleConfig { step: 0, ..config.autoscale.unwrap() }] {
+        let invalid = ServerConfig { autoscale: Some(bounds), ..ServerConfig::default() };
+        assert!(matches!(invalid.validate(), Err(ConfigError::Invalid(_))));
+    }
+    let mut s = ServerThread::with_config(config);
+    let scaled = s.subscribe("autoscale");
+
+    // with nothing running the cap drifts down to the minimum
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(s.max_concurrent_tasks(), 2);
+
+    // a burst past the cap gets throttled, which makes the controller raise it
+    for _ in 0..6 {
+        s.create_task(HashMap::new(), HashMap::new());
+    }
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    let event = scaled.iter().find(|event| matches!(event, ServerEvent::Scaled { to, from, .. } if to > from)).unwrap();
+    let ServerEvent::Scaled { throttle_rate, .. } = event else { unreachable!() };
+    assert!(throttle_rate > 0.0);
+    let cap = s.max_concurrent_tasks();
+    assert!(cap > 2 && cap <= 6, "{cap}");
+}
+
+#[test]
+fn test_query_many() {
+    let mut s = ServerThread::new();
+    s.start_recording();
+    let task_id = s.create_task(
+        [("cpu".into(), "12%".into()), ("mem".into(), "3GB".into())].into(),
+        HashMap::new()
+    );                                                  // req_id: 0
+    s.query_many(task_id, &["cpu", "disk", "mem"]);     // req_id: 1
+    s.query_many(task_id + 1, &["cpu"]);                // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(1, &TaskResult::QueryManyOk {
+        req_id: 1,
+        id: task_id,
+        values: [("cpu".into(), "12%".into()), ("mem".into(), "3GB".into())].into(),
+        missing: vec!["disk".into()],
+    }));
+    assert!(s.expect(2, &TaskResult::NotFound { req_id: 2, id: task_id + 1, ctx: "Task not found for query" }));
+
+    // multi-key queries survive a recording round-trip
+    let recording = s.take_recording().unwrap();
+    assert_eq!(Recording::parse(&recording.to_string()).unwrap(), recording);
+    assert_eq!(recording.entries[1].request, RecordedRequest::QueryMany {
+        id: task_id,
+        keys: vec!["cpu".into(), "disk".into(), "mem".into()],
+    });
+}
+
+#[test]
+fn test_prefix_and_glob_queries() {
+    let mut s = ServerThread::new();
+    s.start_recording();
+    let task_id = s.create_task(
+        [
+            ("metrics/cpu".into(), "12%".into()),
+            ("metrics/mem".into(), "3GB".into()),
+            ("metrics/disk/sda".into(), "40%".into()),
+            ("status".into(), "running".into()),
+        ].into(),
+        HashMap::new()
+    );                                                                         // req_id: 0
+    s.query_matching(task_id, KeyPattern::Prefix("metrics/".into()));         // req_id: 1
+    s.query_matching(task_id, KeyPattern::Glob("metrics/?e?".into()));        // req_id: 2
+    s.query_matching(task_id, KeyPattern::Glob("*/s*".into()));               // req_id: 3
+    s.query_matching(task_id, KeyPattern::Prefix("nothing/".into()));         // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    let values = |req_id: usize| match s.results.get(req_id) {
+        Some(TaskResult::QueryManyOk { values, missing, .. }) => {
+            assert!(missing.is_empty());
+            let mut keys: Vec<String> = values.into_keys().collect();
+            keys.sort();
+            keys
+        }
+        other => panic!("unexpected {other:?}"),
+    };
+    assert_eq!(values(1), vec!["metrics/cpu", "metrics/disk/sda", "metrics/mem"]);
+    assert_eq!(values(2), vec!["metrics/mem"]);
+    assert_eq!(values(3), vec!["metrics/disk/sda"]);
+    assert!(values(4).is_empty());
+
+    let recording = s.take_recording().unwrap();
+    assert_eq!(Recording::parse(&recording.to_string()).unwrap(), recording);
+}
+
+#[test]
+fn test_pluggable_kv_store() {
+    // counts its reads and keeps at most two entries, dropping the oldest write
+    struct CountingStore {
+        entries: Vec<(String, String)>,
+        reads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+    impl KvStore for CountingStore {
+        fn get(&mut self, key: &str) -> Option<String> {
+            self.reads.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+        }
+        fn set(&mut self, key: String, value: String) {
+            self.remove(&key);
+            self.entries.push((key, value));
+            if self.entries.len() > 2 {
+                self.entries.remove(0);
+            }
+        }
+        fn remove(&mut self, key: &str) -> Option<String> {
+            let i = self.entries.iter().position(|(k, _)| k == key)?;
+            Some(self.entries.remove(i).1)
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+            Box::new(self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+        }
+    }
+
+    let reads = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let store = CountingStore { entries: vec![("status".into(), "running".into())], reads: std::sync::Arc::clone(&reads) };
+    let mut s = ServerThread::new();
+    let task_id = s.create_task_with_store(RequestOptions::default(), Box::new(store), HashMap::new(), None); // req_id: 0
+    s.query_task(task_id, "status");                                                                          // req_id: 1
+    s.subscribe_task(task_id, "a");                                                                           // req_id: 2
+    s.subscribe_task(task_id, "b");                                                                           // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.events.publish(ServerEvent::Published { topic: "a".into(), id: 99, payload: "first".into() });
+    s.events.publish(ServerEvent::Published { topic: "b".into(), id: 99, payload: "second".into() });
+    s.query_task(task_id, "event/b");                                                                         // req_id: 4
+    s.query_task(task_id, "status");                                                                          // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id: task_id, value: "running".into(), access: None }));
+    assert!(s.expect(4, &TaskResult::QueryOk { req_id: 4, id: task_id, value: "second".into(), access: None }));
+    // the two deliveries pushed "status" out of the store
+    assert!(matches!(s.results.get(5), Some(TaskResult::QueryError { .. })));
+    assert_eq!(reads.load(std::sync::atomic::Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_task_handler() {
+    // a counter that only counts once it has been opened, and remembers the last event it got
+    #[derive(Default)]
+    struct Counter {
+        open: bool,
+        count: usize,
+        last_event: Option<String>,
+    }
+    impl TaskHandler for Counter {
+        fn handle(&mut self, instr: Instruction) -> TaskResult {
+            match instr {
+                Instruction::Update { req_id, id, update_id } => match (update_id.as_str(), self.open) {
+                    ("open", _) => {
+                        self.open = true;
+                        TaskResult::UpdateOk { req_id, id, value: "open".into() }
+                    }
+                    ("incr", true) => {
+                        self.count += 1;
+                        TaskResult::UpdateOk { req_id, id, value: self.count.to_string() }
+                    }
+                    _ => TaskResult::UpdateError { req_id, id, code: ErrorCode::UpdateFailed, detail: Some(format!("can't {update_id} now")) },
+                },
+                Instruction::Query { req_id, id, query_id } if query_id == "last_event" => {
+                    TaskResult::QueryOk { req_id, id, value: self.last_event.clone().unwrap_or_default(), access: None }
+                }
+                Instruction::Query { req_id, id, .. } => TaskResult::QueryOk { req_id, id, value: self.count.to_string(), access: None },
+                other => TaskResult::QueryError { req_id: other.req_id(), id: 0, code: ErrorCode::KeyNotFound, detail: Some("unsupported".into()) },
+            }
+        }
+
+        fn deliver(&mut self, event: &ServerEvent) {
+            self.last_event = Some(event.topic().to_string());
+        }
+    }
+
+    let mut s = ServerThread::new();
+    let updates = s.subscribe("update/incr");
+    let task_id = s.create_handler_task(Counter::default()); // req_id: 0
+    s.update_task(task_id, "incr");                          // req_id: 1
+    s.update_task(task_id, "open");                          // req_id: 2
+    s.update_task(task_id, "incr");                          // req_id: 3
+    s.update_task(task_id, "incr");                          // req_id: 4
+    s.query_task(task_id, "count");                          // req_id: 5
+    s.subscribe_task(task_id, "ping");                       // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.events.publish(ServerEvent::Published { topic: "ping".into(), id: 99, payload: String::new() });
+    s.query_task(task_id, "last_event");                     // req_id: 7
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(0, &TaskResult::Created { req_id: 0, id: task_id }));
+    assert!(s.expect(1, &TaskResult::UpdateError { req_id: 1, id: task_id, code: ErrorCode::UpdateFailed, detail: Some("can't incr now".into()) }));
+    assert!(s.expect(4, &TaskResult::UpdateOk { req_id: 4, id: task_id, value: "2".into() }));
+    assert!(s.expect(5, &TaskResult::QueryOk { req_id: 5, id: task_id, value: "2".into(), access: None }));
+    assert!(s.expect(6, &TaskResult::Subscribed { req_id: 6, id: task_id, topic: "ping".into() }));
+    assert!(s.expect(7, &TaskResult::QueryOk { req_id: 7, id: task_id, value: "ping".into(), access: None }));
+    assert_eq!(updates.try_iter().count(), 2);
+}
+
+#[test]
+fn test_update_functions_return_results() {
+    let mut s = ServerThread::new();
+    let updates = s.subscribe("update/withdraw");
+    let mut balance = 10;
+    let task_id = s.create_task(
+        HashMap::new(),
+        [("withdraw".into(), update_fn(move |_| {
+            if balance < 6 {
+                return Err(format!("insufficient funds: {balance}"));
+            }
+            balance -= 6;
+            Ok(balance.to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "withdraw"); // req_id: 1
+    s.update_task(task_id, "withdraw"); // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(1, &TaskResult::UpdateOk { req_id: 1, id: task_id, value: "4".into() }));
+    assert!(s.expect(2, &TaskResult::UpdateError { req_id: 2, id: task_id, code: ErrorCode::UpdateFailed, detail: Some("insufficient funds: 4".into()) }));
+    // only the successful update is published
+    assert_eq!(updates.try_iter().count(), 1);
+}
+
+#[test]
+fn test_compare_and_swap_updates() {
+    let mut s = ServerThread::new();
+    s.start_recording();
+    let task_id = s.create_task(
+        HashMap::new(),
+        [("bump".into(), update_fn(|_| Ok("bumped".to_string())))].into()
+    );                                                  // req_id: 0
+    s.update_task_if_version(task_id, "bump", 0);       // req_id: 1, 0 -> 1
+    s.update_task(task_id, "bump");                     // req_id: 2, 1 -> 2
+    s.update_task_if_version(task_id, "bump", 1);       // req_id: 3, stale
+    s.update_task_if_version(task_id, "bump", 2);       // req_id: 4, 2 -> 3
+    s.update_task_if_version(task_id, "missing", 3);    // req_id: 5, fails and leaves the version alone
+    s.update_task_if_version(task_id, "bump", 3);       // req_id: 6, 3 -> 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(1, &TaskResult::UpdateOk { req_id: 1, id: task_id, value: "bumped".into() }));
+    assert!(s.expect(3, &TaskResult::VersionConflict { req_id: 3, id: task_id, expected: 1, actual: 2 }));
+    assert!(s.expect(4, &TaskResult::UpdateOk { req_id: 4, id: task_id, value: "bumped".into() }));
+    assert!(matches!(s.results.get(5), Some(TaskResult::UpdateError { .. })));
+    assert!(s.expect(6, &TaskResult::UpdateOk { req_id: 6, id: task_id, value: "bumped".into() }));
+
+    let recording = s.take_recording().unwrap();
+    assert_eq!(Recording::parse(&recording.to_string()).unwrap(), recording);
+}
+
+#[test]
+fn test_bounded_task_queues() {
+    let config = ServerConfig { task_queue_capacity: Some(2), ..ServerConfig::default() };
+    let mut s = ServerThread::with_config(config);
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let release_rx = std::sync::Mutex::new(release_rx);
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("wait".into(), update_fn(move |_| {
+            let _ = release_rx.lock().unwrap().recv();
+            Ok("released".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "wait");     // req_id: 1, blocks the task
+    thread::sleep(Duration::from_millis(100));
+    s.query_task(task_id, "status");    // req_id: 2, queued
+    s.query_task(task_id, "status");    // req_id: 3, queued
+    s.query_task(task_id, "status");    // req_id: 4, the queue is full
+    assert_eq!(s.expect_eventually(4, &TaskResult::Busy { req_id: 4, id: task_id }, Duration::from_secs(1)), Ok(()));
+
+    release_tx.send(()).unwrap();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    for req_id in [2, 3] {
+        assert!(s.expect(req_id, &TaskResult::QueryOk { req_id, id: task_id, value: "running".into(), access: None }));
+    }
+}
+
+#[test]
+fn test_max_in_flight_per_task() {
+    let config = ServerConfig { max_in_flight_per_task: Some(2), ..ServerConfig::default() };
+    let mut s = ServerThread::with_config(config);
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let release_rx = std::sync::Mutex::new(release_rx);
+    let busy_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("wait".into(), update_fn(move |_| {
+            let _ = release_rx.lock().unwrap().recv();
+            Ok("released".to_string())
+        }))].into()
+    );                                      // req_id: 0
+    let other_id = s.create_task([("status".into(), "idle".into())].into(), HashMap::new()); // req_id: 1
+    s.update_task(busy_id, "wait");         // req_id: 2, running
+    s.query_task(busy_id, "status");        // req_id: 3, queued behind it
+    s.query_task(busy_id, "status");        // req_id: 4, over the limit
+    s.query_task(other_id, "status");       // req_id: 5, the limit is per task
+    assert_eq!(s.expect_eventually(4, &TaskResult::Busy { req_id: 4, id: busy_id }, Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.expect_eventually(5, &TaskResult::QueryOk { req_id: 5, id: other_id, value: "idle".into(), access: None }, Duration::from_secs(1)), Ok(()));
+
+    release_tx.send(()).unwrap();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task(busy_id, "status");        // req_id: 6, answered ones no longer count
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(3, &TaskResult::QueryOk { req_id: 3, id: busy_id, value: "running".into(), access: None }));
+    assert!(s.expect(6, &TaskResult::QueryOk { req_id: 6, id: busy_id, value: "running".into(), access: None }));
+}
+
+#[test]
+fn test_respawn_expired_task_on_access() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        respawn_expired: true,
+        ..Default::default()
+    });
+    let started = std::time::Instant::now();
+
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("bump".into(), update_fn(|_| Ok("bumped".to_string())))].into()
+    );                                              // req_id: 0
+    s.update_task(task_id, "bump");                 // req_id: 1, version 0 -> 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    // let the task get back to waiting, its inactivity timeout starts from there
+    thread::sleep(Duration::from_millis(100));
+
+    clock.advance(Duration::from_secs(TASK_TIMEOUT + 1));
+    while s.health().active_tasks > 0 {
+        assert!(started.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(5));
+    }
+    s.query_task(task_id, "status");                // req_id: 2, respawns the task
+    s.update_task_if_version(task_id, "bump", 1);   // req_id: 3, the version survived
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id: task_id, value: "running".into(), access: None }));
+    assert!(s.result_envelope(2).unwrap().meta.unwrap().respawned);
+    assert!(s.expect(3, &TaskResult::UpdateOk { req_id: 3, id: task_id, value: "bumped".into() }));
+    assert!(!s.result_envelope(3).unwrap().meta.unwrap().respawned);
+    assert_eq!(s.health().active_tasks, 1);
+
+    // a respawn is admitted like a create, at the cap it is throttled and the task stays expired
+    thread::sleep(Duration::from_millis(100));
+    clock.advance(Duration::from_secs(TASK_TIMEOUT + 1));
+    while s.health().active_tasks > 0 {
+        assert!(started.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(5));
+    }
+    s.set_max_concurrent_tasks(1);
+    s.create_task(HashMap::new(), HashMap::new()); // req_id: 4
+    s.query_task(task_id, "status");                // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(5, &TaskResult::Throttled { req_id: 5, id: task_id, group: None }));
+    assert_eq!(s.health().active_tasks, 1);
+
+    s.set_max_concurrent_tasks(2);
+    s.query_task(task_id, "status");                // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(6, &TaskResult::QueryOk { req_id: 6, id: task_id, value: "running".into(), access: None }));
+    assert!(s.result_envelope(6).unwrap().meta.unwrap().respawned);
+    assert_eq!(s.health().active_tasks, 2);
+}
+
+#[test]
+fn test_error_codes() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [
+            ("panic".into(), update_fn(|_| -> UpdateOutcome { panic!("update blew up") })),
+            ("fail".into(), update_fn(|_| Err("nope".to_string()))),
+        ].into()
+    );                                      // req_id: 0
+    s.update_task(task_id, "panic");        // req_id: 1
+    s.update_task(task_id, "panic");        // req_id: 2, lost with its thread
+    s.update_task(task_id, "fail");         // req_id: 3
+    s.query_task(task_id, "missing");       // req_id: 4
+    s.query_task(task_id + 1, "status");    // req_id: 5
+    s.query_task(task_id, "status");        // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    let codes: Vec<_> = (1..=6)
+        .map(|req_id| s.results.get(req_id).unwrap().error_code())
+        .collect();
+    assert_eq!(codes, [
+        Some(ErrorCode::Panicked),
+        Some(ErrorCode::UpdateNotFound),
+        Some(ErrorCode::UpdateFailed),
+        Some(ErrorCode::KeyNotFound),
+        Some(ErrorCode::TaskGone),
+        None,
+    ]);
+    assert!(s.expect(3, &TaskResult::UpdateError {
+        req_id: 3,
+        id: task_id,
+        code: ErrorCode::UpdateFailed,
+        detail: Some("nope".into())
+    }));
+}
+
+#[test]
+fn test_task_history() {
+    let mut s = ServerThread::with_config(ServerConfig { task_history: Some(3), ..ServerConfig::default() });
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    let other_id = s.create_task([("status".into(), "idle".into())].into(), HashMap::new()); // req_id: 1
+    s.query_task(task_id, "status");    // req_id: 2
+    s.query_task(other_id, "status");   // req_id: 3
+    s.query_task(task_id, "missing");   // req_id: 4
+    s.query_task(task_id, "status");    // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // the create fell out, only the last three are kept
+    let history: Vec<_> = s.task_history(task_id).iter().filter_map(TaskResult::req_id).collect();
+    assert_eq!(history, [2, 4, 5]);
+    assert_eq!(s.task_history(other_id).len(), 2);
+    assert!(ServerThread::new().task_history(task_id).is_empty());
+}
+
+#[test]
+fn test_filtered_result_subscriptions() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("bump".into(), update_fn(|_| Ok("bumped".to_string())))].into()
+    );                                                  // req_id: 0
+    let other_id = s.create_task(
+        HashMap::new(),
+        [("bump".into(), update_fn(|_| Ok("other".to_string())))].into()
+    );                                                  // req_id: 1
+    let updates = s.subscribe_results(ResultFilter { kinds: vec!["update_ok"], ..ResultFilter::task(task_id) });
+    let errors = s.subscribe_results(ResultFilter::errors());
+    s.update_task(task_id, "bump");                     // req_id: 2
+    s.update_task(other_id, "bump");                    // req_id: 3, other task
+    s.query_task(task_id, "status");                    // req_id: 4, not an update
+    s.query_task(task_id, "missing");                   // req_id: 5
+    s.update_task(task_id, "missing");                  // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    let updates: Vec<_> = updates.try_iter().collect();
+    assert_eq!(updates, [TaskResult::UpdateOk { req_id: 2, id: task_id, value: "bumped".into() }]);
+    let errors: Vec<_> = errors.try_iter().filter_map(|result| result.error_code()).collect();
+    assert_eq!(errors, [ErrorCode::KeyNotFound, ErrorCode::UpdateNotFound]);
+}
+
+#[test]
+fn test_sharded_listener() {
+    let mut s = ServerThread::with_config(ServerConfig { listener_shards: 3, ..ServerConfig::default() });
+    assert_eq!(s.results.shard_count(), 3);
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    for _ in 1..=10 {
+        s.query_task(task_id, "status");    // req_id: 1..=10, spread over every shard
+    }
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    for req_id in 1..=10 {
+        assert!(s.expect(req_id, &TaskResult::QueryOk { req_id, id: task_id, value: "running".into(), access: None }));
+    }
+    assert_eq!(s.results.snapshot().len(), 11);
+
+    s.shutdown_with(ShutdownMode::Drain);
+    assert!(!s.health().listener_alive);
+    assert!(s.shutdown_flag.load(std::sync::atomic::Ordering::Relaxed));
+}
+
+#[test]
+fn test_result_sink_chain() {
+    #[derive(Debug, Default)]
+    struct Counter(std::sync::atomic::AtomicUsize);
+    impl ResultSink for Counter {
+        fn accept(&self, _req_id: usize, _result: &TaskResult) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    let counter = std::sync::Arc::new(Counter::default());
+    let (forward_tx, forward_rx) = std::sync::mpsc::channel();
+    let config = ServerConfig {
+        result_sinks: SinkChain::new().then_shared(counter.clone()).then(forward_tx),
+        ..ServerConfig::default()
+    };
+    let mut s = ServerThread::with_config(config);
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // the configured sinks run before the store, so they have every result wait_idle saw
+    assert_eq!(counter.0.load(std::sync::atomic::Ordering::Relaxed), 2);
+    let forwarded: Vec<_> = forward_rx.try_iter().collect();
+    assert_eq!(forwarded, [
+        TaskResult::Created { req_id: 0, id: task_id },
+        TaskResult::QueryOk { req_id: 1, id: task_id, value: "running".into(), access: None },
+    ]);
+}
+
+#[test]
+fn test_jsonl_result_log() {
+    let path = std::env::temp_dir().join(format!("sws_results_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let clock = SimClock::new();
+    let log = std::sync::Arc::new(JsonlSink::create(&path, clock.clone(), Duration::from_secs(60)).unwrap());
+    let config = ServerConfig {
+        clock: clock.clone(),
+        result_sinks: SinkChain::new().then_shared(log.clone()),
+        ..ServerConfig::default()
+    };
+    let mut s = ServerThread::with_config(config);
+    let task_id = s.create_task([("status".into(), "\"running\"".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+    s.query_task(task_id, "missing");   // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // nothing is due for a flush yet
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    log.flush().unwrap();
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<_> = log.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with(&format!("{{\"req_id\":0,\"id\":{task_id},\"kind\":\"created\",\"recorded_at_ms\":0,\"error_code\":null,")));
+    assert!(lines[1].contains(r#"value: \"\\\"running\\\"\""#));
+    assert!(lines[2].contains(r#""kind":"query_error""#) && lines[2].contains(r#""error_code":"KeyNotFound""#));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_export_results() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), ..ServerConfig::default() });
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(task_id, "status");    // req_id: 1
+    s.query_task(task_id, "missing");   // req_id: 2
+    s.shutdown_with(ShutdownMode::Drain);
+
+    let dir = std::env::temp_dir();
+    let csv = dir.join(format!("sws_export_{}.csv", std::process::id()));
+    let json = dir.join(format!("sws_export_{}.json", std::process::id()));
+    s.export_results(ExportFormat::Csv, &csv).unwrap();
+    s.export_results(ExportFormat::Json, &json).unwrap();
+
+    assert_eq!(std::fs::read_to_string(&csv).unwrap(), format!(
+        "req_id,id,kind,latency_ms,outcome\n0,{task_id},created,0,ok\n1,{task_id},query_ok,0,ok\n2,{task_id},query_error,0,KeyNotFound\n"
+    ));
+    let json_report = std::fs::read_to_string(&json).unwrap();
+    assert!(json_report.starts_with("[\n  {\"req_id\":0,"));
+    assert!(json_report.contains(r#"{"req_id":2,"id":0,"kind":"query_error","latency_ms":0,"outcome":"KeyNotFound"}"#));
+    let _ = std::fs::remove_file(csv);
+    let _ = std::fs::remove_file(json);
+}
+
+#[test]
+fn test_admin_commands() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    let other_id = s.create_task(HashMap::new(), HashMap::new()); // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.list_tasks(), [task_id, other_id]);
+
+    // requests pile up while intake is paused, admin commands still get through
+    s.admin(AdminCommand::PauseIntake);
+    for _ in 2..=6 {
+        s.query_task(task_id, "status");    // req_id: 2..=6
+    }
+    thread::sleep(Duration::from_millis(100));
+    let stats = s.worker_stats().unwrap();
+    assert!(stats.paused);
+    assert_eq!(stats.queue_depth, 5);
+    assert_eq!(stats.tasks, [task_id, other_id]);
+    assert!(s.kill_task(other_id));
+    assert!(!s.kill_task(other_id));
+    s.admin(AdminCommand::ResumeIntake);
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.list_tasks(), [task_id]);
+}
+
+#[test]
+fn test_kill_task_drops_queued_work() {
+    let mut s = ServerThread::new();
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let task_id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("block".into(), update_fn(move |_| {
+            let _ = started_tx.send(());
+            thread::sleep(Duration::from_millis(300));
+            Ok("done".to_string())
+        }))].into()
+    );                                  // req_id: 0
+    s.update_task(task_id, "block");    // req_id: 1
+    s.query_task(task_id, "status");    // req_id: 2, queued behind the update
+    s.update_task(task_id, "block");    // req_id: 3, queued behind the update
+    started_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    // the worker has handed both over by the time the update runs, so they sit in the task's own queue
+    thread::sleep(Duration::from_millis(50));
+    assert!(s.kill_task(task_id));
+
+    // the update that was running finishes, nothing queued behind it runs
+    assert_eq!(s.wait_idle(Duration::from_millis(800)), Err(vec![2, 3]));
+    assert!(s.expect(1, &TaskResult::UpdateOk { req_id: 1, id: task_id, value: "done".into() }));
+    assert!(s.expect_none(2) && s.expect_none(3));
+    assert_eq!(started_rx.try_recv(), Err(std::sync::mpsc::TryRecvError::Disconnected));
+    assert!(s.list_tasks().is_empty());
+}
+
+#[test]
+fn test_config_from_file_and_env() {
+    let path = std::env::temp_dir().join(format!("sws_config_{}.conf", std::process::id()));
+    std::fs::write(
+        &path,
+        "# short timeouts\ntask_timeout = 0.5\nlistener_timeout = 3 # overridden below\nupdate_timeout = 0.2\ntask_history = 4\ntracing = true\n",
+    )
+    .unwrap();
+    // the overrides are passed in, setting them on the process would race the other tests reading it
+    let vars = [("SWS_LISTENER_TIMEOUT".to_string(), "1".to_string())];
+    let config = ServerConfig::from_file_with_vars(&path, vars).unwrap();
+    assert_eq!(config.timeouts.task, Duration::from_millis(500));
+    assert_eq!(config.timeouts.listener, Duration::from_secs(1));
+    assert_eq!(config.timeouts.worker, Duration::from_secs(WORKER_TIMEOUT));
+    assert_eq!(config.task_history, Some(4));
+    assert!(config.tracing);
+
+    // assumption 2 is checked
+    let mut invalid = ServerConfig::default();
+    invalid.apply_lines("listener_timeout = 2").unwrap();
+    assert!(matches!(invalid.validate(), Err(ConfigError::Invalid(_))));
+    assert!(matches!(ServerConfig::default().apply_lines("nope = 1"), Err(ConfigError::UnknownKey { .. })));
+    assert!(matches!(ServerConfig::default().apply_lines("task_timeout = soon"), Err(ConfigError::BadValue { .. })));
+    assert!(matches!(ServerConfig::default().apply_lines("[timeouts]"), Err(ConfigError::Syntax { line: 1, .. })));
+
+    // the server runs on the loaded timeouts, the listener gives up after a second instead of five
+    let started = std::time::Instant::now();
+    let mut s = ServerThread::with_config(config);
+    s.create_task(HashMap::new(), HashMap::new());
+    while !s.shutdown_flag.load(std::sync::atomic::Ordering::Relaxed) {
+        assert!(started.elapsed() < Duration::from_secs(LISTENER_TIMEOUT), "listener ignored the configured timeout");
+        thread::sleep(Duration::from_millis(50));
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_load_generator() {
+    let mut s = ServerThread::new();
+    let profile = LoadProfile {
+        tasks: 3,
+        update_share: 0.5,
+        arrival: Arrival::Bursty { rate: 100.0, burst: 5 },
+        duration: Duration::from_millis(300),
+        seed: 7,
+        ..LoadProfile::default()
+    };
+    let report = LoadGenerator::new(profile).run(&mut s, Duration::from_secs(3));
+    println!("{report}");
+    // 5 at a time every 50ms for 300ms
+    assert_eq!(report.sent, 30);
+    assert_eq!(report.succeeded, 30);
+    assert_eq!((report.failed, report.missing), (0, 0));
+    assert!(report.throughput > 0.0);
+    assert!(report.p50 <= report.p99 && report.p99 <= report.max);
+
+    let sorted: Vec<_> = (1..=10).map(Duration::from_millis).collect();
+    assert_eq!(loadgen::percentile(&sorted, 50.0), Some(Duration::from_millis(5)));
+    assert_eq!(loadgen::percentile(&sorted, 99.0), Some(Duration::from_millis(10)));
+    assert_eq!(loadgen::percentile(&[], 50.0), None);
+}
+
+#[test]
+fn test_scheduled_failures() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        failures: Some(
+            FailureSchedule::new()
+                .at(Duration::from_secs(2), FailureAction::Disconnect(FaultChannel::Results))
+                .at(Duration::from_secs(1), FailureAction::KillTask(0)),
+        ),
+        ..Default::default()
+    });
+    let failures = s.subscribe("failure");
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(failures.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty));
+
+    // nothing happens before its time, then the kill comes first however the schedule was written
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        failures.recv_timeout(Duration::from_secs(1)),
+        Ok(ServerEvent::FailureInjected { at: Duration::from_secs(1), action: FailureAction::KillTask(task_id) })
+    );
+    // give the task a moment to take the kill, a query ahead of it would be lost with the task
+    thread::sleep(Duration::from_millis(100));
+    s.query_task(task_id, "status");    // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::Undeliverable { req_id: 1, id: task_id }));
+
+    // with the results channel gone nothing more is recorded
+    clock.advance(Duration::from_secs(1));
+    assert!(matches!(failures.recv_timeout(Duration::from_secs(1)), Ok(ServerEvent::FailureInjected { .. })));
+    s.query_task(task_id, "status");    // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_millis(200)), Err(vec![2]));
+    s.shutdown_with(ShutdownMode::Immediate);
+
+    // a poisoned task map takes the worker down with the next create
+    let mut s = ServerThread::with_config(ServerConfig {
+        failures: Some(FailureSchedule::new().at(Duration::ZERO, FailureAction::PoisonTaskMap)),
+        ..Default::default()
+    });
+    let failures = s.subscribe("failure");
+    assert!(failures.recv_timeout(Duration::from_secs(1)).is_ok());
+    s.create_task(HashMap::new(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_millis(200)), Err(vec![0]));
+    assert!(!s.health().worker_alive);
+}
+
+#[test]
+fn test_tenant_isolation() {
+    let mut s = ServerThread::with_config(ServerConfig { tenant_caps: [(1, 1)].into(), ..Default::default() });
+    s.start_recording();
+    let noisy = RequestOptions { tenant: 1, ..Default::default() };
+    let quiet = RequestOptions { tenant: 2, ..Default::default() };
+    let a = s.create_task_with(noisy.clone(), [("owner".into(), "noisy".into())].into(), HashMap::new()); // req_id: 0
+    let b = s.create_task_with(noisy.clone(), HashMap::new(), HashMap::new()); // req_id: 1
+    let c = s.create_task_with(quiet.clone(), [("owner".into(), "quiet".into())].into(), HashMap::new()); // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    // tenant 1 is at its cap after one task, tenant 2 is not held back by it
+    assert!(s.expect(1, &TaskResult::Throttled { req_id: 1, id: b, group: None }));
+    assert!(s.expect(2, &TaskResult::Created { req_id: 2, id: c }));
+
+    // a tenant only reaches its own tasks, the default tenant none of them
+    s.query_task_with(quiet.clone(), a, "owner");    // req_id: 3
+    s.query_task_with(noisy.clone(), a, "owner");    // req_id: 4
+    s.query_task(c, "owner");                        // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(matches!(s.results.get(3), Some(TaskResult::NotFound { .. })));
+    assert!(s.expect(4, &TaskResult::QueryOk { req_id: 4, id: a, value: "noisy".into(), access: None }));
+    assert!(matches!(s.results.get(5), Some(TaskResult::NotFound { .. })));
+
+    assert_eq!(s.tenants.tasks_of(1), vec![a]);
+    assert_eq!(s.tenants.owner(c), Some(2));
+    assert_eq!(s.tenant_stats(1), TenantStats { requests: 3, answered: 3, errors: 1, active_tasks: 1, served: 3 });
+    assert_eq!(s.tenant_stats(2), TenantStats { requests: 2, answered: 2, errors: 1, active_tasks: 1, served: 2 });
+    assert_eq!(s.results_for_tenant(0), vec![s.results.get(5).unwrap()]);
+
+    // recordings keep the tenant
+    let recording = s.take_recording().unwrap();
+    assert_eq!(Recording::parse(&recording.to_string()).unwrap(), recording);
+    assert_eq!(recording.entries[3].opts.tenant, 2);
+}
+
+#[test]
+fn test_weighted_fair_queueing() {
+    // twice the weight, twice the turns while both have work, whoever pushed first
+    let mut queue = FairQueue::new([(1, 2)].into());
+    for n in 0..6 {
+        queue.push(1, n);
+    }
+    for n in 6..9 {
+        queue.push(2, n);
+    }
+    let tenants: Vec<usize> = std::iter::from_fn(|| queue.pop()).map(|(tenant, _)| tenant).collect();
+    assert_eq!(tenants, vec![1, 1, 2, 1, 1, 2, 1, 1, 2]);
+
+    // a flood from one tenant doesn't hold back another one queued behind it
+    let mut s = ServerThread::with_config(ServerConfig { tenant_weights: [(1, 1), (2, 1)].into(), ..Default::default() });
+    let noisy = RequestOptions { tenant: 1, ..Default::default() };
+    let quiet = RequestOptions { tenant: 2, ..Default::default() };
+    let a = s.create_task_with(noisy.clone(), [("k".into(), "v".into())].into(), HashMap::new()); // req_id: 0
+    let b = s.create_task_with(quiet.clone(), [("k".into(), "v".into())].into(), HashMap::new()); // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.pause_worker();
+    for _ in 0..8 {
+        s.query_task_with(noisy.clone(), a, "k"); // req_id: 2..10
+    }
+    s.query_task_with(quiet.clone(), b, "k"); // req_id: 10
+    s.query_task_with(quiet.clone(), b, "k"); // req_id: 11
+    s.resume_worker();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    let mut order: Vec<usize> = (2..12).collect();
+    order.sort_by_key(|req_id| s.lifecycle.get(*req_id).unwrap().dequeued_at);
+    let quiet_turns: Vec<usize> = order.iter().enumerate().filter(|(_, req_id)| **req_id >= 10).map(|(turn, _)| turn).collect();
+    assert_eq!(quiet_turns, vec![1, 3]);
+    assert_eq!(s.tenant_stats(1).served, 9);
+    assert_eq!(s.tenant_stats(2).served, 3);
+}
+
+#[test]
+fn test_pause_and_resume_worker() {
+    let mut s = ServerThread::new();
+    let task_id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    s.pause_worker();
+    for _ in 0..3 {
+        s.query_task(task_id, "status");    // req_id: 1..4
+    }
+    // buffered in the worker's queues, none of them handled
+    assert_eq!(s.wait_idle(Duration::from_millis(200)), Err(vec![1, 2, 3]));
+    let stats = s.worker_stats().unwrap();
+    assert!(stats.paused);
+    assert_eq!(stats.queue_depth, 3);
+
+    s.resume_worker();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(!s.worker_stats().unwrap().paused);
+    for req_id in 1..4 {
+        assert!(s.expect(req_id, &TaskResult::QueryOk { req_id, id: task_id, value: "running".into(), access: None }));
+    }
+}
+
+#[test]
+fn test_checkpoint_and_resume() {
+    let path = std::env::temp_dir().join(format!("sws_checkpoint_{}.tsv", std::process::id()));
+    let bump = || -> UpdateFn { update_fn(|_| Ok("bumped".to_string())) };
+    let tenant = RequestOptions { tenant: 1, ..Default::default() };
+
+    let mut s = ServerThread::new();
+    let a = s.create_task([("status".into(), "running".into())].into(), [("bump".to_string(), bump())].into()); // req_id: 0
+    let b = s.create_task_with(tenant.clone(), [("owner".into(), "tenant\t1".into())].into(), HashMap::new()); // req_id: 1
+    s.update_task(a, "bump");    // req_id: 2
+    s.update_task(a, "bump");    // req_id: 3
+    s.subscribe_task(a, "news"); // req_id: 4
+    s.publish_task_with(tenant.clone(), b, "news", "hello"); // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.checkpoint(&path).unwrap(), CheckpointSummary { written: 2, skipped: vec![] });
+    s.shutdown_with(ShutdownMode::Drain);
+
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    assert_eq!(checkpoint.tasks[0], TaskSnapshot {
+        id: a,
+        tenant: 0,
+        version: 2,
+        values: vec![("event/news".into(), "hello".into()), ("status".into(), "running".into())],
+        update_ids: vec!["bump".into()],
+    });
+
+    // every update function has to be registered
+    let mut fresh = ServerThread::new();
+    assert!(matches!(fresh.resume(&path, &UpdateRegistry::new()), Err(CheckpointError::UnknownUpdate { .. })));
+    assert_eq!(fresh.task_id_counter, 0);
+
+    let registry = UpdateRegistry::new().register("bump", bump);
+    assert_eq!(fresh.resume(&path, &registry).unwrap(), vec![a, b]); // req_id: 0, 1
+    fresh.query_task(a, "event/news");                               // req_id: 2
+    fresh.update_task_if_version(a, "bump", 2);                      // req_id: 3
+    fresh.query_task_with(tenant, b, "owner");                       // req_id: 4
+    assert_eq!(fresh.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(fresh.expect(2, &TaskResult::QueryOk { req_id: 2, id: a, value: "hello".into(), access: None }));
+    assert!(fresh.expect(3, &TaskResult::UpdateOk { req_id: 3, id: a, value: "bumped".into() }));
+    assert!(fresh.expect(4, &TaskResult::QueryOk { req_id: 4, id: b, value: "tenant\t1".into(), access: None }));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_task_templates() {
+    let mut s = ServerThread::new();
+    // update functions are registered before a template can use them
+    assert_eq!(
+        s.register_template("counter", HashMap::new(), &["bump"]),
+        Err(TemplateError::UnknownUpdate { template: "counter".into(), update_id: "bump".into() })
+    );
+    s.register_update("bump", || -> UpdateFn { update_fn(|_| Ok("bumped".to_string())) });
+    s.register_template("counter", [("count".into(), "0".into())].into(), &["bump"]).unwrap();
+
+    let a = s.create_task_from_template("counter").unwrap(); // req_id: 0
+    let b = s.create_task_from_template("counter").unwrap(); // req_id: 1
+    assert_eq!(s.create_task_from_template("missing"), Err(TemplateError::UnknownTemplate("missing".into())));
+    s.query_task(b, "count");    // req_id: 2
+    s.update_task(a, "bump");    // req_id: 3
+    s.update_task(b, "bump");    // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id: b, value: "0".into(), access: None }));
+    // every task gets its own closure
+    assert!(s.expect(3, &TaskResult::UpdateOk { req_id: 3, id: a, value: "bumped".into() }));
+    assert!(s.expect(4, &TaskResult::UpdateOk { req_id: 4, id: b, value: "bumped".into() }));
+}
+
+#[test]
+fn test_task_builder() {
+    assert_eq!(TaskBuilder::new().query("", "v").build().err(), Some(TaskBuildError::EmptyQueryKey));
+    assert_eq!(
+        TaskBuilder::new().query("k", "a").query("k", "b").build().err(),
+        Some(TaskBuildError::DuplicateQueryKey("k".into()))
+    );
+    // a query key may double as an update id unless that is ruled out
+    let shared = || TaskBuilder::new().query("k", "v").update("k", |_| Ok("done".into()));
+    assert!(shared().build().is_ok());
+    assert_eq!(shared().disjoint_keys().build().err(), Some(TaskBuildError::SharedKey("k".into())));
+    let limits = TaskLimits { max_value_bytes: Some(3), ..Default::default() };
+    assert_eq!(
+        TaskBuilder::new().query("k", "long").limits(limits).build().err(),
+        Some(TaskBuildError::TooLarge(Oversize::ValueBytes { key: "k".into(), bytes: 4, max: 3 }))
+    );
+
+    let mut s = ServerThread::new();
+    let spec = TaskBuilder::new()
+        .query("status", "running")
+        .update("finish", |_| Ok("finished".into()))
+        .idle_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let id = s.create_task_from(spec); // req_id: 0
+    s.query_task(id, "status");         // req_id: 1
+    s.update_task(id, "finish");        // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    // gone well before the server's task timeout
+    thread::sleep(Duration::from_millis(500));
+    s.query_task(id, "status");         // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "running".into(), access: None }));
+    assert!(s.expect(2, &TaskResult::UpdateOk { req_id: 2, id, value: "finished".into() }));
+    assert!(matches!(s.results.get(3), Some(TaskResult::NotFound { req_id: 3, .. })));
+}
+
+#[test]
+fn test_task_size_limits() {
+    let config = ServerConfig {
+        task_limits: TaskLimits { max_query_keys: Some(2), max_update_fns: Some(1), max_value_bytes: Some(4) },
+        ..Default::default()
+    };
+    let mut s = ServerThread::with_config(config);
+    let keys: HashMap<String, String> = (0..3).map(|i| (format!("k{i}"), "v".to_string())).collect();
+    let too_many_keys = s.create_task(keys, HashMap::new()); // req_id: 0
+    let mut updates: HashMap<String, UpdateFn> = HashMap::new();
+    updates.insert("a".into(), update_fn(|_| Ok("a".into())));
+    updates.insert("b".into(), update_fn(|_| Ok("b".into())));
+    let too_many_updates = s.create_task(HashMap::new(), updates); // req_id: 1
+    let too_long = s.create_task([("k".into(), "12345".into())].into(), HashMap::new()); // req_id: 2
+    let fits = s.create_task([("k".into(), "1234".into())].into(), HashMap::new()); // req_id: 3
+    s.query_task(too_long, "k"); // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert!(s.expect(0, &TaskResult::RejectedTooLarge {
+        req_id: 0,
+        id: too_many_keys,
+        reason: Oversize::QueryKeys { count: 3, max: 2 },
+    }));
+    assert!(s.expect(1, &TaskResult::RejectedTooLarge {
+        req_id: 1,
+        id: too_many_updates,
+        reason: Oversize::UpdateFns { count: 2, max: 1 },
+    }));
+    assert!(s.expect(2, &TaskResult::RejectedTooLarge {
+        req_id: 2,
+        id: too_long,
+        reason: Oversize::ValueBytes { key: "k".into(), bytes: 5, max: 4 },
+    }));
+    assert!(s.expect(3, &TaskResult::Created { req_id: 3, id: fits }));
+    // a rejected task never exists
+    assert!(matches!(s.results.get(4), Some(TaskResult::NotFound { req_id: 4, .. })));
+    assert_eq!(s.health().active_tasks, 1);
+}
+
+#[test]
+fn test_query_access_metadata() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), access_metadata: true, ..Default::default() });
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("bump".into(), update_fn(|_| Ok("bumped".to_string())))].into(),
+    );                                        // req_id: 0
+    let first_query = clock.now();
+    s.query_task(id, "status");               // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    clock.advance(Duration::from_millis(100));
+    s.query_task(id, "status");               // req_id: 2
+    s.update_task(id, "bump");                // req_id: 3
+    s.subscribe_task(id, "ping");             // req_id: 4
+    s.publish_task(id, "ping", "hello");      // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task(id, "event/ping");           // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    let access = |req_id| match s.results.get(req_id) {
+        Some(TaskResult::QueryOk { access, .. }) => access.unwrap(),
+        other => panic!("req {req_id}: {other:?}"),
+    };
+    assert_eq!(access(1), KeyAccess { queries: 1, updated_at_version: 0, last_access: None });
+    assert_eq!(access(2), KeyAccess { queries: 2, updated_at_version: 0, last_access: Some(first_query) });
+    // written by the delivery after the update moved the task to version 1
+    assert_eq!(access(6), KeyAccess { queries: 1, updated_at_version: 1, last_access: None });
+}
+
+#[test]
+fn test_per_task_stats() {
+    let mut s = ServerThread::new();
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("fail".into(), update_fn(|_| Err("nope".to_string())))].into(),
+    );                              // req_id: 0
+    s.query_task(id, "status");     // req_id: 1
+    s.query_task(id, "missing");    // req_id: 2
+    s.update_task(id, "fail");      // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    let stats = s.task_stats(id).unwrap();
+    assert_eq!((stats.queries, stats.updates, stats.errors), (2, 1, 2));
+    assert_eq!(s.task_stats(id + 1), None);
+    assert_eq!(s.worker_stats().unwrap().finished_tasks, TaskStats::default());
+
+    // added to the worker's totals once the task exits
+    s.join_listener();
+    assert_eq!(s.finished_task_stats(), stats);
+}
+
+#[test]
+fn test_server_stats() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), ..Default::default() });
+    assert_eq!(s.stats().average_latency, None);
+    let ids: Vec<_> = (0..MAX_CONCURRENT_TASKS + 1)
+        .map(|_| s.create_task([("status".into(), "running".into())].into(), HashMap::new()))
+        .collect();                                // req_ids: 0..=4, the last one is throttled
+    s.query_task(ids[0], "status");                // req_id: 5
+    s.query_task(ids[0], "missing");               // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    clock.advance(Duration::from_millis(500));
+
+    let stats = s.stats();
+    assert_eq!(stats.requests, MAX_CONCURRENT_TASKS + 3);
+    assert_eq!(stats.results.get("created"), Some(&MAX_CONCURRENT_TASKS));
+    assert_eq!(stats.results.get("query_ok"), Some(&1));
+    assert_eq!(stats.results.get("query_error"), Some(&1));
+    assert_eq!(stats.throttled, 1);
+    assert_eq!(stats.active_tasks, MAX_CONCURRENT_TASKS);
+    assert!(stats.average_latency.is_some());
+    assert_eq!(stats.uptime, Duration::from_millis(500));
+}
+
+#[test]
+fn test_lifecycle_hooks() {
+    use std::sync::{Arc, Mutex};
+    let mut s = ServerThread::new();
+    let (created, finished, throttled) = (Arc::new(Mutex::new(vec![])), Arc::new(Mutex::new(vec![])), Arc::new(Mutex::new(vec![])));
+    s.on_task_created({
+        let created = Arc::clone(&created);
+        move |id, _| created.lock().unwrap().push(id)
+    });
+    s.on_task_finished({
+        let finished = Arc::clone(&finished);
+        move |id, exit, stats| finished.lock().unwrap().push((id, exit, stats.queries))
+    });
+    s.on_throttled({
+        let throttled = Arc::clone(&throttled);
+        move |req_id, id| throttled.lock().unwrap().push((req_id, id))
+    });
+
+    let ids: Vec<_> = (0..MAX_CONCURRENT_TASKS + 1)
+        .map(|_| s.create_task([("status".into(), "running".into())].into(), HashMap::new()))
+        .collect();                            // req_ids: 0..=4, the last one is throttled
+    s.query_task(ids[0], "status");            // req_id: 5
+    s.join_listener();
+    // hooks run on their own threads, give them a moment to catch up
+    thread::sleep(Duration::from_millis(100));
+
+    let mut created = created.lock().unwrap().clone();
+    created.sort();
+    assert_eq!(created, ids[..MAX_CONCURRENT_TASKS]);
+    assert_eq!(*throttled.lock().unwrap(), vec![(MAX_CONCURRENT_TASKS, ids[MAX_CONCURRENT_TASKS])]);
+    let finished = finished.lock().unwrap();
+    assert_eq!(finished.len(), MAX_CONCURRENT_TASKS);
+    assert!(finished.contains(&(ids[0], TaskExit::Expired, 1)));
+}
+
+#[test]
+fn test_request_interceptors() {
+    use std::sync::{Arc, Mutex};
+    let mut s = ServerThread::new();
+    let seen = Arc::new(Mutex::new(vec![]));
+    // logging
+    s.add_interceptor({
+        let seen = Arc::clone(&seen);
+        move |_: &mut RequestOptions, request: &mut TaskRequest| {
+            seen.lock().unwrap().extend(request.req_id());
+            Verdict::Continue
+        }
+    });
+    // auth: client 7 may not touch anything
+    s.add_interceptor(|opts: &mut RequestOptions, _: &mut TaskRequest| match opts.client {
+        7 => Verdict::Reject("client 7 is not allowed".into()),
+        _ => Verdict::Continue,
+    });
+    // a renamed key, old callers are pointed at the new name
+    s.add_interceptor(|_: &mut RequestOptions, request: &mut TaskRequest| {
+        if let TaskRequest::QueryTask { query_id, .. } = request {
+            if query_id == "state" {
+                *query_id = "status".into();
+            }
+        }
+        Verdict::Continue
+    });
+    // updates go out late
+    s.add_interceptor(|_: &mut RequestOptions, request: &mut TaskRequest| match request {
+        TaskRequest::UpdateTask { .. } => Verdict::Delay(Duration::from_millis(100)),
+        _ => Verdict::Continue,
+    });
+
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("stop".into(), update_fn(|_| Ok("stopped".to_string())))].into(),
+    );                                                                         // req_id: 0
+    s.query_task_with(RequestOptions { client: 7, ..Default::default() }, id, "status"); // req_id: 1
+    s.query_task(id, "state");                                                 // req_id: 2
+    s.update_task(id, "stop");                                                 // req_id: 3
+    s.query_task(id, "status");                                                // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    assert_eq!(*seen.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    assert!(s.expect(1, &TaskResult::Rejected { req_id: 1, id, reason: "client 7 is not allowed".into() }));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id, value: "running".into(), access: None }));
+    assert!(s.expect(3, &TaskResult::UpdateOk { req_id: 3, id, value: "stopped".into() }));
+    // the delayed update was overtaken by the query sent after it
+    let completed = |req_id| s.lifecycle.get(req_id).unwrap().completed_at.unwrap();
+    assert!(completed(4) < completed(3));
+}
+
+#[test]
+fn test_circuit_breaker() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        circuit_breaker: Some(CircuitBreakerConfig { window: 6, failure_ratio: 0.3, cool_down: Duration::from_secs(1) }),
+        ..Default::default()
+    });
+    let ids: Vec<_> = (0..MAX_CONCURRENT_TASKS + 2)
+        .map(|_| s.create_task([("status".into(), "running".into())].into(), HashMap::new()))
+        .collect();                                    // req_ids: 0..=5, the last two are throttled
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.circuit_state(), Some(CircuitState::Open));
+
+    s.query_task(ids[0], "status");                    // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(6, &TaskResult::CircuitOpen { req_id: 6, id: ids[0] }));
+
+    // after the cool-down one probe goes through and closes the circuit again
+    clock.advance(Duration::from_secs(1));
+    s.query_task(ids[0], "status");                    // req_id: 7
+    assert_eq!(s.circuit_state(), Some(CircuitState::HalfOpen));
+    s.query_task(ids[0], "status");                    // req_id: 8
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(7, &TaskResult::QueryOk { req_id: 7, id: ids[0], value: "running".into(), access: None }));
+    assert!(s.expect(8, &TaskResult::CircuitOpen { req_id: 8, id: ids[0] }));
+    assert_eq!(s.circuit_state(), Some(CircuitState::Closed));
+}
+
+#[test]
+fn test_load_shedding() {
+    let mut s = ServerThread::with_config(ServerConfig {
+        load_shedding: Some(LoadShedding { queue_depth: Some(2), ..Default::default() }),
+        ..Default::default()
+    });
+    let batch = RequestOptions { qos: QosClass::Batch, ..Default::default() };
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    s.pause_worker();
+    s.query_task_with(batch.clone(), id, "status"); // req_id: 1
+    s.query_task(id, "status");                     // req_id: 2
+    // two requests queued, the worker counts as overloaded from here on
+    s.query_task_with(batch, id, "status");         // req_id: 3
+    s.query_task(id, "status");                     // req_id: 4
+    assert_eq!(s.expect_eventually(3, &TaskResult::Shed { req_id: 3, id }, Duration::from_secs(1)), Ok(()));
+    s.resume_worker();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    for req_id in [1, 2, 4] {
+        assert!(s.expect(req_id, &TaskResult::QueryOk { req_id, id, value: "running".into(), access: None }));
+    }
+}
+
+#[test]
+fn test_deadline_propagation() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), ..Default::default() });
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("slow".into(), update_fn(|_| {
+            thread::sleep(Duration::from_millis(300));
+            Ok("done".to_string())
+        }))].into(),
+    );                                                              // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    let within = |budget| RequestOptions { deadline: Some(clock.now() + budget), ..Default::default() };
+
+    // still in the worker's queues when the deadline passes
+    s.pause_worker();
+    s.query_task_with(within(Duration::from_millis(100)), id, "status"); // req_id: 1
+    thread::sleep(Duration::from_millis(50));
+    clock.advance(Duration::from_millis(200));
+    s.resume_worker();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::TimedOut { req_id: 1, id, stage: DeadlineStage::Worker }));
+
+    // queued behind a slow update at the task
+    s.update_task(id, "slow");                                     // req_id: 2
+    s.query_task_with(within(Duration::from_millis(100)), id, "status"); // req_id: 3
+    thread::sleep(Duration::from_millis(100));
+    clock.advance(Duration::from_millis(200));
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::UpdateOk { req_id: 2, id, value: "done".into() }));
+    assert!(s.expect(3, &TaskResult::TimedOut { req_id: 3, id, stage: DeadlineStage::Task }));
+
+    // plenty of budget left
+    s.query_task_with(within(Duration::from_secs(1)), id, "status"); // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(4, &TaskResult::QueryOk { req_id: 4, id, value: "running".into(), access: None }));
+}
+
+#[test]
+fn test_derived_timeouts() {
+    assert_eq!(Timeouts::derived(Duration::from_secs(TASK_TIMEOUT)), Timeouts::default());
+    let derived = Timeouts::derived(Duration::from_millis(400));
+    assert_eq!(derived, Timeouts {
+        task: Duration::from_millis(400),
+        listener: Duration::from_secs(1),
+        worker: Duration::from_secs(1),
+    });
+    assert_eq!(derived.validate().ok(), Some(()));
+
+    let second = Duration::from_secs(1);
+    assert_eq!(Timeouts::new(second, 3 * second, second).ok(), Some(Timeouts { task: second, listener: 3 * second, worker: second }));
+    assert!(matches!(Timeouts::new(second, second, second), Err(ConfigError::Invalid(_))));
+    assert!(matches!(Timeouts::new(Duration::ZERO, second, second), Err(ConfigError::Invalid(_))));
+
+    // later keys of a file still override the derived ones
+    let mut config = ServerConfig::default();
+    config.apply_lines("base_timeout = 0.4\nworker_timeout = 2").unwrap();
+    assert_eq!(config.timeouts, Timeouts { worker: Duration::from_secs(2), ..derived });
+    // with the update timeout following the base, the whole config still holds together
+    assert_eq!(config.update_timeout, Duration::from_millis(200));
+    assert_eq!(config.validate().ok(), Some(()));
+}
+
+#[test]
+fn test_persistent_run_mode() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        run_mode: RunMode::Persistent,
+        ..ServerConfig::default()
+    });
+    let old = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // well past the listener timeout, a simulation would have shut down by now
+    for _ in 0..3 {
+        clock.advance(Duration::from_secs(LISTENER_TIMEOUT));
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(!s.shutdown_flag.load(std::sync::atomic::Ordering::Relaxed));
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 1
+    s.query_task(id, "status");     // req_id: 2
+    // tasks still expire from inactivity
+    s.query_task(old, "status");    // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id, value: "running".into(), access: None }));
+    assert!(matches!(s.results.get(3), Some(TaskResult::NotFound { .. })));
+
+    s.shutdown_with(ShutdownMode::Drain);
+    assert!(s.shutdown_flag.load(std::sync::atomic::Ordering::Relaxed));
+}
+
+#[test]
+fn test_worker_exits_promptly_on_shutdown() {
+    // the simulated clock never moves on its own, so a worker left to its receive timeout would never notice
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), ..ServerConfig::default() });
+    s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.worker_stats().is_some());
+
+    clock.advance(Duration::from_secs(LISTENER_TIMEOUT));
+    s.join_listener();
+    assert!(s.shutdown_flag.load(std::sync::atomic::Ordering::Relaxed));
+
+    // the worker is gone once its admin channel is disconnected. a worker_stats probe would wake it up by itself
+    let deadline = std::time::Instant::now() + Duration::from_secs(1);
+    while s.admin_tx.send(AdminCommand::ResumeIntake).is_ok() {
+        assert!(std::time::Instant::now() < deadline, "the worker is still running");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_shutdown_hooks() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), ..ServerConfig::default() });
+    let (tx, rx) = std::sync::mpsc::channel();
+    let results = s.results.clone();
+    s.on_shutdown({
+        let tx = tx.clone();
+        move || tx.send(("first", results.is_recorded(1))).unwrap()
+    });
+    s.on_shutdown({
+        let tx = tx.clone();
+        move || tx.send(("second", true)).unwrap()
+    });
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(id, "status");     // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(!s.shutdown_hooks.has_run());
+
+    // idle timeout
+    clock.advance(Duration::from_secs(LISTENER_TIMEOUT));
+    s.join_listener();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![("first", true), ("second", true)]);
+
+    // exactly once, even with an explicit shutdown after it
+    s.shutdown_with(ShutdownMode::Drain);
+    assert_eq!(rx.try_recv().ok(), None);
+    // too late to wait for it, runs right away
+    s.on_shutdown(move || tx.send(("late", true)).unwrap());
+    assert_eq!(rx.try_recv().ok(), Some(("late", true)));
+}
+
+#[test]
+fn test_drop_tears_down_server() {
+    let started = std::time::Instant::now();
+    let (shutdown_flag, admin_tx, results) = {
+        let mut s = ServerThread::new();
+        let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+        s.query_task(id, "status");     // req_id: 1
+        (std::sync::Arc::clone(&s.shutdown_flag), s.admin_tx.clone(), s.results.clone())
+    };
+    // drained, not cut off
+    assert!(matches!(results.get(1), Some(TaskResult::QueryOk { .. })));
+    assert!(shutdown_flag.load(std::sync::atomic::Ordering::Relaxed));
+    assert!(admin_tx.send(AdminCommand::ResumeIntake).is_err());
+    assert!(started.elapsed() < DROP_TIMEOUT);
+}
+
+#[test]
+fn test_join_worker_summary() {
+    let mut s = ServerThread::new();
+    s.set_max_concurrent_tasks(1);
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("mark_done".into(), update_fn(|_| Ok("done".to_string())))].into(),
+    ); // req_id: 0
+    s.create_task(HashMap::new(), HashMap::new()); // req_id: 1, throttled
+    s.query_task(id, "status");         // req_id: 2
+    s.update_task(id, "mark_done");     // req_id: 3
+    s.shutdown_with(ShutdownMode::Drain);
+
+    let summary = s.join_worker().unwrap();
+    assert_eq!(summary.count("create_task"), 2);
+    assert_eq!(summary.count("query_task"), 1);
+    assert_eq!(summary.count("update_task"), 1);
+    // pings and shutdowns never reach the queues
+    assert_eq!(summary.total_handled(), 4);
+    assert_eq!((summary.tasks_spawned, summary.throttled, summary.killed), (1, 1, false));
+    assert_eq!(s.join_worker(), Some(summary));
+}
+
+#[test]
+fn test_supervisor_restarts_worker() {
+    let mut s = ServerThread::with_config(ServerConfig {
+        failures: Some(FailureSchedule::new().at(Duration::ZERO, FailureAction::PoisonTaskMap)),
+        supervise_worker: true,
+        ..Default::default()
+    });
+    let failures = s.subscribe("failure");
+    let restarts = s.subscribe("worker_restarted");
+    assert!(failures.recv_timeout(Duration::from_secs(1)).is_ok());
+
+    // the create that hits the poisoned task map is lost with the worker
+    s.create_task(HashMap::new(), HashMap::new()); // req_id: 0
+    assert_eq!(restarts.recv_timeout(Duration::from_secs(1)), Ok(ServerEvent::WorkerRestarted { restarts: 1 }));
+    assert!(s.health().worker_alive);
+
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 1
+    s.query_task(id, "status");         // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Err(vec![0]));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id, value: "running".into(), access: None }));
+
+    s.shutdown_with(ShutdownMode::Drain);
+    let summary = s.join_worker().unwrap();
+    assert_eq!(summary.restarts, 1);
+    assert_eq!(summary.count("create_task"), 2);
+}
+
+#[test]
+fn test_worker_heartbeats() {
+    let heartbeat = HeartbeatConfig { interval: Duration::from_millis(100), missed: 3, restart: false };
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        heartbeat: Some(heartbeat),
+        failures: Some(FailureSchedule::new().at(Duration::from_secs(1), FailureAction::KillWorker)),
+        ..ServerConfig::default()
+    });
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.worker_liveness(), Some(Liveness::Alive));
+
+    // a killed worker stops beating, requests fail fast instead of waiting for a worker that is gone
+    clock.advance(Duration::from_secs(1));
+    for _ in 0..10 {
+        thread::sleep(Duration::from_millis(20));
+        clock.advance(heartbeat.interval);
+    }
+    assert_eq!(s.worker_liveness(), Some(Liveness::Dead));
+    s.query_task(id, "status");     // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::Undeliverable { req_id: 1, id }));
+    s.shutdown_with(ShutdownMode::Immediate);
+
+    // with the supervisor a dead worker is replaced and beats again
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        heartbeat: Some(HeartbeatConfig { restart: true, ..heartbeat }),
+        supervise_worker: true,
+        failures: Some(FailureSchedule::new().at(Duration::from_secs(1), FailureAction::KillWorker)),
+        ..ServerConfig::default()
+    });
+    let restarts = s.subscribe("worker_restarted");
+    clock.advance(Duration::from_secs(1));
+    let mut restarted = None;
+    for _ in 0..20 {
+        thread::sleep(Duration::from_millis(20));
+        clock.advance(heartbeat.interval);
+        if let Ok(event) = restarts.try_recv() {
+            restarted = Some(event);
+            break;
+        }
+    }
+    assert_eq!(restarted, Some(ServerEvent::WorkerRestarted { restarts: 1 }));
+    thread::sleep(Duration::from_millis(20));
+    clock.advance(heartbeat.interval);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(s.worker_liveness(), Some(Liveness::Alive));
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    s.query_task(id, "status");     // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "running".into(), access: None }));
+}
+
+#[test]
+fn test_health_readiness() {
+    let mut s = ServerThread::with_config(ServerConfig { ready_queue_depth: Some(2), ..ServerConfig::default() });
+    assert_eq!(s.health().readiness, Readiness::Ready);
+
+    s.set_max_concurrent_tasks(1);
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    let health = s.health();
+    assert!(health.worker_alive && !health.readiness.is_ready());
+    assert_eq!(health.readiness, Readiness::AtCapacity);
+
+    s.set_max_concurrent_tasks(4);
+    s.pause_worker();
+    s.query_task(id, "status");     // req_id: 1
+    s.query_task(id, "status");     // req_id: 2
+    assert_eq!(s.health().readiness, Readiness::QueueFull);
+    s.resume_worker();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.health().readiness, Readiness::Ready);
+
+    s.shutdown_with(ShutdownMode::Drain);
+    assert_eq!(s.health().readiness, Readiness::Draining);
+}
+
+#[test]
+fn test_slow_request_detection() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        timeouts: Timeouts::derived(Duration::from_secs(10)),
+        slow_requests: Some(SlowRequestConfig { threshold: Duration::from_millis(500), keep: 1 }),
+        ..ServerConfig::default()
+    });
+    let slow = s.subscribe("slow_request");
+    let id = s.create_task([("status".into(), "running".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // held up in the paused worker for as long as the clock is moved on
+    for (req_id, held) in [(1, Duration::from_secs(1)), (2, Duration::from_secs(2)), (3, Duration::ZERO)] {
+        s.pause_worker();
+        s.query_task(id, "status");
+        clock.advance(held);
+        s.resume_worker();
+        assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+        assert!(matches!(s.results.get(req_id), Some(TaskResult::QueryOk { .. })));
+    }
+
+    assert_eq!(slow.try_iter().collect::<Vec<_>>(), vec![
+        ServerEvent::SlowRequest { req_id: 1, id, latency: Duration::from_secs(1) },
+        ServerEvent::SlowRequest { req_id: 2, id, latency: Duration::from_secs(2) },
+    ]);
+    assert_eq!(s.stats().slow_requests, 2);
+    assert_eq!(s.slowest_requests(), vec![SlowRequest { req_id: 2, id, kind: "query_ok", latency: Duration::from_secs(2) }]);
+}
+
+#[test]
+fn test_queue_wait_per_hop() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), ..ServerConfig::default() });
+    let (gate_tx, gate_rx) = std::sync::mpsc::channel::<()>();
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("blocked".into(), update_fn(move |_| {
+            let _ = gate_rx.recv();
+            Ok("done".to_string())
+        }))].into(),
+    ); // req_id: 0
+    s.update_task(id, "blocked");   // req_id: 1
+    s.query_task(id, "status");     // req_id: 2, waits on the task's channel behind the update
+    thread::sleep(Duration::from_millis(50));
+    clock.advance(Duration::from_millis(300));
+    gate_tx.send(()).unwrap();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(matches!(s.results.get(2), Some(TaskResult::QueryOk { .. })));
+
+    let worker = s.queue_wait(QueueHop::Worker);
+    assert_eq!((worker.samples, worker.max), (3, Some(Duration::ZERO)));
+    let task = s.queue_wait(QueueHop::Task);
+    assert_eq!(task, WaitDistribution {
+        samples: 2,
+        mean: Some(Duration::from_millis(150)),
+        p50: Some(Duration::ZERO),
+        p90: Some(Duration::from_millis(300)),
+        p99: Some(Duration::from_millis(300)),
+        max: Some(Duration::from_millis(300)),
+    });
+}
+
+#[cfg(feature = "profiler")]
+#[test]
+fn test_sampling_profiler() {
+    let mut s = ServerThread::with_config(ServerConfig {
+        profiler: Some(ProfilerConfig { interval: Duration::from_millis(1) }),
+        ..ServerConfig::default()
+    });
+    let id = s.create_task(
+        [("status".into(), "running".into())].into(),
+        [("slow".into(), update_fn(|_| {
+            thread::sleep(Duration::from_millis(100));
+            Ok("done".to_string())
+        }))].into(),
+    ); // req_id: 0
+    s.update_task(id, "slow");      // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(matches!(s.results.get(1), Some(TaskResult::UpdateOk { .. })));
+
+    let profile = s.profile().unwrap();
+    assert!(profile.rounds > 0);
+    // the task spent most of its life on the update, the worker on its channel
+    assert!(profile.share(Component::Task, Activity::ExecutingUpdate) > 0.5, "{profile}");
+    assert!(profile.count(Component::Worker, Activity::Waiting) > 0, "{profile}");
+    assert!(profile.total(Component::Listener) > 0, "{profile}");
+    assert!(ServerThread::new().profile().is_none());
+}
+
+#[test]
+fn test_load_generator_distributions() {
+    // a recording's gaps drive the arrivals, whatever its requests were
+    let recording = Recording {
+        entries: [0, 10, 30]
+            .into_iter()
+            .enumerate()
+            .map(|(req_id, at)| RecordedEntry {
+                at: Duration::from_millis(at),
+                req_id,
+                opts: RequestOptions::default(),
+                request: RecordedRequest::Query { id: 0, query_id: "status".into() },
+            })
+            .collect(),
+    };
+    let path = std::env::temp_dir().join(format!("sws_loadgen_trace_{}.tsv", std::process::id()));
+    recording.save(&path).unwrap();
+    let arrival = Arrival::load_trace(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(arrival, Arrival::trace(&recording));
+    assert_eq!(arrival, Arrival::Trace { gaps: [Duration::from_millis(10), Duration::from_millis(20)].into() });
+
+    let mut s = ServerThread::new();
+    let profile = LoadProfile { tasks: 2, arrival, duration: Duration::from_millis(100), ..LoadProfile::default() };
+    let report = LoadGenerator::new(profile).run(&mut s, Duration::from_secs(3));
+    // at 0, 10, 30, 40, 60, 70 and 90ms
+    assert_eq!((report.sent, report.succeeded, report.retired), (7, 7, 0));
+
+    // a recording of one request has no gaps, that request is sent and nothing after it. neither is anything after
+    // a gap that never ends
+    let single = Recording { entries: recording.entries[..1].to_vec() };
+    for arrival in [Arrival::trace(&single), Arrival::Constant { rate: 0.0 }] {
+        let mut s = ServerThread::new();
+        let profile = LoadProfile { tasks: 1, arrival, duration: Duration::from_millis(100), ..LoadProfile::default() };
+        let report = LoadGenerator::new(profile).run(&mut s, Duration::from_secs(3));
+        assert_eq!((report.sent, report.succeeded), (1, 1));
+    }
+
+    // tasks come and go while heavy-tailed traffic hits them
+    let mut s = ServerThread::new();
+    let profile = LoadProfile {
+        tasks: 2,
+        arrival: Arrival::Pareto { rate: 200.0, shape: 1.5 },
+        task_lifetime: TaskLifetime::Constant { lifetime: Duration::from_millis(40) },
+        duration: Duration::from_millis(200),
+        seed: 3,
+        ..LoadProfile::default()
+    };
+    let report = LoadGenerator::new(profile).run(&mut s, Duration::from_secs(3));
+    println!("{report}");
+    assert!(report.sent > 0);
+    assert_eq!(report.succeeded, report.sent);
+    assert!(report.retired >= 2, "{report}");
+}
+
+#[test]
+fn test_time_compressed_run() {
+    use std::sync::{Arc, Mutex};
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        run_mode: RunMode::Persistent,
+        respawn_expired: true,
+        timeouts: Timeouts::derived(Duration::from_secs(600)),
+        ..ServerConfig::default()
+    });
+    let expiries = Arc::new(Mutex::new(vec![]));
+    s.on_task_finished({
+        let (clock, expiries) = (clock.clone(), Arc::clone(&expiries));
+        move |_, exit, _| {
+            if exit == TaskExit::Expired {
+                expiries.lock().unwrap().push(clock.now());
+            }
+        }
+    });
+    let hour = Duration::from_secs(3600);
+    let report = SimRun::new(clock.clone())
+        .at(Duration::ZERO, |s| {
+            s.create_task(
+                [("status".into(), "running".into())].into(),
+                [("bump".into(), update_fn(|_| Ok("bumped".to_string())))].into(),
+            ); // req_id: 0
+        })
+        .at(hour, |s| s.query_task(0, "status"))           // req_id: 1
+        .at(3 * hour, |s| s.update_task(0, "bump"))        // req_id: 2
+        .run(&mut s, 4 * hour);
+    println!("{report}");
+    assert_eq!(report.simulated, 4 * hour);
+    assert_eq!(report.fired, 3);
+    assert!(report.wall < Duration::from_secs(20), "{report}");
+
+    assert!(matches!(s.results.get(1), Some(TaskResult::QueryOk { .. })));
+    assert!(matches!(s.results.get(2), Some(TaskResult::UpdateOk { .. })));
+    // the task expired ten minutes after each request, never late and never ahead of the request that followed
+    let ten_minutes = Duration::from_secs(600);
+    assert_eq!(*expiries.lock().unwrap(), vec![ten_minutes, hour + ten_minutes, 3 * hour + ten_minutes]);
+}
+
+#[test]
+fn test_group_caps() {
+    let mut s = ServerThread::with_config(ServerConfig { group_caps: [("io".to_string(), 1)].into(), ..Default::default() });
+    let a = s.create_task_from(TaskBuilder::new().query("kind", "io").group("io").build().unwrap());    // req_id: 0
+    let b = s.create_task_from(TaskBuilder::new().query("kind", "io").group("io").build().unwrap());    // req_id: 1
+    let c = s.create_task_from(TaskBuilder::new().query("kind", "cpu").group("cpu").build().unwrap());  // req_id: 2
+    let d = s.create_task_from(TaskBuilder::new().query("kind", "none").build().unwrap());              // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(0, &TaskResult::Created { req_id: 0, id: a }));
+    // held to the group's cap, well below the server-wide one
+    assert!(s.expect(1, &TaskResult::Throttled { req_id: 1, id: b, group: Some("io".into()) }));
+    assert!(s.expect(2, &TaskResult::Created { req_id: 2, id: c }));
+    assert!(s.expect(3, &TaskResult::Created { req_id: 3, id: d }));
+    assert_eq!((s.group_active_tasks("io"), s.group_active_tasks("cpu")), (1, 1));
+
+    // the group frees up once its task is gone
+    assert!(s.kill_task(a));
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(s.group_active_tasks("io"), 0);
+    let e = s.create_task_from(TaskBuilder::new().group("io").build().unwrap());                          // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(4, &TaskResult::Created { req_id: 4, id: e }));
+}
+
+#[test]
+fn test_priority_preemption() {
+    let mut s = ServerThread::with_config(ServerConfig {
+        preemption: Some(Preemption::Pause),
+        task_history: Some(4),
+        ..Default::default()
+    });
+    s.set_max_concurrent_tasks(2);
+    let low = s.create_task_from(TaskBuilder::new().query("v", "low").build().unwrap());                    // req_id: 0
+    let mid = s.create_task_from(TaskBuilder::new().query("v", "mid").priority(5).build().unwrap());        // req_id: 1
+    let high = s.create_task_from(TaskBuilder::new().query("v", "high").priority(9).build().unwrap());      // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    // the least important task made room, and the create still got its own answer
+    assert!(s.expect(2, &TaskResult::Created { req_id: 2, id: high }));
+    assert_eq!(s.task_history(low).last(), Some(&TaskResult::Preempted { req_id: 2, id: low, by: high, paused: true }));
+    assert_eq!(s.active_tasks.load(std::sync::atomic::Ordering::Acquire), 2);
+
+    // nothing below priority 0 to make room for another task of priority 0
+    let other = s.create_task_from(TaskBuilder::new().build().unwrap());                                    // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(3, &TaskResult::Throttled { req_id: 3, id: other, group: None }));
+
+    // a paused task doesn't take a slot back at the cap, the preemption stands
+    s.query_task(low, "v");                                                                                  // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(4, &TaskResult::Throttled { req_id: 4, id: low, group: None }));
+    assert_eq!(s.active_tasks.load(std::sync::atomic::Ordering::Acquire), 2);
+    assert_eq!(s.task_history(mid).last(), Some(&TaskResult::Created { req_id: 1, id: mid }));
+
+    // it picks up where it left off once there is room for it
+    s.set_max_concurrent_tasks(3);
+    s.query_task(low, "v");                                                                                  // req_id: 5
+    s.query_task(mid, "v");                                                                                  // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(5, &TaskResult::QueryOk { req_id: 5, id: low, value: "low".into(), access: None }));
+    assert!(s.expect(6, &TaskResult::QueryOk { req_id: 6, id: mid, value: "mid".into(), access: None }));
+    assert_eq!(s.active_tasks.load(std::sync::atomic::Ordering::Acquire), 3);
+
+    // a terminated one is gone
+    let mut s = ServerThread::with_config(ServerConfig { preemption: Some(Preemption::Terminate), ..Default::default() });
+    s.set_max_concurrent_tasks(1);
+    let low = s.create_task_from(TaskBuilder::new().query("v", "low").build().unwrap());                    // req_id: 0
+    let high = s.create_task_from(TaskBuilder::new().priority(1).build().unwrap());                         // req_id: 1
+    s.query_task(low, "v");                                                                                  // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::Created { req_id: 1, id: high }));
+    assert!(matches!(s.results.get(2), Some(TaskResult::NotFound { .. })));
+}
+
+#[test]
+fn test_incremental_checkpoint() {
+    let path = std::env::temp_dir().join(format!("sws_incremental_{}.tsv", std::process::id()));
+    let store: HashMap<String, String> = (0..100).map(|i| (format!("key{i}"), format!("value{i}"))).collect();
+
+    let mut s = ServerThread::new();
+    let a = s.create_task(store, HashMap::new());                                 // req_id: 0
+    let b = s.create_task([("status".into(), "idle".into())].into(), HashMap::new()); // req_id: 1
+    s.subscribe_task(a, "news");                                                   // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.checkpoint_incremental(&path).unwrap().written, 2);
+    // nothing changed, nothing is written
+    assert_eq!(s.checkpoint_incremental(&path).unwrap().written, 0);
+
+    s.publish_task(b, "news", "hello"); // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.kill_task(b));
+    assert_eq!(s.checkpoint_incremental(&path).unwrap().written, 2);
+    s.shutdown_with(ShutdownMode::Drain);
+
+    // only the new value of the large task went into the file
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    assert_eq!(checkpoint.deltas, vec![
+        TaskDelta::Changed { id: a, version: 0, changed: vec![("event/news".into(), "hello".into())], removed: vec![], update_ids: vec![] },
+        TaskDelta::Gone { id: b },
+    ]);
+    let restored = checkpoint.restore().unwrap();
+    assert_eq!(restored.len(), 1);
+    assert_eq!(restored[0].values.len(), 101);
+
+    let mut fresh = ServerThread::new();
+    assert_eq!(fresh.resume(&path, &UpdateRegistry::new()).unwrap(), vec![a]); // req_id: 0
+    fresh.query_task(a, "event/news");                                          // req_id: 1
+    fresh.query_task(a, "key42");                                               // req_id: 2
+    assert_eq!(fresh.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(fresh.expect(1, &TaskResult::QueryOk { req_id: 1, id: a, value: "hello".into(), access: None }));
+    assert!(fresh.expect(2, &TaskResult::QueryOk { req_id: 2, id: a, value: "value42".into(), access: None }));
+
+    // a delta without the snapshot it applies to
+    let orphan = Checkpoint { tasks: vec![], deltas: vec![TaskDelta::Gone { id: 7 }, checkpoint.deltas[0].clone()] };
+    assert!(matches!(Checkpoint::parse(&orphan.to_string()).unwrap().restore(), Err(CheckpointError::MissingBase { id }) if id == a));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_checkpoint_lists_skipped_tasks() {
+    // a handler keeps its state to itself, it never gives a snapshot
+    struct Echo;
+    impl TaskHandler for Echo {
+        fn handle(&mut self, instr: Instruction) -> TaskResult {
+            TaskResult::QueryOk { req_id: instr.req_id(), id: 0, value: String::new(), access: None }
+        }
+    }
+    let path = std::env::temp_dir().join(format!("sws_skipped_{}.tsv", std::process::id()));
+
+    let mut s = ServerThread::new();
+    let a = s.create_task([("status".into(), "idle".into())].into(), HashMap::new()); // req_id: 0
+    let echo = s.create_handler_task(Echo);                                           // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.checkpoint(&path).unwrap(), CheckpointSummary { written: 1, skipped: vec![echo] });
+    assert_eq!(Checkpoint::load(&path).unwrap().tasks.iter().map(|task| task.id).collect::<Vec<_>>(), vec![a]);
+
+    assert_eq!(s.checkpoint_incremental(&path).unwrap(), CheckpointSummary { written: 1, skipped: vec![echo] });
+    s.shutdown_with(ShutdownMode::Drain);
+    let _ = std::fs::remove_file(&path);
+
+    // a task skipped by one link of a chain isn't written off as gone, it's still there when it answers again
+    let snapshot = |id| TaskSnapshot { id, tenant: 0, version: 0, values: vec![], update_ids: vec![] };
+    let mut chain = CheckpointChain::new(&path);
+    assert_eq!(chain.next(vec![snapshot(a), snapshot(echo)], &[]).tasks.len(), 2);
+    assert_eq!(chain.next(vec![snapshot(a)], &[echo]).deltas, vec![]);
+    assert_eq!(chain.next(vec![snapshot(a), snapshot(echo)], &[]).deltas, vec![]);
+    assert_eq!(chain.next(vec![snapshot(a)], &[]).deltas, vec![TaskDelta::Gone { id: echo }]);
+}
+
+#[test]
+fn test_query_cache() {
+    let config = ServerConfig { query_cache: true, ..Default::default() };
+    let mut s = ServerThread::with_config(config);
+    let bump = || -> UpdateFn { update_fn(|_| Ok("bumped".to_string())) };
+    let id = s.create_task([("status".into(), "running".into())].into(), [("bump".to_string(), bump())].into()); // req_id: 0
+    s.query_task(id, "status"); // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task(id, "status"); // req_id: 2, from the cache
+    s.query_task_with(RequestOptions { tenant: 1, ..Default::default() }, id, "status"); // req_id: 3, not this tenant's entry
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    let cache = s.query_cache.clone().unwrap();
+    assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 2, 1));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id, value: "running".into(), access: None }));
+    assert!(matches!(s.results.get(3), Some(TaskResult::NotFound { .. })));
+    // a hit never reaches the task
+    assert_eq!(s.lifecycle.get(2).and_then(|lifecycle| lifecycle.meta()), None);
+
+    s.update_task(id, "bump"); // req_id: 4
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(cache.is_empty());
+    s.query_task(id, "status"); // req_id: 5, goes to the task again
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 3, 1));
+}
+
+#[test]
+fn test_negative_cache() {
+    let clock = SimClock::new();
+    let negative_cache = Some(NegativeCacheConfig { ttl: Duration::from_secs(1), capacity: 1 });
+    let mut s = ServerThread::with_config(ServerConfig { clock: clock.clone(), negative_cache, ..Default::default() });
+    let cache = s.negative_cache.clone().unwrap();
+    s.query_task(5, "status"); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task(5, "status"); // req_id: 1, from the cache
+    s.query_task(6, "status"); // req_id: 2, pushes task 5 out
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::NotFound { req_id: 1, id: 5, ctx: "Task not found for query" }));
+    assert_eq!(s.lifecycle.get(1).and_then(|lifecycle| lifecycle.meta()), None);
+    assert_eq!((cache.hits(), cache.len()), (1, 1));
+    s.query_task(5, "status"); // req_id: 3, asks the worker again
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(cache.hits(), 1);
+
+    // an entry runs out after the ttl
+    s.query_task(5, "status"); // req_id: 4, from the cache
+    clock.advance(Duration::from_secs(2));
+    s.query_task(5, "status"); // req_id: 5, asks the worker again
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(cache.hits(), 2);
+    assert!(s.lifecycle.get(5).and_then(|lifecycle| lifecycle.meta()).is_some());
+
+    let mut config = ServerConfig::default();
+    config.apply_lines("negative_cache_ttl = 0.5\nnegative_cache_size = 8").unwrap();
+    assert_eq!(config.negative_cache, Some(NegativeCacheConfig { ttl: Duration::from_millis(500), capacity: 8 }));
+}
+
+#[test]
+fn test_task_exists() {
+    let mut s = ServerThread::new();
+    let a = s.create_task(HashMap::new(), HashMap::new()); // req_id: 0
+    let b = s.create_task_with(RequestOptions { tenant: 1, ..Default::default() }, HashMap::new(), HashMap::new()); // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.task_exists(a) && s.task_exists(b));
+    assert!(!s.task_exists(b + 1) && !s.task_exists(usize::MAX / 2));
+
+    // gone once its thread has exited
+    assert!(s.kill_task(a));
+    let started = std::time::Instant::now();
+    while s.task_exists(a) {
+        assert!(started.elapsed() < Duration::from_secs(1), "killed task still exists");
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(s.task_exists(b));
+}
+
+#[test]
+fn test_instruction_batches() {
+    let mut s = ServerThread::with_config(ServerConfig { instruction_batch: Some(8), ..Default::default() });
+    let bump = || -> UpdateFn { update_fn(|_| Ok("bumped".to_string())) };
+    let a = s.create_task([("status".into(), "running".into())].into(), [("bump".to_string(), bump())].into()); // req_id: 0
+    let b = s.create_task([("status".into(), "idle".into())].into(), HashMap::new()); // req_id: 1
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // staged so the worker takes them all in one turn
+    s.pause_worker();
+    s.query_task(a, "status");   // req_id: 2
+    s.update_task(a, "bump");    // req_id: 3
+    s.query_task(b, "status");   // req_id: 4
+    s.query_task(a, "missing");  // req_id: 5
+    s.query_task(b + 1, "none"); // req_id: 6
+    s.resume_worker();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id: a, value: "running".into(), access: None }));
+    assert!(s.expect(3, &TaskResult::UpdateOk { req_id: 3, id: a, value: "bumped".into() }));
+    assert!(s.expect(4, &TaskResult::QueryOk { req_id: 4, id: b, value: "idle".into(), access: None }));
+    assert!(matches!(s.results.get(5), Some(TaskResult::QueryError { code: ErrorCode::KeyNotFound, .. })));
+    assert!(matches!(s.results.get(6), Some(TaskResult::NotFound { .. })));
+    s.shutdown_with(ShutdownMode::Drain);
+
+    // a's three instructions went out together, b's one on its own
+    let summary = s.join_worker().unwrap();
+    assert_eq!(summary.batches, 1);
+    assert_eq!(summary.count("query_task"), 4);
+}
+
+#[test]
+fn test_batch_result_is_unpacked() {
+    let mut s = ServerThread::with_config(ServerConfig { task_history: Some(4), ..Default::default() });
+    let (first, second) = (s.next_req_id(), s.next_req_id());
+    let results = vec![
+        TaskResult::QueryOk { req_id: first, id: 3, value: "a".into(), access: None },
+        TaskResult::UpdateOk { req_id: second, id: 3, value: "b".into() },
+    ];
+    let batch = TaskResult::Batch { req_ids: vec![first, second], results: results.clone() };
+    assert_eq!((batch.req_id(), batch.id(), batch.kind()), (None, 3, "batch"));
+    s.result_tx(first).send(batch).unwrap();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(first, &results[0]));
+    assert!(s.expect(second, &results[1]));
+    // every sink sees the results one by one
+    assert_eq!(s.task_history(3), results);
+}
+
+#[test]
+fn test_query_chunks() {
+    let mut s = ServerThread::with_config(ServerConfig { query_chunk_size: Some(4), listener_shards: 2, ..Default::default() });
+    let big = "héllo wörld, ".repeat(20);
+    let id = s.create_task(
+        [("big".into(), big.clone()), ("small".into(), "tiny".into())].into(),
+        HashMap::new(),
+    ); // req_id: 0
+    s.query_task(id, "big");   // req_id: 1, in 75 pieces, some of them splitting a character
+    s.query_task(id, "small"); // req_id: 2, fits in one
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: big, access: None }));
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id, value: "tiny".into(), access: None }));
+    assert!(s.unanswered_requests().is_empty());
+}
+
+#[test]
+fn test_binary_values() {
+    let mut s = ServerThread::new();
+    let blob = vec![0u8, 159, 146, 150, 255];
+    let spec = TaskBuilder::new().query("name", "blob server").bytes("image", blob.clone()).build().unwrap();
+    let id = s.create_task_from(spec); // req_id: 0
+    s.query_bytes(id, "image", Encoding::Raw);    // req_id: 1
+    s.query_bytes(id, "image", Encoding::Base64); // req_id: 2
+    s.query_bytes(id, "name", Encoding::Raw);     // req_id: 3, a text value as its utf-8
+    s.query_task(id, "image");                    // req_id: 4, plain queries get base64
+    s.query_bytes(id, "missing", Encoding::Raw);  // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::BytesOk { req_id: 1, id, value: Value::Bytes(blob.clone()) }));
+    assert!(s.expect(2, &TaskResult::BytesOk { req_id: 2, id, value: Value::Text("AJ+Slv8=".into()) }));
+    assert!(s.expect(3, &TaskResult::BytesOk { req_id: 3, id, value: Value::Bytes(b"blob server".to_vec()) }));
+    assert!(s.expect(4, &TaskResult::QueryOk { req_id: 4, id, value: "AJ+Slv8=".into(), access: None }));
+    assert!(s.expect(5, &TaskResult::QueryError {
+        req_id: 5,
+        id,
+        code: ErrorCode::KeyNotFound,
+        detail: Some("Query ID 'missing' not found".into()),
+    }));
+    assert_eq!(base64_decode("AJ+Slv8="), Some(blob));
+    assert_eq!(base64_decode("AJ+Slv8"), None);
+    assert!(TaskBuilder::new().query("k", "v").bytes("k", vec![1]).build().is_err());
+}
+
+#[test]
+fn test_json_path_queries() {
+    let mut s = ServerThread::new();
+    let doc = r#"{"user": {"name": "ada", "tags": ["admin", "ops"], "age": 36}, "note": "not \"quoted\""}"#;
+    let id = s.create_task([("doc".into(), doc.into()), ("plain".into(), "{oops".into())].into(), HashMap::new()); // req_id: 0
+    s.query_path(id, "doc", "$.user.tags[1]".parse().unwrap());     // req_id: 1
+    s.query_path(id, "doc", "$.user".parse().unwrap());             // req_id: 2
+    s.query_path(id, "doc", "$[\"note\"]".parse().unwrap());        // req_id: 3
+    s.query_path(id, "doc", "$.user.tags[5]".parse().unwrap());     // req_id: 4
+    s.query_path(id, "plain", "$".parse().unwrap());                // req_id: 5
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "\"ops\"".into(), access: None }));
+    let user = r#"{"name":"ada","tags":["admin","ops"],"age":36}"#;
+    assert!(s.expect(2, &TaskResult::QueryOk { req_id: 2, id, value: user.into(), access: None }));
+    assert!(s.expect(3, &TaskResult::QueryOk { req_id: 3, id, value: r#""not \"quoted\"""#.into(), access: None }));
+    assert_eq!(s.results.get(4).unwrap().error_code(), Some(ErrorCode::PathNotFound));
+    assert_eq!(s.results.get(5).unwrap().error_code(), Some(ErrorCode::PathNotFound));
+    assert!("user.name".parse::<JsonPath>().is_err());
+    assert!("$.a[x]".parse::<JsonPath>().is_err());
+    assert_eq!("$.a[\"b.c\"][0]".parse::<JsonPath>().unwrap().to_string(), "$.a[\"b.c\"][0]");
+}
+
+#[test]
+fn test_schema_validation() {
+    let schema = Schema::new()
+        .typed("count", ValueType::Integer)
+        .pattern("region", KeyPattern::Prefix("eu-".into()));
+    let bad = TaskBuilder::new().query("count", "many").schema(schema.clone()).build();
+    assert!(matches!(bad, Err(TaskBuildError::SchemaViolation(SchemaViolation { ref key, .. })) if key == "count"));
+
+    let mut s = ServerThread::new();
+    let spec = TaskBuilder::new()
+        .query("count", "1")
+        .query("region", "eu-west")
+        .update("count", |_| Ok("lots".into()))
+        .schema(schema)
+        .build()
+        .unwrap();
+    let id = s.create_task_from(spec); // req_id: 0
+    s.set_value(id, "count", "2");     // req_id: 1
+    s.set_value(id, "region", "us-1"); // req_id: 2
+    s.update_task(id, "count");        // req_id: 3, the update's value isn't an integer either
+    s.set_value(id, "free", "form");   // req_id: 4, not in the schema
+    s.query_task(id, "count");         // req_id: 5
+    s.query_task(id, "region");        // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::UpdateOk { req_id: 1, id, value: "2".into() }));
+    assert_eq!(s.results.get(2).unwrap().error_code(), Some(ErrorCode::SchemaViolation));
+    assert_eq!(s.results.get(3).unwrap().error_code(), Some(ErrorCode::SchemaViolation));
+    assert!(s.expect(4, &TaskResult::UpdateOk { req_id: 4, id, value: "form".into() }));
+    assert!(s.expect(5, &TaskResult::QueryOk { req_id: 5, id, value: "2".into(), access: None }));
+    assert!(s.expect(6, &TaskResult::QueryOk { req_id: 6, id, value: "eu-west".into(), access: None }));
+}
+
+#[test]
+fn test_stateful_updates() {
+    let mut s = ServerThread::new();
+    let spec = TaskBuilder::new()
+        .query("balance", "10")
+        .query("stale", "x")
+        .update("deposit", |state| {
+            let balance: i64 = state.get("balance").unwrap().parse().unwrap();
+            state.set("balance", (balance + 5).to_string());
+            state.remove("stale");
+            let version = state.version();
+            if state.with_extension("versions", |seen: &mut Vec<u64>| seen.push(version)).is_none() {
+                state.insert_extension("versions", vec![version]);
+            }
+            Ok(state.bump("deposits", 1).to_string())
+        })
+        .update("history", |state| Ok(format!("{:?}", state.extension::<Vec<u64>>("versions").unwrap())))
+        .update("broken", |state| {
+            state.set("balance", "0");
+            Err("rolled back".into())
+        })
+        .update("crash", |state| {
+            state.bump("crashes", 1);
+            panic!("crashed")
+        })
+        .update("counts", |state| Ok(format!("{} {}", state.counter("deposits"), state.counter("crashes"))))
+        .build()
+        .unwrap();
+    let id = s.create_task_from(spec); // req_id: 0
+    s.update_task(id, "deposit");      // req_id: 1
+    s.update_task(id, "deposit");      // req_id: 2
+    s.update_task(id, "broken");       // req_id: 3, its write is dropped
+    s.update_task(id, "history");      // req_id: 4
+    s.query_task(id, "balance");       // req_id: 5
+    s.query_task(id, "stale");         // req_id: 6
+    s.update_task(id, "crash");        // req_id: 7
+    s.update_task(id, "counts");       // req_id: 8, the counters outlive the panic
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::UpdateOk { req_id: 2, id, value: "2".into() }));
+    assert_eq!(s.results.get(3).unwrap().error_code(), Some(ErrorCode::UpdateFailed));
+    assert!(s.expect(4, &TaskResult::UpdateOk { req_id: 4, id, value: "[0, 1]".into() }));
+    assert!(s.expect(5, &TaskResult::QueryOk { req_id: 5, id, value: "20".into(), access: None }));
+    assert_eq!(s.results.get(6).unwrap().error_code(), Some(ErrorCode::KeyNotFound));
+    assert_eq!(s.results.get(7).unwrap().error_code(), Some(ErrorCode::Panicked));
+    assert!(s.expect(8, &TaskResult::UpdateOk { req_id: 8, id, value: "2 1".into() }));
+}
+
+#[test]
+fn test_server_context() {
+    let prices: HashMap<String, u32> = [("apple".into(), 3), ("pear".into(), 5)].into();
+    let mut s = ServerThread::with_config(ServerConfig { context: Some(ServerContext::new(prices)), ..Default::default() });
+    let price = |item: &'static str| {
+        move |state: &mut TaskState| {
+            let prices = state.context::<HashMap<String, u32>>().ok_or("no prices")?;
+            Ok(prices[item].to_string())
+        }
+    };
+    let a = s.create_task_from(TaskBuilder::new().update("price", price("apple")).build().unwrap()); // req_id: 0
+    let b = s.create_task_from(TaskBuilder::new().update("price", price("pear")).build().unwrap());  // req_id: 1
+    s.update_task(a, "price"); // req_id: 2
+    s.update_task(b, "price"); // req_id: 3
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(2, &TaskResult::UpdateOk { req_id: 2, id: a, value: "3".into() }));
+    assert!(s.expect(3, &TaskResult::UpdateOk { req_id: 3, id: b, value: "5".into() }));
+    assert_eq!(ServerContext::new(1u8).get::<u16>(), None);
+}
+
+#[test]
+fn test_admin_commands_wake_the_worker() {
+    let mut s = ServerThread::new();
+    let id = s.create_task([("k".into(), "v".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.admin(AdminCommand::PauseIntake);
+    s.query_task(id, "k"); // req_id: 1, waits out the pause
+    thread::sleep(Duration::from_millis(50));
+    assert!(s.expect_none(1));
+    // the worker sleeps until something comes in, a resume on the admin channel alone is enough to wake it
+    let started = std::time::Instant::now();
+    s.admin_tx.send(AdminCommand::ResumeIntake).unwrap();
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "v".into(), access: None }));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(s.worker_stats().is_some_and(|stats| !stats.paused));
+}
+
+#[test]
+fn test_channel_stats() {
+    let mut s = ServerThread::new();
+    let before = s.stats().channel;
+    assert_eq!(before.backend, CHANNEL_BACKEND);
+    assert_eq!(before.average_send, None);
+    let id = s.create_task([("k".into(), "v".into())].into(), HashMap::new()); // req_id: 0
+    for _ in 0..50 {
+        s.query_task(id, "k"); // req_ids: 1..=50
+    }
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(50, &TaskResult::QueryOk { req_id: 50, id, value: "v".into(), access: None }));
+    // every request went through the worker's channel once
+    let stats = s.stats().channel;
+    assert_eq!(stats.backend, CHANNEL_BACKEND);
+    assert!(stats.sends >= 51, "{stats:?}");
+    assert!(stats.average_send.is_some());
+    println!("{stats:?}");
+}
+
+#[test]
+fn test_try_requests() {
+    let mut s = ServerThread::with_config(ServerConfig { ready_queue_depth: Some(2), ..Default::default() });
+    let id = s.create_task(
+        [("k".into(), "v".into())].into(),
+        [("bump".into(), update_fn(|_| Ok("bumped".to_string())))].into(),
+    ); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.try_query_task(id, "k"), Ok(1));
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "v".into(), access: None }));
+
+    // the pause has been handled once the stats come back on the same channel
+    s.admin(AdminCommand::PauseIntake);
+    assert!(s.worker_stats().is_some_and(|stats| stats.paused));
+    assert_eq!(s.try_update_task(id, "bump"), Err(WouldBlock::Paused));
+    s.query_task(id, "k"); // req_id: 2, queued behind the pause
+    s.query_task(id, "k"); // req_id: 3
+    // settled once the worker has both in its queues, in between one may be neither on the channel nor counted yet
+    while s.intake.queued() < 2 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(s.try_query_task(id, "k"), Err(WouldBlock::QueueFull { depth: 2, capacity: 2 }));
+    // nothing was sent for the turned down requests, the next one gets the next id
+    s.admin(AdminCommand::ResumeIntake);
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert_eq!(s.try_update_task(id, "bump"), Ok(4));
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(3, &TaskResult::QueryOk { req_id: 3, id, value: "v".into(), access: None }));
+    assert!(matches!(s.results.get(4), Some(TaskResult::UpdateOk { .. })));
+}
+
+#[test]
+fn test_hedged_queries() {
+    let clock = SimClock::new();
+    let faults = FaultConfig {
+        seed: 7,
+        instructions: FaultRates { delay: 1.0, ..Default::default() },
+        max_delay: Duration::from_secs(1),
+        ..Default::default()
+    };
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        faults: Some(faults),
+        hedge_after: Some(Duration::from_millis(100)),
+        ..Default::default()
+    });
+    let id = s.create_task([("k".into(), "v".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task(id, "k"); // req_id: 1, held back on its way to the task
+    thread::sleep(Duration::from_millis(20));
+    assert!(s.expect_none(1));
+
+    // unanswered past hedge_after, a copy goes out. both arrive once the clock has passed every delay
+    clock.advance(Duration::from_millis(100));
+    while s.stats().hedges.sent < 1 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    clock.advance(Duration::from_secs(1));
+    while s.stats().hedges.suppressed < 1 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "v".into(), access: None }));
+    assert_eq!(s.stats().hedges, HedgeStats { sent: 1, suppressed: 1 });
+    // both answers are in, nothing is left to watch for
+    let hedges = s.hedges.clone().unwrap();
+    assert_eq!(hedges.tracked(), 0);
+
+    // a copy for a task that is gone isn't sent and isn't kept
+    let gone = s.create_task([("k".into(), "v".into())].into(), HashMap::new()); // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task(gone, "k"); // req_id: 3, held back on its way to the task
+    thread::sleep(Duration::from_millis(20));
+    assert!(s.kill_task(gone));
+    clock.advance(Duration::from_millis(100));
+    while hedges.tracked() > 0 {
+        s.worker_stats();
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(s.stats().hedges.sent, 1);
+
+    // a query neither of whose answers comes back is forgotten once it has been kept for the task timeout
+    let lost = s.create_task([("k".into(), "v".into())].into(), HashMap::new()); // req_id: 4
+    assert_eq!(s.expect_eventually(4, &TaskResult::Created { req_id: 4, id: lost }, Duration::from_secs(1)), Ok(()));
+    s.query_task(lost, "k"); // req_id: 5, held back on its way to the task
+    thread::sleep(Duration::from_millis(20));
+    clock.advance(Duration::from_millis(100));
+    while s.stats().hedges.sent < 2 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    // both are still held back, and find the task gone
+    assert!(s.kill_task(lost));
+    clock.advance(Duration::from_secs(1));
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(hedges.tracked(), 1);
+    clock.advance(Duration::from_secs(TASK_TIMEOUT));
+    while hedges.tracked() > 0 {
+        s.worker_stats();
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(s.expect_none(3) && s.expect_none(5));
+}
+
+#[test]
+fn test_query_coalescing() {
+    let mut s = ServerThread::with_config(ServerConfig { coalesce_queries: true, ..Default::default() });
+    let id = s.create_task([("a".into(), "1".into()), ("b".into(), "2".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    // held in the worker's queues, so the first query is still in flight when the others come in
+    s.admin(AdminCommand::PauseIntake);
+    s.query_task(id, "a"); // req_id: 1, sent
+    s.query_task(id, "a"); // req_id: 2, joins 1
+    s.query_task(id, "b"); // req_id: 3, another key, sent
+    s.query_task(id, "a"); // req_id: 4, joins 1
+    s.query_task_with(RequestOptions { tenant: 1, ..Default::default() }, id, "a"); // req_id: 5, another tenant, sent
+    s.admin(AdminCommand::ResumeIntake);
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    for req_id in [1, 2, 4] {
+        assert!(s.expect(req_id, &TaskResult::QueryOk { req_id, id, value: "1".into(), access: None }));
+    }
+    assert!(s.expect(3, &TaskResult::QueryOk { req_id: 3, id, value: "2".into(), access: None }));
+    assert!(matches!(s.results.get(5), Some(TaskResult::NotFound { .. })));
+    assert_eq!(s.stats().coalesced, 2);
+    // once answered, the next one is sent again
+    s.query_task(id, "a"); // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(6, &TaskResult::QueryOk { req_id: 6, id, value: "1".into(), access: None }));
+    assert_eq!(s.coalescer.as_ref().map(QueryCoalescer::in_flight), Some(0));
+    assert_eq!(s.stats().coalesced, 2);
+}
+
+#[test]
+fn test_query_coalescing_across_an_update() {
+    let mut s = ServerThread::with_config(ServerConfig { coalesce_queries: true, ..Default::default() });
+    let id = s.create_task([("a".into(), "1".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.admin(AdminCommand::PauseIntake);
+    s.query_task(id, "a");      // req_id: 1, sent
+    s.query_task(id, "a");      // req_id: 2, joins 1
+    s.set_value(id, "a", "2");  // req_id: 3, closes 1 to joining
+    s.query_task(id, "a");      // req_id: 4, sent after the set
+    s.query_task(id, "a");      // req_id: 5, joins 4
+    s.admin(AdminCommand::ResumeIntake);
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+
+    // the queries from before the set see the old value, the ones after it the new one
+    for (req_id, value) in [(1, "1"), (2, "1"), (4, "2"), (5, "2")] {
+        assert!(s.expect(req_id, &TaskResult::QueryOk { req_id, id, value: value.into(), access: None }));
+    }
+    assert_eq!(s.stats().coalesced, 2);
+    assert_eq!(s.coalescer.as_ref().map(QueryCoalescer::in_flight), Some(0));
+}
+
+#[test]
+fn test_query_coalescing_with_a_lost_leader() {
+    // every request is dropped on its way to the worker, so no leader is ever answered
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        coalesce_queries: true,
+        faults: Some(FaultConfig { requests: FaultRates { drop: 1.0, ..Default::default() }, ..Default::default() }),
+        ..Default::default()
+    });
+    s.query_task(0, "a");   // req_id: 0, sent and lost
+    s.query_task(0, "a");   // req_id: 1, joins 0
+    assert_eq!(s.stats().coalesced, 1);
+
+    // a leader unanswered for the task timeout is given up on, the next query goes out on its own
+    clock.advance(Duration::from_secs(TASK_TIMEOUT));
+    s.query_task(0, "a");   // req_id: 2, sent
+    assert_eq!(s.expect_eventually(1, &TaskResult::Undeliverable { req_id: 1, id: 0 }, Duration::from_secs(1)), Ok(()));
+    assert!(s.expect_none(0));
+    assert_eq!(s.stats().coalesced, 1);
+    assert_eq!(s.coalescer.as_ref().map(QueryCoalescer::in_flight), Some(1));
+
+    // a leader that can't be sent at all is let go right away, with its followers
+    s.shutdown_with(ShutdownMode::Immediate);
+    s.query_task(0, "b");   // req_id: 3, fails to send
+    s.query_task(0, "b");   // req_id: 4, fails to send
+    assert_eq!(s.stats().coalesced, 1);
+    assert_eq!(s.coalescer.as_ref().map(QueryCoalescer::in_flight), Some(1));
+}
+
+#[test]
+fn test_read_replicas() {
+    let clock = SimClock::new();
+    let mut s = ServerThread::with_config(ServerConfig {
+        clock: clock.clone(),
+        replica_interval: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+    let stale_ok = RequestOptions { stale_ok: true, ..Default::default() };
+    let id = s.create_task([("k".into(), "v1".into())].into(), HashMap::new()); // req_id: 0
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    let replicas = s.replicas.clone().unwrap();
+    while replicas.get(id).is_none() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    s.query_task_with(stale_ok.clone(), id, "k"); // req_id: 1, from the replica
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(1, &TaskResult::QueryOk { req_id: 1, id, value: "v1".into(), access: None }));
+    assert_eq!(s.stats().replica_reads, 1);
+
+    // the replica lags until the next copy is due
+    s.set_value(id, "k", "v2"); // req_id: 2
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    s.query_task_with(stale_ok.clone(), id, "k"); // req_id: 3
+    s.query_task(id, "k"); // req_id: 4, asks the task
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(3, &TaskResult::QueryOk { req_id: 3, id, value: "v1".into(), access: None }));
+    assert!(s.expect(4, &TaskResult::QueryOk { req_id: 4, id, value: "v2".into(), access: None }));
+
+    clock.advance(Duration::from_secs(1));
+    while replicas.get(id).is_none_or(|replica| replica.version < 1) {
+        thread::sleep(Duration::from_millis(1));
+    }
+    s.query_task_with(stale_ok.clone(), id, "k"); // req_id: 5
+    // a key the replica doesn't have goes to the task
+    s.query_task_with(stale_ok, id, "missing"); // req_id: 6
+    assert_eq!(s.wait_idle(Duration::from_secs(1)), Ok(()));
+    assert!(s.expect(5, &TaskResult::QueryOk { req_id: 5, id, value: "v2".into(), access: None }));
+    assert_eq!(s.results.get(6).unwrap().error_code(), Some(ErrorCode::KeyNotFound));
+    assert_eq!(s.stats().replica_reads, 3);
+}
+
+#[cfg(feature = "signals")]
+#[test]
+fn test_shutdown_on_signal() {
+    // the watcher exits the process, so the server runs in a second copy of this test binary
+    const LOG_VAR: &str = "SWS_SIGNAL_TEST_LOG";
+    if let Ok(path) = std::env::var(LOG_VAR) {
+        // a flush interval the run never reaches, whatever is in the log got there through the signal's flush
+        let log = std::sync::Arc::new(JsonlSink::create(&path, ServerConfig::default().clock, Duration::from_secs(600)).unwrap());
+        let config = ServerConfig {
+            timeouts: Timeouts {
+                task: Duration::from_millis(500),
+                listener: Duration::from_secs(1),
+                worker: Duration::from_secs(1),
+            },
+            result_sinks: SinkChain::new().then_shared(log),
+            ..ServerConfig::default()
+        };
+        let mut s = ServerThread::with_config(config);
+        s.shutdown_on_signal().unwrap();
+        let task_id = s.create_task(
+            [("status".into(), "running".into())].into(),
+            [("crunch".into(), update_fn(|_| {
+                thread::sleep(Duration::from_millis(300));
+                Ok("crunched".to_string())
+            }))].into()
+        );                                  // req_id: 0
+        s.update_task(task_id, "crunch");   // req_id: 1
+        s.query_task(task_id, "status");    // req_id: 2
+        signal_hook::low_level::raise(signal_hook::consts::SIGTERM).unwrap();
+        thread::sleep(Duration::from_secs(30));
+        panic!("still running 30s after SIGTERM");
+    }
+
+    let path = std::env::temp_dir().join(format!("sws_signal_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["test_shutdown_on_signal", "--exact", "--nocapture", "--test-threads=1"])
+        .env(LOG_VAR, &path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(130), "{}", String::from_utf8_lossy(&output.stdout));
+
+    // the requests queued before the signal were drained and their results flushed before the exit
+    let log = std::fs::read_to_string(&path).unwrap();
+    let kinds: Vec<_> = log.lines().map(|line| line.split("\"kind\":\"").nth(1).unwrap().split('"').next().unwrap()).collect();
+    assert_eq!(kinds, ["created", "update_ok", "query_ok"]);
+    let _ = std::fs::remove_file(&path);
+}